@@ -0,0 +1,518 @@
+use super::*;
+use super::super::decoder::{decode, load_into_imem};
+use super::super::{Machine, Config, StopReason};
+
+fn words(image: &[u8]) -> Vec<u32> {
+    image.chunks(4).map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect()
+}
+
+fn disassembled(src: &str) -> Vec<String> {
+    let image = assemble(src, 0).unwrap();
+    words(&image).iter().map(|&w| decode(w).unwrap().to_string()).collect()
+}
+
+#[test]
+fn test_assembles_test_source_to_test_hex() {
+    let src = read_to_string("risc-v/sources/test.S").unwrap();
+    let mut expected = Vec::new();
+    load_into_imem("risc-v/assembled/test.hex", &mut expected).unwrap();
+    assert_eq!(assemble(&src, 0).unwrap(), expected);
+}
+
+#[test]
+fn test_base_instructions_round_trip_through_decoder() {
+    let src = "
+        lui t0, 0x12345
+        auipc a0, 0xfffff
+        jalr ra, -4(t1)
+        lb a1, -1(sp)
+        lhu a2, 2047(s0)
+        sw t6, -2048(gp)
+        sltiu a3, a4, -1
+        srai s1, s2, 31
+        sub a5, a6, a7
+        sra s3, s4, s5
+        mulhsu t3, t4, t5
+        remu s10, s11, zero
+        ecall
+        ebreak
+        csrrw ra, 0x305, sp
+        csrrci x0, 0x300, 8
+        csrrs a0, mscratch, x0
+    ";
+    assert_eq!(disassembled(src), vec![
+        "lui t0,0x12345",
+        "auipc a0,0xfffff",
+        "jalr ra,-4(t1)",
+        "lb a1,-1(sp)",
+        "lhu a2,2047(s0)",
+        "sw t6,-2048(gp)",
+        "sltiu a3,a4,-1",
+        "srai s1,s2,31",
+        "sub a5,a6,a7",
+        "sra s3,s4,s5",
+        "mulhsu t3,t4,t5",
+        "remu s10,s11,zero",
+        "ecall",
+        "ebreak",
+        "csrrw ra,mtvec,sp",
+        "csrrci zero,mstatus,8",
+        "csrrs a0,mscratch,zero",
+    ]);
+}
+
+#[test]
+fn test_atomics_match_reference_encodings() {
+    // Encodings from llvm-mc -triple=riscv32 -mattr=+a
+    let src = "
+        lr.w a0, (a1)
+        lr.w.aq t0, (sp)
+        sc.w.rl a0, a2, (a1)
+        amoswap.w.aqrl a0, a2, (a1)
+        amoadd.w a0, a2, (a1)
+        amoand.w a0, a2, (a1)
+        amominu.w a0, a2, (a1)
+        amomaxu.w a0, a2, (a1)
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![
+        0x1005a52f, 0x140122af, 0x1ac5a52f, 0x0ec5a52f, 0x00c5a52f, 0x60c5a52f, 0xc0c5a52f, 0xe0c5a52f,
+    ]);
+    assert_eq!(disassembled(src)[..4], [
+        "lr.w a0,(a1)", "lr.w.aq t0,(sp)", "sc.w.rl a0,a2,(a1)", "amoswap.w.aqrl a0,a2,(a1)",
+    ]);
+}
+
+#[test]
+fn test_fences_match_reference_encodings() {
+    // Encodings from llvm-mc -triple=riscv32
+    let src = "
+        fence
+        fence rw, w
+        fence i, o
+        fence.tso
+        fence.i
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![0x0ff0000f, 0x0310000f, 0x0840000f, 0x8330000f, 0x0000100f]);
+    assert_eq!(disassembled(src), vec!["fence iorw,iorw", "fence rw,w", "fence i,o", "fence.tso", "fence.i"]);
+}
+
+#[test]
+fn test_float_instructions_match_reference_encodings() {
+    // Encodings from llvm-mc -triple=riscv32 -mattr=+f
+    let src = "
+        flw fa0, 8(a1)
+        fsw ft1, -4(sp)
+        fmadd.s fa0, fa1, fa2, fa3
+        fnmsub.s ft0, ft1, ft2, ft3, rtz
+        fadd.s fa0, fa1, fa2
+        fsub.s fa0, fa1, fa2, rdn
+        fdiv.s fs0, fs1, fs2
+        fsqrt.s ft11, ft10
+        fsgnjn.s fa0, fa1, fa2
+        fmax.s fa0, fa1, fa2
+        fcvt.w.s a0, fa0, rtz
+        fcvt.wu.s a0, fa0
+        fmv.x.w a0, fa0
+        feq.s a0, fa0, fa1
+        fle.s a0, fa0, fa1
+        fclass.s a0, fa0
+        fcvt.s.wu fa0, a0, rmm
+        fmv.w.x fa0, a0
+        fneg.s fa0, fa1
+        frcsr a0
+        fsrm a1
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![
+        0x0085a507, 0xfe112e27, 0x68c5f543, 0x1820904b, 0x00c5f553, 0x08c5a553, 0x1924f453,
+        0x580f7fd3, 0x20c59553, 0x28c59553, 0xc0051553, 0xc0157553, 0xe0050553, 0xa0b52553,
+        0xa0b50553, 0xe0051553, 0xd0154553, 0xf0050553, 0x20b59553, 0x00302573, 0x00259073,
+    ]);
+    assert_eq!(disassembled(src)[..5], [
+        "flw fa0,8(a1)", "fsw ft1,-4(sp)", "fmadd.s fa0,fa1,fa2,fa3", "fnmsub.s ft0,ft1,ft2,ft3,rtz",
+        "fadd.s fa0,fa1,fa2",
+    ]);
+    assert_eq!(disassembled(src)[10..13], ["fcvt.w.s a0,fa0,rtz", "fcvt.wu.s a0,fa0", "fmv.x.w a0,fa0"]);
+}
+
+#[test]
+fn test_double_instructions_match_reference_encodings() {
+    // Encodings from llvm-mc -triple=riscv32 -mattr=+d
+    let src = "
+        fld fa0, 16(a1)
+        fsd fs0, -8(sp)
+        fmsub.d fa0, fa1, fa2, fa3
+        fadd.d fa0, fa1, fa2, rup
+        fsqrt.d ft0, ft1
+        fsgnjx.d fa0, fa1, fa2
+        fmin.d fa0, fa1, fa2
+        fcvt.w.d a0, fa0, rtz
+        fcvt.d.wu fa0, a0
+        fcvt.s.d fa0, fa1
+        fcvt.d.s fa0, fa1
+        flt.d a0, fa0, fa1
+        fclass.d a0, fa0
+        fabs.d fa0, fa1
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![
+        0x0105b507, 0xfe813c27, 0x6ac5f547, 0x02c5b553, 0x5a00f053, 0x22c5a553, 0x2ac58553,
+        0xc2051553, 0xd2150553, 0x4015f553, 0x42058553, 0xa2b51553, 0xe2051553, 0x22b5a553,
+    ]);
+    assert_eq!(disassembled(src)[..4], ["fld fa0,16(a1)", "fsd fs0,-8(sp)", "fmsub.d fa0,fa1,fa2,fa3", "fadd.d fa0,fa1,fa2,rup"]);
+    assert_eq!(disassembled(src)[8..11], ["fcvt.d.wu fa0,a0", "fcvt.s.d fa0,fa1", "fcvt.d.s fa0,fa1"]);
+}
+
+#[test]
+fn test_quad_instructions_follow_the_double_encodings() {
+    // The reference assembler has no Q, whose encodings are those of D
+    // with fmt = 3 in funct7 (and width 4 for the loads and stores).
+    let pairs = [
+        ("fmadd.d fa0, fa1, fa2, fa3", "fmadd.q fa0, fa1, fa2, fa3"),
+        ("fadd.d fa0, fa1, fa2, rup", "fadd.q fa0, fa1, fa2, rup"),
+        ("fdiv.d fs0, fs1, fs2", "fdiv.q fs0, fs1, fs2"),
+        ("fsqrt.d ft0, ft1", "fsqrt.q ft0, ft1"),
+        ("fsgnjx.d fa0, fa1, fa2", "fsgnjx.q fa0, fa1, fa2"),
+        ("fmax.d fa0, fa1, fa2", "fmax.q fa0, fa1, fa2"),
+        ("fcvt.w.d a0, fa0, rtz", "fcvt.w.q a0, fa0, rtz"),
+        ("fcvt.d.wu fa0, a0", "fcvt.q.wu fa0, a0"),
+        ("fle.d a0, fa0, fa1", "fle.q a0, fa0, fa1"),
+        ("fclass.d a0, fa0", "fclass.q a0, fa0"),
+    ];
+    for &(double, quad) in &pairs {
+        let (d, q) = (words(&assemble(double, 0).unwrap())[0], words(&assemble(quad, 0).unwrap())[0]);
+        assert_eq!(q, d | 0b10 << 25, "{}", quad);
+    }
+    let src = "
+        flq fa0, 16(a1)
+        fsq fs0, -16(sp)
+        fcvt.q.d fa0, fa1
+        fcvt.d.q fa0, fa1
+        fcvt.s.q fa0, fa1, rtz
+        fcvt.q.l fa0, a0
+        fneg.q fa0, fa1
+    ";
+    assert_eq!(words(&assemble(src, 0).unwrap()), vec![
+        0x0105c507, 0xfe814827, 0x46158553, 0x4235f553, 0x40359553, 0xd6250553, 0x26b59553,
+    ]);
+    assert_eq!(disassembled(src), vec![
+        "flq fa0,16(a1)", "fsq fs0,-16(sp)", "fcvt.q.d fa0,fa1", "fcvt.d.q fa0,fa1", "fcvt.s.q fa0,fa1,rtz",
+        "fcvt.q.l fa0,a0", "fsgnjn.q fa0,fa1,fa1",
+    ]);
+}
+
+#[test]
+fn test_rv64_instructions_match_reference_encodings() {
+    // Encodings from llvm-mc -triple=riscv64 -mattr=+m,+d
+    let src = "
+        ld a0, 8(a1)
+        sd s0, -16(sp)
+        lwu t0, 4(a2)
+        addiw a0, a1, -1
+        slli a0, a1, 63
+        srai t0, t1, 40
+        slliw a0, a1, 31
+        sraiw a0, a1, 3
+        addw a0, a1, a2
+        subw a0, a1, a2
+        sraw a0, a1, a2
+        mulw a0, a1, a2
+        divuw a0, a1, a2
+        remw a0, a1, a2
+        sext.w a0, a1
+        negw a0, a1
+        fcvt.l.d a0, fa0, rtz
+        fcvt.lu.s a0, fa0
+        fcvt.d.l fa0, a0
+        fcvt.s.lu fa0, a0, rne
+        fmv.x.d a0, fa0
+        fmv.d.x fa0, a0
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![
+        0x0085b503, 0xfe813823, 0x00466283, 0xfff5851b, 0x03f59513, 0x42835293, 0x01f5951b, 0x4035d51b,
+        0x00c5853b, 0x40c5853b, 0x40c5d53b, 0x02c5853b, 0x02c5d53b, 0x02c5e53b, 0x0005851b, 0x40b0053b,
+        0xc2251553, 0xc0357553, 0xd2257553, 0xd0350553, 0xe2050553, 0xf2050553,
+    ]);
+    assert_eq!(disassembled(src)[..6], [
+        "ld a0,8(a1)", "sd s0,-16(sp)", "lwu t0,4(a2)", "addiw a0,a1,-1", "slli a0,a1,63", "srai t0,t1,40",
+    ]);
+    assert_eq!(disassembled(src)[16..], [
+        "fcvt.l.d a0,fa0,rtz", "fcvt.lu.s a0,fa0", "fcvt.d.l fa0,a0", "fcvt.s.lu fa0,a0,rne",
+        "fmv.x.d a0,fa0", "fmv.d.x fa0,a0",
+    ]);
+    assert!(assemble("slliw a0, a1, 32", 0).is_err());
+}
+
+#[test]
+fn test_bitmanip_instructions_match_reference_encodings() {
+    // Encodings from llvm-mc -triple=riscv32 -mattr=+zba,+zbb,+zbc,+zbs
+    let src = "
+        sh1add a0, a1, a2
+        sh2add a0, a1, a2
+        sh3add a0, a1, a2
+        andn a0, a1, a2
+        orn a0, a1, a2
+        xnor a0, a1, a2
+        clz a0, a1
+        ctz a0, a1
+        cpop a0, a1
+        max a0, a1, a2
+        maxu a0, a1, a2
+        min a0, a1, a2
+        minu a0, a1, a2
+        sext.b a0, a1
+        sext.h a0, a1
+        zext.h a0, a1
+        rol a0, a1, a2
+        ror a0, a1, a2
+        rori a0, a1, 31
+        orc.b a0, a1
+        rev8 a0, a1
+        clmul a0, a1, a2
+        clmulr a0, a1, a2
+        clmulh a0, a1, a2
+        bclr a0, a1, a2
+        bclri a0, a1, 31
+        bext a0, a1, a2
+        bexti a0, a1, 5
+        binv a0, a1, a2
+        binvi a0, a1, 7
+        bset a0, a1, a2
+        bseti a0, a1, 0
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![
+        0x20c5a533, 0x20c5c533, 0x20c5e533, 0x40c5f533, 0x40c5e533, 0x40c5c533, 0x60059513, 0x60159513,
+        0x60259513, 0x0ac5e533, 0x0ac5f533, 0x0ac5c533, 0x0ac5d533, 0x60459513, 0x60559513, 0x0805c533,
+        0x60c59533, 0x60c5d533, 0x61f5d513, 0x2875d513, 0x6985d513, 0x0ac59533, 0x0ac5a533, 0x0ac5b533,
+        0x48c59533, 0x49f59513, 0x48c5d533, 0x4855d513, 0x68c59533, 0x68759513, 0x28c59533, 0x28059513,
+    ]);
+    assert_eq!(disassembled(src)[13..21], [
+        "sext.b a0,a1", "sext.h a0,a1", "zext.h a0,a1", "rol a0,a1,a2", "ror a0,a1,a2", "rori a0,a1,31",
+        "orc.b a0,a1", "rev8 a0,a1",
+    ]);
+
+    // Encodings from llvm-mc -triple=riscv64 -mattr=+zba,+zbb,+zbs
+    let src = "
+        add.uw a0, a1, a2
+        sh1add.uw a0, a1, a2
+        sh2add.uw a0, a1, a2
+        sh3add.uw a0, a1, a2
+        slli.uw a0, a1, 40
+        clzw a0, a1
+        ctzw a0, a1
+        cpopw a0, a1
+        rolw a0, a1, a2
+        rorw a0, a1, a2
+        roriw a0, a1, 31
+        rori a0, a1, 63
+        bseti a0, a1, 63
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![
+        0x08c5853b, 0x20c5a53b, 0x20c5c53b, 0x20c5e53b, 0x0a85951b, 0x6005951b, 0x6015951b, 0x6025951b,
+        0x60c5953b, 0x60c5d53b, 0x61f5d51b, 0x63f5d513, 0x2bf59513,
+    ]);
+    assert_eq!(disassembled(src)[..5], [
+        "add.uw a0,a1,a2", "sh1add.uw a0,a1,a2", "sh2add.uw a0,a1,a2", "sh3add.uw a0,a1,a2", "slli.uw a0,a1,40",
+    ]);
+    assert!(assemble("roriw a0, a1, 32", 0).is_err());
+}
+
+#[test]
+fn test_vector_instructions_match_reference_encodings() {
+    // Encodings from llvm-mc -triple=riscv32 -mattr=+v
+    let src = "
+        vsetvli a0, a1, e32, m1, ta, ma
+        vsetvli t0, zero, e8, m8, tu, mu
+        vsetivli a0, 4, e8, mf2, tu, mu
+        vsetvl a0, a1, a2
+        vle8.v v1, (a0)
+        vle16.v v2, (a0), v0.t
+        vlse32.v v1, (a0), a1
+        vluxei32.v v1, (a0), v2
+        vloxei8.v v1, (a0), v2, v0.t
+        vlm.v v0, (a0)
+        vl1re32.v v1, (a0)
+        vs1r.v v1, (a0)
+        vadd.vv v1, v2, v3, v0.t
+        vadd.vx v1, v2, a0
+        vadd.vi v1, v2, -16
+        vmsne.vv v1, v2, v3
+        vsrl.vx v1, v2, a0
+        vmulhu.vv v1, v2, v3
+        vmacc.vx v1, a0, v2
+        vnmsac.vv v1, v3, v2, v0.t
+        vmadd.vx v1, a0, v2
+        vmerge.vvm v1, v2, v3, v0
+        vmv.v.v v1, v2
+        vmv.v.x v1, a0
+        vredsum.vs v1, v2, v3
+        vmand.mm v1, v2, v3
+        vmv.s.x v1, a0
+        vcpop.m a0, v2
+        vcpop.m a0, v2, v0.t
+        viota.m v1, v2, v0.t
+        vid.v v1
+        vmv1r.v v2, v4
+        vmv2r.v v2, v4
+        vmv8r.v v8, v16
+    ";
+    let image = assemble(src, 0).unwrap();
+    assert_eq!(words(&image), vec![
+        0x0d05f557, 0x003072d7, 0xc0727557, 0x80c5f557, 0x02050087, 0x00055107, 0x0ab56087, 0x06256087,
+        0x0c250087, 0x02b50007, 0x02856087, 0x028500a7, 0x002180d7, 0x022540d7, 0x022830d7, 0x662180d7,
+        0xa22540d7, 0x9221a0d7, 0xb62560d7, 0xbc21a0d7, 0xa62560d7, 0x5c2180d7, 0x5e0100d7, 0x5e0540d7,
+        0x0221a0d7, 0x6621a0d7, 0x420560d7, 0x42282557, 0x40282557, 0x502820d7, 0x5208a0d7, 0x9e403157,
+        0x9e40b157, 0x9f03b457,
+    ]);
+    assert_eq!(disassembled(src)[..6], [
+        "vsetvli a0,a1,e32,m1,ta,ma", "vsetvli t0,zero,e8,m8,tu,mu", "vsetivli a0,4,e8,mf2,tu,mu",
+        "vsetvl a0,a1,a2", "vle8.v v1,(a0)", "vle16.v v2,(a0),v0.t",
+    ]);
+    assert_eq!(disassembled(src)[18..22], [
+        "vmacc.vx v1,a0,v2", "vnmsac.vv v1,v3,v2,v0.t", "vmadd.vx v1,a0,v2", "vmerge.vvm v1,v2,v3,v0",
+    ]);
+
+    // The policies default to undisturbed and LMUL to 1.
+    assert_eq!(words(&assemble("vsetvli a0, a1, e16", 0).unwrap()), vec![0x0085f557]);
+    assert!(assemble("vsetvli a0, a1, e128, m1", 0).is_err());
+    assert!(assemble("vmv.x.s a0, v1, v0.t", 0).is_err());
+    assert!(assemble("vadd.vi v1, v2, 16", 0).is_err());
+}
+
+#[test]
+fn test_labels_and_pseudo_instructions() {
+    let src = "
+    start:  li a0, 5
+            beqz a0, done   # never taken
+    loop:   addi a0, a0, -1
+            bnez a0, loop
+    done:   j start
+            mv a1, a2
+            ret
+    ";
+    assert_eq!(disassembled(src), vec![
+        "addi a0,zero,5",
+        "beq a0,zero,.+12",
+        "addi a0,a0,-1",
+        "bne a0,zero,.-4",
+        "jal zero,.-16",
+        "addi a1,a2,0",
+        "jalr zero,0(ra)",
+    ]);
+}
+
+#[test]
+fn test_counter_pseudo_instructions() {
+    // Encodings from llvm-mc -triple=riscv32
+    let image = assemble("rdcycle a0\nrdtimeh a1\nrdinstret a2", 0).unwrap();
+    assert_eq!(words(&image), vec![0xc0002573, 0xc81025f3, 0xc0202673]);
+}
+
+#[test]
+fn test_sfence_vma_operands_are_optional() {
+    // Encodings from llvm-mc -triple=riscv32
+    let image = assemble("sfence.vma\nsfence.vma a0\nsfence.vma a0, a1\nsret", 0).unwrap();
+    assert_eq!(words(&image), vec![0x12000073, 0x12050073, 0x12b50073, 0x10200073]);
+    assert_eq!(disassembled("sfence.vma zero, a1"), vec!["sfence.vma zero,a1"]);
+}
+
+#[test]
+fn test_li_picks_the_shortest_sequence() {
+    assert_eq!(disassembled("li t0, 0x12345678"), vec!["lui t0,0x12345", "addi t0,t0,1656"]);
+    assert_eq!(disassembled("li t0, 0xfffff800"), vec!["addi t0,zero,-2048"]);
+    assert_eq!(disassembled("li t0, 0x80000000"), vec!["lui t0,0x80000"]);
+    assert_eq!(disassembled("li t0, -2049"), vec!["lui t0,0xfffff", "addi t0,t0,2047"]);
+}
+
+#[test]
+fn test_data_section_follows_text() {
+    let src = "
+        .text
+        la a0, message
+        lw a1, value
+        .data
+    message: .asciz \"hi\"
+        .align 2
+    value: .word 0xdeadbeef, message
+    ";
+    let image = assemble(src, 0x100).unwrap();
+    // Two instructions for `la` and one for `lw`, then the data section.
+    assert_eq!(&image[12..15], b"hi\0");
+    assert_eq!(&image[16..20], &0xdeadbeefu32.to_le_bytes());
+    assert_eq!(&image[20..24], &0x10cu32.to_le_bytes());
+    assert_eq!(decode(words(&image)[0]).unwrap().to_string(), "auipc a0,0x0");
+    assert_eq!(decode(words(&image)[1]).unwrap().to_string(), "addi a0,a0,12");
+    assert_eq!(decode(words(&image)[2]).unwrap().to_string(), "lw a1,272(zero)");
+}
+
+#[test]
+fn test_assembled_program_runs() {
+    let src = "
+            .equ COUNT, 10
+            li t0, COUNT
+            li a0, 0
+    loop:   add a0, a0, t0
+            addi t0, t0, -1
+            bnez t0, loop
+            call double
+            la t1, result
+            sw a0, 0(t1)
+            j end
+    double: slli a0, a0, 1
+            ret
+    end:    j end
+            .data
+    result: .word 0
+    ";
+    let image = assemble(src, 0).unwrap();
+    let mut machine = Machine::new(Config::default());
+    machine.load(0, &image).unwrap();
+
+    assert_eq!(machine.run(Some(1000)), StopReason::InstructionLimit);
+    assert_eq!(machine.reg(10), 110);
+    let result = (image.len() - 4) as u64;
+    assert_eq!(machine.bus_mut().read_u32(result), Ok(110));
+}
+
+#[test]
+fn test_errors_report_their_line() {
+    assert_eq!(assemble("nop\nfrob a0, a1", 0),
+        Err(AssembleError { line: 2, message: "Unknown instruction `frob`".into() }));
+    assert_eq!(assemble("addi a0, a0, 2048", 0),
+        Err(AssembleError { line: 1, message: "Immediate 2048 does not fit in 12 signed bits".into() }));
+    assert_eq!(assemble("\n\nj nowhere", 0),
+        Err(AssembleError { line: 3, message: "Unknown symbol `nowhere`".into() }));
+    assert_eq!(assemble("add a0, a1, x32", 0),
+        Err(AssembleError { line: 1, message: "Unknown register `x32`".into() }));
+    assert_eq!(assemble("fadd.s fa0, fa1, fa2, rxx", 0),
+        Err(AssembleError { line: 1, message: "Invalid rounding mode `rxx`".into() }));
+    assert_eq!(assemble("fsgnj.s fa0, fa1, fa2, rne", 0),
+        Err(AssembleError { line: 1, message: "`fsgnj.s` expects 3 operands, found 4".into() }));
+    assert_eq!(assemble("a:\na:", 0),
+        Err(AssembleError { line: 2, message: "Symbol `a` is defined more than once".into() }));
+}
+
+#[test]
+fn test_directive_operands_out_of_range() {
+    let error = |line, message: &str| Err(AssembleError { line, message: message.into() });
+    assert_eq!(assemble(".align 40", 0), error(1, ".align 40 is out of range"));
+    assert_eq!(assemble("nop\n.p2align -1", 0), error(2, ".p2align -1 is out of range"));
+    assert_eq!(assemble(".balign 0x100000000", 0), error(1, ".balign 4294967296 is out of range"));
+    assert_eq!(assemble(".space -1", 0), error(1, ".space -1 is out of range"));
+    assert_eq!(assemble(".zero 0x100000000", 0), error(1, ".zero 4294967296 is out of range"));
+    assert_eq!(assemble(".byte 1\n.balign 0\n.align 12", 0).map(|image| image.len()), Ok(4096));
+}
+
+#[test]
+fn test_overflowing_expressions() {
+    let error = |expression: &str| Err(AssembleError { line: 1, message: format!("Expression `{}` overflows", expression) });
+    assert_eq!(assemble(".dword 0x7fffffffffffffff + 1", 0), error("0x7fffffffffffffff + 1"));
+    assert_eq!(assemble(".dword 0 - 0x8000000000000000", 0), error("0 - 0x8000000000000000"));
+    assert_eq!(assemble("lui a0, %hi(0x7fffffffffffffff)", 0), error("%hi(0x7fffffffffffffff)"));
+    assert_eq!(assemble(".dword 0x7ffffffffffff000 + 0xfff", 0).map(|image| image[7]), Ok(0x7f));
+}
@@ -0,0 +1,709 @@
+use super::*;
+
+pub(crate) const NOP: u32 = 0x0000_0013;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    R,
+    I,
+    Shift,
+    Unary,
+    Load,
+    S,
+    B,
+    U,
+    J,
+    Jalr,
+    System,
+    Csr,
+    CsrI,
+    Fence,
+    SfenceVma,
+    Lr,
+    Amo,
+    // Floating point. Entries whose funct3 is 7 take an optional rounding
+    // mode operand; the number in parentheses is a fixed rs2 field.
+    FpLoad,
+    FpStore,
+    FpR,
+    FpR4,
+    FpUnary(u32),
+    FpCmp,
+    FpToInt(u32),
+    FpFromInt(u32),
+}
+
+use self::Format::*;
+
+// mnemonic, format, opcode, funct3, funct7 (or the fixed imm[11:0] for System
+// and Unary, or rs3 and fmt for FpR4)
+const OPCODES: &[(&str, Format, u32, u32, u32)] = &[
+    ("lui",    U,      0x37, 0x0, 0x00),
+    ("auipc",  U,      0x17, 0x0, 0x00),
+    ("jal",    J,      0x6F, 0x0, 0x00),
+    ("jalr",   Jalr,   0x67, 0x0, 0x00),
+    ("beq",    B,      0x63, 0x0, 0x00),
+    ("bne",    B,      0x63, 0x1, 0x00),
+    ("blt",    B,      0x63, 0x4, 0x00),
+    ("bge",    B,      0x63, 0x5, 0x00),
+    ("bltu",   B,      0x63, 0x6, 0x00),
+    ("bgeu",   B,      0x63, 0x7, 0x00),
+    ("lb",     Load,   0x03, 0x0, 0x00),
+    ("lh",     Load,   0x03, 0x1, 0x00),
+    ("lw",     Load,   0x03, 0x2, 0x00),
+    ("lbu",    Load,   0x03, 0x4, 0x00),
+    ("lhu",    Load,   0x03, 0x5, 0x00),
+    ("sb",     S,      0x23, 0x0, 0x00),
+    ("sh",     S,      0x23, 0x1, 0x00),
+    ("sw",     S,      0x23, 0x2, 0x00),
+    ("lwu",    Load,   0x03, 0x6, 0x00),
+    ("ld",     Load,   0x03, 0x3, 0x00),
+    ("sd",     S,      0x23, 0x3, 0x00),
+    ("addi",   I,      0x13, 0x0, 0x00),
+    ("slti",   I,      0x13, 0x2, 0x00),
+    ("sltiu",  I,      0x13, 0x3, 0x00),
+    ("xori",   I,      0x13, 0x4, 0x00),
+    ("ori",    I,      0x13, 0x6, 0x00),
+    ("andi",   I,      0x13, 0x7, 0x00),
+    ("slli",   Shift,  0x13, 0x1, 0x00),
+    ("srli",   Shift,  0x13, 0x5, 0x00),
+    ("srai",   Shift,  0x13, 0x5, 0x20),
+    ("add",    R,      0x33, 0x0, 0x00),
+    ("sub",    R,      0x33, 0x0, 0x20),
+    ("sll",    R,      0x33, 0x1, 0x00),
+    ("slt",    R,      0x33, 0x2, 0x00),
+    ("sltu",   R,      0x33, 0x3, 0x00),
+    ("xor",    R,      0x33, 0x4, 0x00),
+    ("srl",    R,      0x33, 0x5, 0x00),
+    ("sra",    R,      0x33, 0x5, 0x20),
+    ("or",     R,      0x33, 0x6, 0x00),
+    ("and",    R,      0x33, 0x7, 0x00),
+    ("addiw",  I,      0x1B, 0x0, 0x00),
+    ("slliw",  Shift,  0x1B, 0x1, 0x00),
+    ("srliw",  Shift,  0x1B, 0x5, 0x00),
+    ("sraiw",  Shift,  0x1B, 0x5, 0x20),
+    ("addw",   R,      0x3B, 0x0, 0x00),
+    ("subw",   R,      0x3B, 0x0, 0x20),
+    ("sllw",   R,      0x3B, 0x1, 0x00),
+    ("srlw",   R,      0x3B, 0x5, 0x00),
+    ("sraw",   R,      0x3B, 0x5, 0x20),
+    ("fence",  Fence,  0x0F, 0x0, 0x00),
+    ("fence.tso", System, 0x0F, 0x0, 0x833),
+    ("fence.i", System, 0x0F, 0x1, 0x000),
+    ("ecall",  System, 0x73, 0x0, 0x000),
+    ("ebreak", System, 0x73, 0x0, 0x001),
+    ("mret",   System, 0x73, 0x0, 0x302),
+    ("sret",   System, 0x73, 0x0, 0x102),
+    ("wfi",    System, 0x73, 0x0, 0x105),
+    ("sfence.vma", SfenceVma, 0x73, 0x0, 0x09),
+    ("csrrw",  Csr,    0x73, 0x1, 0x00),
+    ("csrrs",  Csr,    0x73, 0x2, 0x00),
+    ("csrrc",  Csr,    0x73, 0x3, 0x00),
+    ("csrrwi", CsrI,   0x73, 0x5, 0x00),
+    ("csrrsi", CsrI,   0x73, 0x6, 0x00),
+    ("csrrci", CsrI,   0x73, 0x7, 0x00),
+    ("mul",    R,      0x33, 0x0, 0x01),
+    ("mulh",   R,      0x33, 0x1, 0x01),
+    ("mulhsu", R,      0x33, 0x2, 0x01),
+    ("mulhu",  R,      0x33, 0x3, 0x01),
+    ("div",    R,      0x33, 0x4, 0x01),
+    ("divu",   R,      0x33, 0x5, 0x01),
+    ("rem",    R,      0x33, 0x6, 0x01),
+    ("remu",   R,      0x33, 0x7, 0x01),
+    ("mulw",   R,      0x3B, 0x0, 0x01),
+    ("divw",   R,      0x3B, 0x4, 0x01),
+    ("divuw",  R,      0x3B, 0x5, 0x01),
+    ("remw",   R,      0x3B, 0x6, 0x01),
+    ("remuw",  R,      0x3B, 0x7, 0x01),
+    ("sh1add",    R,     0x33, 0x2, 0x10),
+    ("sh2add",    R,     0x33, 0x4, 0x10),
+    ("sh3add",    R,     0x33, 0x6, 0x10),
+    ("add.uw",    R,     0x3B, 0x0, 0x04),
+    ("sh1add.uw", R,     0x3B, 0x2, 0x10),
+    ("sh2add.uw", R,     0x3B, 0x4, 0x10),
+    ("sh3add.uw", R,     0x3B, 0x6, 0x10),
+    ("slli.uw",   Shift, 0x1B, 0x1, 0x04),
+    ("andn",      R,     0x33, 0x7, 0x20),
+    ("orn",       R,     0x33, 0x6, 0x20),
+    ("xnor",      R,     0x33, 0x4, 0x20),
+    ("clz",       Unary, 0x13, 0x1, 0x600),
+    ("ctz",       Unary, 0x13, 0x1, 0x601),
+    ("cpop",      Unary, 0x13, 0x1, 0x602),
+    ("clzw",      Unary, 0x1B, 0x1, 0x600),
+    ("ctzw",      Unary, 0x1B, 0x1, 0x601),
+    ("cpopw",     Unary, 0x1B, 0x1, 0x602),
+    ("max",       R,     0x33, 0x6, 0x05),
+    ("maxu",      R,     0x33, 0x7, 0x05),
+    ("min",       R,     0x33, 0x4, 0x05),
+    ("minu",      R,     0x33, 0x5, 0x05),
+    ("sext.b",    Unary, 0x13, 0x1, 0x604),
+    ("sext.h",    Unary, 0x13, 0x1, 0x605),
+    // zext.h and rev8 have the RV32 encodings.
+    ("zext.h",    Unary, 0x33, 0x4, 0x080),
+    ("rol",       R,     0x33, 0x1, 0x30),
+    ("ror",       R,     0x33, 0x5, 0x30),
+    ("rori",      Shift, 0x13, 0x5, 0x30),
+    ("rolw",      R,     0x3B, 0x1, 0x30),
+    ("rorw",      R,     0x3B, 0x5, 0x30),
+    ("roriw",     Shift, 0x1B, 0x5, 0x30),
+    ("orc.b",     Unary, 0x13, 0x5, 0x287),
+    ("rev8",      Unary, 0x13, 0x5, 0x698),
+    ("clmul",     R,     0x33, 0x1, 0x05),
+    ("clmulr",    R,     0x33, 0x2, 0x05),
+    ("clmulh",    R,     0x33, 0x3, 0x05),
+    ("bclr",      R,     0x33, 0x1, 0x24),
+    ("bclri",     Shift, 0x13, 0x1, 0x24),
+    ("bext",      R,     0x33, 0x5, 0x24),
+    ("bexti",     Shift, 0x13, 0x5, 0x24),
+    ("binv",      R,     0x33, 0x1, 0x34),
+    ("binvi",     Shift, 0x13, 0x1, 0x34),
+    ("bset",      R,     0x33, 0x1, 0x14),
+    ("bseti",     Shift, 0x13, 0x1, 0x14),
+    ("lr.w",      Lr,  0x2F, 0x2, 0x08),
+    ("sc.w",      Amo, 0x2F, 0x2, 0x0C),
+    ("amoswap.w", Amo, 0x2F, 0x2, 0x04),
+    ("amoadd.w",  Amo, 0x2F, 0x2, 0x00),
+    ("amoxor.w",  Amo, 0x2F, 0x2, 0x10),
+    ("amoand.w",  Amo, 0x2F, 0x2, 0x30),
+    ("amoor.w",   Amo, 0x2F, 0x2, 0x20),
+    ("amomin.w",  Amo, 0x2F, 0x2, 0x40),
+    ("amomax.w",  Amo, 0x2F, 0x2, 0x50),
+    ("amominu.w", Amo, 0x2F, 0x2, 0x60),
+    ("amomaxu.w", Amo, 0x2F, 0x2, 0x70),
+    ("flw",       FpLoad,       0x07, 0x2, 0x00),
+    ("fsw",       FpStore,      0x27, 0x2, 0x00),
+    ("fmadd.s",   FpR4,         0x43, 0x7, 0x00),
+    ("fmsub.s",   FpR4,         0x47, 0x7, 0x00),
+    ("fnmsub.s",  FpR4,         0x4B, 0x7, 0x00),
+    ("fnmadd.s",  FpR4,         0x4F, 0x7, 0x00),
+    ("fadd.s",    FpR,          0x53, 0x7, 0x00),
+    ("fsub.s",    FpR,          0x53, 0x7, 0x04),
+    ("fmul.s",    FpR,          0x53, 0x7, 0x08),
+    ("fdiv.s",    FpR,          0x53, 0x7, 0x0C),
+    ("fsqrt.s",   FpUnary(0),   0x53, 0x7, 0x2C),
+    ("fsgnj.s",   FpR,          0x53, 0x0, 0x10),
+    ("fsgnjn.s",  FpR,          0x53, 0x1, 0x10),
+    ("fsgnjx.s",  FpR,          0x53, 0x2, 0x10),
+    ("fmin.s",    FpR,          0x53, 0x0, 0x14),
+    ("fmax.s",    FpR,          0x53, 0x1, 0x14),
+    ("fcvt.w.s",  FpToInt(0),   0x53, 0x7, 0x60),
+    ("fcvt.wu.s", FpToInt(1),   0x53, 0x7, 0x60),
+    ("fmv.x.w",   FpToInt(0),   0x53, 0x0, 0x70),
+    ("feq.s",     FpCmp,        0x53, 0x2, 0x50),
+    ("flt.s",     FpCmp,        0x53, 0x1, 0x50),
+    ("fle.s",     FpCmp,        0x53, 0x0, 0x50),
+    ("fclass.s",  FpToInt(0),   0x53, 0x1, 0x70),
+    ("fcvt.s.w",  FpFromInt(0), 0x53, 0x7, 0x68),
+    ("fcvt.s.wu", FpFromInt(1), 0x53, 0x7, 0x68),
+    ("fmv.w.x",   FpFromInt(0), 0x53, 0x0, 0x78),
+    ("fcvt.l.s",  FpToInt(2),   0x53, 0x7, 0x60),
+    ("fcvt.lu.s", FpToInt(3),   0x53, 0x7, 0x60),
+    ("fcvt.s.l",  FpFromInt(2), 0x53, 0x7, 0x68),
+    ("fcvt.s.lu", FpFromInt(3), 0x53, 0x7, 0x68),
+    ("fld",       FpLoad,       0x07, 0x3, 0x00),
+    ("fsd",       FpStore,      0x27, 0x3, 0x00),
+    ("fmadd.d",   FpR4,         0x43, 0x7, 0x01),
+    ("fmsub.d",   FpR4,         0x47, 0x7, 0x01),
+    ("fnmsub.d",  FpR4,         0x4B, 0x7, 0x01),
+    ("fnmadd.d",  FpR4,         0x4F, 0x7, 0x01),
+    ("fadd.d",    FpR,          0x53, 0x7, 0x01),
+    ("fsub.d",    FpR,          0x53, 0x7, 0x05),
+    ("fmul.d",    FpR,          0x53, 0x7, 0x09),
+    ("fdiv.d",    FpR,          0x53, 0x7, 0x0D),
+    ("fsqrt.d",   FpUnary(0),   0x53, 0x7, 0x2D),
+    ("fsgnj.d",   FpR,          0x53, 0x0, 0x11),
+    ("fsgnjn.d",  FpR,          0x53, 0x1, 0x11),
+    ("fsgnjx.d",  FpR,          0x53, 0x2, 0x11),
+    ("fmin.d",    FpR,          0x53, 0x0, 0x15),
+    ("fmax.d",    FpR,          0x53, 0x1, 0x15),
+    ("fcvt.w.d",  FpToInt(0),   0x53, 0x7, 0x61),
+    ("fcvt.wu.d", FpToInt(1),   0x53, 0x7, 0x61),
+    ("feq.d",     FpCmp,        0x53, 0x2, 0x51),
+    ("flt.d",     FpCmp,        0x53, 0x1, 0x51),
+    ("fle.d",     FpCmp,        0x53, 0x0, 0x51),
+    ("fclass.d",  FpToInt(0),   0x53, 0x1, 0x71),
+    ("fcvt.d.w",  FpFromInt(0), 0x53, 0x0, 0x69),
+    ("fcvt.d.wu", FpFromInt(1), 0x53, 0x0, 0x69),
+    ("fcvt.s.d",  FpUnary(1),   0x53, 0x7, 0x20),
+    ("fcvt.d.s",  FpUnary(0),   0x53, 0x0, 0x21),
+    ("fcvt.l.d",  FpToInt(2),   0x53, 0x7, 0x61),
+    ("fcvt.lu.d", FpToInt(3),   0x53, 0x7, 0x61),
+    ("fmv.x.d",   FpToInt(0),   0x53, 0x0, 0x71),
+    ("fcvt.d.l",  FpFromInt(2), 0x53, 0x7, 0x69),
+    ("fcvt.d.lu", FpFromInt(3), 0x53, 0x7, 0x69),
+    ("fmv.d.x",   FpFromInt(0), 0x53, 0x0, 0x79),
+    ("flq",       FpLoad,       0x07, 0x4, 0x00),
+    ("fsq",       FpStore,      0x27, 0x4, 0x00),
+    ("fmadd.q",   FpR4,         0x43, 0x7, 0x03),
+    ("fmsub.q",   FpR4,         0x47, 0x7, 0x03),
+    ("fnmsub.q",  FpR4,         0x4B, 0x7, 0x03),
+    ("fnmadd.q",  FpR4,         0x4F, 0x7, 0x03),
+    ("fadd.q",    FpR,          0x53, 0x7, 0x03),
+    ("fsub.q",    FpR,          0x53, 0x7, 0x07),
+    ("fmul.q",    FpR,          0x53, 0x7, 0x0B),
+    ("fdiv.q",    FpR,          0x53, 0x7, 0x0F),
+    ("fsqrt.q",   FpUnary(0),   0x53, 0x7, 0x2F),
+    ("fsgnj.q",   FpR,          0x53, 0x0, 0x13),
+    ("fsgnjn.q",  FpR,          0x53, 0x1, 0x13),
+    ("fsgnjx.q",  FpR,          0x53, 0x2, 0x13),
+    ("fmin.q",    FpR,          0x53, 0x0, 0x17),
+    ("fmax.q",    FpR,          0x53, 0x1, 0x17),
+    ("fcvt.w.q",  FpToInt(0),   0x53, 0x7, 0x63),
+    ("fcvt.wu.q", FpToInt(1),   0x53, 0x7, 0x63),
+    ("fcvt.l.q",  FpToInt(2),   0x53, 0x7, 0x63),
+    ("fcvt.lu.q", FpToInt(3),   0x53, 0x7, 0x63),
+    ("feq.q",     FpCmp,        0x53, 0x2, 0x53),
+    ("flt.q",     FpCmp,        0x53, 0x1, 0x53),
+    ("fle.q",     FpCmp,        0x53, 0x0, 0x53),
+    ("fclass.q",  FpToInt(0),   0x53, 0x1, 0x73),
+    ("fcvt.q.w",  FpFromInt(0), 0x53, 0x0, 0x6B),
+    ("fcvt.q.wu", FpFromInt(1), 0x53, 0x0, 0x6B),
+    ("fcvt.q.l",  FpFromInt(2), 0x53, 0x0, 0x6B),
+    ("fcvt.q.lu", FpFromInt(3), 0x53, 0x0, 0x6B),
+    ("fcvt.s.q",  FpUnary(3),   0x53, 0x7, 0x20),
+    ("fcvt.d.q",  FpUnary(3),   0x53, 0x7, 0x21),
+    ("fcvt.q.s",  FpUnary(0),   0x53, 0x0, 0x23),
+    ("fcvt.q.d",  FpUnary(1),   0x53, 0x0, 0x23),
+];
+
+type Opcode = (&'static str, Format, u32, u32, u32);
+
+/// The table entry for `mnemonic`, along with the aq/rl bits of an atomic
+/// instruction's `.aq`, `.rl` or `.aqrl` suffix.
+fn lookup(mnemonic: &str) -> Option<(&'static Opcode, u32)> {
+    if let Some(op) = OPCODES.iter().find(|op| op.0 == mnemonic) {
+        return Some((op, 0));
+    }
+    [(".aqrl", 0x3), (".aq", 0x2), (".rl", 0x1)].iter()
+        .filter_map(|&(suffix, bits)| mnemonic.strip_suffix(suffix).map(|base| (base, bits)))
+        .filter_map(|(base, bits)| OPCODES.iter().find(|op| op.0 == base).map(|op| (op, bits)))
+        .find(|&(op, _)| op.1 == Lr || op.1 == Amo)
+}
+
+/// Bytes a statement will occupy. Only `li` with a constant known during the
+/// first pass can pick its shortest expansion; everything else is fixed.
+pub(crate) fn instruction_size(mnemonic: &str, operands: &[String], constants: &HashMap<String, i64>)
+    -> Result<u32, String> {
+    match mnemonic {
+        "la" | "lla" | "call" | "tail" => Ok(8),
+        "li" => {
+            match operands.get(1).map(|value| evaluate(value, constants)) {
+                Some(Ok(value)) => Ok(4 * li_sequence(0, value)?.len() as u32),
+                _ => Ok(8),
+            }
+        }
+        _ if is_pseudo(mnemonic) || lookup(mnemonic).is_some() || is_vector(mnemonic) => Ok(4),
+        _ => Err(format!("Unknown instruction `{}`", mnemonic)),
+    }
+}
+
+fn is_pseudo(mnemonic: &str) -> bool {
+    matches!(mnemonic,
+        "nop" | "mv" | "not" | "neg" | "negw" | "sext.w" | "seqz" | "snez" | "sltz" | "sgtz" |
+        "beqz" | "bnez" | "blez" | "bgez" | "bltz" | "bgtz" | "bgt" | "ble" | "bgtu" | "bleu" |
+        "j" | "jr" | "ret" | "csrr" | "csrw" | "csrs" | "csrc" | "csrwi" | "csrsi" | "csrci" |
+        "fmv.s" | "fneg.s" | "fabs.s" | "fmv.x.s" | "fmv.s.x" | "fmv.d" | "fneg.d" | "fabs.d" | "fmv.q" | "fneg.q" | "fabs.q" |
+        "frcsr" | "fscsr" | "frrm" | "fsrm" | "frflags" | "fsflags" | "fsrmi" | "fsflagsi" |
+        "rdcycle" | "rdtime" | "rdinstret" | "rdcycleh" | "rdtimeh" | "rdinstreth")
+}
+
+/// Encode one statement at `address` into `size / 4` instruction words,
+/// expanding pseudo-instructions along the way.
+pub(crate) fn encode(mnemonic: &str, operands: &[String], address: u32, size: u32, symbols: &HashMap<String, i64>)
+    -> Result<Vec<u32>, String> {
+
+    let op = |i: usize| operands[i].clone();
+    let base = |mnemonic: &str, operands: Vec<String>| encode_base(mnemonic, &operands, address, symbols).map(|w| vec![w]);
+
+    match mnemonic {
+        "li" => {
+            expect(mnemonic, operands, 2)?;
+            let rd = parse_register(&operands[0])?;
+            let value = evaluate(&operands[1], symbols)?;
+            let mut words = li_sequence(rd, value)?;
+            if size == 8 && words.len() == 1 {
+                let value = value as i32 as i64;
+                words = vec![u_type(hi(value) as u32, rd, 0x37), i_type(lo(value) as u32, rd, 0x0, rd, 0x13)];
+            }
+            Ok(words)
+        }
+        "la" | "lla" | "call" | "tail" => {
+            let (link, target) = match mnemonic {
+                "call" => { expect(mnemonic, operands, 1)?; (1, &operands[0]) }
+                "tail" => { expect(mnemonic, operands, 1)?; (6, &operands[0]) }
+                _ => { expect(mnemonic, operands, 2)?; (parse_register(&operands[0])?, &operands[1]) }
+            };
+            let delta = evaluate(target, symbols)?.checked_sub(address as i64)
+                .ok_or_else(|| format!("Expression `{}` overflows", target))?;
+            let auipc = u_type(hi(delta) as u32, link, 0x17);
+            let second = match mnemonic {
+                "call" => i_type(lo(delta) as u32, link, 0x0, 1, 0x67),
+                "tail" => i_type(lo(delta) as u32, link, 0x0, 0, 0x67),
+                _ => i_type(lo(delta) as u32, link, 0x0, link, 0x13),
+            };
+            Ok(vec![auipc, second])
+        }
+        "nop" => { expect(mnemonic, operands, 0)?; Ok(vec![NOP]) }
+        "mv" => { expect(mnemonic, operands, 2)?; base("addi", vec![op(0), op(1), "0".into()]) }
+        "not" => { expect(mnemonic, operands, 2)?; base("xori", vec![op(0), op(1), "-1".into()]) }
+        "neg" => { expect(mnemonic, operands, 2)?; base("sub", vec![op(0), "x0".into(), op(1)]) }
+        "negw" => { expect(mnemonic, operands, 2)?; base("subw", vec![op(0), "x0".into(), op(1)]) }
+        "sext.w" => { expect(mnemonic, operands, 2)?; base("addiw", vec![op(0), op(1), "0".into()]) }
+        "seqz" => { expect(mnemonic, operands, 2)?; base("sltiu", vec![op(0), op(1), "1".into()]) }
+        "snez" => { expect(mnemonic, operands, 2)?; base("sltu", vec![op(0), "x0".into(), op(1)]) }
+        "sltz" => { expect(mnemonic, operands, 2)?; base("slt", vec![op(0), op(1), "x0".into()]) }
+        "sgtz" => { expect(mnemonic, operands, 2)?; base("slt", vec![op(0), "x0".into(), op(1)]) }
+        "beqz" => { expect(mnemonic, operands, 2)?; base("beq", vec![op(0), "x0".into(), op(1)]) }
+        "bnez" => { expect(mnemonic, operands, 2)?; base("bne", vec![op(0), "x0".into(), op(1)]) }
+        "blez" => { expect(mnemonic, operands, 2)?; base("bge", vec!["x0".into(), op(0), op(1)]) }
+        "bgez" => { expect(mnemonic, operands, 2)?; base("bge", vec![op(0), "x0".into(), op(1)]) }
+        "bltz" => { expect(mnemonic, operands, 2)?; base("blt", vec![op(0), "x0".into(), op(1)]) }
+        "bgtz" => { expect(mnemonic, operands, 2)?; base("blt", vec!["x0".into(), op(0), op(1)]) }
+        "bgt" => { expect(mnemonic, operands, 3)?; base("blt", vec![op(1), op(0), op(2)]) }
+        "ble" => { expect(mnemonic, operands, 3)?; base("bge", vec![op(1), op(0), op(2)]) }
+        "bgtu" => { expect(mnemonic, operands, 3)?; base("bltu", vec![op(1), op(0), op(2)]) }
+        "bleu" => { expect(mnemonic, operands, 3)?; base("bgeu", vec![op(1), op(0), op(2)]) }
+        "j" => { expect(mnemonic, operands, 1)?; base("jal", vec!["x0".into(), op(0)]) }
+        "jal" if operands.len() == 1 => base("jal", vec!["ra".into(), op(0)]),
+        "jr" => { expect(mnemonic, operands, 1)?; base("jalr", vec!["x0".into(), op(0), "0".into()]) }
+        "jalr" if operands.len() == 1 => base("jalr", vec!["ra".into(), op(0), "0".into()]),
+        "ret" => { expect(mnemonic, operands, 0)?; base("jalr", vec!["x0".into(), "ra".into(), "0".into()]) }
+        "csrr" => { expect(mnemonic, operands, 2)?; base("csrrs", vec![op(0), op(1), "x0".into()]) }
+        "csrw" => { expect(mnemonic, operands, 2)?; base("csrrw", vec!["x0".into(), op(0), op(1)]) }
+        "csrs" => { expect(mnemonic, operands, 2)?; base("csrrs", vec!["x0".into(), op(0), op(1)]) }
+        "csrc" => { expect(mnemonic, operands, 2)?; base("csrrc", vec!["x0".into(), op(0), op(1)]) }
+        "csrwi" => { expect(mnemonic, operands, 2)?; base("csrrwi", vec!["x0".into(), op(0), op(1)]) }
+        "csrsi" => { expect(mnemonic, operands, 2)?; base("csrrsi", vec!["x0".into(), op(0), op(1)]) }
+        "csrci" => { expect(mnemonic, operands, 2)?; base("csrrci", vec!["x0".into(), op(0), op(1)]) }
+        "fmv.s" => { expect(mnemonic, operands, 2)?; base("fsgnj.s", vec![op(0), op(1), op(1)]) }
+        "fneg.s" => { expect(mnemonic, operands, 2)?; base("fsgnjn.s", vec![op(0), op(1), op(1)]) }
+        "fabs.s" => { expect(mnemonic, operands, 2)?; base("fsgnjx.s", vec![op(0), op(1), op(1)]) }
+        "fmv.d" => { expect(mnemonic, operands, 2)?; base("fsgnj.d", vec![op(0), op(1), op(1)]) }
+        "fneg.d" => { expect(mnemonic, operands, 2)?; base("fsgnjn.d", vec![op(0), op(1), op(1)]) }
+        "fabs.d" => { expect(mnemonic, operands, 2)?; base("fsgnjx.d", vec![op(0), op(1), op(1)]) }
+        "fmv.q" => { expect(mnemonic, operands, 2)?; base("fsgnj.q", vec![op(0), op(1), op(1)]) }
+        "fneg.q" => { expect(mnemonic, operands, 2)?; base("fsgnjn.q", vec![op(0), op(1), op(1)]) }
+        "fabs.q" => { expect(mnemonic, operands, 2)?; base("fsgnjx.q", vec![op(0), op(1), op(1)]) }
+        "fmv.x.s" => base("fmv.x.w", operands.to_vec()),
+        "fmv.s.x" => base("fmv.w.x", operands.to_vec()),
+        "frcsr" | "frrm" | "frflags" => {
+            expect(mnemonic, operands, 1)?;
+            base("csrrs", vec![op(0), fp_csr(mnemonic).into(), "x0".into()])
+        }
+        // rdcycle, rdtime and rdinstret name the counter they read.
+        "rdcycle" | "rdtime" | "rdinstret" | "rdcycleh" | "rdtimeh" | "rdinstreth" => {
+            expect(mnemonic, operands, 1)?;
+            base("csrrs", vec![op(0), mnemonic[2..].into(), "x0".into()])
+        }
+        "fscsr" | "fsrm" | "fsflags" | "fsrmi" | "fsflagsi" => {
+            // The old value is only written back when a destination is given.
+            let (rd, rs) = match operands.len() {
+                1 => ("x0".into(), op(0)),
+                2 => (op(0), op(1)),
+                _ => return Err(format!("`{}` expects 1 or 2 operands", mnemonic)),
+            };
+            let csrrw = if mnemonic.ends_with('i') { "csrrwi" } else { "csrrw" };
+            base(csrrw, vec![rd, fp_csr(mnemonic).into(), rs])
+        }
+        _ if is_vector(mnemonic) => encode_vector(mnemonic, operands, symbols).map(|w| vec![w]),
+        _ => base(mnemonic, operands.to_vec()),
+    }
+}
+
+/// Conversions that can never round. They accept a rounding mode but
+/// default to `rne`, as other assemblers do.
+fn is_exact_conversion(mnemonic: &str) -> bool {
+    matches!(mnemonic,
+        "fcvt.d.w" | "fcvt.d.wu" | "fcvt.d.s" | "fcvt.q.w" | "fcvt.q.wu" | "fcvt.q.l" | "fcvt.q.lu" |
+        "fcvt.q.s" | "fcvt.q.d")
+}
+
+/// The floating-point CSR a `fr*`/`fs*` pseudo-instruction accesses.
+fn fp_csr(mnemonic: &str) -> &'static str {
+    match mnemonic {
+        "frcsr" | "fscsr" => "fcsr",
+        "frrm" | "fsrm" | "fsrmi" => "frm",
+        _ => "fflags",
+    }
+}
+
+fn encode_base(mnemonic: &str, operands: &[String], address: u32, symbols: &HashMap<String, i64>)
+    -> Result<u32, String> {
+
+    let (&(_, format, opcode, f3, f7), ordering) = lookup(mnemonic)
+        .ok_or_else(|| format!("Unknown instruction `{}`", mnemonic))?;
+
+    let reg = |i: usize| parse_register(&operands[i]);
+    let freg = |i: usize| parse_float_register(&operands[i]);
+    let imm = |i: usize| evaluate(&operands[i], symbols);
+    // A trailing rounding mode, for the entries that take one.
+    let rm = |count: usize| -> Result<u32, String> {
+        match operands.len() {
+            n if n == count => Ok(f3),
+            n if n == count + 1 && (f3 == 0x7 || is_exact_conversion(mnemonic)) => rounding_mode(&operands[count]),
+            _ if f3 == 0x7 || is_exact_conversion(mnemonic) => Err(format!("`{}` expects {} or {} operands, found {}", mnemonic, count, count + 1, operands.len())),
+            _ => expect(mnemonic, operands, count).map(|_| f3),
+        }
+    };
+
+    match format {
+        R => {
+            expect(mnemonic, operands, 3)?;
+            Ok(r_type(f7, reg(2)?, reg(1)?, f3, reg(0)?, opcode))
+        }
+        I => {
+            expect(mnemonic, operands, 3)?;
+            Ok(i_type(signed(imm(2)?, 12)?, reg(1)?, f3, reg(0)?, opcode))
+        }
+        Shift => {
+            // slli, srli and srai take 6-bit amounts for RV64; the upper bit
+            // lands in the low bit of funct7. The *w forms take 5 bits, but
+            // slli.uw shifts a word into a doubleword.
+            expect(mnemonic, operands, 3)?;
+            let width = if opcode == 0x13 || mnemonic == "slli.uw" { 6 } else { 5 };
+            Ok(r_type(f7, unsigned(imm(2)?, width)?, reg(1)?, f3, reg(0)?, opcode))
+        }
+        Unary => {
+            expect(mnemonic, operands, 2)?;
+            Ok(i_type(f7, reg(1)?, f3, reg(0)?, opcode))
+        }
+        Load => {
+            expect(mnemonic, operands, 2)?;
+            let (offset, rs1) = memory_operand(&operands[1], symbols)?;
+            Ok(i_type(offset, rs1, f3, reg(0)?, opcode))
+        }
+        S => {
+            expect(mnemonic, operands, 2)?;
+            let (offset, rs1) = memory_operand(&operands[1], symbols)?;
+            Ok(s_type(offset, reg(0)?, rs1, f3, opcode))
+        }
+        B => {
+            expect(mnemonic, operands, 3)?;
+            let offset = pc_offset(&operands[2], address, symbols, 13)?;
+            Ok(b_type(offset, reg(1)?, reg(0)?, f3, opcode))
+        }
+        U => {
+            expect(mnemonic, operands, 2)?;
+            let value = imm(1)?;
+            if !(-0x80000..=0xFFFFF).contains(&value) {
+                return Err(format!("Immediate {} does not fit in 20 bits", value));
+            }
+            Ok(u_type(value as u32 & 0xFFFFF, reg(0)?, opcode))
+        }
+        J => {
+            expect(mnemonic, operands, 2)?;
+            let offset = pc_offset(&operands[1], address, symbols, 21)?;
+            Ok(j_type(offset, reg(0)?, opcode))
+        }
+        Jalr => {
+            let (offset, rs1) = match operands.len() {
+                2 => memory_operand(&operands[1], symbols)?,
+                3 => (signed(imm(2)?, 12)?, reg(1)?),
+                _ => return Err(format!("`{}` expects 2 or 3 operands", mnemonic)),
+            };
+            Ok(i_type(offset, rs1, f3, reg(0)?, opcode))
+        }
+        System => {
+            expect(mnemonic, operands, 0)?;
+            Ok(i_type(f7, 0, f3, 0, opcode))
+        }
+        Csr => {
+            expect(mnemonic, operands, 3)?;
+            Ok(i_type(csr_operand(&operands[1], symbols)?, reg(2)?, f3, reg(0)?, opcode))
+        }
+        CsrI => {
+            expect(mnemonic, operands, 3)?;
+            Ok(i_type(csr_operand(&operands[1], symbols)?, unsigned(imm(2)?, 5)?, f3, reg(0)?, opcode))
+        }
+        Fence => {
+            let (pred, succ) = match operands.len() {
+                0 => (0xF, 0xF),
+                2 => (fence_set(&operands[0])?, fence_set(&operands[1])?),
+                _ => return Err(format!("`{}` expects 0 or 2 operands", mnemonic)),
+            };
+            Ok(i_type((pred << 4) | succ, 0, f3, 0, opcode))
+        }
+        SfenceVma => {
+            // The address and ASID operands default to x0.
+            if operands.len() > 2 {
+                return Err(format!("`{}` expects at most 2 operands, found {}", mnemonic, operands.len()));
+            }
+            let rs1 = if operands.is_empty() { 0 } else { reg(0)? };
+            let rs2 = if operands.len() == 2 { reg(1)? } else { 0 };
+            Ok(r_type(f7, rs2, rs1, f3, 0, opcode))
+        }
+        Lr => {
+            expect(mnemonic, operands, 2)?;
+            Ok(r_type(f7 | ordering, 0, address_operand(&operands[1])?, f3, reg(0)?, opcode))
+        }
+        Amo => {
+            expect(mnemonic, operands, 3)?;
+            Ok(r_type(f7 | ordering, reg(1)?, address_operand(&operands[2])?, f3, reg(0)?, opcode))
+        }
+        FpLoad => {
+            expect(mnemonic, operands, 2)?;
+            let (offset, rs1) = memory_operand(&operands[1], symbols)?;
+            Ok(i_type(offset, rs1, f3, freg(0)?, opcode))
+        }
+        FpStore => {
+            expect(mnemonic, operands, 2)?;
+            let (offset, rs1) = memory_operand(&operands[1], symbols)?;
+            Ok(s_type(offset, freg(0)?, rs1, f3, opcode))
+        }
+        FpR => {
+            let f3 = rm(3)?;
+            Ok(r_type(f7, freg(2)?, freg(1)?, f3, freg(0)?, opcode))
+        }
+        FpR4 => {
+            let f3 = rm(4)?;
+            Ok(r_type((freg(3)? << 2) | f7, freg(2)?, freg(1)?, f3, freg(0)?, opcode))
+        }
+        FpUnary(rs2) => {
+            let f3 = rm(2)?;
+            Ok(r_type(f7, rs2, freg(1)?, f3, freg(0)?, opcode))
+        }
+        FpCmp => {
+            expect(mnemonic, operands, 3)?;
+            Ok(r_type(f7, freg(2)?, freg(1)?, f3, reg(0)?, opcode))
+        }
+        FpToInt(rs2) => {
+            let f3 = rm(2)?;
+            Ok(r_type(f7, rs2, freg(1)?, f3, reg(0)?, opcode))
+        }
+        FpFromInt(rs2) => {
+            let f3 = rm(2)?;
+            Ok(r_type(f7, rs2, reg(1)?, f3, freg(0)?, opcode))
+        }
+    }
+}
+
+fn li_sequence(rd: u32, value: i64) -> Result<Vec<u32>, String> {
+    if !(-0x8000_0000..=0xFFFF_FFFF).contains(&value) {
+        return Err(format!("Immediate {} does not fit in 32 bits", value));
+    }
+    let value = value as i32 as i64;
+    if (-2048..2048).contains(&value) {
+        return Ok(vec![i_type(value as u32, 0, 0x0, rd, 0x13)]);
+    }
+    let mut words = vec![u_type(hi(value) as u32, rd, 0x37)];
+    if lo(value) != 0 {
+        words.push(i_type(lo(value) as u32, rd, 0x0, rd, 0x13));
+    }
+    Ok(words)
+}
+
+pub(crate) fn expect(mnemonic: &str, operands: &[String], count: usize) -> Result<(), String> {
+    if operands.len() == count { Ok(()) }
+    else { Err(format!("`{}` expects {} operands, found {}", mnemonic, count, operands.len())) }
+}
+
+pub(crate) fn signed(value: i64, bits: u32) -> Result<u32, String> {
+    let limit = 1i64 << (bits - 1);
+    if value < -limit || value >= limit {
+        return Err(format!("Immediate {} does not fit in {} signed bits", value, bits));
+    }
+    Ok(value as u32 & ((1 << bits) - 1))
+}
+
+pub(crate) fn unsigned(value: i64, bits: u32) -> Result<u32, String> {
+    if value < 0 || value >= 1i64 << bits {
+        return Err(format!("Immediate {} does not fit in {} unsigned bits", value, bits));
+    }
+    Ok(value as u32)
+}
+
+/// Branch and jump targets: symbols are resolved against `address`, plain
+/// numbers are taken as offsets from it.
+fn pc_offset(operand: &str, address: u32, symbols: &HashMap<String, i64>, bits: u32) -> Result<u32, String> {
+    let offset = match parse_number(operand) {
+        Some(offset) => offset,
+        None => evaluate(operand, symbols)? - address as i64,
+    };
+    if offset % 2 != 0 {
+        return Err(format!("Target offset {} is not a multiple of 2", offset));
+    }
+    signed(offset, bits)
+}
+
+/// `offset(register)`, `(register)` or a bare offset meaning `offset(zero)`.
+fn memory_operand(operand: &str, symbols: &HashMap<String, i64>) -> Result<(u32, u32), String> {
+    match operand.rfind('(') {
+        Some(open) => {
+            let close = operand.rfind(')').filter(|&close| close > open)
+                .ok_or_else(|| format!("Malformed memory operand `{}`", operand))?;
+            let rs1 = parse_register(&operand[open + 1..close])?;
+            let offset = operand[..open].trim();
+            let offset = if offset.is_empty() { 0 } else { evaluate(offset, symbols)? };
+            Ok((signed(offset, 12)?, rs1))
+        }
+        None => Ok((signed(evaluate(operand, symbols)?, 12)?, 0)),
+    }
+}
+
+/// The `(register)` operand of an atomic instruction, which takes no offset.
+pub(crate) fn address_operand(operand: &str) -> Result<u32, String> {
+    operand.trim().strip_prefix('(').and_then(|rest| rest.strip_suffix(')'))
+        .ok_or_else(|| format!("Expected `(register)`, found `{}`", operand))
+        .and_then(parse_register)
+}
+
+/// A CSR given by name (`mstatus`) or by number.
+fn csr_operand(operand: &str, symbols: &HashMap<String, i64>) -> Result<u32, String> {
+    match csr::address_of(operand.trim()) {
+        Some(address) => Ok(address as u32),
+        None => unsigned(evaluate(operand, symbols)?, 12),
+    }
+}
+
+fn rounding_mode(operand: &str) -> Result<u32, String> {
+    match operand.trim() {
+        "rne" => Ok(0),
+        "rtz" => Ok(1),
+        "rdn" => Ok(2),
+        "rup" => Ok(3),
+        "rmm" => Ok(4),
+        "dyn" => Ok(7),
+        _ => Err(format!("Invalid rounding mode `{}`", operand)),
+    }
+}
+
+fn fence_set(operand: &str) -> Result<u32, String> {
+    if operand.trim() == "0" { return Ok(0); }
+    let mut set = 0;
+    for c in operand.trim().chars() {
+        set |= match c {
+            'i' => 0x8,
+            'o' => 0x4,
+            'r' => 0x2,
+            'w' => 0x1,
+            _ => return Err(format!("Invalid fence set `{}`", operand)),
+        };
+    }
+    Ok(set)
+}
+
+pub(crate) fn r_type(f7: u32, rs2: u32, rs1: u32, f3: u32, rd: u32, opcode: u32) -> u32 {
+    (f7 << 25) | (rs2 << 20) | (rs1 << 15) | (f3 << 12) | (rd << 7) | opcode
+}
+
+pub(crate) fn i_type(imm: u32, rs1: u32, f3: u32, rd: u32, opcode: u32) -> u32 {
+    ((imm & 0xFFF) << 20) | (rs1 << 15) | (f3 << 12) | (rd << 7) | opcode
+}
+
+pub(crate) fn s_type(imm: u32, rs2: u32, rs1: u32, f3: u32, opcode: u32) -> u32 {
+    (((imm >> 5) & 0x7F) << 25) | (rs2 << 20) | (rs1 << 15) | (f3 << 12) | ((imm & 0x1F) << 7) | opcode
+}
+
+pub(crate) fn b_type(imm: u32, rs2: u32, rs1: u32, f3: u32, opcode: u32) -> u32 {
+    (((imm >> 12) & 0x1) << 31) | (((imm >> 5) & 0x3F) << 25) | (rs2 << 20) | (rs1 << 15) |
+        (f3 << 12) | (((imm >> 1) & 0xF) << 8) | (((imm >> 11) & 0x1) << 7) | opcode
+}
+
+pub(crate) fn u_type(imm: u32, rd: u32, opcode: u32) -> u32 {
+    ((imm & 0xFFFFF) << 12) | (rd << 7) | opcode
+}
+
+pub(crate) fn j_type(imm: u32, rd: u32, opcode: u32) -> u32 {
+    (((imm >> 20) & 0x1) << 31) | (((imm >> 1) & 0x3FF) << 21) | (((imm >> 11) & 0x1) << 20) |
+        (((imm >> 12) & 0xFF) << 12) | (rd << 7) | opcode
+}
@@ -1,71 +1,425 @@
 use std::fs::File;
 use std::io::prelude::*;
 use std::collections::HashMap;
+use std::fmt;
 
-const INST_WIDTH: usize = 32;
+use super::bus::Bus;
+use super::csr;
+use super::decoder::{ABI_NAMES, FP_ABI_NAMES};
+use super::TEXT_BASE;
 
-pub fn assemble_and_load(filepath: &str, mem: &mut [u8], imem: &mut [u8]) -> () {
-    let src: String = read_to_string(filepath);
-    
-    let text: Vec<String> = get_section_text(&src).unwrap();
-    let base_instructions = resolve_labels(&text).expect("Could not resolve all labels");
-    let machine_code = assemble(&base_instructions);
+mod encoder;
+mod vector;
+use self::encoder::*;
+use self::vector::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
 }
 
-fn assemble(instructions: &Vec<String>) {
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Text,
+    Data,
+}
 
+enum Item {
+    Instruction { mnemonic: String, operands: Vec<String> },
+    Values { width: usize, values: Vec<String> },
+    Bytes(Vec<u8>),
 }
 
-fn resolve_labels(text: &Vec<String>) -> Result<Vec<String>, &'static str>{
-    //TODO: resolve global references
-    
-    let mut label_map: HashMap<&str, usize> = HashMap::new();
-    let mut ret: Vec<String> = Vec::new();
+struct Statement {
+    line: usize,
+    section: Section,
+    offset: u32,
+    size: u32,
+    item: Item,
+}
 
-    for i in 0..text.len() {
-        if let Some(num) = text[i].find(':') { 
-            label_map.insert(text[i].get(0..num).expect("Could not parse label"), i);
-            ret.push(text[i].get(num..).expect("Could not remove label").into());
+/// Assemble, place the text section at `TEXT_BASE` with the data section
+/// right after it, and copy the result into memory.
+pub fn assemble_and_load(filepath: &str, bus: &mut Bus) -> Result<(), String> {
+    let src: String = read_to_string(filepath)?;
+    let image = assemble(&src, TEXT_BASE as u32).map_err(|e| e.to_string())?;
+    bus.load(TEXT_BASE, &image).map_err(|e| format!("Could not load program: {}", e))
+}
+
+/// Two-pass assembler for RV32IM source. The first pass lays out every
+/// statement and records label addresses, the second encodes them. Returns
+/// the text section followed by the word-aligned data section.
+pub fn assemble(src: &str, base: u32) -> Result<Vec<u8>, AssembleError> {
+    let mut constants: HashMap<String, i64> = HashMap::new();
+    let mut labels: Vec<(String, Section, u32, usize)> = Vec::new();
+    let statements = layout(src, &mut constants, &mut labels)?;
+
+    let text_size: u32 = statements.iter().filter(|s| s.section == Section::Text).map(|s| s.size).sum();
+    let data_base = base + align_up(text_size, 4);
+    let data_size: u32 = statements.iter().filter(|s| s.section == Section::Data).map(|s| s.size).sum();
+
+    let mut symbols = constants;
+    for (name, section, offset, line) in labels {
+        let address = match section { Section::Text => base + offset, Section::Data => data_base + offset };
+        if symbols.insert(name.clone(), address as i64).is_some() {
+            return Err(AssembleError { line, message: format!("Symbol `{}` is defined more than once", name) });
         }
-        else {
-            ret.push(text[i].clone());
+    }
+
+    let mut image = vec![0u8; (data_base - base + data_size) as usize];
+    for statement in &statements {
+        let address = match statement.section {
+            Section::Text => base + statement.offset,
+            Section::Data => data_base + statement.offset,
+        };
+        let bytes = emit(statement, address, &symbols)
+            .map_err(|message| AssembleError { line: statement.line, message })?;
+        let start = (address - base) as usize;
+        image[start..start + bytes.len()].copy_from_slice(&bytes);
+    }
+
+    Ok(image)
+}
+
+// First pass
+
+fn layout(src: &str, constants: &mut HashMap<String, i64>, labels: &mut Vec<(String, Section, u32, usize)>)
+    -> Result<Vec<Statement>, AssembleError> {
+
+    let mut statements = Vec::new();
+    let mut section = Section::Text;
+    let mut offsets = [0u32; 2];
+
+    for (index, raw) in src.lines().enumerate() {
+        let line = index + 1;
+        let err = |message: String| AssembleError { line, message };
+        let mut rest = strip_comment(raw).trim();
+
+        // Any number of labels may prefix a statement.
+        while let Some(colon) = label_end(rest) {
+            let name = rest[..colon].trim();
+            if !is_symbol(name) { return Err(err(format!("Invalid label `{}`", name))); }
+            labels.push((name.into(), section, offsets[section as usize], line));
+            rest = rest[colon + 1..].trim();
+        }
+        if rest.is_empty() { continue; }
+
+        let (head, tail) = match rest.find(char::is_whitespace) {
+            Some(split) => (&rest[..split], rest[split..].trim()),
+            None => (rest, ""),
+        };
+        let head = head.to_lowercase();
+        let operands = split_operands(tail);
+        let offset = offsets[section as usize];
+
+        let (size, item) = if head.starts_with('.') {
+            match head.as_str() {
+                ".text" => { section = Section::Text; continue; }
+                ".data" | ".rodata" | ".bss" => { section = Section::Data; continue; }
+                ".section" => {
+                    let name = operands.first().map(|s| s.as_str()).unwrap_or("");
+                    section = if name.starts_with(".text") { Section::Text } else { Section::Data };
+                    continue;
+                }
+                ".globl" | ".global" | ".local" | ".type" | ".size" | ".file" | ".ident" |
+                ".option" | ".attribute" | ".weak" => continue,
+                ".equ" | ".set" => {
+                    if operands.len() != 2 || !is_symbol(&operands[0]) {
+                        return Err(err(format!("{} expects a name and a value", head)));
+                    }
+                    let value = evaluate(&operands[1], constants).map_err(err)?;
+                    constants.insert(operands[0].clone(), value);
+                    continue;
+                }
+                ".align" | ".p2align" | ".balign" => {
+                    let amount = operands.first()
+                        .ok_or_else(|| err(format!("{} expects an alignment", head)))
+                        .and_then(|s| evaluate(s, constants).map_err(err))?;
+                    // .balign takes a byte count and the others a power of two.
+                    let alignment = match head.as_str() {
+                        ".balign" if (0..=u32::MAX as i64).contains(&amount) => (amount as u32).max(1),
+                        ".align" | ".p2align" if (0..=31).contains(&amount) => 1 << amount,
+                        _ => return Err(err(format!("{} {} is out of range", head, amount))),
+                    };
+                    let padding = (alignment - offset % alignment) % alignment;
+                    (padding, Item::Bytes(padding_bytes(section, padding)))
+                }
+                ".space" | ".zero" | ".skip" => {
+                    let amount = operands.first()
+                        .ok_or_else(|| err(format!("{} expects a size", head)))
+                        .and_then(|s| evaluate(s, constants).map_err(err))?;
+                    if !(0..=u32::MAX as i64).contains(&amount) {
+                        return Err(err(format!("{} {} is out of range", head, amount)));
+                    }
+                    (amount as u32, Item::Bytes(vec![0; amount as u32 as usize]))
+                }
+                ".dword" | ".8byte" | ".quad" => (8 * operands.len() as u32, Item::Values { width: 8, values: operands }),
+                ".word" | ".4byte" | ".long" => (4 * operands.len() as u32, Item::Values { width: 4, values: operands }),
+                ".half" | ".short" | ".2byte" => (2 * operands.len() as u32, Item::Values { width: 2, values: operands }),
+                ".byte" => (operands.len() as u32, Item::Values { width: 1, values: operands }),
+                ".ascii" | ".asciz" | ".string" => {
+                    let mut bytes = Vec::new();
+                    for operand in &operands {
+                        bytes.extend(parse_string(operand).map_err(err)?);
+                        if head != ".ascii" { bytes.push(0); }
+                    }
+                    (bytes.len() as u32, Item::Bytes(bytes))
+                }
+                _ => return Err(err(format!("Unknown directive `{}`", head))),
+            }
+        } else {
+            let size = instruction_size(&head, &operands, constants).map_err(err)?;
+            (size, Item::Instruction { mnemonic: head, operands })
+        };
+
+        offsets[section as usize] = offset.checked_add(size)
+            .ok_or_else(|| err("The program does not fit in 32 bits".into()))?;
+        statements.push(Statement { line, section, offset, size, item });
+    }
+
+    Ok(statements)
+}
+
+fn padding_bytes(section: Section, padding: u32) -> Vec<u8> {
+    if section == Section::Text && padding.is_multiple_of(4) {
+        (0..padding / 4).flat_map(|_| NOP.to_le_bytes().to_vec()).collect()
+    } else {
+        vec![0; padding as usize]
+    }
+}
+
+// Second pass
+
+fn emit(statement: &Statement, address: u32, symbols: &HashMap<String, i64>) -> Result<Vec<u8>, String> {
+    match statement.item {
+        Item::Bytes(ref bytes) => Ok(bytes.clone()),
+        Item::Values { width, ref values } => {
+            let mut bytes = Vec::new();
+            for value in values {
+                let value = evaluate(value, symbols)?;
+                bytes.extend_from_slice(&value.to_le_bytes()[..width]);
+            }
+            Ok(bytes)
+        }
+        Item::Instruction { ref mnemonic, ref operands } => {
+            let words = encode(mnemonic, operands, address, statement.size, symbols)?;
+            Ok(words.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect())
         }
     }
+}
 
-    for i in 0..ret.len() {
-        for (label, inst) in &label_map {
-            ret[i] = ret[i].replace(label, &inst.to_string());
+// Operand parsing
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut previous = ' ';
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' if previous != '\\' => in_string = !in_string,
+            '#' | ';' if !in_string => return &line[..i],
+            '/' if !in_string && line[i..].starts_with("//") => return &line[..i],
+            _ => {}
         }
+        previous = c;
     }
+    line
+}
 
-    Ok(ret)
-}
-
-fn get_section_text(src: &String) -> Option<Vec<String>> {
-    
-    //TODO: Allow other .sections after .text
-    if let Some(text_start) = src.find(".text") {
-        Some(
-            src.get(text_start + 5 ..)
-                .expect("Could not parse text section")
-                .split("\n")
-                .map(|s: &str| s.into())
-                .collect()
-        )
-    } else if src.split(".section").collect::<Vec<&str>>().len() == 1 {
-        Some(
-            src.split("\n")
-                .map(|s: &str| s.into())
-                .collect()
-        )
-    }  else {
+fn label_end(statement: &str) -> Option<usize> {
+    let colon = statement.find(':')?;
+    if statement[..colon].contains('"') || statement[..colon].trim().contains(char::is_whitespace) {
         None
+    } else {
+        Some(colon)
+    }
+}
+
+fn split_operands(operands: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current = String::new();
+    let mut in_string = false;
+    let mut depth = 0;
+    let mut previous = ' ';
+
+    for c in operands.chars() {
+        match c {
+            '"' if previous != '\\' => in_string = !in_string,
+            '(' if !in_string => depth += 1,
+            ')' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                result.push(current.trim().to_string());
+                current.clear();
+                previous = c;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+        previous = c;
     }
+    if !current.trim().is_empty() { result.push(current.trim().to_string()); }
+    result
 }
 
-fn read_to_string(filepath: &str) -> String {
-    let mut file = File::open(filepath).or_else(|_| return Err("Could not open instruction file")).unwrap();
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.' || c == '$' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$')
+}
+
+fn parse_string(operand: &str) -> Result<Vec<u8>, String> {
+    if operand.len() < 2 || !operand.starts_with('"') || !operand.ends_with('"') {
+        return Err(format!("Expected a string literal, found `{}`", operand));
+    }
+    let mut bytes = Vec::new();
+    let mut chars = operand[1..operand.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buffer = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            continue;
+        }
+        bytes.push(match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('0') => 0,
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('\'') => b'\'',
+            other => return Err(format!("Unknown escape sequence `\\{}`", other.unwrap_or(' '))),
+        });
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn parse_register(operand: &str) -> Result<u32, String> {
+    let name = operand.trim();
+    if name == "fp" { return Ok(8); }
+    if let Some(index) = ABI_NAMES.iter().position(|&abi| abi == name) {
+        return Ok(index as u32);
+    }
+    if let Some(number) = name.strip_prefix('x') {
+        if let Ok(index) = number.parse::<u32>() {
+            if index < 32 { return Ok(index); }
+        }
+    }
+    Err(format!("Unknown register `{}`", name))
+}
+
+pub(crate) fn parse_float_register(operand: &str) -> Result<u32, String> {
+    let name = operand.trim();
+    if let Some(index) = FP_ABI_NAMES.iter().position(|&abi| abi == name) {
+        return Ok(index as u32);
+    }
+    if let Some(number) = name.strip_prefix('f') {
+        if let Ok(index) = number.parse::<u32>() {
+            if index < 32 { return Ok(index); }
+        }
+    }
+    Err(format!("Unknown floating-point register `{}`", name))
+}
+
+pub(crate) fn parse_vector_register(operand: &str) -> Result<u32, String> {
+    let name = operand.trim();
+    match name.strip_prefix('v').and_then(|number| number.parse::<u32>().ok()) {
+        Some(index) if index < 32 => Ok(index),
+        _ => Err(format!("Unknown vector register `{}`", name)),
+    }
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest.trim()),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        // Hex and binary literals may use all 64 bits, as in `.dword`.
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        u64::from_str_radix(bin, 2).ok()? as i64
+    } else if digits.len() == 3 && digits.starts_with('\'') && digits.ends_with('\'') {
+        digits.as_bytes()[1] as i64
+    } else {
+        digits.parse::<i64>().ok()?
+    };
+    Some(if negative { value.wrapping_neg() } else { value })
+}
+
+/// Evaluate a sum of numbers and symbols, optionally wrapped in `%hi(...)`
+/// or `%lo(...)`.
+pub(crate) fn evaluate(expression: &str, symbols: &HashMap<String, i64>) -> Result<i64, String> {
+    let expression = expression.trim();
+    let overflow = || format!("Expression `{}` overflows", expression);
+
+    for (prefix, part) in [("%hi(", hi as fn(i64) -> i64), ("%lo(", lo as fn(i64) -> i64)].iter() {
+        if let Some(inner) = expression.strip_prefix(prefix) {
+            let inner = inner.strip_suffix(')').ok_or_else(|| format!("Unbalanced `{}`", expression))?;
+            let value = evaluate(inner, symbols)?;
+            // %hi rounds to the nearest page, which must not carry out of 64 bits.
+            if *prefix == "%hi(" && value.checked_add(0x800).is_none() { return Err(overflow()); }
+            return Ok(part(value));
+        }
+    }
+
+    if let Some(value) = parse_number(expression) { return Ok(value); }
+
+    // Split into terms on top-level `+` and `-`, keeping a leading sign.
+    let mut total = 0i64;
+    let mut term = String::new();
+    let mut sign = 1i64;
+    for (i, c) in expression.char_indices() {
+        if (c == '+' || c == '-') && i > 0 && !term.trim().is_empty() {
+            total = term_value(&term, symbols)?.checked_mul(sign)
+                .and_then(|value| total.checked_add(value))
+                .ok_or_else(overflow)?;
+            term.clear();
+            sign = if c == '+' { 1 } else { -1 };
+        } else {
+            term.push(c);
+        }
+    }
+    term_value(&term, symbols)?.checked_mul(sign)
+        .and_then(|value| total.checked_add(value))
+        .ok_or_else(overflow)
+}
+
+fn term_value(term: &str, symbols: &HashMap<String, i64>) -> Result<i64, String> {
+    let term = term.trim();
+    if let Some(value) = parse_number(term) { return Ok(value); }
+    if let Some(name) = term.strip_prefix('-') {
+        return term_value(name, symbols)?.checked_neg().ok_or_else(|| format!("Expression `{}` overflows", term));
+    }
+    symbols.get(term).cloned().ok_or_else(|| format!("Unknown symbol `{}`", term))
+}
+
+/// Upper 20 bits of `value`, rounded so that adding `lo(value)` restores it.
+pub(crate) fn hi(value: i64) -> i64 { (value.wrapping_add(0x800) >> 12) & 0xF_FF_FF }
+
+/// Low 12 bits of `value`, sign-extended.
+pub(crate) fn lo(value: i64) -> i64 { ((value & 0xFFF) ^ 0x800) - 0x800 }
+
+fn align_up(value: u32, alignment: u32) -> u32 {
+    value.div_ceil(alignment) * alignment
+}
+
+fn read_to_string(filepath: &str) -> Result<String, String> {
+    let mut file = File::open(filepath).map_err(|e| format!("Could not open {}: {}", filepath, e))?;
     let mut instructions = String::new();
-    file.read_to_string(&mut instructions).or_else(|_| return Err("Error reading file")).unwrap();
-    instructions
-}
\ No newline at end of file
+    file.read_to_string(&mut instructions).map_err(|e| format!("Error reading {}: {}", filepath, e))?;
+    Ok(instructions)
+}
+
+#[cfg(test)]
+mod assembler_test;
@@ -0,0 +1,290 @@
+use super::*;
+
+// mnemonic without its suffix, funct6, funct3 of the .vv form (OPIVV or
+// OPMVV) and the forms it has: `v` for .vv, `x` for .vx and `i` for .vi
+const ARITHMETIC: &[(&str, u32, u32, &str)] = &[
+    ("vadd",    0x00, 0x0, "vxi"),
+    ("vsub",    0x02, 0x0, "vx"),
+    ("vrsub",   0x03, 0x0, "xi"),
+    ("vminu",   0x04, 0x0, "vx"),
+    ("vmin",    0x05, 0x0, "vx"),
+    ("vmaxu",   0x06, 0x0, "vx"),
+    ("vmax",    0x07, 0x0, "vx"),
+    ("vand",    0x09, 0x0, "vxi"),
+    ("vor",     0x0A, 0x0, "vxi"),
+    ("vxor",    0x0B, 0x0, "vxi"),
+    ("vmseq",   0x18, 0x0, "vxi"),
+    ("vmsne",   0x19, 0x0, "vxi"),
+    ("vmsltu",  0x1A, 0x0, "vx"),
+    ("vmslt",   0x1B, 0x0, "vx"),
+    ("vmsleu",  0x1C, 0x0, "vxi"),
+    ("vmsle",   0x1D, 0x0, "vxi"),
+    ("vmsgtu",  0x1E, 0x0, "xi"),
+    ("vmsgt",   0x1F, 0x0, "xi"),
+    ("vsll",    0x25, 0x0, "vxi"),
+    ("vsrl",    0x28, 0x0, "vxi"),
+    ("vsra",    0x29, 0x0, "vxi"),
+    ("vdivu",   0x20, 0x2, "vx"),
+    ("vdiv",    0x21, 0x2, "vx"),
+    ("vremu",   0x22, 0x2, "vx"),
+    ("vrem",    0x23, 0x2, "vx"),
+    ("vmulhu",  0x24, 0x2, "vx"),
+    ("vmul",    0x25, 0x2, "vx"),
+    ("vmulhsu", 0x26, 0x2, "vx"),
+    ("vmulh",   0x27, 0x2, "vx"),
+    ("vmadd",   0x29, 0x2, "vx"),
+    ("vnmsub",  0x2B, 0x2, "vx"),
+    ("vmacc",   0x2D, 0x2, "vx"),
+    ("vnmsac",  0x2F, 0x2, "vx"),
+];
+
+// The OPMVV instructions with a single form: reductions (.vs) and mask
+// logic (.mm).
+const REDUCTIONS: &[(&str, u32)] = &[
+    ("vredsum.vs", 0x00), ("vredand.vs", 0x01), ("vredor.vs", 0x02), ("vredxor.vs", 0x03),
+    ("vredminu.vs", 0x04), ("vredmin.vs", 0x05), ("vredmaxu.vs", 0x06), ("vredmax.vs", 0x07),
+];
+
+const MASK_LOGIC: &[(&str, u32)] = &[
+    ("vmandn.mm", 0x18), ("vmand.mm", 0x19), ("vmor.mm", 0x1A), ("vmxor.mm", 0x1B),
+    ("vmorn.mm", 0x1C), ("vmnand.mm", 0x1D), ("vmnor.mm", 0x1E), ("vmxnor.mm", 0x1F),
+];
+
+/// How a vector load or store addresses memory, with the mop field it
+/// encodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    UnitStride,
+    Strided,
+    Indexed { ordered: bool },
+    Mask,
+    WholeRegister(u32),
+}
+
+/// The element width, and whether it is a load, of a vector load or store
+/// mnemonic such as `vle32.v`, `vlse8.v`, `vluxei16.v` or `vl2re64.v`.
+fn memory_mnemonic(mnemonic: &str) -> Option<(bool, u32, Access)> {
+    match mnemonic {
+        "vlm.v" => return Some((true, 8, Access::Mask)),
+        "vsm.v" => return Some((false, 8, Access::Mask)),
+        _ => {}
+    }
+    let name = mnemonic.strip_suffix(".v")?;
+    let width = |digits: &str| match digits {
+        "8" => Some(8),
+        "16" => Some(16),
+        "32" => Some(32),
+        "64" => Some(64),
+        _ => None,
+    };
+    let registers = |digits: &str| match digits {
+        "1" => Some(1),
+        "2" => Some(2),
+        "4" => Some(4),
+        "8" => Some(8),
+        _ => None,
+    };
+    let prefixes = [
+        ("vle", true, Access::UnitStride), ("vlse", true, Access::Strided),
+        ("vluxei", true, Access::Indexed { ordered: false }), ("vloxei", true, Access::Indexed { ordered: true }),
+        ("vse", false, Access::UnitStride), ("vsse", false, Access::Strided),
+        ("vsuxei", false, Access::Indexed { ordered: false }), ("vsoxei", false, Access::Indexed { ordered: true }),
+    ];
+    for &(prefix, load, access) in &prefixes {
+        if let Some(eew) = name.strip_prefix(prefix).and_then(width) {
+            return Some((load, eew, access));
+        }
+    }
+    // vl<n>re<eew>.v and vs<n>r.v; whole-register stores always use EEW 8.
+    if let Some((count, eew)) = name.strip_prefix("vl").and_then(|rest| rest.split_once("re")) {
+        return Some((true, width(eew)?, Access::WholeRegister(registers(count)?)));
+    }
+    let count = name.strip_prefix("vs")?.strip_suffix('r')?;
+    Some((false, 8, Access::WholeRegister(registers(count)?)))
+}
+
+/// The table entry and funct3 of an arithmetic mnemonic such as `vadd.vx`.
+fn arithmetic_mnemonic(mnemonic: &str) -> Option<(u32, u32)> {
+    let (name, form) = mnemonic.rsplit_once('.')?;
+    let &(_, funct6, f3, forms) = ARITHMETIC.iter().find(|op| op.0 == name)?;
+    let f3 = match form {
+        "vv" if forms.contains('v') => f3,
+        "vx" if forms.contains('x') => f3 | 0x4,
+        "vi" if forms.contains('i') => 0x3,
+        _ => return None,
+    };
+    Some((funct6, f3))
+}
+
+/// The number of registers `vmv<n>r.v` copies.
+fn whole_move(mnemonic: &str) -> Option<u32> {
+    match mnemonic {
+        "vmv1r.v" => Some(1),
+        "vmv2r.v" => Some(2),
+        "vmv4r.v" => Some(4),
+        "vmv8r.v" => Some(8),
+        _ => None,
+    }
+}
+
+pub(crate) fn is_vector(mnemonic: &str) -> bool {
+    matches!(mnemonic,
+        "vsetvli" | "vsetivli" | "vsetvl" | "vmerge.vvm" | "vmerge.vxm" | "vmerge.vim" |
+        "vmv.v.v" | "vmv.v.x" | "vmv.v.i" | "vmv.x.s" | "vmv.s.x" | "vcpop.m" | "vfirst.m" |
+        "viota.m" | "vid.v") ||
+        arithmetic_mnemonic(mnemonic).is_some() || memory_mnemonic(mnemonic).is_some() ||
+        whole_move(mnemonic).is_some() ||
+        REDUCTIONS.iter().chain(MASK_LOGIC).any(|op| op.0 == mnemonic)
+}
+
+/// Encode a vector instruction. Masked forms end in a `v0.t` operand.
+pub(crate) fn encode_vector(mnemonic: &str, operands: &[String], symbols: &HashMap<String, i64>)
+    -> Result<u32, String> {
+
+    // vm is 0 for a masked instruction.
+    let (operands, vm) = match operands.split_last() {
+        Some((last, rest)) if last.trim() == "v0.t" => (rest, 0),
+        _ => (operands, 1),
+    };
+    let unmasked = || if vm == 0 { Err(format!("`{}` cannot be masked", mnemonic)) } else { Ok(()) };
+    let reg = |i: usize| parse_register(&operands[i]);
+    let vreg = |i: usize| parse_vector_register(&operands[i]);
+    let imm = |i: usize| evaluate(&operands[i], symbols);
+    let op_v = |funct6: u32, vs2: u32, vs1: u32, f3: u32, vd: u32| r_type(funct6 << 1 | vm, vs2, vs1, f3, vd, 0x57);
+
+    if let Some((load, eew, access)) = memory_mnemonic(mnemonic) {
+        let (mop, field, nf) = match access {
+            Access::UnitStride => { expect(mnemonic, operands, 2)?; (0x0, 0x00, 0) }
+            Access::Mask => { expect(mnemonic, operands, 2)?; unmasked()?; (0x0, 0x0B, 0) }
+            Access::WholeRegister(count) => { expect(mnemonic, operands, 2)?; unmasked()?; (0x0, 0x08, count - 1) }
+            Access::Strided => { expect(mnemonic, operands, 3)?; (0x2, reg(2)?, 0) }
+            Access::Indexed { ordered } => { expect(mnemonic, operands, 3)?; (if ordered { 0x3 } else { 0x1 }, vreg(2)?, 0) }
+        };
+        let width = match eew { 8 => 0x0, 16 => 0x5, 32 => 0x6, _ => 0x7 };
+        let opcode = if load { 0x07 } else { 0x27 };
+        return Ok(r_type(nf << 4 | mop << 1 | vm, field, address_operand(&operands[1])?, width, vreg(0)?, opcode));
+    }
+
+    if let Some((funct6, f3)) = arithmetic_mnemonic(mnemonic) {
+        expect(mnemonic, operands, 3)?;
+        // The multiply-adds list the multiplier before vs2.
+        let (second, vs2) = if matches!(funct6, 0x29 | 0x2B | 0x2D | 0x2F) && f3 & 0x3 == 0x2 { (1, 2) } else { (2, 1) };
+        let source = match f3 {
+            0x3 if matches!(funct6, 0x25 | 0x28 | 0x29) => unsigned(imm(second)?, 5)?,
+            0x3 => signed(imm(second)?, 5)?,
+            0x4 | 0x6 => reg(second)?,
+            _ => vreg(second)?,
+        };
+        return Ok(op_v(funct6, vreg(vs2)?, source, f3, vreg(0)?));
+    }
+    if let Some(&(_, funct6)) = REDUCTIONS.iter().find(|op| op.0 == mnemonic) {
+        expect(mnemonic, operands, 3)?;
+        return Ok(op_v(funct6, vreg(1)?, vreg(2)?, 0x2, vreg(0)?));
+    }
+    if let Some(&(_, funct6)) = MASK_LOGIC.iter().find(|op| op.0 == mnemonic) {
+        expect(mnemonic, operands, 3)?;
+        unmasked()?;
+        return Ok(op_v(funct6, vreg(1)?, vreg(2)?, 0x2, vreg(0)?));
+    }
+    if let Some(count) = whole_move(mnemonic) {
+        expect(mnemonic, operands, 2)?;
+        unmasked()?;
+        return Ok(op_v(0x27, vreg(1)?, count - 1, 0x3, vreg(0)?));
+    }
+
+    match mnemonic {
+        "vsetvli" | "vsetivli" => {
+            if operands.len() < 3 {
+                return Err(format!("`{}` expects a destination, a length and a vtype", mnemonic));
+            }
+            unmasked()?;
+            if mnemonic == "vsetvli" {
+                Ok(i_type(vtype(&operands[2..], 11, symbols)?, reg(1)?, 0x7, reg(0)?, 0x57))
+            } else {
+                Ok(0x3 << 30 | i_type(vtype(&operands[2..], 10, symbols)?, unsigned(imm(1)?, 5)?, 0x7, reg(0)?, 0x57))
+            }
+        }
+        "vsetvl" => {
+            expect(mnemonic, operands, 3)?;
+            unmasked()?;
+            Ok(r_type(0x40, reg(2)?, reg(1)?, 0x7, reg(0)?, 0x57))
+        }
+        "vmerge.vvm" | "vmerge.vxm" | "vmerge.vim" => {
+            // The mask is written out as a plain `v0` operand.
+            expect(mnemonic, operands, 4)?;
+            if vm == 0 || operands[3].trim() != "v0" {
+                return Err(format!("`{}` takes v0 as its last operand", mnemonic));
+            }
+            let (source, f3) = match mnemonic {
+                "vmerge.vvm" => (vreg(2)?, 0x0),
+                "vmerge.vxm" => (reg(2)?, 0x4),
+                _ => (signed(imm(2)?, 5)?, 0x3),
+            };
+            Ok(r_type(0x17 << 1, vreg(1)?, source, f3, vreg(0)?, 0x57))
+        }
+        "vmv.v.v" | "vmv.v.x" | "vmv.v.i" => {
+            expect(mnemonic, operands, 2)?;
+            unmasked()?;
+            let (source, f3) = match mnemonic {
+                "vmv.v.v" => (vreg(1)?, 0x0),
+                "vmv.v.x" => (reg(1)?, 0x4),
+                _ => (signed(imm(1)?, 5)?, 0x3),
+            };
+            Ok(op_v(0x17, 0, source, f3, vreg(0)?))
+        }
+        "vmv.x.s" => {
+            expect(mnemonic, operands, 2)?;
+            unmasked()?;
+            Ok(op_v(0x10, vreg(1)?, 0x00, 0x2, reg(0)?))
+        }
+        "vmv.s.x" => {
+            expect(mnemonic, operands, 2)?;
+            unmasked()?;
+            Ok(op_v(0x10, 0, reg(1)?, 0x6, vreg(0)?))
+        }
+        "vcpop.m" | "vfirst.m" => {
+            expect(mnemonic, operands, 2)?;
+            Ok(op_v(0x10, vreg(1)?, if mnemonic == "vcpop.m" { 0x10 } else { 0x11 }, 0x2, reg(0)?))
+        }
+        "viota.m" => {
+            expect(mnemonic, operands, 2)?;
+            Ok(op_v(0x14, vreg(1)?, 0x10, 0x2, vreg(0)?))
+        }
+        "vid.v" => {
+            expect(mnemonic, operands, 1)?;
+            Ok(op_v(0x14, 0, 0x11, 0x2, vreg(0)?))
+        }
+        _ => Err(format!("Unknown instruction `{}`", mnemonic)),
+    }
+}
+
+/// A `vtype` given as `e32, m1, ta, ma` or as a number. LMUL defaults to
+/// m1 and the policies to undisturbed, as in other assemblers.
+fn vtype(operands: &[String], bits: u32, symbols: &HashMap<String, i64>) -> Result<u32, String> {
+    if let [value] = operands {
+        if !value.trim().starts_with('e') { return unsigned(evaluate(value, symbols)?, bits); }
+    }
+    let (mut sew, mut lmul, mut tail, mut mask) = (None, 0, 0, 0);
+    for operand in operands {
+        match operand.trim() {
+            "e8" => sew = Some(0),
+            "e16" => sew = Some(1),
+            "e32" => sew = Some(2),
+            "e64" => sew = Some(3),
+            "m1" => lmul = 0,
+            "m2" => lmul = 1,
+            "m4" => lmul = 2,
+            "m8" => lmul = 3,
+            "mf8" => lmul = 5,
+            "mf4" => lmul = 6,
+            "mf2" => lmul = 7,
+            "tu" => tail = 0,
+            "ta" => tail = 1,
+            "mu" => mask = 0,
+            "ma" => mask = 1,
+            other => return Err(format!("Invalid vtype field `{}`", other)),
+        }
+    }
+    let sew = sew.ok_or_else(|| "vtype needs an element width such as e32".to_string())?;
+    Ok(mask << 7 | tail << 6 | sew << 3 | lmul)
+}
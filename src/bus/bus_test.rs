@@ -0,0 +1,81 @@
+use super::*;
+
+struct Scratch {
+    value: u64,
+}
+
+impl Device for Scratch {
+    fn read(&mut self, offset: u64, _size: usize) -> Option<u64> {
+        if offset == 0 { Some(self.value) } else { None }
+    }
+
+    fn write(&mut self, offset: u64, _size: usize, value: u64) -> Option<()> {
+        if offset == 0 { self.value = value; Some(()) } else { None }
+    }
+}
+
+#[test]
+fn test_ram_is_little_endian() {
+    let mut bus = Bus::new();
+    bus.map_ram(0x1000, 16);
+
+    bus.write_u32(0x1000, 0xDEADBEEF).unwrap();
+    assert_eq!(bus.read_u8(0x1000), Ok(0xEF));
+    assert_eq!(bus.read_u16(0x1002), Ok(0xDEAD));
+    assert_eq!(bus.read_u32(0x1000), Ok(0xDEADBEEF));
+}
+
+#[test]
+fn test_unmapped_access_faults() {
+    let mut bus = Bus::new();
+    bus.map_ram(0x1000, 16);
+
+    assert_eq!(bus.read_u8(0xFFF), Err(AccessFault { address: 0xFFF }));
+    assert_eq!(bus.write_u32(0x2000, 1), Err(AccessFault { address: 0x2000 }));
+    // Accesses straddling the end of a region fault too.
+    assert_eq!(bus.read_u32(0x100E), Err(AccessFault { address: 0x100E }));
+}
+
+#[test]
+fn test_rom_rejects_stores_but_accepts_loads() {
+    let mut bus = Bus::new();
+    bus.map_rom(0x0, vec![0x13, 0x00, 0x00, 0x00]);
+
+    assert_eq!(bus.read_u32(0x0), Ok(0x13));
+    assert_eq!(bus.write_u8(0x0, 0xFF), Err(AccessFault { address: 0x0 }));
+    bus.load(0x0, &[0x37]).unwrap();
+    assert_eq!(bus.read_u8(0x0), Ok(0x37));
+}
+
+#[test]
+fn test_mmio_is_routed_to_device() {
+    let mut bus = Bus::new();
+    bus.map_device(0x1000_0000, 0x100, Box::new(Scratch { value: 0 }));
+
+    bus.write_u32(0x1000_0000, 42).unwrap();
+    assert_eq!(bus.read_u32(0x1000_0000), Ok(42));
+    assert_eq!(bus.read_u32(0x1000_0004), Err(AccessFault { address: 0x1000_0004 }));
+}
+
+#[test]
+fn test_accesses_at_the_top_of_the_address_space_fault() {
+    let mut bus = Bus::new();
+    bus.map_ram(0x0, 0x100);
+    assert_eq!(bus.read_u64(!0 - 3), Err(AccessFault { address: !0 - 3 }));
+    assert_eq!(bus.write_u16(!0, 1), Err(AccessFault { address: !0 }));
+}
+
+#[test]
+#[should_panic(expected = "runs past the end of the address space")]
+fn test_regions_cannot_wrap() {
+    let mut bus = Bus::new();
+    bus.map_ram(!0 - 0xF, 0x100);
+}
+
+#[test]
+#[should_panic]
+fn test_overlapping_regions_panic() {
+    let mut bus = Bus::new();
+    bus.map_ram(0x0, 0x100);
+    bus.map_ram(0x80, 0x100);
+}
@@ -0,0 +1,187 @@
+use std::fmt;
+
+/// An access to an address that no region maps, or that the region refuses
+/// (such as a store into ROM).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccessFault {
+    pub address: u64,
+}
+
+impl fmt::Display for AccessFault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "access fault at 0x{:08x}", self.address)
+    }
+}
+
+/// A memory-mapped peripheral. Offsets are relative to the base the device
+/// was mapped at and `size` is the access width in bytes.
+pub trait Device {
+    /// Returns `None` if the device has nothing readable at `offset`.
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64>;
+
+    /// Returns `None` if the device has nothing writable at `offset`.
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Option<()>;
+
+    /// Whether the device is asserting its interrupt line.
+    fn interrupt_pending(&mut self) -> bool { false }
+}
+
+enum Backing {
+    Ram(Vec<u8>),
+    Rom(Vec<u8>),
+    Mmio(Box<dyn Device>),
+}
+
+struct Region {
+    base: u64,
+    size: u64,
+    backing: Backing,
+}
+
+impl Region {
+    fn contains(&self, address: u64, size: usize) -> bool {
+        address.checked_sub(self.base)
+            .and_then(|offset| offset.checked_add(size as u64))
+            .is_some_and(|end| end <= self.size)
+    }
+}
+
+/// The single byte-addressable, little-endian address space every load, store
+/// and instruction fetch goes through.
+#[derive(Default)]
+pub struct Bus {
+    regions: Vec<Region>,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus { regions: Vec::new() }
+    }
+
+    pub fn map_ram(&mut self, base: u64, size: usize) {
+        self.map(base, size as u64, Backing::Ram(vec![0; size]));
+    }
+
+    pub fn map_rom(&mut self, base: u64, contents: Vec<u8>) {
+        let size = contents.len() as u64;
+        self.map(base, size, Backing::Rom(contents));
+    }
+
+    pub fn map_device(&mut self, base: u64, size: u64, device: Box<dyn Device>) {
+        self.map(base, size, Backing::Mmio(device));
+    }
+
+    fn map(&mut self, base: u64, size: u64, backing: Backing) {
+        let end = base.checked_add(size)
+            .unwrap_or_else(|| panic!("Region at 0x{:08x} runs past the end of the address space", base));
+        for region in &self.regions {
+            if base < region.base + region.size && region.base < end {
+                panic!("Region at 0x{:08x} overlaps the region at 0x{:08x}", base, region.base);
+            }
+        }
+        self.regions.push(Region { base, size, backing });
+    }
+
+    /// Returns true if a `size` byte access at `address` lands in a single region.
+    pub fn is_mapped(&self, address: u64, size: usize) -> bool {
+        self.regions.iter().any(|r| r.contains(address, size))
+    }
+
+    /// Whether the device mapped at `base` is asserting its interrupt line.
+    pub fn interrupt_pending(&mut self, base: u64) -> bool {
+        self.regions.iter_mut()
+            .find(|r| r.base == base)
+            .is_some_and(|r| match r.backing {
+                Backing::Mmio(ref mut device) => device.interrupt_pending(),
+                _ => false,
+            })
+    }
+
+    fn region(&mut self, address: u64, size: usize) -> Result<&mut Region, AccessFault> {
+        self.regions.iter_mut()
+            .find(|r| r.contains(address, size))
+            .ok_or(AccessFault { address })
+    }
+
+    pub(crate) fn read(&mut self, address: u64, size: usize) -> Result<u64, AccessFault> {
+        let region = self.region(address, size)?;
+        let offset = address - region.base;
+        match region.backing {
+            Backing::Ram(ref bytes) | Backing::Rom(ref bytes) => {
+                let start = offset as usize;
+                Ok(bytes[start..start + size].iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64))
+            }
+            Backing::Mmio(ref mut device) => {
+                device.read(offset, size).ok_or(AccessFault { address })
+            }
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: u64, size: usize, value: u64) -> Result<(), AccessFault> {
+        let region = self.region(address, size)?;
+        let offset = address - region.base;
+        match region.backing {
+            Backing::Ram(ref mut bytes) => {
+                let start = offset as usize;
+                for (i, byte) in bytes[start..start + size].iter_mut().enumerate() {
+                    *byte = (value >> (8 * i)) as u8;
+                }
+                Ok(())
+            }
+            Backing::Rom(_) => Err(AccessFault { address }),
+            Backing::Mmio(ref mut device) => {
+                device.write(offset, size, value).ok_or(AccessFault { address })
+            }
+        }
+    }
+
+    pub fn read_u8(&mut self, address: u64) -> Result<u8, AccessFault> {
+        self.read(address, 1).map(|v| v as u8)
+    }
+
+    pub fn read_u16(&mut self, address: u64) -> Result<u16, AccessFault> {
+        self.read(address, 2).map(|v| v as u16)
+    }
+
+    pub fn read_u32(&mut self, address: u64) -> Result<u32, AccessFault> {
+        self.read(address, 4).map(|v| v as u32)
+    }
+
+    pub fn read_u64(&mut self, address: u64) -> Result<u64, AccessFault> {
+        self.read(address, 8)
+    }
+
+    pub fn write_u8(&mut self, address: u64, value: u8) -> Result<(), AccessFault> {
+        self.write(address, 1, value as u64)
+    }
+
+    pub fn write_u16(&mut self, address: u64, value: u16) -> Result<(), AccessFault> {
+        self.write(address, 2, value as u64)
+    }
+
+    pub fn write_u32(&mut self, address: u64, value: u32) -> Result<(), AccessFault> {
+        self.write(address, 4, value as u64)
+    }
+
+    pub fn write_u64(&mut self, address: u64, value: u64) -> Result<(), AccessFault> {
+        self.write(address, 8, value)
+    }
+
+    /// Copy `bytes` into RAM or ROM starting at `address`. Unlike the typed
+    /// writes this may fill ROM, so it is what program loaders use.
+    pub fn load(&mut self, address: u64, bytes: &[u8]) -> Result<(), AccessFault> {
+        if bytes.is_empty() { return Ok(()); }
+        let region = self.region(address, bytes.len())?;
+        let start = (address - region.base) as usize;
+        match region.backing {
+            Backing::Ram(ref mut contents) | Backing::Rom(ref mut contents) => {
+                contents[start..start + bytes.len()].copy_from_slice(bytes);
+                Ok(())
+            }
+            Backing::Mmio(_) => Err(AccessFault { address }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod bus_test;
@@ -0,0 +1,222 @@
+use super::*;
+
+fn csr_file() -> CsrFile {
+    CsrFile::new(&Extensions { m: true, c: true, ..Extensions::default() }, 32)
+}
+
+#[test]
+fn test_misa_reflects_extensions() {
+    let misa = csr_file().read(MISA).unwrap();
+    assert_eq!(misa >> 30, 1);
+    assert_eq!(misa & 0x3FF_FFFF, (1 << 8) | (1 << 12) | (1 << 2)); // I, M, C
+
+    let rv32e = CsrFile::new(&Extensions { e: true, ..Extensions::default() }, 32);
+    assert_eq!(rv32e.read(MISA).unwrap() & 0x3FF_FFFF, 1 << 4);
+}
+
+#[test]
+fn test_warl_fields() {
+    let mut csrs = csr_file();
+
+    csrs.write(MSTATUS, 0xFFFF_FFFF).unwrap();
+    assert_eq!(csrs.read(MSTATUS), Some(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP));
+    csrs.write(MSTATUS, 0).unwrap();
+    assert_eq!(csrs.read(MSTATUS), Some(MSTATUS_MPP));
+
+    csrs.write(MTVEC, 0x8000_0101).unwrap();
+    assert_eq!(csrs.read(MTVEC), Some(0x8000_0101));
+    csrs.write(MTVEC, 0x8000_0102).unwrap();
+    assert_eq!(csrs.read(MTVEC), Some(0x8000_0100));
+
+    csrs.write(MIE, 0xFFFF_FFFF).unwrap();
+    assert_eq!(csrs.read(MIE), Some(MIP_MSIP | MIP_MTIP | MIP_MEIP));
+
+    csrs.write(MEPC, 0x1003).unwrap();
+    assert_eq!(csrs.read(MEPC), Some(0x1002));
+    let mut no_c = CsrFile::new(&Extensions::default(), 32);
+    no_c.write(MEPC, 0x1003).unwrap();
+    assert_eq!(no_c.read(MEPC), Some(0x1000));
+
+    csrs.write(MISA, 0).unwrap();
+    assert_eq!(csrs.read(MISA), csr_file().read(MISA));
+}
+
+#[test]
+fn test_rv64_csr_layout() {
+    let mut csrs = CsrFile::new(&Extensions { d: true, ..Extensions::default() }, 64);
+    assert_eq!(csrs.read(MISA).unwrap() >> 62, 2);
+    assert_eq!(csrs.read(MSTATUSH), None);
+    csrs.write(MSCRATCH, 0x1234_5678_9abc_def0).unwrap();
+    assert_eq!(csrs.read(MSCRATCH), Some(0x1234_5678_9abc_def0));
+    csrs.write(FFLAGS, 0x01).unwrap();
+    assert_eq!(csrs.read(MSTATUS).unwrap() & (MSTATUS_SD | MSTATUS64_SD), MSTATUS64_SD);
+}
+
+#[test]
+fn test_misa_reports_b_for_zba_zbb_and_zbs() {
+    let b = 1 << (b'B' - b'A');
+    let bitmanip = Extensions { zba: true, zbb: true, zbs: true, ..Extensions::default() };
+    assert_eq!(CsrFile::new(&bitmanip, 32).read(MISA).unwrap() & b, b);
+    let partial = Extensions { zba: true, zbb: true, ..Extensions::default() };
+    assert_eq!(CsrFile::new(&partial, 32).read(MISA).unwrap() & b, 0);
+}
+
+#[test]
+fn test_read_only_and_unknown_csrs() {
+    let mut csrs = csr_file();
+    assert_eq!(csrs.read(MHARTID), Some(0));
+    assert_eq!(csrs.write(MHARTID, 1), None);
+    assert_eq!(csrs.read(0x7FF), None);
+    assert_eq!(csrs.write(0x7FF, 1), None);
+}
+
+#[test]
+fn test_names() {
+    assert_eq!(name(MSCRATCH), Some("mscratch"));
+    assert_eq!(address_of("mtvec"), Some(MTVEC));
+    assert_eq!(address_of("x1"), None);
+}
+
+#[test]
+fn test_float_csrs() {
+    assert_eq!(csr_file().read(FCSR), None, "no F extension");
+
+    let mut csrs = CsrFile::new(&Extensions { f: true, ..Extensions::default() }, 32);
+    assert_eq!(csrs.read(MSTATUS).unwrap() & MSTATUS_FS, FS_INITIAL);
+    csrs.write(FCSR, 0xFFFF_FFFF).unwrap();
+    assert_eq!(csrs.read(FCSR), Some(0xFF));
+    assert_eq!(csrs.read(FRM), Some(0x7));
+    csrs.write(FFLAGS, 0x01).unwrap();
+    assert_eq!(csrs.read(FCSR), Some(0xE1));
+    assert_eq!(csrs.read(MSTATUS).unwrap() & (MSTATUS_FS | MSTATUS_SD), FS_DIRTY | MSTATUS_SD);
+
+    // Turning the unit off hides its CSRs.
+    csrs.write(MSTATUS, 0).unwrap();
+    assert_eq!(csrs.read(FFLAGS), None);
+    assert_eq!(csrs.write(FRM, 0), None);
+}
+
+#[test]
+fn test_vector_csrs() {
+    assert_eq!(csr_file().read(VL), None, "no V extension");
+
+    let mut csrs = CsrFile::new(&Extensions { v: true, ..Extensions::default() }, 32);
+    assert_eq!(csrs.read(MISA).unwrap() & 1 << (b'V' - b'A'), 1 << (b'V' - b'A'));
+    assert_eq!(csrs.read(MSTATUS).unwrap() & MSTATUS_VS, VS_INITIAL);
+    // vill is set until the first vsetvl, and vl, vtype and vlenb are read-only.
+    assert_eq!(csrs.read(VTYPE), Some(0x8000_0000));
+    assert_eq!(csrs.read(VLENB), Some(16));
+    assert_eq!(csrs.write(VL, 1), None);
+    csrs.write(VCSR, 0x7).unwrap();
+    assert_eq!((csrs.read(VXRM), csrs.read(VXSAT)), (Some(0x3), Some(0x1)));
+    csrs.write(VSTART, 0x1FF).unwrap();
+    assert_eq!(csrs.read(VSTART), Some(0x7F));
+    assert_eq!(csrs.read(MSTATUS).unwrap() & (MSTATUS_VS | MSTATUS_SD), VS_DIRTY | MSTATUS_SD);
+
+    csrs.write(MSTATUS, 0).unwrap();
+    assert_eq!(csrs.read(VLENB), None);
+}
+
+#[test]
+fn test_counters() {
+    let mut csrs = csr_file();
+    csrs.write(MCYCLEH, 1).unwrap();
+    csrs.write(MCYCLE, 0xFFFF_FFFF).unwrap();
+    // The instruction that writes a counter does not also count itself.
+    csrs.advance_counters(true);
+    assert_eq!((csrs.read(CYCLE), csrs.read(CYCLEH)), (Some(0xFFFF_FFFF), Some(1)));
+    csrs.advance_counters(false);
+    assert_eq!((csrs.read(CYCLE), csrs.read(CYCLEH)), (Some(0), Some(2)));
+    assert_eq!(csrs.read(INSTRET), Some(1));
+    assert_eq!(csrs.write(CYCLE, 0), None);
+    assert_eq!(csrs.read(HPMCOUNTER3 + 1), Some(0));
+    csrs.write(MHPMEVENT3, 5).unwrap();
+    assert_eq!(csrs.read(MHPMEVENT3), Some(0));
+
+    csrs.write(MCOUNTINHIBIT, !0).unwrap();
+    assert_eq!(csrs.read(MCOUNTINHIBIT), Some(COUNTER_CY | COUNTER_IR));
+    csrs.advance_counters(true);
+    assert_eq!(csrs.read(MINSTRET), Some(1));
+
+    csrs.write(MCOUNTEREN, COUNTER_TM).unwrap();
+    assert!(csrs.counter_enabled(TIME) && csrs.counter_enabled(TIMEH));
+    assert!(!csrs.counter_enabled(CYCLE) && !csrs.counter_enabled(HPMCOUNTER31H));
+
+    let rv64 = CsrFile::new(&Extensions::default(), 64);
+    assert_eq!(rv64.read(CYCLEH), None);
+}
+
+#[test]
+fn test_supervisor_csrs() {
+    assert_eq!(csr_file().read(SSTATUS), None);
+    assert_eq!(csr_file().read(MEDELEG), None);
+
+    let mut csrs = CsrFile::new(&Extensions { s: true, u: true, ..Extensions::default() }, 32);
+    assert_eq!(csrs.read(MISA).unwrap() & (1 << 18 | 1 << 20), 1 << 18 | 1 << 20);
+
+    // MPP keeps its value when written with the reserved mode.
+    csrs.write(MSTATUS, MSTATUS_SPP | 0b01 << 11).unwrap();
+    csrs.write(MSTATUS, MSTATUS_SPP | 0b10 << 11).unwrap();
+    assert_eq!(csrs.read(MSTATUS), Some(MSTATUS_SPP | 0b01 << 11));
+    csrs.write(SSTATUS, MSTATUS_SIE | MSTATUS_MIE).unwrap();
+    assert_eq!(csrs.read(MSTATUS), Some(MSTATUS_SIE | 0b01 << 11));
+    assert_eq!(csrs.read(SSTATUS), Some(MSTATUS_SIE));
+
+    csrs.write(MEDELEG, !0).unwrap();
+    assert_eq!(csrs.read(MEDELEG).unwrap() >> 8 & 0b1111, 0b0011, "ecalls from S and U only");
+    csrs.write(MIDELEG, !0).unwrap();
+    assert_eq!(csrs.read(MIDELEG), Some(MIP_SSIP | MIP_STIP | MIP_SEIP));
+    csrs.write(MIDELEG, MIP_SSIP).unwrap();
+    csrs.write(MIE, !0).unwrap();
+    assert_eq!(csrs.read(SIE), Some(MIP_SSIP));
+    csrs.write(SIP, !0).unwrap();
+    assert_eq!(csrs.read(MIP), Some(MIP_SSIP));
+
+    // RV32 has Sv32, but RV64 only has Bare.
+    csrs.write(SATP, 0x8000_0123).unwrap();
+    assert_eq!(csrs.read(SATP), Some(0x8000_0123));
+    let mut rv64 = CsrFile::new(&Extensions { s: true, u: true, ..Extensions::default() }, 64);
+    rv64.write(SATP, 8 << 60 | 0x123).unwrap();
+    assert_eq!(rv64.read(SATP), Some(0));
+
+    assert!(!csrs.accessible(MSTATUS, Privilege::Supervisor));
+    assert!(csrs.accessible(SSCRATCH, Privilege::Supervisor));
+    assert!(!csrs.accessible(SSCRATCH, Privilege::User));
+    csrs.write(MSTATUS, MSTATUS_TVM).unwrap();
+    assert!(!csrs.accessible(SATP, Privilege::Supervisor));
+    csrs.write(MCOUNTEREN, COUNTER_CY).unwrap();
+    assert!(csrs.accessible(CYCLE, Privilege::Supervisor));
+    assert!(!csrs.accessible(CYCLE, Privilege::User), "scounteren is still clear");
+    csrs.write(SCOUNTEREN, COUNTER_CY).unwrap();
+    assert!(csrs.accessible(CYCLE, Privilege::User));
+}
+
+#[test]
+fn test_pmp_csrs() {
+    let mut csrs = csr_file();
+    csrs.write(PMPCFG0, 0xFFFF_FFFF).unwrap();
+    assert_eq!(csrs.read(PMPCFG0), Some(0), "no PMP entries are implemented");
+
+    csrs.pmp = true;
+    // W without R and the reserved bits 6:5 read as zero.
+    csrs.write(PMPCFG0, 0x891D_7F02).unwrap();
+    assert_eq!(csrs.read(PMPCFG0), Some(0x891D_1F00));
+    csrs.write(PMPCFG0, 0).unwrap();
+    assert_eq!(csrs.read(PMPCFG0), Some(0x8900_0000), "entry 3 is locked");
+
+    // Locked TOR entry 3 also locks the base address in pmpaddr2.
+    csrs.write(PMPADDR0 + 1, !0).unwrap();
+    csrs.write(PMPADDR0 + 2, 5).unwrap();
+    csrs.write(PMPADDR0 + 3, 5).unwrap();
+    assert_eq!(csrs.read(PMPADDR0 + 1), Some(0xFFFF_FFFF));
+    assert_eq!((csrs.read(PMPADDR0 + 2), csrs.read(PMPADDR0 + 3)), (Some(0), Some(0)));
+    assert_eq!(address_of("pmpaddr15"), Some(PMPADDR15));
+
+    let mut rv64 = CsrFile::new(&Extensions::default(), 64);
+    rv64.pmp = true;
+    assert_eq!(rv64.read(PMPCFG0 + 1), None);
+    rv64.write(PMPCFG0 + 2, 0x0F << 56).unwrap();
+    assert_eq!(rv64.pmpcfg[15], 0x0F);
+    rv64.write(PMPADDR0, !0).unwrap();
+    assert_eq!(rv64.read(PMPADDR0), Some((1 << 54) - 1));
+}
@@ -0,0 +1,643 @@
+use super::{Extensions, DEFAULT_VLEN};
+
+// Floating-point control and status
+pub const FFLAGS: u16 = 0x001;
+pub const FRM: u16 = 0x002;
+pub const FCSR: u16 = 0x003;
+
+// Vector control and status
+pub const VSTART: u16 = 0x008;
+pub const VXSAT: u16 = 0x009;
+pub const VXRM: u16 = 0x00A;
+pub const VCSR: u16 = 0x00F;
+pub const VL: u16 = 0xC20;
+pub const VTYPE: u16 = 0xC21;
+pub const VLENB: u16 = 0xC22;
+
+// Counters and timers, with the upper halves RV32 reads separately.
+// hpmcounter3 to hpmcounter31 count no events and always read as zero.
+pub const CYCLE: u16 = 0xC00;
+pub const TIME: u16 = 0xC01;
+pub const INSTRET: u16 = 0xC02;
+pub const HPMCOUNTER3: u16 = 0xC03;
+pub const HPMCOUNTER31: u16 = 0xC1F;
+pub const CYCLEH: u16 = 0xC80;
+pub const TIMEH: u16 = 0xC81;
+pub const INSTRETH: u16 = 0xC82;
+pub const HPMCOUNTER3H: u16 = 0xC83;
+pub const HPMCOUNTER31H: u16 = 0xC9F;
+
+// Machine counters and their setup
+pub const MCYCLE: u16 = 0xB00;
+pub const MINSTRET: u16 = 0xB02;
+pub const MHPMCOUNTER3: u16 = 0xB03;
+pub const MHPMCOUNTER31: u16 = 0xB1F;
+pub const MCYCLEH: u16 = 0xB80;
+pub const MINSTRETH: u16 = 0xB82;
+pub const MHPMCOUNTER3H: u16 = 0xB83;
+pub const MHPMCOUNTER31H: u16 = 0xB9F;
+pub const MCOUNTEREN: u16 = 0x306;
+pub const MCOUNTINHIBIT: u16 = 0x320;
+pub const MHPMEVENT3: u16 = 0x323;
+pub const MHPMEVENT31: u16 = 0x33F;
+
+// Supervisor trap setup, trap handling and address translation
+pub const SSTATUS: u16 = 0x100;
+pub const SIE: u16 = 0x104;
+pub const STVEC: u16 = 0x105;
+pub const SCOUNTEREN: u16 = 0x106;
+pub const SSCRATCH: u16 = 0x140;
+pub const SEPC: u16 = 0x141;
+pub const SCAUSE: u16 = 0x142;
+pub const STVAL: u16 = 0x143;
+pub const SIP: u16 = 0x144;
+pub const SATP: u16 = 0x180;
+
+// Machine information registers
+pub const MVENDORID: u16 = 0xF11;
+pub const MARCHID: u16 = 0xF12;
+pub const MIMPID: u16 = 0xF13;
+pub const MHARTID: u16 = 0xF14;
+pub const MCONFIGPTR: u16 = 0xF15;
+
+// Machine trap setup
+pub const MSTATUS: u16 = 0x300;
+pub const MISA: u16 = 0x301;
+pub const MEDELEG: u16 = 0x302;
+pub const MIDELEG: u16 = 0x303;
+pub const MIE: u16 = 0x304;
+pub const MTVEC: u16 = 0x305;
+pub const MSTATUSH: u16 = 0x310;
+
+// Machine trap handling
+pub const MSCRATCH: u16 = 0x340;
+pub const MEPC: u16 = 0x341;
+pub const MCAUSE: u16 = 0x342;
+pub const MTVAL: u16 = 0x343;
+pub const MIP: u16 = 0x344;
+
+// Physical memory protection. RV32 packs four entry configurations into
+// each pmpcfg register; RV64 packs eight and has only the even registers.
+pub const PMPCFG0: u16 = 0x3A0;
+pub const PMPCFG3: u16 = 0x3A3;
+pub const PMPADDR0: u16 = 0x3B0;
+pub const PMPADDR15: u16 = 0x3BF;
+pub const PMP_ENTRIES: usize = 16;
+
+const NAMES: &[(u16, &str)] = &[
+    (FFLAGS, "fflags"), (FRM, "frm"), (FCSR, "fcsr"),
+    (VSTART, "vstart"), (VXSAT, "vxsat"), (VXRM, "vxrm"), (VCSR, "vcsr"), (VL, "vl"), (VTYPE, "vtype"),
+    (VLENB, "vlenb"),
+    (CYCLE, "cycle"), (TIME, "time"), (INSTRET, "instret"), (CYCLEH, "cycleh"), (TIMEH, "timeh"),
+    (INSTRETH, "instreth"),
+    (MCYCLE, "mcycle"), (MINSTRET, "minstret"), (MCYCLEH, "mcycleh"), (MINSTRETH, "minstreth"),
+    (MCOUNTEREN, "mcounteren"), (MCOUNTINHIBIT, "mcountinhibit"),
+    (SSTATUS, "sstatus"), (SIE, "sie"), (STVEC, "stvec"), (SCOUNTEREN, "scounteren"), (SSCRATCH, "sscratch"),
+    (SEPC, "sepc"), (SCAUSE, "scause"), (STVAL, "stval"), (SIP, "sip"), (SATP, "satp"),
+    (MVENDORID, "mvendorid"), (MARCHID, "marchid"), (MIMPID, "mimpid"), (MHARTID, "mhartid"),
+    (MCONFIGPTR, "mconfigptr"),
+    (MSTATUS, "mstatus"), (MISA, "misa"), (MEDELEG, "medeleg"), (MIDELEG, "mideleg"), (MIE, "mie"), (MTVEC, "mtvec"), (MSTATUSH, "mstatush"),
+    (MSCRATCH, "mscratch"), (MEPC, "mepc"), (MCAUSE, "mcause"), (MTVAL, "mtval"), (MIP, "mip"),
+    (PMPCFG0, "pmpcfg0"), (PMPCFG0 + 1, "pmpcfg1"), (PMPCFG0 + 2, "pmpcfg2"), (PMPCFG3, "pmpcfg3"),
+    (PMPADDR0, "pmpaddr0"), (PMPADDR0 + 1, "pmpaddr1"), (PMPADDR0 + 2, "pmpaddr2"), (PMPADDR0 + 3, "pmpaddr3"),
+    (PMPADDR0 + 4, "pmpaddr4"), (PMPADDR0 + 5, "pmpaddr5"), (PMPADDR0 + 6, "pmpaddr6"), (PMPADDR0 + 7, "pmpaddr7"),
+    (PMPADDR0 + 8, "pmpaddr8"), (PMPADDR0 + 9, "pmpaddr9"), (PMPADDR0 + 10, "pmpaddr10"),
+    (PMPADDR0 + 11, "pmpaddr11"), (PMPADDR0 + 12, "pmpaddr12"), (PMPADDR0 + 13, "pmpaddr13"),
+    (PMPADDR0 + 14, "pmpaddr14"), (PMPADDR15, "pmpaddr15"),
+];
+
+pub const MSTATUS_SIE: u64 = 1 << 1;
+pub const MSTATUS_MIE: u64 = 1 << 3;
+pub const MSTATUS_SPIE: u64 = 1 << 5;
+pub const MSTATUS_MPIE: u64 = 1 << 7;
+pub const MSTATUS_SPP: u64 = 1 << 8;
+pub const MSTATUS_VS: u64 = 0b11 << 9;
+pub const MSTATUS_MPP: u64 = 0b11 << 11;
+pub const MSTATUS_FS: u64 = 0b11 << 13;
+pub const MSTATUS_MPRV: u64 = 1 << 17;
+pub const MSTATUS_SUM: u64 = 1 << 18;
+pub const MSTATUS_MXR: u64 = 1 << 19;
+pub const MSTATUS_TVM: u64 = 1 << 20;
+pub const MSTATUS_TW: u64 = 1 << 21;
+pub const MSTATUS_TSR: u64 = 1 << 22;
+// The XLEN of user and supervisor mode, fixed to 64 on RV64.
+pub const MSTATUS_UXL: u64 = 0b11 << 32;
+pub const MSTATUS_SXL: u64 = 0b11 << 34;
+// SD is the top bit of mstatus, so it moves with XLEN.
+pub const MSTATUS_SD: u64 = 1 << 31;
+pub const MSTATUS64_SD: u64 = 1 << 63;
+
+// States of mstatus.FS. Off makes floating-point instructions illegal.
+pub const FS_OFF: u64 = 0;
+pub const FS_INITIAL: u64 = 1 << 13;
+pub const FS_DIRTY: u64 = 0b11 << 13;
+
+// States of mstatus.VS, which does the same for the vector unit.
+pub const VS_OFF: u64 = 0;
+pub const VS_INITIAL: u64 = 1 << 9;
+pub const VS_DIRTY: u64 = 0b11 << 9;
+
+// Bits of mcounteren and mcountinhibit, one per counter in address order.
+pub const COUNTER_CY: u64 = 1 << 0;
+pub const COUNTER_TM: u64 = 1 << 1;
+pub const COUNTER_IR: u64 = 1 << 2;
+
+pub const MIP_SSIP: u64 = 1 << 1;
+pub const MIP_MSIP: u64 = 1 << 3;
+pub const MIP_STIP: u64 = 1 << 5;
+pub const MIP_MTIP: u64 = 1 << 7;
+pub const MIP_SEIP: u64 = 1 << 9;
+pub const MIP_MEIP: u64 = 1 << 11;
+const SUPERVISOR_INTERRUPTS: u64 = MIP_SSIP | MIP_STIP | MIP_SEIP;
+
+// Fields of a PMP entry configuration. A selects how pmpaddr describes the
+// entry's region.
+pub const PMP_R: u8 = 1 << 0;
+pub const PMP_W: u8 = 1 << 1;
+pub const PMP_X: u8 = 1 << 2;
+pub const PMP_A: u8 = 0b11 << 3;
+pub const PMP_L: u8 = 1 << 7;
+pub const PMP_OFF: u8 = 0;
+pub const PMP_TOR: u8 = 1 << 3;
+pub const PMP_NA4: u8 = 2 << 3;
+pub const PMP_NAPOT: u8 = 3 << 3;
+
+/// The exceptions medeleg can hand to supervisor mode: every standard
+/// cause up to 15 except an ecall from machine mode.
+const DELEGABLE_EXCEPTIONS: u64 = 0xB3FF;
+
+/// The privilege modes, numbered as mstatus.MPP and bits 9:8 of a CSR
+/// address encode them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+
+impl Privilege {
+    /// The mode a two-bit MPP or one-bit SPP field holds. The reserved
+    /// value 2 never gets stored, because writes to MPP are legalized.
+    pub fn from_bits(bits: u64) -> Privilege {
+        match bits & 0b11 {
+            0 => Privilege::User,
+            1 => Privilege::Supervisor,
+            _ => Privilege::Machine,
+        }
+    }
+}
+
+/// The architectural name of the CSR at `address`, if it is one we implement.
+pub fn name(address: u16) -> Option<&'static str> {
+    NAMES.iter().find(|&&(a, _)| a == address).map(|&(_, n)| n)
+}
+
+/// The address of the CSR called `name`.
+pub fn address_of(name: &str) -> Option<u16> {
+    NAMES.iter().find(|&&(_, n)| n == name).map(|&(a, _)| a)
+}
+
+/// CSRs whose top two address bits are set may only be read.
+pub fn is_read_only(address: u16) -> bool {
+    address >> 10 == 0b11
+}
+
+/// An mtvec or stvec value with its mode legalized. Direct (0) and
+/// vectored (1) are the only modes; anything else falls back to direct.
+fn trap_vector(value: u64) -> u64 {
+    let mode = if value & 0b11 == 1 { 1 } else { 0 };
+    (value & !0b11) | mode
+}
+
+/// Whether the counter-enable register `counteren` has the bit for the
+/// counter at `address` set. CSRs other than counters are always enabled.
+fn counter_bit(counteren: u64, address: u16) -> bool {
+    match address {
+        CYCLE..=HPMCOUNTER31 => counteren >> (address - CYCLE) & 1 != 0,
+        CYCLEH..=HPMCOUNTER31H => counteren >> (address - CYCLEH) & 1 != 0,
+        _ => true,
+    }
+}
+
+fn misa(extensions: &Extensions, xlen: u32) -> u64 {
+    let letter = |c: char| 1 << (c as u32 - 'A' as u32);
+    // MXL, in the top two bits, is 1 for RV32 and 2 for RV64.
+    let mut misa = (xlen as u64 / 32) << (xlen - 2);
+    misa |= if extensions.e { letter('E') } else { letter('I') };
+    for &(enabled, c) in &[(extensions.m, 'M'), (extensions.a, 'A'), (extensions.f, 'F'),
+                           (extensions.d, 'D'), (extensions.q, 'Q'), (extensions.c, 'C'), (extensions.v, 'V'),
+                           (extensions.s, 'S'), (extensions.u, 'U'),
+                           // B stands for Zba, Zbb and Zbs together.
+                           (extensions.zba && extensions.zbb && extensions.zbs, 'B')] {
+        if enabled { misa |= letter(c); }
+    }
+    misa
+}
+
+/// The control and status registers of a single hart. Fields hold the raw
+/// register contents; `read` and `write` apply the WARL rules.
+#[derive(Debug, Clone)]
+pub struct CsrFile {
+    xlen: u32,
+    misa: u64,
+    ialign_mask: u64,
+    float: bool,
+    vector: bool,
+    supervisor: bool,
+    user: bool,
+    pub(crate) fflags: u32,
+    pub(crate) frm: u32,
+    /// VLEN in bytes. The machine replaces the default with the vector
+    /// length it was configured with.
+    pub(crate) vlenb: u64,
+    pub(crate) vstart: u64,
+    pub(crate) vxsat: u64,
+    pub(crate) vxrm: u64,
+    pub(crate) vl: u64,
+    pub(crate) vtype: u64,
+    pub(crate) cycle: u64,
+    pub(crate) time: u64,
+    pub(crate) instret: u64,
+    mcounteren: u64,
+    scounteren: u64,
+    mcountinhibit: u64,
+    /// The counters the current instruction wrote, as mcountinhibit bits.
+    /// An explicit write replaces that instruction's own increment.
+    counters_written: u64,
+    pub(crate) mstatus: u64,
+    pub(crate) medeleg: u64,
+    pub(crate) mideleg: u64,
+    pub(crate) mie: u64,
+    pub(crate) mip: u64,
+    /// The PLIC's supervisor-mode line. SEIP reads as set while it is high,
+    /// whatever software wrote to the bit.
+    pub(crate) seip: bool,
+    pub(crate) mtvec: u64,
+    pub(crate) mscratch: u64,
+    pub(crate) mepc: u64,
+    pub(crate) mcause: u64,
+    pub(crate) mtval: u64,
+    pub(crate) stvec: u64,
+    pub(crate) sscratch: u64,
+    pub(crate) sepc: u64,
+    pub(crate) scause: u64,
+    pub(crate) stval: u64,
+    pub(crate) satp: u64,
+    /// Whether the PMP entries are implemented. The machine sets this from
+    /// its configuration; without them the PMP CSRs are hardwired to zero.
+    pub(crate) pmp: bool,
+    pub(crate) pmpcfg: [u8; PMP_ENTRIES],
+    /// Bits 33:2 of each region address on RV32, and bits 55:2 on RV64.
+    pub(crate) pmpaddr: [u64; PMP_ENTRIES],
+}
+
+impl CsrFile {
+    pub fn new(extensions: &Extensions, xlen: u32) -> CsrFile {
+        let float = extensions.f || extensions.d || extensions.q;
+        CsrFile {
+            xlen,
+            misa: misa(extensions, xlen),
+            ialign_mask: if extensions.c { !0b1 } else { !0b11 },
+            float,
+            vector: extensions.v,
+            supervisor: extensions.s,
+            user: extensions.u,
+            fflags: 0,
+            frm: 0,
+            vlenb: DEFAULT_VLEN as u64 / 8,
+            vstart: 0,
+            vxsat: 0,
+            vxrm: 0,
+            vl: 0,
+            // No vector configuration is valid until the first vsetvl.
+            vtype: 1 << (xlen - 1),
+            cycle: 0,
+            time: 0,
+            instret: 0,
+            mcounteren: 0,
+            scounteren: 0,
+            mcountinhibit: 0,
+            counters_written: 0,
+            // Without user mode MPP always reads back as M; with it, M is
+            // the reset value. The floating-point and vector units come out
+            // of reset enabled.
+            mstatus: MSTATUS_MPP | if float { FS_INITIAL } else { FS_OFF } |
+                if extensions.v { VS_INITIAL } else { VS_OFF } |
+                if xlen == 64 && extensions.u { 2 << 32 } else { 0 } |
+                if xlen == 64 && extensions.s { 2 << 34 } else { 0 },
+            medeleg: 0,
+            mideleg: 0,
+            mie: 0,
+            mip: 0,
+            seip: false,
+            mtvec: 0,
+            mscratch: 0,
+            mepc: 0,
+            mcause: 0,
+            mtval: 0,
+            stvec: 0,
+            sscratch: 0,
+            sepc: 0,
+            scause: 0,
+            stval: 0,
+            satp: 0,
+            pmp: false,
+            pmpcfg: [0; PMP_ENTRIES],
+            pmpaddr: [0; PMP_ENTRIES],
+        }
+    }
+
+    /// The value of the CSR at `address`, or `None` if it does not exist.
+    pub fn read(&self, address: u16) -> Option<u64> {
+        let value = match address {
+            FFLAGS | FRM | FCSR if !self.float_enabled() => return None,
+            FFLAGS => self.fflags as u64,
+            FRM => self.frm as u64,
+            FCSR => ((self.frm << 5) | self.fflags) as u64,
+            VSTART | VXSAT | VXRM | VCSR | VL | VTYPE | VLENB if !self.vector_enabled() => return None,
+            VSTART => self.vstart,
+            VXSAT => self.vxsat,
+            VXRM => self.vxrm,
+            VCSR => (self.vxrm << 1) | self.vxsat,
+            VL => self.vl,
+            VTYPE => self.vtype,
+            VLENB => self.vlenb,
+            CYCLE | MCYCLE => self.cycle & self.xlen_mask(),
+            TIME => self.time & self.xlen_mask(),
+            INSTRET | MINSTRET => self.instret & self.xlen_mask(),
+            HPMCOUNTER3..=HPMCOUNTER31 | MHPMCOUNTER3..=MHPMCOUNTER31 | MHPMEVENT3..=MHPMEVENT31 => 0,
+            CYCLEH | MCYCLEH if self.xlen == 32 => self.cycle >> 32,
+            TIMEH if self.xlen == 32 => self.time >> 32,
+            INSTRETH | MINSTRETH if self.xlen == 32 => self.instret >> 32,
+            HPMCOUNTER3H..=HPMCOUNTER31H | MHPMCOUNTER3H..=MHPMCOUNTER31H if self.xlen == 32 => 0,
+            MCOUNTEREN => self.mcounteren,
+            SSTATUS | SIE | STVEC | SCOUNTEREN | SSCRATCH | SEPC | SCAUSE | STVAL | SIP | SATP |
+            MEDELEG | MIDELEG if !self.supervisor => return None,
+            SSTATUS => self.read(MSTATUS)? & self.sstatus_mask(),
+            SIE => self.mie & self.mideleg,
+            STVEC => self.stvec,
+            SCOUNTEREN => self.scounteren,
+            SSCRATCH => self.sscratch,
+            SEPC => self.sepc & self.ialign_mask,
+            SCAUSE => self.scause,
+            STVAL => self.stval,
+            SIP => self.pending_interrupts() & self.mideleg,
+            SATP => self.satp,
+            MEDELEG => self.medeleg,
+            MIDELEG => self.mideleg,
+            MCOUNTINHIBIT => self.mcountinhibit,
+            MVENDORID | MARCHID | MIMPID | MHARTID | MCONFIGPTR => 0,
+            MSTATUS if self.mstatus & MSTATUS_FS == FS_DIRTY || self.mstatus & MSTATUS_VS == VS_DIRTY => {
+                self.mstatus | 1 << (self.xlen - 1)
+            }
+            MSTATUS => self.mstatus,
+            MISA => self.misa,
+            MIE => self.mie,
+            MTVEC => self.mtvec,
+            // RV64 keeps the upper half of mstatus in mstatus itself.
+            MSTATUSH if self.xlen == 32 => 0,
+            MSCRATCH => self.mscratch,
+            MEPC => self.mepc & self.ialign_mask,
+            MCAUSE => self.mcause,
+            MTVAL => self.mtval,
+            MIP => self.pending_interrupts(),
+            PMPCFG0..=PMPCFG3 if self.xlen == 64 && address & 1 != 0 => return None,
+            PMPCFG0..=PMPCFG3 => {
+                let first = (address - PMPCFG0) as usize * 4;
+                let entries = &self.pmpcfg[first..first + self.xlen as usize / 8];
+                entries.iter().rev().fold(0, |acc, &cfg| (acc << 8) | cfg as u64)
+            }
+            PMPADDR0..=PMPADDR15 => self.pmpaddr[(address - PMPADDR0) as usize],
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Write the CSR at `address`, keeping only the legal values of each
+    /// field. Returns `None` if the CSR does not exist or is read-only.
+    pub fn write(&mut self, address: u16, value: u64) -> Option<()> {
+        if is_read_only(address) { return None; }
+        match address {
+            FFLAGS | FRM | FCSR if !self.float_enabled() => return None,
+            FFLAGS => self.fflags = value as u32 & 0x1F,
+            FRM => self.frm = value as u32 & 0x7,
+            FCSR => {
+                self.fflags = value as u32 & 0x1F;
+                self.frm = (value as u32 >> 5) & 0x7;
+            }
+            VSTART | VXSAT | VXRM | VCSR if !self.vector_enabled() => return None,
+            VSTART => self.vstart = value & (self.vlenb * 8 - 1),
+            VXSAT => self.vxsat = value & 0x1,
+            VXRM => self.vxrm = value & 0x3,
+            VCSR => {
+                self.vxsat = value & 0x1;
+                self.vxrm = (value >> 1) & 0x3;
+            }
+            MSTATUS => {
+                let mut writable = MSTATUS_MIE | MSTATUS_MPIE;
+                if self.float { writable |= MSTATUS_FS; }
+                if self.vector { writable |= MSTATUS_VS; }
+                if self.user { writable |= MSTATUS_MPRV | MSTATUS_TW; }
+                if self.supervisor {
+                    writable |= MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP | MSTATUS_SUM | MSTATUS_MXR |
+                        MSTATUS_TVM | MSTATUS_TSR;
+                }
+                // MPP only takes modes the hart has; other values leave it alone.
+                if self.privilege_supported(Privilege::from_bits(value >> 11)) && value >> 11 & 0b11 != 2 {
+                    writable |= MSTATUS_MPP;
+                }
+                self.mstatus = (self.mstatus & !writable) | (value & writable);
+            }
+            SSTATUS | SIE | STVEC | SCOUNTEREN | SSCRATCH | SEPC | SCAUSE | STVAL | SIP | SATP |
+            MEDELEG | MIDELEG if !self.supervisor => return None,
+            SSTATUS => {
+                let writable = self.sstatus_mask() & !(MSTATUS_UXL | 1 << (self.xlen - 1));
+                self.mstatus = (self.mstatus & !writable) | (value & writable);
+            }
+            // Only the delegated interrupts are visible from supervisor mode,
+            // and of their pending bits only SSIP is writable.
+            SIE => self.mie = (self.mie & !self.mideleg) | (value & self.mideleg),
+            SIP => {
+                let writable = self.mideleg & MIP_SSIP;
+                self.mip = (self.mip & !writable) | (value & writable);
+            }
+            STVEC => self.stvec = trap_vector(value),
+            SCOUNTEREN => self.scounteren = value & 0xFFFF_FFFF,
+            SSCRATCH => self.sscratch = value,
+            SEPC => self.sepc = value & !0b1,
+            SCAUSE => self.scause = value,
+            STVAL => self.stval = value,
+            // RV32 has Bare and Sv32, and RV64 only Bare. Selecting any other
+            // mode leaves the whole register unchanged.
+            SATP => if self.xlen == 32 || value >> 60 == 0 { self.satp = value & self.xlen_mask(); },
+            MEDELEG => self.medeleg = value & DELEGABLE_EXCEPTIONS,
+            MIDELEG => self.mideleg = value & SUPERVISOR_INTERRUPTS,
+            // On RV32 the counters are written one half at a time.
+            MCYCLE => self.cycle = self.replace_low(self.cycle, value),
+            MINSTRET => self.instret = self.replace_low(self.instret, value),
+            MCYCLEH if self.xlen == 32 => self.cycle = (self.cycle & 0xFFFF_FFFF) | value << 32,
+            MINSTRETH if self.xlen == 32 => self.instret = (self.instret & 0xFFFF_FFFF) | value << 32,
+            MHPMCOUNTER3..=MHPMCOUNTER31 | MHPMEVENT3..=MHPMEVENT31 => {}
+            MHPMCOUNTER3H..=MHPMCOUNTER31H if self.xlen == 32 => {}
+            MCOUNTEREN => self.mcounteren = value & 0xFFFF_FFFF,
+            // time cannot be stopped, and the hpm counters never count.
+            MCOUNTINHIBIT => self.mcountinhibit = value & (COUNTER_CY | COUNTER_IR),
+            // Extensions are fixed at startup, so writes are ignored.
+            MISA => {}
+            MSTATUSH if self.xlen == 32 => {}
+            MIE => self.mie = value & self.interrupts(),
+            MTVEC => self.mtvec = trap_vector(value),
+            MSCRATCH => self.mscratch = value,
+            MEPC => self.mepc = value & !0b1,
+            MCAUSE => self.mcause = value,
+            MTVAL => self.mtval = value,
+            // The machine-level pending bits are driven by the interrupt
+            // sources, not by software. Machine mode raises the supervisor
+            // ones to pass interrupts down.
+            MIP if self.supervisor => {
+                self.mip = (self.mip & !SUPERVISOR_INTERRUPTS) | (value & SUPERVISOR_INTERRUPTS);
+            }
+            MIP => {}
+            PMPCFG0..=PMPCFG3 if self.xlen == 64 && address & 1 != 0 => return None,
+            PMPCFG0..=PMPCFG3 => {
+                let first = (address - PMPCFG0) as usize * 4;
+                for i in 0..self.xlen as usize / 8 {
+                    self.write_pmpcfg(first + i, (value >> (8 * i)) as u8);
+                }
+            }
+            PMPADDR0..=PMPADDR15 => {
+                let index = (address - PMPADDR0) as usize;
+                if self.pmp && !self.pmpaddr_locked(index) {
+                    let bits = if self.xlen == 32 { 32 } else { 54 };
+                    self.pmpaddr[index] = value & ((1 << bits) - 1);
+                }
+            }
+            _ => return None,
+        }
+        if let FFLAGS | FRM | FCSR = address { self.mstatus |= FS_DIRTY; }
+        if let VSTART | VXSAT | VXRM | VCSR = address { self.mstatus |= VS_DIRTY; }
+        match address {
+            MCYCLE | MCYCLEH => self.counters_written |= COUNTER_CY,
+            MINSTRET | MINSTRETH => self.counters_written |= COUNTER_IR,
+            _ => {}
+        }
+        Some(())
+    }
+
+    fn xlen_mask(&self) -> u64 { !0 >> (64 - self.xlen) }
+
+    /// mip as software reads it, with the PLIC's line added to SEIP.
+    pub(crate) fn pending_interrupts(&self) -> u64 {
+        self.mip | if self.seip && self.supervisor { MIP_SEIP } else { 0 }
+    }
+
+    /// The interrupts this hart has: the machine-level ones, and the
+    /// supervisor-level ones if it has supervisor mode.
+    fn interrupts(&self) -> u64 {
+        let machine = MIP_MSIP | MIP_MTIP | MIP_MEIP;
+        if self.supervisor { machine | SUPERVISOR_INTERRUPTS } else { machine }
+    }
+
+    /// The fields of mstatus that sstatus shows.
+    fn sstatus_mask(&self) -> u64 {
+        let mut mask = MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP | MSTATUS_SUM | MSTATUS_MXR | MSTATUS_UXL |
+            1 << (self.xlen - 1);
+        if self.float { mask |= MSTATUS_FS; }
+        if self.vector { mask |= MSTATUS_VS; }
+        mask
+    }
+
+    /// Set the configuration of PMP entry `index`, unless it is locked. The
+    /// reserved bits read as zero, and so does W without R.
+    fn write_pmpcfg(&mut self, index: usize, value: u8) {
+        if !self.pmp || self.pmpcfg[index] & PMP_L != 0 { return; }
+        let mut cfg = value & (PMP_L | PMP_A | PMP_X | PMP_W | PMP_R);
+        if cfg & (PMP_R | PMP_W) == PMP_W { cfg &= !PMP_W; }
+        self.pmpcfg[index] = cfg;
+    }
+
+    /// A locked entry locks its own address, and also the one below it
+    /// when it is a TOR entry that uses that address as its base.
+    fn pmpaddr_locked(&self, index: usize) -> bool {
+        let locked = |i: usize| self.pmpcfg[i] & PMP_L != 0;
+        locked(index) ||
+            index + 1 < PMP_ENTRIES && locked(index + 1) && self.pmpcfg[index + 1] & PMP_A == PMP_TOR
+    }
+
+    /// Whether the hart implements the privilege mode `privilege`.
+    pub fn privilege_supported(&self, privilege: Privilege) -> bool {
+        match privilege {
+            Privilege::Machine => true,
+            Privilege::Supervisor => self.supervisor,
+            Privilege::User => self.user,
+        }
+    }
+
+    /// The least privileged mode the hart has, which xRET leaves in xPP.
+    pub(crate) fn lowest_privilege(&self) -> Privilege {
+        if self.user { Privilege::User } else { Privilege::Machine }
+    }
+
+    /// `counter` with its low XLEN bits replaced by `value`.
+    fn replace_low(&self, counter: u64, value: u64) -> u64 {
+        (counter & !self.xlen_mask()) | (value & self.xlen_mask())
+    }
+
+    /// Count one cycle, and one instruction if the last one retired rather
+    /// than trapping. mcountinhibit stops either count.
+    pub(crate) fn advance_counters(&mut self, retired: bool) {
+        let stopped = self.mcountinhibit | self.counters_written;
+        if stopped & COUNTER_CY == 0 { self.cycle = self.cycle.wrapping_add(1); }
+        if retired && stopped & COUNTER_IR == 0 { self.instret = self.instret.wrapping_add(1); }
+        self.counters_written = 0;
+    }
+
+    /// Whether mcounteren lets privilege modes below M read the
+    /// unprivileged counter at `address`. Machine mode always can.
+    pub fn counter_enabled(&self, address: u16) -> bool {
+        counter_bit(self.mcounteren, address)
+    }
+
+    /// Whether code running in `privilege` may access the CSR at
+    /// `address`. Bits 9:8 of the address give the least privileged mode
+    /// that can; below M, mcounteren (and from user mode, scounteren)
+    /// guards the counters, and mstatus.TVM keeps supervisor mode away
+    /// from satp.
+    pub fn accessible(&self, address: u16, privilege: Privilege) -> bool {
+        if address >> 8 & 0b11 > privilege as u16 { return false; }
+        match privilege {
+            Privilege::Machine => true,
+            Privilege::Supervisor => {
+                self.counter_enabled(address) && !(address == SATP && self.mstatus & MSTATUS_TVM != 0)
+            }
+            Privilege::User => {
+                self.counter_enabled(address) && (!self.supervisor || counter_bit(self.scounteren, address))
+            }
+        }
+    }
+
+    /// Whether floating-point instructions and CSRs may be used, which
+    /// needs both the extension and mstatus.FS not Off.
+    pub fn float_enabled(&self) -> bool {
+        self.float && self.mstatus & MSTATUS_FS != FS_OFF
+    }
+
+    /// Record that the floating-point state was modified.
+    pub(crate) fn set_float_dirty(&mut self) {
+        self.mstatus |= FS_DIRTY;
+    }
+
+    /// Whether vector instructions and CSRs may be used, which needs both
+    /// the extension and mstatus.VS not Off.
+    pub fn vector_enabled(&self) -> bool {
+        self.vector && self.mstatus & MSTATUS_VS != VS_OFF
+    }
+
+    /// Record that the vector state was modified.
+    pub(crate) fn set_vector_dirty(&mut self) {
+        self.mstatus |= VS_DIRTY;
+    }
+}
+
+#[cfg(test)]
+mod csr_test;
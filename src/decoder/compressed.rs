@@ -0,0 +1,160 @@
+use super::*;
+
+/// Bits `hi..=lo` of a parcel, shifted down to bit 0.
+fn bits(parcel: u16, hi: u32, lo: u32) -> u32 {
+    (parcel as u32 >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// Sign-extend the low `width` bits of `value`.
+fn sign_extend(value: u32, width: u32) -> i32 {
+    ((value << (32 - width)) as i32) >> (32 - width)
+}
+
+/// One of the eight registers x8-x15 named by a three-bit field.
+fn creg(parcel: u16, lo: u32) -> usize { 8 + bits(parcel, lo + 2, lo) as usize }
+
+/// Offset of c.lw, c.sw, c.flw and c.fsw: uimm[5:3] in bits 12:10, [2|6] in 6:5.
+fn word_offset(parcel: u16) -> i32 {
+    (bits(parcel, 12, 10) << 3 | bits(parcel, 6, 6) << 2 | bits(parcel, 5, 5) << 6) as i32
+}
+
+/// Offset of c.fld, c.fsd, c.ld and c.sd: uimm[5:3] in bits 12:10, [7:6] in 6:5.
+fn double_offset(parcel: u16) -> i32 {
+    (bits(parcel, 12, 10) << 3 | bits(parcel, 6, 5) << 6) as i32
+}
+
+/// The 6-bit signed immediate of c.addi, c.li and c.andi.
+fn small_immediate(parcel: u16) -> i32 {
+    sign_extend(bits(parcel, 12, 12) << 5 | bits(parcel, 6, 2), 6)
+}
+
+/// Target offset of c.j and c.jal: imm[11|4|9:8|10|6|7|3:1|5] in bits 12:2.
+fn jump_offset(parcel: u16) -> i32 {
+    let imm = bits(parcel, 12, 12) << 11 | bits(parcel, 11, 11) << 4 | bits(parcel, 10, 9) << 8 |
+        bits(parcel, 8, 8) << 10 | bits(parcel, 7, 7) << 6 | bits(parcel, 6, 6) << 7 |
+        bits(parcel, 5, 3) << 1 | bits(parcel, 2, 2) << 5;
+    sign_extend(imm, 12)
+}
+
+/// Target offset of c.beqz and c.bnez: imm[8|4:3] in bits 12:10, [7:6|2:1|5] in 6:2.
+fn branch_offset(parcel: u16) -> i32 {
+    let imm = bits(parcel, 12, 12) << 8 | bits(parcel, 11, 10) << 3 | bits(parcel, 6, 5) << 6 |
+        bits(parcel, 4, 3) << 1 | bits(parcel, 2, 2) << 5;
+    sign_extend(imm, 9)
+}
+
+/// Offset of c.fldsp and c.ldsp: uimm[5] in bit 12, [4:3|8:6] in 6:2.
+fn double_sp_offset(parcel: u16) -> i32 {
+    (bits(parcel, 12, 12) << 5 | bits(parcel, 6, 5) << 3 | bits(parcel, 4, 2) << 6) as i32
+}
+
+/// Offset of c.fsdsp and c.sdsp: uimm[5:3|8:6] in bits 12:7.
+fn double_sp_store_offset(parcel: u16) -> i32 {
+    (bits(parcel, 12, 10) << 3 | bits(parcel, 9, 7) << 6) as i32
+}
+
+/// Expand a 16-bit instruction into the 32-bit instruction it stands for.
+/// `xlen` selects between RV32C and RV64C, which reuse some encodings.
+/// Reserved encodings, and those only defined for a wider XLEN, are
+/// rejected; hints decode like the instructions they are encoded as.
+pub fn decode_compressed(parcel: u16, xlen: u32) -> Result<Instruction, DecodeError> {
+    use self::Instruction::*;
+
+    let err = DecodeError { word: parcel as u32 };
+    let rv64 = xlen == 64;
+    // Shifts by 32 or more only exist on RV64.
+    let shamt = bits(parcel, 12, 12) << 5 | bits(parcel, 6, 2);
+    let rd = bits(parcel, 11, 7) as usize;
+    let rs2 = bits(parcel, 6, 2) as usize;
+    let (rd_, rs1_, rs2_) = (creg(parcel, 2), creg(parcel, 7), creg(parcel, 2));
+
+    let inst = match (parcel & 0x3, bits(parcel, 15, 13)) {
+        // Quadrant 0
+        (0x0, 0x0) => {
+            let imm = bits(parcel, 12, 11) << 4 | bits(parcel, 10, 7) << 6 |
+                bits(parcel, 6, 6) << 2 | bits(parcel, 5, 5) << 3;
+            // c.addi4spn with a zero immediate is reserved; so is the all-zero parcel.
+            if imm == 0 { return Err(err); }
+            Addi { rd: rd_, rs1: 2, imm: imm as i32 }
+        }
+        (0x0, 0x1) => Fload { rd: rd_, rs1: rs1_, imm: double_offset(parcel), fmt: Precision::D },
+        (0x0, 0x2) => Lw { rd: rd_, rs1: rs1_, imm: word_offset(parcel) },
+        (0x0, 0x3) if rv64 => Ld { rd: rd_, rs1: rs1_, imm: double_offset(parcel) },
+        (0x0, 0x3) => Fload { rd: rd_, rs1: rs1_, imm: word_offset(parcel), fmt: Precision::S },
+        (0x0, 0x5) => Fstore { rs1: rs1_, rs2: rs2_, imm: double_offset(parcel), fmt: Precision::D },
+        (0x0, 0x6) => Sw { rs1: rs1_, rs2: rs2_, imm: word_offset(parcel) },
+        (0x0, 0x7) if rv64 => Sd { rs1: rs1_, rs2: rs2_, imm: double_offset(parcel) },
+        (0x0, 0x7) => Fstore { rs1: rs1_, rs2: rs2_, imm: word_offset(parcel), fmt: Precision::S },
+
+        // Quadrant 1
+        (0x1, 0x0) => Addi { rd, rs1: rd, imm: small_immediate(parcel) },
+        (0x1, 0x1) if rv64 && rd == 0 => return Err(err),
+        (0x1, 0x1) if rv64 => Addiw { rd, rs1: rd, imm: small_immediate(parcel) },
+        (0x1, 0x1) => Jal { rd: 1, imm: jump_offset(parcel) },
+        (0x1, 0x2) => Addi { rd, rs1: 0, imm: small_immediate(parcel) },
+        (0x1, 0x3) if rd == 2 => {
+            let imm = bits(parcel, 12, 12) << 9 | bits(parcel, 6, 6) << 4 | bits(parcel, 5, 5) << 6 |
+                bits(parcel, 4, 3) << 7 | bits(parcel, 2, 2) << 5;
+            if imm == 0 { return Err(err); }
+            Addi { rd: 2, rs1: 2, imm: sign_extend(imm, 10) }
+        }
+        (0x1, 0x3) => {
+            let imm = small_immediate(parcel);
+            if imm == 0 { return Err(err); }
+            Lui { rd, imm: imm << 12 }
+        }
+        (0x1, 0x4) => {
+            match (bits(parcel, 12, 12), bits(parcel, 11, 10), bits(parcel, 6, 5)) {
+                (_, 0x0, _) if rv64 || shamt < 32 => Srli { rd: rs1_, rs1: rs1_, shamt },
+                (_, 0x1, _) if rv64 || shamt < 32 => Srai { rd: rs1_, rs1: rs1_, shamt },
+                (_, 0x2, _) => Andi { rd: rs1_, rs1: rs1_, imm: small_immediate(parcel) },
+                (0, 0x3, 0x0) => Sub { rd: rs1_, rs1: rs1_, rs2: rs2_ },
+                (0, 0x3, 0x1) => Xor { rd: rs1_, rs1: rs1_, rs2: rs2_ },
+                (0, 0x3, 0x2) => Or { rd: rs1_, rs1: rs1_, rs2: rs2_ },
+                (0, 0x3, 0x3) => And { rd: rs1_, rs1: rs1_, rs2: rs2_ },
+                (1, 0x3, 0x0) if rv64 => Subw { rd: rs1_, rs1: rs1_, rs2: rs2_ },
+                (1, 0x3, 0x1) if rv64 => Addw { rd: rs1_, rs1: rs1_, rs2: rs2_ },
+                _ => return Err(err),
+            }
+        }
+        (0x1, 0x5) => Jal { rd: 0, imm: jump_offset(parcel) },
+        (0x1, 0x6) => Beq { rs1: rs1_, rs2: 0, imm: branch_offset(parcel) },
+        (0x1, 0x7) => Bne { rs1: rs1_, rs2: 0, imm: branch_offset(parcel) },
+
+        // Quadrant 2
+        (0x2, 0x0) if rv64 || shamt < 32 => Slli { rd, rs1: rd, shamt },
+        (0x2, 0x1) => Fload { rd, rs1: 2, imm: double_sp_offset(parcel), fmt: Precision::D },
+        (0x2, 0x3) if rv64 && rd == 0 => return Err(err),
+        (0x2, 0x3) if rv64 => Ld { rd, rs1: 2, imm: double_sp_offset(parcel) },
+        (0x2, 0x2) | (0x2, 0x3) => {
+            let imm = (bits(parcel, 12, 12) << 5 | bits(parcel, 6, 4) << 2 | bits(parcel, 3, 2) << 6) as i32;
+            match bits(parcel, 15, 13) {
+                0x2 if rd == 0 => return Err(err),
+                0x2 => Lw { rd, rs1: 2, imm },
+                _ => Fload { rd, rs1: 2, imm, fmt: Precision::S },
+            }
+        }
+        (0x2, 0x4) => {
+            match (bits(parcel, 12, 12), rd, rs2) {
+                (0, 0, 0) => return Err(err),
+                (0, rs1, 0) => Jalr { rd: 0, rs1, imm: 0 },
+                (0, rd, rs2) => Add { rd, rs1: 0, rs2 },
+                (_, 0, 0) => Ebreak,
+                (_, rs1, 0) => Jalr { rd: 1, rs1, imm: 0 },
+                (_, rd, rs2) => Add { rd, rs1: rd, rs2 },
+            }
+        }
+        (0x2, 0x5) => Fstore { rs1: 2, rs2, imm: double_sp_store_offset(parcel), fmt: Precision::D },
+        (0x2, 0x7) if rv64 => Sd { rs1: 2, rs2, imm: double_sp_store_offset(parcel) },
+        (0x2, 0x6) | (0x2, 0x7) => {
+            let imm = (bits(parcel, 12, 9) << 2 | bits(parcel, 8, 7) << 6) as i32;
+            match bits(parcel, 15, 13) {
+                0x6 => Sw { rs1: 2, rs2, imm },
+                _ => Fstore { rs1: 2, rs2, imm, fmt: Precision::S },
+            }
+        }
+        _ => return Err(err),
+    };
+
+    Ok(inst)
+}
@@ -53,4 +53,117 @@ fn test_decode_uj_immediate() {
     let predicted_imm = decode_uj_type_immediate(&[0x6f, 0xf0, 0xdf, 0xf7]);
     println!("Decoded: {:032b}\n Actual: {:032b}", predicted_imm, -0x84); 
     assert_eq!(predicted_imm, -0x84);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_decode_instructions() {
+    assert_eq!(decode(0xfff00293), Ok(Instruction::Addi { rd: 5, rs1: 0, imm: -1 }));
+    assert_eq!(decode(0x006283b3), Ok(Instruction::Add { rd: 7, rs1: 5, rs2: 6 }));
+    assert_eq!(decode(0x4042d793), Ok(Instruction::Srai { rd: 15, rs1: 5, shamt: 4 }));
+    assert_eq!(decode(0x00535463), Ok(Instruction::Bge { rs1: 6, rs2: 5, imm: 8 }));
+    assert_eq!(decode(0x0262e433), Ok(Instruction::Rem { rd: 8, rs1: 5, rs2: 6 }));
+    assert_eq!(decode(0x3002d0f3), Ok(Instruction::Csrrwi { rd: 1, uimm: 5, csr: 0x300 }));
+    assert_eq!(decode(0x00100073), Ok(Instruction::Ebreak));
+    assert_eq!(decode(0x10200073), Ok(Instruction::Sret));
+    assert_eq!(decode(0x10500073), Ok(Instruction::Wfi));
+    assert_eq!(decode(0x0ff0000f), Ok(Instruction::Fence { pred: 0xF, succ: 0xF }));
+    assert_eq!(decode(0x8330000f), Ok(Instruction::FenceTso));
+    assert_eq!(decode(0x0000100f), Ok(Instruction::FenceI));
+    // rev8 and zext.h have one encoding for each XLEN.
+    assert_eq!(decode(0x6985d513), Ok(Instruction::Rev8 { rd: 10, rs1: 11, xlen: 32 }));
+    assert_eq!(decode(0x6b85d513), Ok(Instruction::Rev8 { rd: 10, rs1: 11, xlen: 64 }));
+    assert_eq!(decode(0x0805c533), Ok(Instruction::ZextH { rd: 10, rs1: 11, xlen: 32 }));
+    assert_eq!(decode(0x0805c53b), Ok(Instruction::ZextH { rd: 10, rs1: 11, xlen: 64 }));
+    assert_eq!(decode(0x68c5f543), Ok(Instruction::Fmadd { rd: 10, rs1: 11, rs2: 12, rs3: 13, rm: 7, fmt: Precision::S }));
+    assert_eq!(decode(0xc0157553), Ok(Instruction::FcvtToInt { rd: 10, rs1: 10, rm: 7, fmt: Precision::S, int: IntFormat::Wu }));
+}
+
+#[test]
+fn test_decode_vector_instructions() {
+    // Encodings from llvm-mc -triple=riscv32 -mattr=+v
+    assert_eq!(decode(0x0d05f557), Ok(Instruction::Vsetvli { rd: 10, rs1: 11, vtypei: 0xD0 }));
+    assert_eq!(decode(0xc0727557), Ok(Instruction::Vsetivli { rd: 10, uimm: 4, vtypei: 0x7 }));
+    assert_eq!(decode(0x0ab56087), Ok(Instruction::Vload {
+        vd: 1, rs1: 10, eew: 32, mode: VectorAddressing::Strided(11), masked: false,
+    }));
+    assert_eq!(decode(0x0c250087), Ok(Instruction::Vload {
+        vd: 1, rs1: 10, eew: 8, mode: VectorAddressing::Indexed { vs2: 2, ordered: true }, masked: true,
+    }));
+    assert_eq!(decode(0x028500a7), Ok(Instruction::Vstore {
+        vs3: 1, rs1: 10, eew: 8, mode: VectorAddressing::WholeRegister(1), masked: false,
+    }));
+    assert_eq!(decode(0x022830d7), Ok(Instruction::Varith {
+        op: VectorOp::Add, vd: 1, vs2: 2, src: VectorOperand::Immediate(-16), masked: false,
+    }));
+    assert_eq!(decode(0xb62560d7), Ok(Instruction::Varith {
+        op: VectorOp::Macc, vd: 1, vs2: 2, src: VectorOperand::Scalar(10), masked: false,
+    }));
+    assert_eq!(decode(0x5c2180d7), Ok(Instruction::Vmerge { vd: 1, vs2: 2, src: VectorOperand::Vector(3) }));
+    assert_eq!(decode(0x0221a0d7), Ok(Instruction::Vreduce {
+        op: VectorReduction::Sum, vd: 1, vs2: 2, vs1: 3, masked: false,
+    }));
+    assert_eq!(decode(0x40282557), Ok(Instruction::Vcpop { rd: 10, vs2: 2, masked: true }));
+    assert_eq!(decode(0x9e40b157), Ok(Instruction::VmvNr { vd: 2, vs2: 4, nregs: 2 }));
+
+    // Segment, fault-only-first and floating-point forms are not supported.
+    for &word in &[0x22050087, 0x03050087, 0x022190d7] {
+        assert_eq!(decode(word), Err(DecodeError { word }), "{:08x}", word);
+    }
+}
+
+#[test]
+fn test_decode_rejects_unknown_encodings() {
+    assert_eq!(decode(0xffffffff), Err(DecodeError { word: 0xffffffff }));
+    // slli with a non-zero funct7
+    assert_eq!(decode(0x40129293), Err(DecodeError { word: 0x40129293 }));
+    // fadd.s with the reserved rounding mode 5, and fadd.h
+    assert_eq!(decode(0x00c5d553), Err(DecodeError { word: 0x00c5d553 }));
+    assert_eq!(decode(0x04c5f553), Err(DecodeError { word: 0x04c5f553 }));
+}
+
+#[test]
+fn test_decode_compressed_instructions() {
+    // Encodings from llvm-mc -triple=riscv32 -mattr=+c,+d
+    assert_eq!(decode_compressed(0x0808, 32), Ok(Instruction::Addi { rd: 10, rs1: 2, imm: 16 }));
+    assert_eq!(decode_compressed(0x40b2, 32), Ok(Instruction::Lw { rd: 1, rs1: 2, imm: 12 }));
+    assert_eq!(decode_compressed(0xbff5, 32), Ok(Instruction::Jal { rd: 0, imm: -4 }));
+    assert_eq!(decode_compressed(0xc581, 32), Ok(Instruction::Beq { rs1: 11, rs2: 0, imm: 8 }));
+    assert_eq!(decode_compressed(0x9782, 32), Ok(Instruction::Jalr { rd: 1, rs1: 15, imm: 0 }));
+    assert_eq!(decode_compressed(0x9002, 32), Ok(Instruction::Ebreak));
+    assert_eq!(decode_compressed(0xa422, 32), Ok(Instruction::Fstore { rs1: 2, rs2: 8, imm: 8, fmt: Precision::D }));
+    assert_eq!(decode_compressed(0x5575, 32), Ok(Instruction::Addi { rd: 10, rs1: 0, imm: -3 }));
+    assert_eq!(decode_compressed(0x713d, 32), Ok(Instruction::Addi { rd: 2, rs1: 2, imm: -32 }));
+    assert_eq!(decode_compressed(0x860d, 32), Ok(Instruction::Srai { rd: 12, rs1: 12, shamt: 3 }));
+    assert_eq!(decode_compressed(0x8c1d, 32), Ok(Instruction::Sub { rd: 8, rs1: 8, rs2: 15 }));
+    assert_eq!(decode_compressed(0xc22a, 32), Ok(Instruction::Sw { rs1: 2, rs2: 10, imm: 4 }));
+}
+
+#[test]
+fn test_decode_compressed_rejects_reserved_encodings() {
+    for &parcel in &[
+        0x0000, // all zeros
+        0x4002, // c.lwsp with rd = x0
+        0x6081, // c.lui with a zero immediate
+        0x6101, // c.addi16sp with a zero immediate
+        0x9001, // c.srli with shamt[5] set
+        0x8002, // c.jr with rs1 = x0
+        0x9c21, // c.addw, RV64 only
+    ] {
+        assert_eq!(decode_compressed(parcel, 32), Err(DecodeError { word: parcel as u32 }), "{:04x}", parcel);
+    }
+}
+
+#[test]
+fn test_decode_rv64_compressed_instructions() {
+    // Encodings from llvm-mc -triple=riscv64 -mattr=+c
+    assert_eq!(decode_compressed(0x6588, 64), Ok(Instruction::Ld { rd: 10, rs1: 11, imm: 8 }));
+    assert_eq!(decode_compressed(0xe188, 64), Ok(Instruction::Sd { rs1: 11, rs2: 10, imm: 0 }));
+    assert_eq!(decode_compressed(0x357d, 64), Ok(Instruction::Addiw { rd: 10, rs1: 10, imm: -1 }));
+    assert_eq!(decode_compressed(0x9d0d, 64), Ok(Instruction::Subw { rd: 10, rs1: 10, rs2: 11 }));
+    assert_eq!(decode_compressed(0x6522, 64), Ok(Instruction::Ld { rd: 10, rs1: 2, imm: 8 }));
+    assert_eq!(decode_compressed(0xe42a, 64), Ok(Instruction::Sd { rs1: 2, rs2: 10, imm: 8 }));
+    assert_eq!(decode_compressed(0x1502, 64), Ok(Instruction::Slli { rd: 10, rs1: 10, shamt: 32 }));
+    // The same parcels mean c.flw and c.jal on RV32, where wide shifts are reserved.
+    assert_eq!(decode_compressed(0x6588, 32), Ok(Instruction::Fload { rd: 10, rs1: 11, imm: 8, fmt: Precision::S }));
+    assert_eq!(decode_compressed(0x1502, 32), Err(DecodeError { word: 0x1502 }));
+}
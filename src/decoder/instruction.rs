@@ -0,0 +1,971 @@
+use std::fmt;
+
+/// A fully decoded instruction. Register fields are indices into the integer
+/// register file, or the floating-point one for floating-point operands, and
+/// immediates are already sign-extended.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    // RV32I
+    Lui { rd: usize, imm: i32 },
+    Auipc { rd: usize, imm: i32 },
+    Jal { rd: usize, imm: i32 },
+    Jalr { rd: usize, rs1: usize, imm: i32 },
+
+    Beq { rs1: usize, rs2: usize, imm: i32 },
+    Bne { rs1: usize, rs2: usize, imm: i32 },
+    Blt { rs1: usize, rs2: usize, imm: i32 },
+    Bge { rs1: usize, rs2: usize, imm: i32 },
+    Bltu { rs1: usize, rs2: usize, imm: i32 },
+    Bgeu { rs1: usize, rs2: usize, imm: i32 },
+
+    Lb { rd: usize, rs1: usize, imm: i32 },
+    Lh { rd: usize, rs1: usize, imm: i32 },
+    Lw { rd: usize, rs1: usize, imm: i32 },
+    Lbu { rd: usize, rs1: usize, imm: i32 },
+    Lhu { rd: usize, rs1: usize, imm: i32 },
+
+    Sb { rs1: usize, rs2: usize, imm: i32 },
+    Sh { rs1: usize, rs2: usize, imm: i32 },
+    Sw { rs1: usize, rs2: usize, imm: i32 },
+
+    Addi { rd: usize, rs1: usize, imm: i32 },
+    Slti { rd: usize, rs1: usize, imm: i32 },
+    Sltiu { rd: usize, rs1: usize, imm: i32 },
+    Xori { rd: usize, rs1: usize, imm: i32 },
+    Ori { rd: usize, rs1: usize, imm: i32 },
+    Andi { rd: usize, rs1: usize, imm: i32 },
+    Slli { rd: usize, rs1: usize, shamt: u32 },
+    Srli { rd: usize, rs1: usize, shamt: u32 },
+    Srai { rd: usize, rs1: usize, shamt: u32 },
+
+    Add { rd: usize, rs1: usize, rs2: usize },
+    Sub { rd: usize, rs1: usize, rs2: usize },
+    Sll { rd: usize, rs1: usize, rs2: usize },
+    Slt { rd: usize, rs1: usize, rs2: usize },
+    Sltu { rd: usize, rs1: usize, rs2: usize },
+    Xor { rd: usize, rs1: usize, rs2: usize },
+    Srl { rd: usize, rs1: usize, rs2: usize },
+    Sra { rd: usize, rs1: usize, rs2: usize },
+    Or { rd: usize, rs1: usize, rs2: usize },
+    And { rd: usize, rs1: usize, rs2: usize },
+
+    /// `pred` and `succ` are the IORW sets, I in bit 3 down to W in bit 0.
+    Fence { pred: u8, succ: u8 },
+    FenceTso,
+    Ecall,
+    Ebreak,
+
+    // RV64I
+    Lwu { rd: usize, rs1: usize, imm: i32 },
+    Ld { rd: usize, rs1: usize, imm: i32 },
+    Sd { rs1: usize, rs2: usize, imm: i32 },
+    Addiw { rd: usize, rs1: usize, imm: i32 },
+    Slliw { rd: usize, rs1: usize, shamt: u32 },
+    Srliw { rd: usize, rs1: usize, shamt: u32 },
+    Sraiw { rd: usize, rs1: usize, shamt: u32 },
+    Addw { rd: usize, rs1: usize, rs2: usize },
+    Subw { rd: usize, rs1: usize, rs2: usize },
+    Sllw { rd: usize, rs1: usize, rs2: usize },
+    Srlw { rd: usize, rs1: usize, rs2: usize },
+    Sraw { rd: usize, rs1: usize, rs2: usize },
+
+    // Privileged
+    Mret,
+    Sret,
+    Wfi,
+    SfenceVma { rs1: usize, rs2: usize },
+
+    // Zifencei
+    FenceI,
+
+    // Zicsr
+    Csrrw { rd: usize, rs1: usize, csr: u16 },
+    Csrrs { rd: usize, rs1: usize, csr: u16 },
+    Csrrc { rd: usize, rs1: usize, csr: u16 },
+    Csrrwi { rd: usize, uimm: u32, csr: u16 },
+    Csrrsi { rd: usize, uimm: u32, csr: u16 },
+    Csrrci { rd: usize, uimm: u32, csr: u16 },
+
+    // RV32M
+    Mul { rd: usize, rs1: usize, rs2: usize },
+    Mulh { rd: usize, rs1: usize, rs2: usize },
+    Mulhsu { rd: usize, rs1: usize, rs2: usize },
+    Mulhu { rd: usize, rs1: usize, rs2: usize },
+    Div { rd: usize, rs1: usize, rs2: usize },
+    Divu { rd: usize, rs1: usize, rs2: usize },
+    Rem { rd: usize, rs1: usize, rs2: usize },
+    Remu { rd: usize, rs1: usize, rs2: usize },
+
+    // RV64M
+    Mulw { rd: usize, rs1: usize, rs2: usize },
+    Divw { rd: usize, rs1: usize, rs2: usize },
+    Divuw { rd: usize, rs1: usize, rs2: usize },
+    Remw { rd: usize, rs1: usize, rs2: usize },
+    Remuw { rd: usize, rs1: usize, rs2: usize },
+
+    // Zba
+    Sh1add { rd: usize, rs1: usize, rs2: usize },
+    Sh2add { rd: usize, rs1: usize, rs2: usize },
+    Sh3add { rd: usize, rs1: usize, rs2: usize },
+    AddUw { rd: usize, rs1: usize, rs2: usize },
+    Sh1addUw { rd: usize, rs1: usize, rs2: usize },
+    Sh2addUw { rd: usize, rs1: usize, rs2: usize },
+    Sh3addUw { rd: usize, rs1: usize, rs2: usize },
+    SlliUw { rd: usize, rs1: usize, shamt: u32 },
+
+    // Zbb. `rev8` and `zext.h` are encoded differently for each XLEN, and
+    // `xlen` records which one the encoding is for.
+    Andn { rd: usize, rs1: usize, rs2: usize },
+    Orn { rd: usize, rs1: usize, rs2: usize },
+    Xnor { rd: usize, rs1: usize, rs2: usize },
+    Clz { rd: usize, rs1: usize },
+    Ctz { rd: usize, rs1: usize },
+    Cpop { rd: usize, rs1: usize },
+    Clzw { rd: usize, rs1: usize },
+    Ctzw { rd: usize, rs1: usize },
+    Cpopw { rd: usize, rs1: usize },
+    Max { rd: usize, rs1: usize, rs2: usize },
+    Maxu { rd: usize, rs1: usize, rs2: usize },
+    Min { rd: usize, rs1: usize, rs2: usize },
+    Minu { rd: usize, rs1: usize, rs2: usize },
+    SextB { rd: usize, rs1: usize },
+    SextH { rd: usize, rs1: usize },
+    ZextH { rd: usize, rs1: usize, xlen: u32 },
+    Rol { rd: usize, rs1: usize, rs2: usize },
+    Ror { rd: usize, rs1: usize, rs2: usize },
+    Rori { rd: usize, rs1: usize, shamt: u32 },
+    Rolw { rd: usize, rs1: usize, rs2: usize },
+    Rorw { rd: usize, rs1: usize, rs2: usize },
+    Roriw { rd: usize, rs1: usize, shamt: u32 },
+    OrcB { rd: usize, rs1: usize },
+    Rev8 { rd: usize, rs1: usize, xlen: u32 },
+
+    // Zbc
+    Clmul { rd: usize, rs1: usize, rs2: usize },
+    Clmulh { rd: usize, rs1: usize, rs2: usize },
+    Clmulr { rd: usize, rs1: usize, rs2: usize },
+
+    // Zbs
+    Bclr { rd: usize, rs1: usize, rs2: usize },
+    Bclri { rd: usize, rs1: usize, shamt: u32 },
+    Bext { rd: usize, rs1: usize, rs2: usize },
+    Bexti { rd: usize, rs1: usize, shamt: u32 },
+    Binv { rd: usize, rs1: usize, rs2: usize },
+    Binvi { rd: usize, rs1: usize, shamt: u32 },
+    Bset { rd: usize, rs1: usize, rs2: usize },
+    Bseti { rd: usize, rs1: usize, shamt: u32 },
+
+    // RV32A
+    LrW { rd: usize, rs1: usize, aq: bool, rl: bool },
+    ScW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoswapW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoaddW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoxorW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoandW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmoorW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmominW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmomaxW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmominuW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+    AmomaxuW { rd: usize, rs1: usize, rs2: usize, aq: bool, rl: bool },
+
+    // RV32F and RV32D. `rm` is the raw rounding-mode field, where 7 selects `frm`.
+    Fload { rd: usize, rs1: usize, imm: i32, fmt: Precision },
+    Fstore { rs1: usize, rs2: usize, imm: i32, fmt: Precision },
+    Fmadd { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: u8, fmt: Precision },
+    Fmsub { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: u8, fmt: Precision },
+    Fnmsub { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: u8, fmt: Precision },
+    Fnmadd { rd: usize, rs1: usize, rs2: usize, rs3: usize, rm: u8, fmt: Precision },
+    Fadd { rd: usize, rs1: usize, rs2: usize, rm: u8, fmt: Precision },
+    Fsub { rd: usize, rs1: usize, rs2: usize, rm: u8, fmt: Precision },
+    Fmul { rd: usize, rs1: usize, rs2: usize, rm: u8, fmt: Precision },
+    Fdiv { rd: usize, rs1: usize, rs2: usize, rm: u8, fmt: Precision },
+    Fsqrt { rd: usize, rs1: usize, rm: u8, fmt: Precision },
+    Fsgnj { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    Fsgnjn { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    Fsgnjx { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    Fmin { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    Fmax { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    FcvtToInt { rd: usize, rs1: usize, rm: u8, fmt: Precision, int: IntFormat },
+    FcvtFromInt { rd: usize, rs1: usize, rm: u8, fmt: Precision, int: IntFormat },
+    FmvToInt { rd: usize, rs1: usize, fmt: Precision },
+    FmvFromInt { rd: usize, rs1: usize, fmt: Precision },
+    Feq { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    Flt { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    Fle { rd: usize, rs1: usize, rs2: usize, fmt: Precision },
+    Fclass { rd: usize, rs1: usize, fmt: Precision },
+    FcvtFloat { rd: usize, rs1: usize, rm: u8, fmt: Precision, from: Precision },
+
+    // RVV 1.0. `vs2` is the first source, as in the encoding, and `masked`
+    // instructions only touch the elements whose bit in v0 is set.
+    Vsetvli { rd: usize, rs1: usize, vtypei: u32 },
+    Vsetivli { rd: usize, uimm: u32, vtypei: u32 },
+    Vsetvl { rd: usize, rs1: usize, rs2: usize },
+    Vload { vd: usize, rs1: usize, eew: u32, mode: VectorAddressing, masked: bool },
+    Vstore { vs3: usize, rs1: usize, eew: u32, mode: VectorAddressing, masked: bool },
+    Varith { op: VectorOp, vd: usize, vs2: usize, src: VectorOperand, masked: bool },
+    Vmerge { vd: usize, vs2: usize, src: VectorOperand },
+    Vmv { vd: usize, src: VectorOperand },
+    Vreduce { op: VectorReduction, vd: usize, vs2: usize, vs1: usize, masked: bool },
+    Vmask { op: MaskOp, vd: usize, vs2: usize, vs1: usize },
+    VmvXS { rd: usize, vs2: usize },
+    VmvSX { vd: usize, rs1: usize },
+    Vcpop { rd: usize, vs2: usize, masked: bool },
+    Vfirst { rd: usize, vs2: usize, masked: bool },
+    Viota { vd: usize, vs2: usize, masked: bool },
+    Vid { vd: usize, masked: bool },
+    VmvNr { vd: usize, vs2: usize, nregs: u32 },
+}
+
+/// The operand format of a floating-point instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    S,
+    D,
+    Q,
+}
+
+impl Precision {
+    pub fn width(self) -> u32 {
+        match self {
+            Precision::S => 32,
+            Precision::D => 64,
+            Precision::Q => 128,
+        }
+    }
+}
+
+/// The integer side of an `fcvt` between integer and floating-point values.
+/// The 64-bit `L` and `Lu` need RV64.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntFormat {
+    W,
+    Wu,
+    L,
+    Lu,
+}
+
+impl IntFormat {
+    pub fn is_signed(self) -> bool { self == IntFormat::W || self == IntFormat::L }
+
+    pub fn width(self) -> u32 {
+        match self {
+            IntFormat::W | IntFormat::Wu => 32,
+            IntFormat::L | IntFormat::Lu => 64,
+        }
+    }
+}
+
+/// How a vector load or store walks memory.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorAddressing {
+    UnitStride,
+    /// A byte stride held in an integer register.
+    Strided(usize),
+    /// Byte offsets held in a vector register, accessed in element order
+    /// if `ordered`.
+    Indexed { vs2: usize, ordered: bool },
+    /// `vlm.v` and `vsm.v`, which move the bytes of a mask register.
+    Mask,
+    /// `vl<n>re<eew>.v` and `vs<n>r.v`, which move whole registers
+    /// whatever `vtype` and `vl` are.
+    WholeRegister(u32),
+}
+
+/// The second source of a vector arithmetic instruction: a vector register
+/// for the `.vv` forms, an integer register for `.vx` and a 5-bit immediate
+/// for `.vi`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorOperand {
+    Vector(usize),
+    Scalar(usize),
+    Immediate(i32),
+}
+
+/// Element-wise vector operations. The `Ms*` compares write a mask, and
+/// `Macc` through `Nmsub` also read the destination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorOp {
+    Add, Sub, Rsub, Minu, Min, Maxu, Max, And, Or, Xor, Sll, Srl, Sra,
+    Mseq, Msne, Msltu, Mslt, Msleu, Msle, Msgtu, Msgt,
+    Mul, Mulh, Mulhu, Mulhsu, Divu, Div, Remu, Rem, Macc, Nmsac, Madd, Nmsub,
+}
+
+impl VectorOp {
+    /// Whether the result is a mask bit rather than an element.
+    pub fn writes_mask(self) -> bool {
+        use self::VectorOp::*;
+        matches!(self, Mseq | Msne | Msltu | Mslt | Msleu | Msle | Msgtu | Msgt)
+    }
+
+    /// The multiply-adds, whose assembly lists the multiplier before `vs2`.
+    pub fn is_multiply_add(self) -> bool {
+        use self::VectorOp::*;
+        matches!(self, Macc | Nmsac | Madd | Nmsub)
+    }
+}
+
+/// Reductions of `vs2` into element 0 of `vd`, starting from element 0 of `vs1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VectorReduction {
+    Sum, And, Or, Xor, Minu, Min, Maxu, Max,
+}
+
+/// Bitwise operations between two mask registers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MaskOp {
+    Andn, And, Or, Xor, Orn, Nand, Nor, Xnor,
+}
+
+pub const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2",
+    "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
+    "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
+    "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6",
+];
+
+pub const FP_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7",
+    "fs0", "fs1", "fa0", "fa1", "fa2", "fa3", "fa4", "fa5",
+    "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7",
+    "fs8", "fs9", "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+/// An AMO mnemonic with its `.aq`, `.rl` or `.aqrl` ordering suffix.
+macro_rules! ordered {
+    ($name:literal, $aq:expr, $rl:expr) => {
+        match ($aq, $rl) {
+            (false, false) => $name,
+            (true, false) => concat!($name, ".aq"),
+            (false, true) => concat!($name, ".rl"),
+            (true, true) => concat!($name, ".aqrl"),
+        }
+    }
+}
+
+/// A floating-point mnemonic with the suffix of its operand format, and an
+/// optional tail for conversions such as `fcvt.s.w`.
+macro_rules! precision {
+    ($name:literal, $fmt:expr) => { precision!($name, $fmt, "") };
+    ($name:literal, $fmt:expr, $tail:literal) => {
+        match $fmt {
+            Precision::S => concat!($name, ".s", $tail),
+            Precision::D => concat!($name, ".d", $tail),
+            Precision::Q => concat!($name, ".q", $tail),
+        }
+    }
+}
+
+/// A vector arithmetic mnemonic with the suffix of its second source.
+macro_rules! vector_form {
+    ($name:literal, $src:expr) => {
+        match $src {
+            VectorOperand::Vector(_) => concat!($name, ".vv"),
+            VectorOperand::Scalar(_) => concat!($name, ".vx"),
+            VectorOperand::Immediate(_) => concat!($name, ".vi"),
+        }
+    }
+}
+
+/// A vector load or store mnemonic with its element width, as in `vle32.v`.
+macro_rules! element_width {
+    ($name:literal, $eew:expr) => {
+        match $eew {
+            8 => concat!($name, "8.v"),
+            16 => concat!($name, "16.v"),
+            32 => concat!($name, "32.v"),
+            _ => concat!($name, "64.v"),
+        }
+    }
+}
+
+fn reg(index: usize) -> &'static str { ABI_NAMES[index] }
+
+/// A CSR by name, as objdump shows it, or by address if it has none.
+fn csr_name(address: u16) -> String {
+    ::csr::name(address).map_or_else(|| format!("0x{:x}", address), str::to_string)
+}
+
+fn vreg(index: usize) -> String { format!("v{}", index) }
+
+/// The trailing operand of a masked vector instruction.
+fn mask(masked: bool) -> &'static str { if masked { ",v0.t" } else { "" } }
+
+fn vector_source(src: VectorOperand) -> String {
+    match src {
+        VectorOperand::Vector(vs1) => vreg(vs1),
+        VectorOperand::Scalar(rs1) => reg(rs1).into(),
+        VectorOperand::Immediate(imm) => imm.to_string(),
+    }
+}
+
+/// The operand a vector load or store adds after its base address.
+fn addressing(mode: VectorAddressing) -> String {
+    match mode {
+        VectorAddressing::Strided(rs2) => format!(",{}", reg(rs2)),
+        VectorAddressing::Indexed { vs2, .. } => format!(",{}", vreg(vs2)),
+        _ => String::new(),
+    }
+}
+
+/// A `vtype` immediate as `e32,m1,ta,ma`, or as a number if it is reserved.
+fn vtype_name(vtypei: u32) -> String {
+    let lmul = match vtypei & 0x7 {
+        0 => "m1",
+        1 => "m2",
+        2 => "m4",
+        3 => "m8",
+        5 => "mf8",
+        6 => "mf4",
+        7 => "mf2",
+        _ => return vtypei.to_string(),
+    };
+    if vtypei >> 8 != 0 || (vtypei >> 3) & 0x7 > 3 { return vtypei.to_string(); }
+    let tail = if vtypei & 0x40 != 0 { "ta" } else { "tu" };
+    let mask = if vtypei & 0x80 != 0 { "ma" } else { "mu" };
+    format!("e{},{},{},{}", 8 << ((vtypei >> 3) & 0x7), lmul, tail, mask)
+}
+
+fn freg(index: usize) -> &'static str { FP_ABI_NAMES[index] }
+
+/// A static rounding mode as an extra operand; the dynamic mode is implied.
+fn rounding(rm: u8) -> &'static str {
+    match rm {
+        0 => ",rne",
+        1 => ",rtz",
+        2 => ",rdn",
+        3 => ",rup",
+        4 => ",rmm",
+        _ => "",
+    }
+}
+
+/// A fence's predecessor or successor set, as `iorw` letters or `0` if empty.
+fn fence_set(set: u8) -> String {
+    if set == 0 { return "0".into(); }
+    "iorw".chars().enumerate().filter(|&(i, _)| set & (0x8 >> i) != 0).map(|(_, c)| c).collect()
+}
+
+fn target(pc: Option<u64>, imm: i32) -> String {
+    match pc {
+        Some(pc) => format!("0x{:x}", pc.wrapping_add(imm as i64 as u64)),
+        None if imm < 0 => format!(".-{}", -(imm as i64)),
+        None => format!(".+{}", imm),
+    }
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        use self::Instruction::*;
+        match *self {
+            Lui { .. } => "lui",
+            Auipc { .. } => "auipc",
+            Jal { .. } => "jal",
+            Jalr { .. } => "jalr",
+            Beq { .. } => "beq",
+            Bne { .. } => "bne",
+            Blt { .. } => "blt",
+            Bge { .. } => "bge",
+            Bltu { .. } => "bltu",
+            Bgeu { .. } => "bgeu",
+            Lb { .. } => "lb",
+            Lh { .. } => "lh",
+            Lw { .. } => "lw",
+            Lbu { .. } => "lbu",
+            Lhu { .. } => "lhu",
+            Sb { .. } => "sb",
+            Sh { .. } => "sh",
+            Sw { .. } => "sw",
+            Addi { .. } => "addi",
+            Slti { .. } => "slti",
+            Sltiu { .. } => "sltiu",
+            Xori { .. } => "xori",
+            Ori { .. } => "ori",
+            Andi { .. } => "andi",
+            Slli { .. } => "slli",
+            Srli { .. } => "srli",
+            Srai { .. } => "srai",
+            Add { .. } => "add",
+            Sub { .. } => "sub",
+            Sll { .. } => "sll",
+            Slt { .. } => "slt",
+            Sltu { .. } => "sltu",
+            Xor { .. } => "xor",
+            Srl { .. } => "srl",
+            Sra { .. } => "sra",
+            Or { .. } => "or",
+            And { .. } => "and",
+            Fence { .. } => "fence",
+            FenceTso => "fence.tso",
+            Ecall => "ecall",
+            Ebreak => "ebreak",
+            Lwu { .. } => "lwu",
+            Ld { .. } => "ld",
+            Sd { .. } => "sd",
+            Addiw { .. } => "addiw",
+            Slliw { .. } => "slliw",
+            Srliw { .. } => "srliw",
+            Sraiw { .. } => "sraiw",
+            Addw { .. } => "addw",
+            Subw { .. } => "subw",
+            Sllw { .. } => "sllw",
+            Srlw { .. } => "srlw",
+            Sraw { .. } => "sraw",
+            Mret => "mret",
+            Sret => "sret",
+            Wfi => "wfi",
+            SfenceVma { .. } => "sfence.vma",
+            FenceI => "fence.i",
+            Csrrw { .. } => "csrrw",
+            Csrrs { .. } => "csrrs",
+            Csrrc { .. } => "csrrc",
+            Csrrwi { .. } => "csrrwi",
+            Csrrsi { .. } => "csrrsi",
+            Csrrci { .. } => "csrrci",
+            Mul { .. } => "mul",
+            Mulh { .. } => "mulh",
+            Mulhsu { .. } => "mulhsu",
+            Mulhu { .. } => "mulhu",
+            Div { .. } => "div",
+            Divu { .. } => "divu",
+            Rem { .. } => "rem",
+            Remu { .. } => "remu",
+            Mulw { .. } => "mulw",
+            Divw { .. } => "divw",
+            Divuw { .. } => "divuw",
+            Remw { .. } => "remw",
+            Remuw { .. } => "remuw",
+            Sh1add { .. } => "sh1add",
+            Sh2add { .. } => "sh2add",
+            Sh3add { .. } => "sh3add",
+            AddUw { .. } => "add.uw",
+            Sh1addUw { .. } => "sh1add.uw",
+            Sh2addUw { .. } => "sh2add.uw",
+            Sh3addUw { .. } => "sh3add.uw",
+            SlliUw { .. } => "slli.uw",
+            Andn { .. } => "andn",
+            Orn { .. } => "orn",
+            Xnor { .. } => "xnor",
+            Clz { .. } => "clz",
+            Ctz { .. } => "ctz",
+            Cpop { .. } => "cpop",
+            Clzw { .. } => "clzw",
+            Ctzw { .. } => "ctzw",
+            Cpopw { .. } => "cpopw",
+            Max { .. } => "max",
+            Maxu { .. } => "maxu",
+            Min { .. } => "min",
+            Minu { .. } => "minu",
+            SextB { .. } => "sext.b",
+            SextH { .. } => "sext.h",
+            ZextH { .. } => "zext.h",
+            Rol { .. } => "rol",
+            Ror { .. } => "ror",
+            Rori { .. } => "rori",
+            Rolw { .. } => "rolw",
+            Rorw { .. } => "rorw",
+            Roriw { .. } => "roriw",
+            OrcB { .. } => "orc.b",
+            Rev8 { .. } => "rev8",
+            Clmul { .. } => "clmul",
+            Clmulh { .. } => "clmulh",
+            Clmulr { .. } => "clmulr",
+            Bclr { .. } => "bclr",
+            Bclri { .. } => "bclri",
+            Bext { .. } => "bext",
+            Bexti { .. } => "bexti",
+            Binv { .. } => "binv",
+            Binvi { .. } => "binvi",
+            Bset { .. } => "bset",
+            Bseti { .. } => "bseti",
+            LrW { aq, rl, .. } => ordered!("lr.w", aq, rl),
+            ScW { aq, rl, .. } => ordered!("sc.w", aq, rl),
+            AmoswapW { aq, rl, .. } => ordered!("amoswap.w", aq, rl),
+            AmoaddW { aq, rl, .. } => ordered!("amoadd.w", aq, rl),
+            AmoxorW { aq, rl, .. } => ordered!("amoxor.w", aq, rl),
+            AmoandW { aq, rl, .. } => ordered!("amoand.w", aq, rl),
+            AmoorW { aq, rl, .. } => ordered!("amoor.w", aq, rl),
+            AmominW { aq, rl, .. } => ordered!("amomin.w", aq, rl),
+            AmomaxW { aq, rl, .. } => ordered!("amomax.w", aq, rl),
+            AmominuW { aq, rl, .. } => ordered!("amominu.w", aq, rl),
+            AmomaxuW { aq, rl, .. } => ordered!("amomaxu.w", aq, rl),
+            Fload { fmt: Precision::S, .. } => "flw",
+            Fload { fmt: Precision::D, .. } => "fld",
+            Fload { fmt: Precision::Q, .. } => "flq",
+            Fstore { fmt: Precision::S, .. } => "fsw",
+            Fstore { fmt: Precision::D, .. } => "fsd",
+            Fstore { fmt: Precision::Q, .. } => "fsq",
+            Fmadd { fmt, .. } => precision!("fmadd", fmt),
+            Fmsub { fmt, .. } => precision!("fmsub", fmt),
+            Fnmsub { fmt, .. } => precision!("fnmsub", fmt),
+            Fnmadd { fmt, .. } => precision!("fnmadd", fmt),
+            Fadd { fmt, .. } => precision!("fadd", fmt),
+            Fsub { fmt, .. } => precision!("fsub", fmt),
+            Fmul { fmt, .. } => precision!("fmul", fmt),
+            Fdiv { fmt, .. } => precision!("fdiv", fmt),
+            Fsqrt { fmt, .. } => precision!("fsqrt", fmt),
+            Fsgnj { fmt, .. } => precision!("fsgnj", fmt),
+            Fsgnjn { fmt, .. } => precision!("fsgnjn", fmt),
+            Fsgnjx { fmt, .. } => precision!("fsgnjx", fmt),
+            Fmin { fmt, .. } => precision!("fmin", fmt),
+            Fmax { fmt, .. } => precision!("fmax", fmt),
+            FcvtToInt { fmt, int: IntFormat::W, .. } => precision!("fcvt.w", fmt),
+            FcvtToInt { fmt, int: IntFormat::Wu, .. } => precision!("fcvt.wu", fmt),
+            FcvtToInt { fmt, int: IntFormat::L, .. } => precision!("fcvt.l", fmt),
+            FcvtToInt { fmt, int: IntFormat::Lu, .. } => precision!("fcvt.lu", fmt),
+            FcvtFromInt { fmt, int: IntFormat::W, .. } => precision!("fcvt", fmt, ".w"),
+            FcvtFromInt { fmt, int: IntFormat::Wu, .. } => precision!("fcvt", fmt, ".wu"),
+            FcvtFromInt { fmt, int: IntFormat::L, .. } => precision!("fcvt", fmt, ".l"),
+            FcvtFromInt { fmt, int: IntFormat::Lu, .. } => precision!("fcvt", fmt, ".lu"),
+            FmvToInt { fmt: Precision::S, .. } => "fmv.x.w",
+            FmvToInt { fmt: Precision::D, .. } => "fmv.x.d",
+            FmvToInt { fmt: Precision::Q, .. } => "fmv.x.q",
+            FmvFromInt { fmt: Precision::S, .. } => "fmv.w.x",
+            FmvFromInt { fmt: Precision::D, .. } => "fmv.d.x",
+            FmvFromInt { fmt: Precision::Q, .. } => "fmv.q.x",
+            Feq { fmt, .. } => precision!("feq", fmt),
+            Flt { fmt, .. } => precision!("flt", fmt),
+            Fle { fmt, .. } => precision!("fle", fmt),
+            Fclass { fmt, .. } => precision!("fclass", fmt),
+            FcvtFloat { fmt, from: Precision::S, .. } => precision!("fcvt", fmt, ".s"),
+            FcvtFloat { fmt, from: Precision::D, .. } => precision!("fcvt", fmt, ".d"),
+            FcvtFloat { fmt, from: Precision::Q, .. } => precision!("fcvt", fmt, ".q"),
+            Vsetvli { .. } => "vsetvli",
+            Vsetivli { .. } => "vsetivli",
+            Vsetvl { .. } => "vsetvl",
+            Vload { eew, mode, .. } => {
+                match mode {
+                    VectorAddressing::UnitStride => element_width!("vle", eew),
+                    VectorAddressing::Strided(_) => element_width!("vlse", eew),
+                    VectorAddressing::Indexed { ordered: false, .. } => element_width!("vluxei", eew),
+                    VectorAddressing::Indexed { ordered: true, .. } => element_width!("vloxei", eew),
+                    VectorAddressing::Mask => "vlm.v",
+                    VectorAddressing::WholeRegister(1) => element_width!("vl1re", eew),
+                    VectorAddressing::WholeRegister(2) => element_width!("vl2re", eew),
+                    VectorAddressing::WholeRegister(4) => element_width!("vl4re", eew),
+                    VectorAddressing::WholeRegister(_) => element_width!("vl8re", eew),
+                }
+            }
+            Vstore { eew, mode, .. } => {
+                match mode {
+                    VectorAddressing::UnitStride => element_width!("vse", eew),
+                    VectorAddressing::Strided(_) => element_width!("vsse", eew),
+                    VectorAddressing::Indexed { ordered: false, .. } => element_width!("vsuxei", eew),
+                    VectorAddressing::Indexed { ordered: true, .. } => element_width!("vsoxei", eew),
+                    VectorAddressing::Mask => "vsm.v",
+                    VectorAddressing::WholeRegister(1) => "vs1r.v",
+                    VectorAddressing::WholeRegister(2) => "vs2r.v",
+                    VectorAddressing::WholeRegister(4) => "vs4r.v",
+                    VectorAddressing::WholeRegister(_) => "vs8r.v",
+                }
+            }
+            Varith { op, src, .. } => {
+                match op {
+                    VectorOp::Add => vector_form!("vadd", src),
+                    VectorOp::Sub => vector_form!("vsub", src),
+                    VectorOp::Rsub => vector_form!("vrsub", src),
+                    VectorOp::Minu => vector_form!("vminu", src),
+                    VectorOp::Min => vector_form!("vmin", src),
+                    VectorOp::Maxu => vector_form!("vmaxu", src),
+                    VectorOp::Max => vector_form!("vmax", src),
+                    VectorOp::And => vector_form!("vand", src),
+                    VectorOp::Or => vector_form!("vor", src),
+                    VectorOp::Xor => vector_form!("vxor", src),
+                    VectorOp::Sll => vector_form!("vsll", src),
+                    VectorOp::Srl => vector_form!("vsrl", src),
+                    VectorOp::Sra => vector_form!("vsra", src),
+                    VectorOp::Mseq => vector_form!("vmseq", src),
+                    VectorOp::Msne => vector_form!("vmsne", src),
+                    VectorOp::Msltu => vector_form!("vmsltu", src),
+                    VectorOp::Mslt => vector_form!("vmslt", src),
+                    VectorOp::Msleu => vector_form!("vmsleu", src),
+                    VectorOp::Msle => vector_form!("vmsle", src),
+                    VectorOp::Msgtu => vector_form!("vmsgtu", src),
+                    VectorOp::Msgt => vector_form!("vmsgt", src),
+                    VectorOp::Mul => vector_form!("vmul", src),
+                    VectorOp::Mulh => vector_form!("vmulh", src),
+                    VectorOp::Mulhu => vector_form!("vmulhu", src),
+                    VectorOp::Mulhsu => vector_form!("vmulhsu", src),
+                    VectorOp::Divu => vector_form!("vdivu", src),
+                    VectorOp::Div => vector_form!("vdiv", src),
+                    VectorOp::Remu => vector_form!("vremu", src),
+                    VectorOp::Rem => vector_form!("vrem", src),
+                    VectorOp::Macc => vector_form!("vmacc", src),
+                    VectorOp::Nmsac => vector_form!("vnmsac", src),
+                    VectorOp::Madd => vector_form!("vmadd", src),
+                    VectorOp::Nmsub => vector_form!("vnmsub", src),
+                }
+            }
+            Vmerge { src: VectorOperand::Vector(_), .. } => "vmerge.vvm",
+            Vmerge { src: VectorOperand::Scalar(_), .. } => "vmerge.vxm",
+            Vmerge { src: VectorOperand::Immediate(_), .. } => "vmerge.vim",
+            Vmv { src: VectorOperand::Vector(_), .. } => "vmv.v.v",
+            Vmv { src: VectorOperand::Scalar(_), .. } => "vmv.v.x",
+            Vmv { src: VectorOperand::Immediate(_), .. } => "vmv.v.i",
+            Vreduce { op, .. } => {
+                match op {
+                    VectorReduction::Sum => "vredsum.vs",
+                    VectorReduction::And => "vredand.vs",
+                    VectorReduction::Or => "vredor.vs",
+                    VectorReduction::Xor => "vredxor.vs",
+                    VectorReduction::Minu => "vredminu.vs",
+                    VectorReduction::Min => "vredmin.vs",
+                    VectorReduction::Maxu => "vredmaxu.vs",
+                    VectorReduction::Max => "vredmax.vs",
+                }
+            }
+            Vmask { op, .. } => {
+                match op {
+                    MaskOp::Andn => "vmandn.mm",
+                    MaskOp::And => "vmand.mm",
+                    MaskOp::Or => "vmor.mm",
+                    MaskOp::Xor => "vmxor.mm",
+                    MaskOp::Orn => "vmorn.mm",
+                    MaskOp::Nand => "vmnand.mm",
+                    MaskOp::Nor => "vmnor.mm",
+                    MaskOp::Xnor => "vmxnor.mm",
+                }
+            }
+            VmvXS { .. } => "vmv.x.s",
+            VmvSX { .. } => "vmv.s.x",
+            Vcpop { .. } => "vcpop.m",
+            Vfirst { .. } => "vfirst.m",
+            Viota { .. } => "viota.m",
+            Vid { .. } => "vid.v",
+            VmvNr { nregs: 1, .. } => "vmv1r.v",
+            VmvNr { nregs: 2, .. } => "vmv2r.v",
+            VmvNr { nregs: 4, .. } => "vmv4r.v",
+            VmvNr { .. } => "vmv8r.v",
+        }
+    }
+
+    /// The operand list with ABI register names. Branch and jump targets are
+    /// printed as absolute addresses when the instruction's `pc` is known.
+    pub fn operands(&self, pc: Option<u64>) -> String {
+        use self::Instruction::*;
+        match *self {
+            Lui { rd, imm } | Auipc { rd, imm } => {
+                format!("{},0x{:x}", reg(rd), (imm as u32) >> 12)
+            }
+            Jal { rd, imm } => format!("{},{}", reg(rd), target(pc, imm)),
+            Beq { rs1, rs2, imm } | Bne { rs1, rs2, imm } | Blt { rs1, rs2, imm } |
+            Bge { rs1, rs2, imm } | Bltu { rs1, rs2, imm } | Bgeu { rs1, rs2, imm } => {
+                format!("{},{},{}", reg(rs1), reg(rs2), target(pc, imm))
+            }
+            Jalr { rd, rs1, imm } | Lb { rd, rs1, imm } | Lh { rd, rs1, imm } |
+            Lw { rd, rs1, imm } | Lbu { rd, rs1, imm } | Lhu { rd, rs1, imm } |
+            Lwu { rd, rs1, imm } | Ld { rd, rs1, imm } => {
+                format!("{},{}({})", reg(rd), imm, reg(rs1))
+            }
+            Sb { rs1, rs2, imm } | Sh { rs1, rs2, imm } | Sw { rs1, rs2, imm } | Sd { rs1, rs2, imm } => {
+                format!("{},{}({})", reg(rs2), imm, reg(rs1))
+            }
+            Addi { rd, rs1, imm } | Slti { rd, rs1, imm } | Sltiu { rd, rs1, imm } |
+            Xori { rd, rs1, imm } | Ori { rd, rs1, imm } | Andi { rd, rs1, imm } |
+            Addiw { rd, rs1, imm } => {
+                format!("{},{},{}", reg(rd), reg(rs1), imm)
+            }
+            Slli { rd, rs1, shamt } | Srli { rd, rs1, shamt } | Srai { rd, rs1, shamt } |
+            Slliw { rd, rs1, shamt } | Srliw { rd, rs1, shamt } | Sraiw { rd, rs1, shamt } |
+            SlliUw { rd, rs1, shamt } | Rori { rd, rs1, shamt } | Roriw { rd, rs1, shamt } |
+            Bclri { rd, rs1, shamt } | Bexti { rd, rs1, shamt } | Binvi { rd, rs1, shamt } |
+            Bseti { rd, rs1, shamt } => {
+                format!("{},{},{}", reg(rd), reg(rs1), shamt)
+            }
+            Clz { rd, rs1 } | Ctz { rd, rs1 } | Cpop { rd, rs1 } | Clzw { rd, rs1 } |
+            Ctzw { rd, rs1 } | Cpopw { rd, rs1 } | SextB { rd, rs1 } | SextH { rd, rs1 } |
+            ZextH { rd, rs1, .. } | OrcB { rd, rs1 } | Rev8 { rd, rs1, .. } => {
+                format!("{},{}", reg(rd), reg(rs1))
+            }
+            Add { rd, rs1, rs2 } | Sub { rd, rs1, rs2 } | Sll { rd, rs1, rs2 } |
+            Slt { rd, rs1, rs2 } | Sltu { rd, rs1, rs2 } | Xor { rd, rs1, rs2 } |
+            Srl { rd, rs1, rs2 } | Sra { rd, rs1, rs2 } | Or { rd, rs1, rs2 } |
+            And { rd, rs1, rs2 } | Mul { rd, rs1, rs2 } | Mulh { rd, rs1, rs2 } |
+            Mulhsu { rd, rs1, rs2 } | Mulhu { rd, rs1, rs2 } | Div { rd, rs1, rs2 } |
+            Divu { rd, rs1, rs2 } | Rem { rd, rs1, rs2 } | Remu { rd, rs1, rs2 } |
+            Addw { rd, rs1, rs2 } | Subw { rd, rs1, rs2 } | Sllw { rd, rs1, rs2 } |
+            Srlw { rd, rs1, rs2 } | Sraw { rd, rs1, rs2 } | Mulw { rd, rs1, rs2 } |
+            Divw { rd, rs1, rs2 } | Divuw { rd, rs1, rs2 } | Remw { rd, rs1, rs2 } |
+            Remuw { rd, rs1, rs2 } |
+            Sh1add { rd, rs1, rs2 } | Sh2add { rd, rs1, rs2 } | Sh3add { rd, rs1, rs2 } |
+            AddUw { rd, rs1, rs2 } | Sh1addUw { rd, rs1, rs2 } | Sh2addUw { rd, rs1, rs2 } |
+            Sh3addUw { rd, rs1, rs2 } | Andn { rd, rs1, rs2 } | Orn { rd, rs1, rs2 } |
+            Xnor { rd, rs1, rs2 } | Max { rd, rs1, rs2 } | Maxu { rd, rs1, rs2 } |
+            Min { rd, rs1, rs2 } | Minu { rd, rs1, rs2 } | Rol { rd, rs1, rs2 } |
+            Ror { rd, rs1, rs2 } | Rolw { rd, rs1, rs2 } | Rorw { rd, rs1, rs2 } |
+            Clmul { rd, rs1, rs2 } | Clmulh { rd, rs1, rs2 } | Clmulr { rd, rs1, rs2 } |
+            Bclr { rd, rs1, rs2 } | Bext { rd, rs1, rs2 } | Binv { rd, rs1, rs2 } |
+            Bset { rd, rs1, rs2 } => {
+                format!("{},{},{}", reg(rd), reg(rs1), reg(rs2))
+            }
+            LrW { rd, rs1, .. } => format!("{},({})", reg(rd), reg(rs1)),
+            ScW { rd, rs1, rs2, .. } | AmoswapW { rd, rs1, rs2, .. } | AmoaddW { rd, rs1, rs2, .. } |
+            AmoxorW { rd, rs1, rs2, .. } | AmoandW { rd, rs1, rs2, .. } | AmoorW { rd, rs1, rs2, .. } |
+            AmominW { rd, rs1, rs2, .. } | AmomaxW { rd, rs1, rs2, .. } | AmominuW { rd, rs1, rs2, .. } |
+            AmomaxuW { rd, rs1, rs2, .. } => {
+                format!("{},{},({})", reg(rd), reg(rs2), reg(rs1))
+            }
+            Fload { rd, rs1, imm, .. } => format!("{},{}({})", freg(rd), imm, reg(rs1)),
+            Fstore { rs1, rs2, imm, .. } => format!("{},{}({})", freg(rs2), imm, reg(rs1)),
+            Fmadd { rd, rs1, rs2, rs3, rm, .. } | Fmsub { rd, rs1, rs2, rs3, rm, .. } |
+            Fnmsub { rd, rs1, rs2, rs3, rm, .. } | Fnmadd { rd, rs1, rs2, rs3, rm, .. } => {
+                format!("{},{},{},{}{}", freg(rd), freg(rs1), freg(rs2), freg(rs3), rounding(rm))
+            }
+            Fadd { rd, rs1, rs2, rm, .. } | Fsub { rd, rs1, rs2, rm, .. } |
+            Fmul { rd, rs1, rs2, rm, .. } | Fdiv { rd, rs1, rs2, rm, .. } => {
+                format!("{},{},{}{}", freg(rd), freg(rs1), freg(rs2), rounding(rm))
+            }
+            Fsqrt { rd, rs1, rm, .. } => format!("{},{}{}", freg(rd), freg(rs1), rounding(rm)),
+            FcvtFloat { rd, rs1, rm, .. } => {
+                let rm = if self.is_exact() && rm == 0 { "" } else { rounding(rm) };
+                format!("{},{}{}", freg(rd), freg(rs1), rm)
+            }
+            Fsgnj { rd, rs1, rs2, .. } | Fsgnjn { rd, rs1, rs2, .. } | Fsgnjx { rd, rs1, rs2, .. } |
+            Fmin { rd, rs1, rs2, .. } | Fmax { rd, rs1, rs2, .. } => {
+                format!("{},{},{}", freg(rd), freg(rs1), freg(rs2))
+            }
+            FcvtToInt { rd, rs1, rm, .. } => format!("{},{}{}", reg(rd), freg(rs1), rounding(rm)),
+            FcvtFromInt { rd, rs1, rm, .. } => {
+                let rm = if self.is_exact() && rm == 0 { "" } else { rounding(rm) };
+                format!("{},{}{}", freg(rd), reg(rs1), rm)
+            }
+            FmvToInt { rd, rs1, .. } | Fclass { rd, rs1, .. } => format!("{},{}", reg(rd), freg(rs1)),
+            FmvFromInt { rd, rs1, .. } => format!("{},{}", freg(rd), reg(rs1)),
+            Feq { rd, rs1, rs2, .. } | Flt { rd, rs1, rs2, .. } | Fle { rd, rs1, rs2, .. } => {
+                format!("{},{},{}", reg(rd), freg(rs1), freg(rs2))
+            }
+            Fence { pred, succ } => format!("{},{}", fence_set(pred), fence_set(succ)),
+            FenceTso | FenceI | Ecall | Ebreak | Mret | Sret | Wfi => String::new(),
+            // Trailing x0 operands are left out.
+            SfenceVma { rs1: 0, rs2: 0 } => String::new(),
+            SfenceVma { rs1, rs2: 0 } => reg(rs1).to_string(),
+            SfenceVma { rs1, rs2 } => format!("{},{}", reg(rs1), reg(rs2)),
+            Csrrw { rd, rs1, csr } | Csrrs { rd, rs1, csr } | Csrrc { rd, rs1, csr } => {
+                format!("{},{},{}", reg(rd), csr_name(csr), reg(rs1))
+            }
+            Csrrwi { rd, uimm, csr } | Csrrsi { rd, uimm, csr } | Csrrci { rd, uimm, csr } => {
+                format!("{},{},{}", reg(rd), csr_name(csr), uimm)
+            }
+            Vsetvli { rd, rs1, vtypei } => format!("{},{},{}", reg(rd), reg(rs1), vtype_name(vtypei)),
+            Vsetivli { rd, uimm, vtypei } => format!("{},{},{}", reg(rd), uimm, vtype_name(vtypei)),
+            Vsetvl { rd, rs1, rs2 } => format!("{},{},{}", reg(rd), reg(rs1), reg(rs2)),
+            Vload { vd: v, rs1, mode, masked, .. } | Vstore { vs3: v, rs1, mode, masked, .. } => {
+                format!("{},({}){}{}", vreg(v), reg(rs1), addressing(mode), mask(masked))
+            }
+            Varith { op, vd, vs2, src, masked } if op.is_multiply_add() => {
+                format!("{},{},{}{}", vreg(vd), vector_source(src), vreg(vs2), mask(masked))
+            }
+            Varith { vd, vs2, src, masked, .. } => {
+                format!("{},{},{}{}", vreg(vd), vreg(vs2), vector_source(src), mask(masked))
+            }
+            Vmerge { vd, vs2, src } => format!("{},{},{},v0", vreg(vd), vreg(vs2), vector_source(src)),
+            Vmv { vd, src } => format!("{},{}", vreg(vd), vector_source(src)),
+            Vreduce { vd, vs2, vs1, masked, .. } => {
+                format!("{},{},{}{}", vreg(vd), vreg(vs2), vreg(vs1), mask(masked))
+            }
+            Vmask { vd, vs2, vs1, .. } => format!("{},{},{}", vreg(vd), vreg(vs2), vreg(vs1)),
+            VmvXS { rd, vs2 } => format!("{},{}", reg(rd), vreg(vs2)),
+            VmvSX { vd, rs1 } => format!("{},{}", vreg(vd), reg(rs1)),
+            Vcpop { rd, vs2, masked } | Vfirst { rd, vs2, masked } => {
+                format!("{},{}{}", reg(rd), vreg(vs2), mask(masked))
+            }
+            Viota { vd, vs2, masked } => format!("{},{}{}", vreg(vd), vreg(vs2), mask(masked)),
+            Vid { vd, masked } => format!("{}{}", vreg(vd), mask(masked)),
+            VmvNr { vd, vs2, .. } => format!("{},{}", vreg(vd), vreg(vs2)),
+        }
+    }
+
+    /// Conversions that never round, whose default `rne` rounding mode
+    /// is left out of the disassembly.
+    fn is_exact(&self) -> bool {
+        use self::Instruction::*;
+        match *self {
+            FcvtFromInt { fmt, int, .. } => fmt.width() >= 2 * int.width(),
+            FcvtFloat { fmt, from, .. } => fmt.width() > from.width(),
+            _ => false,
+        }
+    }
+
+    /// Every integer register the instruction reads or writes, padded
+    /// with x0. Floating-point register operands are not included.
+    pub fn integer_registers(&self) -> [usize; 3] {
+        use self::Instruction::*;
+        match *self {
+            Lui { rd, .. } | Auipc { rd, .. } | Jal { rd, .. } | Csrrwi { rd, .. } |
+            Csrrsi { rd, .. } | Csrrci { rd, .. } | FcvtToInt { rd, .. } | FmvToInt { rd, .. } |
+            Fclass { rd, .. } | Feq { rd, .. } | Flt { rd, .. } | Fle { rd, .. } => [rd, 0, 0],
+            Jalr { rd, rs1, .. } | Lb { rd, rs1, .. } | Lh { rd, rs1, .. } | Lw { rd, rs1, .. } |
+            Lbu { rd, rs1, .. } | Lhu { rd, rs1, .. } | Addi { rd, rs1, .. } | Slti { rd, rs1, .. } |
+            Sltiu { rd, rs1, .. } | Xori { rd, rs1, .. } | Ori { rd, rs1, .. } | Andi { rd, rs1, .. } |
+            Slli { rd, rs1, .. } | Srli { rd, rs1, .. } | Srai { rd, rs1, .. } | Csrrw { rd, rs1, .. } |
+            Csrrs { rd, rs1, .. } | Csrrc { rd, rs1, .. } | LrW { rd, rs1, .. } | Lwu { rd, rs1, .. } |
+            Ld { rd, rs1, .. } | Addiw { rd, rs1, .. } | Slliw { rd, rs1, .. } | Srliw { rd, rs1, .. } |
+            Sraiw { rd, rs1, .. } | SlliUw { rd, rs1, .. } | Clz { rd, rs1 } | Ctz { rd, rs1 } |
+            Cpop { rd, rs1 } | Clzw { rd, rs1 } | Ctzw { rd, rs1 } | Cpopw { rd, rs1 } |
+            SextB { rd, rs1 } | SextH { rd, rs1 } | ZextH { rd, rs1, .. } | Rori { rd, rs1, .. } |
+            Roriw { rd, rs1, .. } | OrcB { rd, rs1 } | Rev8 { rd, rs1, .. } | Bclri { rd, rs1, .. } |
+            Bexti { rd, rs1, .. } | Binvi { rd, rs1, .. } | Bseti { rd, rs1, .. } => [rd, rs1, 0],
+            Beq { rs1, rs2, .. } | Bne { rs1, rs2, .. } | Blt { rs1, rs2, .. } | Bge { rs1, rs2, .. } |
+            Bltu { rs1, rs2, .. } | Bgeu { rs1, rs2, .. } | Sb { rs1, rs2, .. } | Sh { rs1, rs2, .. } |
+            Sw { rs1, rs2, .. } | Sd { rs1, rs2, .. } => [rs1, rs2, 0],
+            Add { rd, rs1, rs2 } | Sub { rd, rs1, rs2 } | Sll { rd, rs1, rs2 } | Slt { rd, rs1, rs2 } |
+            Sltu { rd, rs1, rs2 } | Xor { rd, rs1, rs2 } | Srl { rd, rs1, rs2 } | Sra { rd, rs1, rs2 } |
+            Or { rd, rs1, rs2 } | And { rd, rs1, rs2 } | Mul { rd, rs1, rs2 } | Mulh { rd, rs1, rs2 } |
+            Mulhsu { rd, rs1, rs2 } | Mulhu { rd, rs1, rs2 } | Div { rd, rs1, rs2 } | Divu { rd, rs1, rs2 } |
+            Rem { rd, rs1, rs2 } | Remu { rd, rs1, rs2 } | Addw { rd, rs1, rs2 } | Subw { rd, rs1, rs2 } |
+            Sllw { rd, rs1, rs2 } | Srlw { rd, rs1, rs2 } | Sraw { rd, rs1, rs2 } | Mulw { rd, rs1, rs2 } |
+            Divw { rd, rs1, rs2 } | Divuw { rd, rs1, rs2 } | Remw { rd, rs1, rs2 } |
+            Remuw { rd, rs1, rs2 } |
+            Sh1add { rd, rs1, rs2 } | Sh2add { rd, rs1, rs2 } | Sh3add { rd, rs1, rs2 } |
+            AddUw { rd, rs1, rs2 } | Sh1addUw { rd, rs1, rs2 } | Sh2addUw { rd, rs1, rs2 } |
+            Sh3addUw { rd, rs1, rs2 } | Andn { rd, rs1, rs2 } | Orn { rd, rs1, rs2 } |
+            Xnor { rd, rs1, rs2 } | Max { rd, rs1, rs2 } | Maxu { rd, rs1, rs2 } |
+            Min { rd, rs1, rs2 } | Minu { rd, rs1, rs2 } | Rol { rd, rs1, rs2 } |
+            Ror { rd, rs1, rs2 } | Rolw { rd, rs1, rs2 } | Rorw { rd, rs1, rs2 } |
+            Clmul { rd, rs1, rs2 } | Clmulh { rd, rs1, rs2 } | Clmulr { rd, rs1, rs2 } |
+            Bclr { rd, rs1, rs2 } | Bext { rd, rs1, rs2 } | Binv { rd, rs1, rs2 } |
+            Bset { rd, rs1, rs2 } => [rd, rs1, rs2],
+            ScW { rd, rs1, rs2, .. } | AmoswapW { rd, rs1, rs2, .. } | AmoaddW { rd, rs1, rs2, .. } |
+            AmoxorW { rd, rs1, rs2, .. } | AmoandW { rd, rs1, rs2, .. } | AmoorW { rd, rs1, rs2, .. } |
+            AmominW { rd, rs1, rs2, .. } | AmomaxW { rd, rs1, rs2, .. } | AmominuW { rd, rs1, rs2, .. } |
+            AmomaxuW { rd, rs1, rs2, .. } => [rd, rs1, rs2],
+            Fload { rs1, .. } | Fstore { rs1, .. } | FcvtFromInt { rs1, .. } | FmvFromInt { rs1, .. } => [rs1, 0, 0],
+            SfenceVma { rs1, rs2 } => [rs1, rs2, 0],
+            Fence { .. } | FenceTso | FenceI | Ecall | Ebreak | Mret | Sret | Wfi | Fmadd { .. } | Fmsub { .. } | Fnmsub { .. } | Fnmadd { .. } |
+            Fadd { .. } | Fsub { .. } | Fmul { .. } | Fdiv { .. } | Fsqrt { .. } | Fsgnj { .. } |
+            Fsgnjn { .. } | Fsgnjx { .. } | Fmin { .. } | Fmax { .. } | FcvtFloat { .. } => [0, 0, 0],
+            Vsetvli { rd, rs1, .. } => [rd, rs1, 0],
+            Vsetivli { rd, .. } | VmvXS { rd, .. } | Vcpop { rd, .. } | Vfirst { rd, .. } => [rd, 0, 0],
+            Vsetvl { rd, rs1, rs2 } => [rd, rs1, rs2],
+            Vload { rs1, mode: VectorAddressing::Strided(rs2), .. } |
+            Vstore { rs1, mode: VectorAddressing::Strided(rs2), .. } => [rs1, rs2, 0],
+            Vload { rs1, .. } | Vstore { rs1, .. } | VmvSX { rs1, .. } |
+            Varith { src: VectorOperand::Scalar(rs1), .. } | Vmerge { src: VectorOperand::Scalar(rs1), .. } |
+            Vmv { src: VectorOperand::Scalar(rs1), .. } => [rs1, 0, 0],
+            Varith { .. } | Vmerge { .. } | Vmv { .. } | Vreduce { .. } | Vmask { .. } | Viota { .. } |
+            Vid { .. } | VmvNr { .. } => [0, 0, 0],
+        }
+    }
+
+    /// The address a pc-relative jump or branch at `pc` transfers to.
+    pub fn branch_target(&self, pc: u64) -> Option<u64> {
+        use self::Instruction::*;
+        match *self {
+            Jal { imm, .. } | Beq { imm, .. } | Bne { imm, .. } | Blt { imm, .. } |
+            Bge { imm, .. } | Bltu { imm, .. } | Bgeu { imm, .. } => Some(pc.wrapping_add(imm as i64 as u64)),
+            _ => None,
+        }
+    }
+
+    /// Like `to_string`, but with branch and jump targets resolved against `pc`.
+    pub fn display_at(&self, pc: u64) -> String {
+        let operands = self.operands(Some(pc));
+        if operands.is_empty() { self.mnemonic().into() } else { format!("{} {}", self.mnemonic(), operands) }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operands = self.operands(None);
+        if operands.is_empty() { write!(f, "{}", self.mnemonic()) } else { write!(f, "{} {}", self.mnemonic(), operands) }
+    }
+}
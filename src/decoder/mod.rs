@@ -4,7 +4,27 @@ use std::num::ParseIntError;
 use std::fmt::Write;
 use std::fs::File;
 use std::io::prelude::*;
+use std::fmt;
 
+mod instruction;
+mod compressed;
+mod vector;
+pub use self::compressed::decode_compressed;
+use self::vector::{decode_vector, decode_vector_memory};
+pub use self::instruction::{Instruction, Precision, IntFormat, ABI_NAMES, FP_ABI_NAMES};
+pub use self::instruction::{VectorAddressing, VectorOperand, VectorOp, VectorReduction, MaskOp};
+
+/// A word that does not encode any instruction the decoder knows about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecodeError {
+    pub word: u32,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:08x}", self.word)
+    }
+}
 
 // Decode an even length hex string into its constituent bytes
 // Code adapted from StackOverflow user `Sven Marnach`
@@ -38,10 +58,10 @@ pub fn get_f7(bytes: &[u8]) -> u8 { bytes[3] >> 1 }
 
 pub fn get_bits(first_byte: u8) -> i32 {
     match first_byte {
-        b@_ if b & 0x03 <  0x03 => 16, // 16 bit instruction
-        b@_ if b & 0x1F <  0x1F => 32, // 32 bit instruction
-        b@_ if b & 0x3F == 0x1F => 48, // 48 bit instruction
-        b@_ if b & 0x7F == 0x3F => 64, // 64 bit instruction
+        b if b & 0x03 <  0x03 => 16, // 16 bit instruction
+        b if b & 0x1F <  0x1F => 32, // 32 bit instruction
+        b if b & 0x3F == 0x1F => 48, // 48 bit instruction
+        b if b & 0x7F == 0x3F => 64, // 64 bit instruction
         _ => -1, // >= 80 bit instruction
     }
 }
@@ -57,7 +77,7 @@ pub fn decode_sb_immediate(bytes: &[u8]) -> i32 {
 }
 
 pub fn decode_u_type_immediate(bytes: &[u8]) -> i32 {
-    (((((bytes[1] as u32) >> 4) + ((bytes[2] as u32) << 4) + ((bytes[3] as u32) << 12))) << 12) as i32
+    ((((bytes[1] as u32) >> 4) + ((bytes[2] as u32) << 4) + ((bytes[3] as u32) << 12)) << 12) as i32
 }
 
 pub fn decode_uj_type_immediate(bytes: &[u8]) -> i32 {
@@ -77,12 +97,320 @@ pub fn decode_i_type_immediate(bytes: &[u8]) -> i32 {
         if bytes[3] >> 7 == 0x1 { 0xFF_FF_F0_00 } else { 0x0 }) as i32
 }
 
+/// The operand format in the low two bits of a floating-point funct7.
+fn precision(fmt: u8) -> Option<Precision> {
+    match fmt {
+        0x0 => Some(Precision::S),
+        0x1 => Some(Precision::D),
+        0x3 => Some(Precision::Q),
+        _ => None,
+    }
+}
+
+/// The integer format in the rs2 field of an `fcvt` to or from an integer.
+fn int_format(rs2: usize) -> IntFormat {
+    match rs2 {
+        0 => IntFormat::W,
+        1 => IntFormat::Wu,
+        2 => IntFormat::L,
+        _ => IntFormat::Lu,
+    }
+}
+
+/// A rounding-mode field, rejecting the two reserved encodings.
+fn rounding_mode(f3: u8) -> Option<u8> {
+    match f3 {
+        0x5 | 0x6 => None,
+        rm => Some(rm),
+    }
+}
+
+pub fn decode(word: u32) -> Result<Instruction, DecodeError> {
+    use self::Instruction::*;
+
+    let bytes = word.to_le_bytes();
+    let bytes = &bytes[..];
+
+    let opcode = get_opcode(bytes);
+    let rd     = get_rd(bytes) as usize;
+    let f3     = get_f3(bytes);
+    let rs1    = get_rs1(bytes) as usize;
+    let rs2    = get_rs2(bytes) as usize;
+    let f7     = get_f7(bytes);
+
+    let inst = match opcode {
+        0x37 => Lui { rd, imm: decode_u_type_immediate(bytes) },
+        0x17 => Auipc { rd, imm: decode_u_type_immediate(bytes) },
+        0x6F => Jal { rd, imm: decode_uj_type_immediate(bytes) },
+        0x67 if f3 == 0x0 => Jalr { rd, rs1, imm: decode_i_type_immediate(bytes) },
+        0x63 => {
+            let imm = decode_sb_immediate(bytes);
+            match f3 {
+                0x0 => Beq { rs1, rs2, imm },
+                0x1 => Bne { rs1, rs2, imm },
+                0x4 => Blt { rs1, rs2, imm },
+                0x5 => Bge { rs1, rs2, imm },
+                0x6 => Bltu { rs1, rs2, imm },
+                0x7 => Bgeu { rs1, rs2, imm },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x03 => {
+            let imm = decode_i_type_immediate(bytes);
+            match f3 {
+                0x0 => Lb { rd, rs1, imm },
+                0x1 => Lh { rd, rs1, imm },
+                0x2 => Lw { rd, rs1, imm },
+                0x3 => Ld { rd, rs1, imm },
+                0x4 => Lbu { rd, rs1, imm },
+                0x5 => Lhu { rd, rs1, imm },
+                0x6 => Lwu { rd, rs1, imm },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x23 => {
+            let imm = decode_s_type_immediate(bytes);
+            match f3 {
+                0x0 => Sb { rs1, rs2, imm },
+                0x1 => Sh { rs1, rs2, imm },
+                0x2 => Sw { rs1, rs2, imm },
+                0x3 => Sd { rs1, rs2, imm },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x13 => {
+            let imm = decode_i_type_immediate(bytes);
+            // Shift amounts take six bits, though only RV64 may set the top one.
+            let shamt = (word >> 20) & 0x3F;
+            match (f3, f7 >> 1) {
+                (0x0, _) => Addi { rd, rs1, imm },
+                (0x2, _) => Slti { rd, rs1, imm },
+                (0x3, _) => Sltiu { rd, rs1, imm },
+                (0x4, _) => Xori { rd, rs1, imm },
+                (0x6, _) => Ori { rd, rs1, imm },
+                (0x7, _) => Andi { rd, rs1, imm },
+                (0x1, 0x00) => Slli { rd, rs1, shamt },
+                (0x5, 0x00) => Srli { rd, rs1, shamt },
+                (0x5, 0x10) => Srai { rd, rs1, shamt },
+                (0x1, 0x18) if f7 == 0x30 => {
+                    match rs2 {
+                        0x0 => Clz { rd, rs1 },
+                        0x1 => Ctz { rd, rs1 },
+                        0x2 => Cpop { rd, rs1 },
+                        0x4 => SextB { rd, rs1 },
+                        0x5 => SextH { rd, rs1 },
+                        _ => return Err(DecodeError { word }),
+                    }
+                }
+                (0x5, 0x18) => Rori { rd, rs1, shamt },
+                (0x5, 0x0A) if word >> 20 == 0x287 => OrcB { rd, rs1 },
+                (0x5, 0x1A) if word >> 20 == 0x698 => Rev8 { rd, rs1, xlen: 32 },
+                (0x5, 0x1A) if word >> 20 == 0x6B8 => Rev8 { rd, rs1, xlen: 64 },
+                (0x1, 0x12) => Bclri { rd, rs1, shamt },
+                (0x5, 0x12) => Bexti { rd, rs1, shamt },
+                (0x1, 0x1A) => Binvi { rd, rs1, shamt },
+                (0x1, 0x0A) => Bseti { rd, rs1, shamt },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x1B => {
+            let shamt = rs2 as u32;
+            match (f3, f7) {
+                (0x0, _) => Addiw { rd, rs1, imm: decode_i_type_immediate(bytes) },
+                (0x1, 0x00) => Slliw { rd, rs1, shamt },
+                (0x5, 0x00) => Srliw { rd, rs1, shamt },
+                (0x5, 0x20) => Sraiw { rd, rs1, shamt },
+                // slli.uw shifts by up to 63, so its funct6 has room for one more bit.
+                (0x1, 0x04) | (0x1, 0x05) => SlliUw { rd, rs1, shamt: (word >> 20) & 0x3F },
+                (0x1, 0x30) => {
+                    match rs2 {
+                        0x0 => Clzw { rd, rs1 },
+                        0x1 => Ctzw { rd, rs1 },
+                        0x2 => Cpopw { rd, rs1 },
+                        _ => return Err(DecodeError { word }),
+                    }
+                }
+                (0x5, 0x30) => Roriw { rd, rs1, shamt },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x3B => {
+            match (f7, f3) {
+                (0x00, 0x0) => Addw { rd, rs1, rs2 },
+                (0x20, 0x0) => Subw { rd, rs1, rs2 },
+                (0x00, 0x1) => Sllw { rd, rs1, rs2 },
+                (0x00, 0x5) => Srlw { rd, rs1, rs2 },
+                (0x20, 0x5) => Sraw { rd, rs1, rs2 },
+                (0x01, 0x0) => Mulw { rd, rs1, rs2 },
+                (0x01, 0x4) => Divw { rd, rs1, rs2 },
+                (0x01, 0x5) => Divuw { rd, rs1, rs2 },
+                (0x01, 0x6) => Remw { rd, rs1, rs2 },
+                (0x01, 0x7) => Remuw { rd, rs1, rs2 },
+                (0x04, 0x0) => AddUw { rd, rs1, rs2 },
+                (0x10, 0x2) => Sh1addUw { rd, rs1, rs2 },
+                (0x10, 0x4) => Sh2addUw { rd, rs1, rs2 },
+                (0x10, 0x6) => Sh3addUw { rd, rs1, rs2 },
+                (0x04, 0x4) if rs2 == 0 => ZextH { rd, rs1, xlen: 64 },
+                (0x30, 0x1) => Rolw { rd, rs1, rs2 },
+                (0x30, 0x5) => Rorw { rd, rs1, rs2 },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x33 => {
+            match (f7, f3) {
+                (0x00, 0x0) => Add { rd, rs1, rs2 },
+                (0x20, 0x0) => Sub { rd, rs1, rs2 },
+                (0x00, 0x1) => Sll { rd, rs1, rs2 },
+                (0x00, 0x2) => Slt { rd, rs1, rs2 },
+                (0x00, 0x3) => Sltu { rd, rs1, rs2 },
+                (0x00, 0x4) => Xor { rd, rs1, rs2 },
+                (0x00, 0x5) => Srl { rd, rs1, rs2 },
+                (0x20, 0x5) => Sra { rd, rs1, rs2 },
+                (0x00, 0x6) => Or { rd, rs1, rs2 },
+                (0x00, 0x7) => And { rd, rs1, rs2 },
+                (0x01, 0x0) => Mul { rd, rs1, rs2 },
+                (0x01, 0x1) => Mulh { rd, rs1, rs2 },
+                (0x01, 0x2) => Mulhsu { rd, rs1, rs2 },
+                (0x01, 0x3) => Mulhu { rd, rs1, rs2 },
+                (0x01, 0x4) => Div { rd, rs1, rs2 },
+                (0x01, 0x5) => Divu { rd, rs1, rs2 },
+                (0x01, 0x6) => Rem { rd, rs1, rs2 },
+                (0x01, 0x7) => Remu { rd, rs1, rs2 },
+                (0x10, 0x2) => Sh1add { rd, rs1, rs2 },
+                (0x10, 0x4) => Sh2add { rd, rs1, rs2 },
+                (0x10, 0x6) => Sh3add { rd, rs1, rs2 },
+                (0x20, 0x7) => Andn { rd, rs1, rs2 },
+                (0x20, 0x6) => Orn { rd, rs1, rs2 },
+                (0x20, 0x4) => Xnor { rd, rs1, rs2 },
+                (0x05, 0x4) => Min { rd, rs1, rs2 },
+                (0x05, 0x5) => Minu { rd, rs1, rs2 },
+                (0x05, 0x6) => Max { rd, rs1, rs2 },
+                (0x05, 0x7) => Maxu { rd, rs1, rs2 },
+                (0x04, 0x4) if rs2 == 0 => ZextH { rd, rs1, xlen: 32 },
+                (0x30, 0x1) => Rol { rd, rs1, rs2 },
+                (0x30, 0x5) => Ror { rd, rs1, rs2 },
+                (0x05, 0x1) => Clmul { rd, rs1, rs2 },
+                (0x05, 0x2) => Clmulr { rd, rs1, rs2 },
+                (0x05, 0x3) => Clmulh { rd, rs1, rs2 },
+                (0x24, 0x1) => Bclr { rd, rs1, rs2 },
+                (0x24, 0x5) => Bext { rd, rs1, rs2 },
+                (0x34, 0x1) => Binv { rd, rs1, rs2 },
+                (0x14, 0x1) => Bset { rd, rs1, rs2 },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x2F if f3 == 0x2 => {
+            let aq = f7 & 0x2 != 0;
+            let rl = f7 & 0x1 != 0;
+            match f7 >> 2 {
+                0x02 if rs2 == 0 => LrW { rd, rs1, aq, rl },
+                0x03 => ScW { rd, rs1, rs2, aq, rl },
+                0x01 => AmoswapW { rd, rs1, rs2, aq, rl },
+                0x00 => AmoaddW { rd, rs1, rs2, aq, rl },
+                0x04 => AmoxorW { rd, rs1, rs2, aq, rl },
+                0x0C => AmoandW { rd, rs1, rs2, aq, rl },
+                0x08 => AmoorW { rd, rs1, rs2, aq, rl },
+                0x10 => AmominW { rd, rs1, rs2, aq, rl },
+                0x14 => AmomaxW { rd, rs1, rs2, aq, rl },
+                0x18 => AmominuW { rd, rs1, rs2, aq, rl },
+                0x1C => AmomaxuW { rd, rs1, rs2, aq, rl },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x07 | 0x27 => {
+            let fmt = match f3 {
+                0x2 => Precision::S,
+                0x3 => Precision::D,
+                0x4 => Precision::Q,
+                _ => return decode_vector_memory(word),
+            };
+            if opcode == 0x07 { Fload { rd, rs1, imm: decode_i_type_immediate(bytes), fmt } }
+            else { Fstore { rs1, rs2, imm: decode_s_type_immediate(bytes), fmt } }
+        }
+        0x57 => return decode_vector(word),
+        0x43 | 0x47 | 0x4B | 0x4F => {
+            let fmt = precision(f7 & 0x3).ok_or(DecodeError { word })?;
+            let rm = rounding_mode(f3).ok_or(DecodeError { word })?;
+            let rs3 = (f7 >> 2) as usize;
+            match opcode {
+                0x43 => Fmadd { rd, rs1, rs2, rs3, rm, fmt },
+                0x47 => Fmsub { rd, rs1, rs2, rs3, rm, fmt },
+                0x4B => Fnmsub { rd, rs1, rs2, rs3, rm, fmt },
+                _ => Fnmadd { rd, rs1, rs2, rs3, rm, fmt },
+            }
+        }
+        0x53 => {
+            let fmt = precision(f7 & 0x3).ok_or(DecodeError { word })?;
+            let rm = || rounding_mode(f3).ok_or(DecodeError { word });
+            match (f7 >> 2, f3, rs2) {
+                (0x00, _, _) => Fadd { rd, rs1, rs2, rm: rm()?, fmt },
+                (0x01, _, _) => Fsub { rd, rs1, rs2, rm: rm()?, fmt },
+                (0x02, _, _) => Fmul { rd, rs1, rs2, rm: rm()?, fmt },
+                (0x03, _, _) => Fdiv { rd, rs1, rs2, rm: rm()?, fmt },
+                (0x0B, _, 0) => Fsqrt { rd, rs1, rm: rm()?, fmt },
+                (0x04, 0x0, _) => Fsgnj { rd, rs1, rs2, fmt },
+                (0x04, 0x1, _) => Fsgnjn { rd, rs1, rs2, fmt },
+                (0x04, 0x2, _) => Fsgnjx { rd, rs1, rs2, fmt },
+                (0x05, 0x0, _) => Fmin { rd, rs1, rs2, fmt },
+                (0x05, 0x1, _) => Fmax { rd, rs1, rs2, fmt },
+                (0x14, 0x2, _) => Feq { rd, rs1, rs2, fmt },
+                (0x14, 0x1, _) => Flt { rd, rs1, rs2, fmt },
+                (0x14, 0x0, _) => Fle { rd, rs1, rs2, fmt },
+                (0x18, _, 0..=3) => FcvtToInt { rd, rs1, rm: rm()?, fmt, int: int_format(rs2) },
+                (0x1A, _, 0..=3) => FcvtFromInt { rd, rs1, rm: rm()?, fmt, int: int_format(rs2) },
+                (0x08, _, _) => {
+                    let from = precision(rs2 as u8).filter(|&from| from != fmt).ok_or(DecodeError { word })?;
+                    FcvtFloat { rd, rs1, rm: rm()?, fmt, from }
+                }
+                // Q has no moves to or from the integer registers below RV128.
+                (0x1C, 0x0, 0) if fmt != Precision::Q => FmvToInt { rd, rs1, fmt },
+                (0x1C, 0x1, 0) => Fclass { rd, rs1, fmt },
+                (0x1E, 0x0, 0) if fmt != Precision::Q => FmvFromInt { rd, rs1, fmt },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        // MISC-MEM. The rd and rs1 fields are reserved and ignored, as are
+        // fence modes other than TSO, which order like a plain fence.
+        0x0F => {
+            let (fm, pred, succ) = (word >> 28, (word >> 24) as u8 & 0xF, (word >> 20) as u8 & 0xF);
+            match f3 {
+                0x0 if fm == 0x8 && pred == 0x3 && succ == 0x3 => FenceTso,
+                0x0 => Fence { pred, succ },
+                0x1 => FenceI,
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        0x73 => {
+            let csr = (word >> 20) as u16;
+            let uimm = rs1 as u32;
+            match f3 {
+                0x0 if rd == 0 && rs1 == 0 && word >> 20 == 0x000 => Ecall,
+                0x0 if rd == 0 && rs1 == 0 && word >> 20 == 0x001 => Ebreak,
+                0x0 if rd == 0 && rs1 == 0 && word >> 20 == 0x302 => Mret,
+                0x0 if rd == 0 && rs1 == 0 && word >> 20 == 0x102 => Sret,
+                0x0 if rd == 0 && rs1 == 0 && word >> 20 == 0x105 => Wfi,
+                0x0 if rd == 0 && f7 == 0x09 => SfenceVma { rs1, rs2 },
+                0x1 => Csrrw { rd, rs1, csr },
+                0x2 => Csrrs { rd, rs1, csr },
+                0x3 => Csrrc { rd, rs1, csr },
+                0x5 => Csrrwi { rd, uimm, csr },
+                0x6 => Csrrsi { rd, uimm, csr },
+                0x7 => Csrrci { rd, uimm, csr },
+                _ => return Err(DecodeError { word }),
+            }
+        }
+        _ => return Err(DecodeError { word }),
+    };
+
+    Ok(inst)
+}
+
 pub fn load_into_imem(filepath: &str, imem: &mut Vec<u8>) -> Result<(), &'static str> {
-    let mut file = File::open(filepath).or_else(|_| return Err("Could not open instruction file")).unwrap();
+    let mut file = File::open(filepath).map_err(|_| "Could not open instruction file")?;
     let mut instructions = String::new();
-    file.read_to_string(&mut instructions).or_else(|_| return Err("Error reading file")).unwrap();
+    file.read_to_string(&mut instructions).map_err(|_| "Error reading file")?;
 
-    for mut hex_str in instructions.split(|c: char| !(c.is_digit(16) || c == 'x')) {
+    for mut hex_str in instructions.split(|c: char| !(c.is_ascii_hexdigit() || c == 'x')) {
 
         if hex_str.is_empty() { continue; }
 
@@ -90,7 +418,10 @@ pub fn load_into_imem(filepath: &str, imem: &mut Vec<u8>) -> Result<(), &'static
         let bytes = decode_hex_to_bytes(hex_str).expect("Could not decode instruction to bytes");
 
         match get_bits(bytes[bytes.len() - 1]) {
-            16 if bytes.len() == 2 => { return Err("16-bit instructions not supported"); } // 16 bit instruction
+            16 if bytes.len() == 2 => { // 16 bit instruction
+                imem.push(bytes[1]);
+                imem.push(bytes[0]);
+            }
             32 if bytes.len() == 4 => { // 32 bit instruction
 
                 if bytes.len() == 4 {
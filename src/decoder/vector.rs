@@ -0,0 +1,161 @@
+use super::*;
+
+/// Bits `hi..=lo` of a word, shifted down to bit 0.
+fn bits(word: u32, hi: u32, lo: u32) -> u32 {
+    (word >> lo) & ((1 << (hi - lo + 1)) - 1)
+}
+
+/// The OPIVV/OPIVX/OPIVI operations by funct6, with the forms each one has:
+/// `v` for .vv, `x` for .vx and `i` for .vi.
+const INTEGER_OPS: &[(u32, VectorOp, &str)] = &[
+    (0x00, VectorOp::Add, "vxi"),
+    (0x02, VectorOp::Sub, "vx"),
+    (0x03, VectorOp::Rsub, "xi"),
+    (0x04, VectorOp::Minu, "vx"),
+    (0x05, VectorOp::Min, "vx"),
+    (0x06, VectorOp::Maxu, "vx"),
+    (0x07, VectorOp::Max, "vx"),
+    (0x09, VectorOp::And, "vxi"),
+    (0x0A, VectorOp::Or, "vxi"),
+    (0x0B, VectorOp::Xor, "vxi"),
+    (0x18, VectorOp::Mseq, "vxi"),
+    (0x19, VectorOp::Msne, "vxi"),
+    (0x1A, VectorOp::Msltu, "vx"),
+    (0x1B, VectorOp::Mslt, "vx"),
+    (0x1C, VectorOp::Msleu, "vxi"),
+    (0x1D, VectorOp::Msle, "vxi"),
+    (0x1E, VectorOp::Msgtu, "xi"),
+    (0x1F, VectorOp::Msgt, "xi"),
+    (0x25, VectorOp::Sll, "vxi"),
+    (0x28, VectorOp::Srl, "vxi"),
+    (0x29, VectorOp::Sra, "vxi"),
+];
+
+/// The OPMVV/OPMVX multiplies and divides by funct6. All have both forms.
+const MULTIPLY_OPS: &[(u32, VectorOp)] = &[
+    (0x20, VectorOp::Divu),
+    (0x21, VectorOp::Div),
+    (0x22, VectorOp::Remu),
+    (0x23, VectorOp::Rem),
+    (0x24, VectorOp::Mulhu),
+    (0x25, VectorOp::Mul),
+    (0x26, VectorOp::Mulhsu),
+    (0x27, VectorOp::Mulh),
+    (0x29, VectorOp::Madd),
+    (0x2B, VectorOp::Nmsub),
+    (0x2D, VectorOp::Macc),
+    (0x2F, VectorOp::Nmsac),
+];
+
+const REDUCTIONS: [VectorReduction; 8] = [
+    VectorReduction::Sum, VectorReduction::And, VectorReduction::Or, VectorReduction::Xor,
+    VectorReduction::Minu, VectorReduction::Min, VectorReduction::Maxu, VectorReduction::Max,
+];
+
+const MASK_OPS: [MaskOp; 8] = [
+    MaskOp::Andn, MaskOp::And, MaskOp::Or, MaskOp::Xor, MaskOp::Orn, MaskOp::Nand, MaskOp::Nor, MaskOp::Xnor,
+];
+
+/// Decode a vector load (opcode 0x07) or store (0x27), whose width field
+/// is one the scalar floating-point loads and stores leave free. Segment
+/// and fault-only-first accesses are not supported.
+pub fn decode_vector_memory(word: u32) -> Result<Instruction, DecodeError> {
+    use self::Instruction::*;
+
+    let err = DecodeError { word };
+    let load = word & 0x7F == 0x07;
+    let (vd, rs1, field) = (bits(word, 11, 7) as usize, bits(word, 19, 15) as usize, bits(word, 24, 20) as usize);
+    let masked = bits(word, 25, 25) == 0;
+    let nf = bits(word, 31, 29);
+    let eew = match bits(word, 14, 12) {
+        0x0 => 8,
+        0x5 => 16,
+        0x6 => 32,
+        0x7 => 64,
+        _ => return Err(err),
+    };
+    // mew selects element widths of 128 bits and up, which are reserved.
+    if bits(word, 28, 28) != 0 { return Err(err); }
+
+    let mode = match (bits(word, 27, 26), field) {
+        // Whole-register stores always encode an element width of 8.
+        (0x0, 0x08) if !masked && matches!(nf, 0 | 1 | 3 | 7) && (load || eew == 8) => {
+            VectorAddressing::WholeRegister(nf + 1)
+        }
+        _ if nf != 0 => return Err(err),
+        (0x0, 0x00) => VectorAddressing::UnitStride,
+        (0x0, 0x0B) if !masked && eew == 8 => VectorAddressing::Mask,
+        (0x0, _) => return Err(err),
+        (0x1, vs2) => VectorAddressing::Indexed { vs2, ordered: false },
+        (0x2, rs2) => VectorAddressing::Strided(rs2),
+        (_, vs2) => VectorAddressing::Indexed { vs2, ordered: true },
+    };
+
+    Ok(if load { Vload { vd, rs1, eew, mode, masked } } else { Vstore { vs3: vd, rs1, eew, mode, masked } })
+}
+
+/// Decode an instruction in the OP-V major opcode (0x57): the integer
+/// arithmetic, mask and configuration-setting instructions. The
+/// floating-point forms are not supported.
+pub fn decode_vector(word: u32) -> Result<Instruction, DecodeError> {
+    use self::Instruction::*;
+
+    let err = DecodeError { word };
+    let funct6 = word >> 26;
+    let masked = bits(word, 25, 25) == 0;
+    let (vd, vs1, vs2) = (bits(word, 11, 7) as usize, bits(word, 19, 15) as usize, bits(word, 24, 20) as usize);
+    let f3 = bits(word, 14, 12);
+
+    let inst = match f3 {
+        // OPIVV, OPIVI and OPIVX
+        0x0 | 0x3 | 0x4 => {
+            // Shifts take their immediate unsigned; everything else sign-extends it.
+            let imm = if matches!(funct6, 0x25 | 0x28 | 0x29) { vs1 as i32 } else { ((vs1 as i32) << 27) >> 27 };
+            let (src, form) = match f3 {
+                0x0 => (VectorOperand::Vector(vs1), 'v'),
+                0x3 => (VectorOperand::Immediate(imm), 'i'),
+                _ => (VectorOperand::Scalar(vs1), 'x'),
+            };
+            match funct6 {
+                0x17 if masked => Vmerge { vd, vs2, src },
+                0x17 if vs2 == 0 => Vmv { vd, src },
+                // vmv<nr>r.v encodes the register count minus one as its immediate.
+                0x27 if f3 == 0x3 && !masked && matches!(vs1, 0 | 1 | 3 | 7) => {
+                    VmvNr { vd, vs2, nregs: vs1 as u32 + 1 }
+                }
+                _ => {
+                    let &(_, op, _) = INTEGER_OPS.iter()
+                        .find(|&&(f, _, forms)| f == funct6 && forms.contains(form))
+                        .ok_or(err)?;
+                    Varith { op, vd, vs2, src, masked }
+                }
+            }
+        }
+        // OPMVV and OPMVX
+        0x2 | 0x6 => {
+            let vv = f3 == 0x2;
+            match funct6 {
+                0x00..=0x07 if vv => Vreduce { op: REDUCTIONS[funct6 as usize], vd, vs2, vs1, masked },
+                0x10 if vv && vs1 == 0x00 && !masked => VmvXS { rd: vd, vs2 },
+                0x10 if vv && vs1 == 0x10 => Vcpop { rd: vd, vs2, masked },
+                0x10 if vv && vs1 == 0x11 => Vfirst { rd: vd, vs2, masked },
+                0x10 if !vv && vs2 == 0 && !masked => VmvSX { vd, rs1: vs1 },
+                0x14 if vv && vs1 == 0x10 => Viota { vd, vs2, masked },
+                0x14 if vv && vs1 == 0x11 && vs2 == 0 => Vid { vd, masked },
+                0x18..=0x1F if vv && !masked => Vmask { op: MASK_OPS[funct6 as usize - 0x18], vd, vs2, vs1 },
+                _ => {
+                    let &(_, op) = MULTIPLY_OPS.iter().find(|&&(f, _)| f == funct6).ok_or(err)?;
+                    let src = if vv { VectorOperand::Vector(vs1) } else { VectorOperand::Scalar(vs1) };
+                    Varith { op, vd, vs2, src, masked }
+                }
+            }
+        }
+        // OPCFG
+        0x7 if word >> 31 == 0 => Vsetvli { rd: vd, rs1: vs1, vtypei: bits(word, 30, 20) },
+        0x7 if word >> 30 == 0b11 => Vsetivli { rd: vd, uimm: vs1 as u32, vtypei: bits(word, 29, 20) },
+        0x7 if bits(word, 30, 25) == 0 => Vsetvl { rd: vd, rs1: vs1, rs2: vs2 },
+        _ => return Err(err),
+    };
+
+    Ok(inst)
+}
@@ -0,0 +1,91 @@
+use super::Device;
+
+// Register offsets for hart 0, the only hart.
+const MSIP: u64 = 0x0;
+const MTIMECMP: u64 = 0x4000;
+const MTIME: u64 = 0xBFF8;
+
+/// A SiFive-compatible core-local interruptor: the software interrupt bit
+/// and the timer compare register for hart 0, and the `mtime` counter the
+/// `time` CSR mirrors. The machine advances `mtime` as it runs and takes
+/// MSIP and MTIP from here.
+pub struct Clint {
+    msip: bool,
+    mtimecmp: u64,
+    mtime: u64,
+}
+
+impl Default for Clint {
+    fn default() -> Clint { Clint::new() }
+}
+
+impl Clint {
+    /// A CLINT with `mtime` at zero. `mtimecmp` starts at its largest value
+    /// so that the timer stays quiet until software programs it.
+    pub fn new() -> Clint {
+        Clint { msip: false, mtimecmp: !0, mtime: 0 }
+    }
+
+    pub fn mtime(&self) -> u64 { self.mtime }
+
+    /// Let `ticks` ticks of real time pass.
+    pub fn tick(&mut self, ticks: u64) {
+        self.mtime = self.mtime.wrapping_add(ticks);
+    }
+
+    pub fn software_interrupt(&self) -> bool { self.msip }
+
+    /// The timer interrupt is pending for as long as `mtime` has reached
+    /// `mtimecmp`.
+    pub fn timer_interrupt(&self) -> bool { self.mtime >= self.mtimecmp }
+
+    /// The ticks until the timer interrupt becomes pending; zero if it
+    /// already is. `None` while `mtimecmp` holds its largest value, which
+    /// software uses to turn the timer off.
+    pub fn ticks_until_timer(&self) -> Option<u64> {
+        if self.mtimecmp == !0 { None } else { Some(self.mtimecmp.saturating_sub(self.mtime)) }
+    }
+}
+
+/// Read the `size` bytes at `offset` into the 64-bit register at `base`.
+/// Either half may be accessed on its own, for RV32.
+fn read_half(register: u64, base: u64, offset: u64, size: usize) -> Option<u64> {
+    match (offset - base, size) {
+        (0, 8) => Some(register),
+        (0, 4) => Some(register & 0xFFFF_FFFF),
+        (4, 4) => Some(register >> 32),
+        _ => None,
+    }
+}
+
+fn write_half(register: &mut u64, base: u64, offset: u64, size: usize, value: u64) -> Option<()> {
+    *register = match (offset - base, size) {
+        (0, 8) => value,
+        (0, 4) => (*register & !0xFFFF_FFFF) | (value & 0xFFFF_FFFF),
+        (4, 4) => (*register & 0xFFFF_FFFF) | value << 32,
+        _ => return None,
+    };
+    Some(())
+}
+
+impl Device for Clint {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        match offset {
+            MSIP if size == 4 => Some(self.msip as u64),
+            MTIMECMP..=0x4007 => read_half(self.mtimecmp, MTIMECMP, offset, size),
+            MTIME..=0xBFFF => read_half(self.mtime, MTIME, offset, size),
+            _ => None,
+        }
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Option<()> {
+        match offset {
+            // Only bit 0 of msip is implemented.
+            MSIP if size == 4 => self.msip = value & 1 != 0,
+            MTIMECMP..=0x4007 => write_half(&mut self.mtimecmp, MTIMECMP, offset, size, value)?,
+            MTIME..=0xBFFF => write_half(&mut self.mtime, MTIME, offset, size, value)?,
+            _ => return None,
+        }
+        Some(())
+    }
+}
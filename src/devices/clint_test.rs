@@ -0,0 +1,58 @@
+use super::*;
+use bus::Bus;
+use {CLINT_BASE, CLINT_SIZE};
+
+const MSIP: u64 = CLINT_BASE;
+const MTIMECMP: u64 = CLINT_BASE + 0x4000;
+const MTIME: u64 = CLINT_BASE + 0xBFF8;
+
+fn clint_bus() -> (Bus, Rc<RefCell<Clint>>) {
+    let clint = Rc::new(RefCell::new(Clint::new()));
+    let mut bus = Bus::new();
+    bus.map_device(CLINT_BASE, CLINT_SIZE, Box::new(clint.clone()));
+    (bus, clint)
+}
+
+#[test]
+fn test_msip() {
+    let (mut bus, clint) = clint_bus();
+    assert_eq!(bus.read_u32(MSIP), Ok(0));
+    bus.write_u32(MSIP, 0xFFFF_FFFF).unwrap();
+    assert_eq!(bus.read_u32(MSIP), Ok(1));
+    assert!(clint.borrow().software_interrupt());
+    bus.write_u32(MSIP, 0).unwrap();
+    assert!(!clint.borrow().software_interrupt());
+    assert!(bus.read_u8(MSIP).is_err());
+}
+
+#[test]
+fn test_timer_registers_take_either_half() {
+    let (mut bus, clint) = clint_bus();
+    assert_eq!(bus.read_u64(MTIMECMP), Ok(!0));
+    bus.write_u32(MTIMECMP, 100).unwrap();
+    bus.write_u32(MTIMECMP + 4, 0).unwrap();
+    assert_eq!(bus.read_u64(MTIMECMP), Ok(100));
+
+    bus.write_u64(MTIME, 0x1_0000_0002).unwrap();
+    assert_eq!(bus.read_u32(MTIME), Ok(2));
+    assert_eq!(bus.read_u32(MTIME + 4), Ok(1));
+    bus.write_u32(MTIME + 4, 0).unwrap();
+    assert_eq!(clint.borrow().mtime(), 2);
+    assert!(bus.read_u16(MTIME).is_err());
+}
+
+#[test]
+fn test_timer_interrupt() {
+    let (mut bus, clint) = clint_bus();
+    assert!(!clint.borrow().timer_interrupt());
+    assert_eq!(clint.borrow().ticks_until_timer(), None, "the timer is off out of reset");
+    bus.write_u64(MTIMECMP, 10).unwrap();
+    assert_eq!(clint.borrow().ticks_until_timer(), Some(10));
+
+    clint.borrow_mut().tick(9);
+    assert!(!clint.borrow().timer_interrupt());
+    clint.borrow_mut().tick(1);
+    assert!(clint.borrow().timer_interrupt());
+    assert_eq!(clint.borrow().ticks_until_timer(), Some(0));
+    assert_eq!(bus.read_u64(MTIME), Ok(10));
+}
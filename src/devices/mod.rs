@@ -0,0 +1,36 @@
+//! Memory-mapped peripherals that can be attached to the `Bus`.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::bus::Device;
+
+mod clint;
+mod plic;
+mod uart;
+pub use self::clint::Clint;
+pub use self::plic::{Plic, PLIC_CONTEXTS, PLIC_SOURCES};
+pub use self::uart::Uart;
+
+/// A device the bus shares with whoever else holds it, such as the machine
+/// reading the CLINT's timer between instructions.
+impl<T: Device> Device for Rc<RefCell<T>> {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        self.borrow_mut().read(offset, size)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Option<()> {
+        self.borrow_mut().write(offset, size, value)
+    }
+
+    fn interrupt_pending(&mut self) -> bool {
+        self.borrow_mut().interrupt_pending()
+    }
+}
+
+#[cfg(test)]
+mod clint_test;
+#[cfg(test)]
+mod plic_test;
+#[cfg(test)]
+mod uart_test;
@@ -0,0 +1,147 @@
+use super::Device;
+
+/// Interrupt sources 1 to 63; source 0 means "no interrupt".
+pub const PLIC_SOURCES: usize = 64;
+/// Context 0 interrupts hart 0 in machine mode and context 1 in
+/// supervisor mode.
+pub const PLIC_CONTEXTS: usize = 2;
+
+// Register offsets, SiFive layout.
+const PRIORITY: u64 = 0x0;
+const PENDING: u64 = 0x1000;
+const ENABLE: u64 = 0x2000;
+const ENABLE_STRIDE: u64 = 0x80;
+const CONTEXT: u64 = 0x20_0000;
+const CONTEXT_STRIDE: u64 = 0x1000;
+
+/// Priorities and thresholds are 3 bits wide.
+const PRIORITY_MASK: u32 = 0x7;
+
+/// A SiFive-compatible platform-level interrupt controller. Device lines
+/// are level-triggered: a source is pending while its line is high and it
+/// has not been claimed, and it can be claimed again once the handler
+/// writes its id back to complete it. A context's interrupt is asserted
+/// while it has an enabled pending source whose priority is above its
+/// threshold.
+pub struct Plic {
+    priority: [u32; PLIC_SOURCES],
+    levels: u64,
+    claimed: u64,
+    enable: [u64; PLIC_CONTEXTS],
+    threshold: [u32; PLIC_CONTEXTS],
+}
+
+impl Default for Plic {
+    fn default() -> Plic { Plic::new() }
+}
+
+impl Plic {
+    pub fn new() -> Plic {
+        Plic {
+            priority: [0; PLIC_SOURCES],
+            levels: 0,
+            claimed: 0,
+            enable: [0; PLIC_CONTEXTS],
+            threshold: [0; PLIC_CONTEXTS],
+        }
+    }
+
+    /// Drive the interrupt line of `source`.
+    pub fn set_level(&mut self, source: u32, high: bool) {
+        assert!(source > 0 && (source as usize) < PLIC_SOURCES, "No PLIC source {}", source);
+        if high { self.levels |= 1 << source; } else { self.levels &= !(1 << source); }
+    }
+
+    fn pending(&self) -> u64 { self.levels & !self.claimed & !1 }
+
+    /// The enabled pending source with the highest priority above the
+    /// context's threshold, the lowest id winning a tie.
+    fn best(&self, context: usize) -> Option<u32> {
+        let candidates = self.pending() & self.enable[context];
+        (1..PLIC_SOURCES as u32)
+            .filter(|&source| candidates >> source & 1 != 0)
+            .filter(|&source| self.priority[source as usize] > self.threshold[context])
+            .fold(None, |best: Option<u32>, source| match best {
+                Some(b) if self.priority[b as usize] >= self.priority[source as usize] => Some(b),
+                _ => Some(source),
+            })
+    }
+
+    /// Whether the PLIC is interrupting `context`.
+    pub fn interrupt_pending(&self, context: usize) -> bool { self.best(context).is_some() }
+
+    fn claim(&mut self, context: usize) -> u32 {
+        match self.best(context) {
+            Some(source) => {
+                self.claimed |= 1 << source;
+                source
+            }
+            None => 0,
+        }
+    }
+
+    /// Completing a source the context does not have enabled is ignored.
+    fn complete(&mut self, context: usize, source: u64) {
+        if (source as usize) < PLIC_SOURCES && self.enable[context] >> source & 1 != 0 {
+            self.claimed &= !(1 << source);
+        }
+    }
+}
+
+impl Device for Plic {
+    fn read(&mut self, offset: u64, size: usize) -> Option<u64> {
+        if size != 4 { return None; }
+        let value = match offset {
+            PRIORITY..=0xFC => self.priority[(offset / 4) as usize],
+            PENDING | 0x1004 => (self.pending() >> (8 * (offset - PENDING))) as u32,
+            _ if offset >= ENABLE && offset < ENABLE + ENABLE_STRIDE * PLIC_CONTEXTS as u64 => {
+                let context = ((offset - ENABLE) / ENABLE_STRIDE) as usize;
+                match (offset - ENABLE) % ENABLE_STRIDE {
+                    word @ 0 | word @ 4 => (self.enable[context] >> (8 * word)) as u32,
+                    _ => return None,
+                }
+            }
+            _ if offset >= CONTEXT && offset < CONTEXT + CONTEXT_STRIDE * PLIC_CONTEXTS as u64 => {
+                let context = ((offset - CONTEXT) / CONTEXT_STRIDE) as usize;
+                match (offset - CONTEXT) % CONTEXT_STRIDE {
+                    0 => self.threshold[context],
+                    4 => self.claim(context),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        Some(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, size: usize, value: u64) -> Option<()> {
+        if size != 4 { return None; }
+        let value = value & 0xFFFF_FFFF;
+        match offset {
+            // Source 0 does not exist, so its priority stays zero.
+            PRIORITY => {}
+            0x4..=0xFC => self.priority[(offset / 4) as usize] = value as u32 & PRIORITY_MASK,
+            // The pending bits are read-only.
+            PENDING | 0x1004 => {}
+            _ if offset >= ENABLE && offset < ENABLE + ENABLE_STRIDE * PLIC_CONTEXTS as u64 => {
+                let context = ((offset - ENABLE) / ENABLE_STRIDE) as usize;
+                let enable = &mut self.enable[context];
+                match (offset - ENABLE) % ENABLE_STRIDE {
+                    0 => *enable = (*enable & !0xFFFF_FFFF) | (value & !1),
+                    4 => *enable = (*enable & 0xFFFF_FFFF) | value << 32,
+                    _ => return None,
+                }
+            }
+            _ if offset >= CONTEXT && offset < CONTEXT + CONTEXT_STRIDE * PLIC_CONTEXTS as u64 => {
+                let context = ((offset - CONTEXT) / CONTEXT_STRIDE) as usize;
+                match (offset - CONTEXT) % CONTEXT_STRIDE {
+                    0 => self.threshold[context] = value as u32 & PRIORITY_MASK,
+                    4 => self.complete(context, value),
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+}
@@ -0,0 +1,76 @@
+use super::*;
+use bus::Bus;
+use {PLIC_BASE, PLIC_SIZE};
+
+const PRIORITY: u64 = PLIC_BASE;
+const PENDING: u64 = PLIC_BASE + 0x1000;
+const ENABLE: u64 = PLIC_BASE + 0x2000;
+const THRESHOLD: u64 = PLIC_BASE + 0x20_0000;
+const CLAIM: u64 = PLIC_BASE + 0x20_0004;
+// The supervisor context's registers follow the machine context's.
+const S_ENABLE: u64 = ENABLE + 0x80;
+const S_CLAIM: u64 = CLAIM + 0x1000;
+
+fn plic_bus() -> (Bus, Rc<RefCell<Plic>>) {
+    let plic = Rc::new(RefCell::new(Plic::new()));
+    let mut bus = Bus::new();
+    bus.map_device(PLIC_BASE, PLIC_SIZE, Box::new(plic.clone()));
+    (bus, plic)
+}
+
+#[test]
+fn test_registers() {
+    let (mut bus, _plic) = plic_bus();
+    bus.write_u32(PRIORITY, 5).unwrap();
+    assert_eq!(bus.read_u32(PRIORITY), Ok(0), "source 0 does not exist");
+    bus.write_u32(PRIORITY + 4 * 3, 0xFF).unwrap();
+    assert_eq!(bus.read_u32(PRIORITY + 4 * 3), Ok(7));
+    bus.write_u32(ENABLE, 0xFFFF_FFFF).unwrap();
+    assert_eq!(bus.read_u32(ENABLE), Ok(0xFFFF_FFFE));
+    assert_eq!(bus.read_u32(S_ENABLE), Ok(0));
+    bus.write_u32(THRESHOLD, 9).unwrap();
+    assert_eq!(bus.read_u32(THRESHOLD), Ok(1));
+    assert!(bus.read_u8(THRESHOLD).is_err());
+}
+
+#[test]
+fn test_claim_takes_the_highest_priority_source() {
+    let (mut bus, plic) = plic_bus();
+    for &(source, priority) in &[(2, 1), (3, 4), (40, 4)] {
+        bus.write_u32(PRIORITY + 4 * source, priority).unwrap();
+        plic.borrow_mut().set_level(source as u32, true);
+    }
+    assert_eq!(bus.read_u32(PENDING), Ok(0b1100));
+    assert_eq!(bus.read_u32(PENDING + 4), Ok(1 << 8));
+    assert!(!plic.borrow().interrupt_pending(0), "nothing is enabled");
+
+    bus.write_u32(ENABLE, 0b1100).unwrap();
+    bus.write_u32(ENABLE + 4, 1 << 8).unwrap();
+    assert!(plic.borrow().interrupt_pending(0));
+    assert_eq!(bus.read_u32(CLAIM), Ok(3), "the lower id wins a tie");
+    assert_eq!(bus.read_u32(CLAIM), Ok(40));
+    bus.write_u32(THRESHOLD, 1).unwrap();
+    assert!(!plic.borrow().interrupt_pending(0), "source 2 is not above the threshold");
+    assert_eq!(bus.read_u32(CLAIM), Ok(0));
+    assert_eq!(bus.read_u32(S_CLAIM), Ok(0));
+}
+
+#[test]
+fn test_a_claimed_source_waits_for_completion() {
+    let (mut bus, plic) = plic_bus();
+    bus.write_u32(PRIORITY + 4, 1).unwrap();
+    bus.write_u32(S_ENABLE, 0b10).unwrap();
+    plic.borrow_mut().set_level(1, true);
+    assert!(plic.borrow().interrupt_pending(1));
+    assert_eq!(bus.read_u32(S_CLAIM), Ok(1));
+    assert!(!plic.borrow().interrupt_pending(1));
+
+    // Completing through a context that does not enable the source is ignored.
+    bus.write_u32(CLAIM, 1).unwrap();
+    assert_eq!(bus.read_u32(PENDING), Ok(0));
+    bus.write_u32(S_CLAIM, 1).unwrap();
+    assert_eq!(bus.read_u32(PENDING), Ok(0b10), "the line is still high");
+
+    plic.borrow_mut().set_level(1, false);
+    assert!(!plic.borrow().interrupt_pending(1));
+}
@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+use super::Device;
+
+// Register offsets. 0 and 1 are the divisor latch while LCR.DLAB is set.
+const RBR_THR: u64 = 0;
+const IER: u64 = 1;
+const IIR_FCR: u64 = 2;
+const LCR: u64 = 3;
+const MCR: u64 = 4;
+const LSR: u64 = 5;
+const MSR: u64 = 6;
+const SCR: u64 = 7;
+
+const IER_RDA: u8 = 0x01;
+const IER_THRE: u8 = 0x02;
+
+const IIR_NONE: u8 = 0x01;
+const IIR_THRE: u8 = 0x02;
+const IIR_RDA: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xC0;
+
+const LCR_DLAB: u8 = 0x80;
+const MCR_LOOP: u8 = 0x10;
+
+const LSR_DR: u8 = 0x01;
+const LSR_THRE: u8 = 0x20;
+const LSR_TEMT: u8 = 0x40;
+
+/// An NS16550A-compatible UART. Transmitted bytes go straight to `output`,
+/// so the transmitter is always empty; received bytes arrive on a channel
+/// and wait in the receive FIFO until the guest reads RBR.
+pub struct Uart {
+    output: Box<dyn Write>,
+    input: Receiver<u8>,
+    rx_fifo: VecDeque<u8>,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    divisor: u16,
+    thre_pending: bool,
+}
+
+impl Uart {
+    pub fn new(output: Box<dyn Write>, input: Receiver<u8>) -> Uart {
+        Uart {
+            output,
+            input,
+            rx_fifo: VecDeque::new(),
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            divisor: 0,
+            thre_pending: false,
+        }
+    }
+
+    /// A UART connected to the host console. Stdin is read on a background
+    /// thread so that polling LSR never blocks the hart.
+    pub fn stdio() -> Uart {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let mut stdin = io::stdin();
+            let mut buffer = [0; 64];
+            while let Ok(count) = stdin.read(&mut buffer) {
+                if count == 0 || buffer[..count].iter().any(|&byte| sender.send(byte).is_err()) { break; }
+            }
+        });
+        Uart::new(Box::new(io::stdout()), receiver)
+    }
+
+    fn poll_input(&mut self) {
+        while let Ok(byte) = self.input.try_recv() {
+            self.rx_fifo.push_back(byte);
+        }
+    }
+
+    fn transmit(&mut self, byte: u8) {
+        if self.mcr & MCR_LOOP != 0 {
+            self.rx_fifo.push_back(byte);
+        }
+        else {
+            // The console has nowhere to report a failed write; drop the byte.
+            let _ = self.output.write_all(&[byte]).and_then(|_| self.output.flush());
+        }
+        self.thre_pending = true;
+    }
+
+    fn line_status(&mut self) -> u8 {
+        self.poll_input();
+        let ready = if self.rx_fifo.is_empty() { 0 } else { LSR_DR };
+        ready | LSR_THRE | LSR_TEMT
+    }
+
+    /// The highest priority interrupt the guest has enabled, as IIR reports it.
+    fn interrupt_id(&mut self) -> u8 {
+        if self.ier & IER_RDA != 0 && self.line_status() & LSR_DR != 0 {
+            IIR_RDA
+        }
+        else if self.ier & IER_THRE != 0 && self.thre_pending {
+            IIR_THRE
+        }
+        else {
+            IIR_NONE
+        }
+    }
+}
+
+impl Device for Uart {
+    fn read(&mut self, offset: u64, _size: usize) -> Option<u64> {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        let value = match offset {
+            RBR_THR if dlab => self.divisor as u8,
+            IER if dlab => (self.divisor >> 8) as u8,
+            RBR_THR => {
+                self.poll_input();
+                self.rx_fifo.pop_front().unwrap_or(0)
+            }
+            IER => self.ier,
+            IIR_FCR => {
+                let id = self.interrupt_id();
+                // Reading IIR acknowledges a transmitter-empty interrupt.
+                if id == IIR_THRE { self.thre_pending = false; }
+                let fifo = if self.fcr & 0x01 != 0 { IIR_FIFO_ENABLED } else { 0 };
+                id | fifo
+            }
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => self.line_status(),
+            MSR => 0,
+            SCR => self.scr,
+            _ => return None,
+        };
+        Some(value as u64)
+    }
+
+    fn write(&mut self, offset: u64, _size: usize, value: u64) -> Option<()> {
+        let value = value as u8;
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match offset {
+            RBR_THR if dlab => self.divisor = (self.divisor & 0xFF00) | value as u16,
+            IER if dlab => self.divisor = (self.divisor & 0x00FF) | (value as u16) << 8,
+            RBR_THR => self.transmit(value),
+            IER => {
+                // Enabling the transmitter interrupt raises it immediately,
+                // since the holding register is always empty.
+                if value & IER_THRE != 0 && self.ier & IER_THRE == 0 { self.thre_pending = true; }
+                self.ier = value & 0x0F;
+            }
+            IIR_FCR => {
+                if value & 0x02 != 0 { self.rx_fifo.clear(); }
+                self.fcr = value & 0xC9;
+            }
+            LCR => self.lcr = value,
+            MCR => self.mcr = value & 0x1F,
+            LSR | MSR => {}
+            SCR => self.scr = value,
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn interrupt_pending(&mut self) -> bool {
+        self.interrupt_id() != IIR_NONE
+    }
+}
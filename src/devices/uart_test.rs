@@ -0,0 +1,107 @@
+use super::*;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::mpsc::{channel, Sender};
+use bus::Bus;
+use {UART_BASE, UART_SIZE};
+
+const THR: u64 = UART_BASE;
+const IER: u64 = UART_BASE + 1;
+const IIR: u64 = UART_BASE + 2;
+const LCR: u64 = UART_BASE + 3;
+const MCR: u64 = UART_BASE + 4;
+const LSR: u64 = UART_BASE + 5;
+
+#[derive(Clone, Default)]
+struct Console(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Console {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
+
+fn uart_bus() -> (Bus, Console, Sender<u8>) {
+    let console = Console::default();
+    let (sender, receiver) = channel();
+    let mut bus = Bus::new();
+    bus.map_device(UART_BASE, UART_SIZE, Box::new(Uart::new(Box::new(console.clone()), receiver)));
+    (bus, console, sender)
+}
+
+#[test]
+fn test_transmit() {
+    let (mut bus, console, _sender) = uart_bus();
+    assert_eq!(bus.read_u8(LSR), Ok(0x60));
+    for &byte in b"hi\n" {
+        bus.write_u8(THR, byte).unwrap();
+    }
+    assert_eq!(&console.0.borrow()[..], b"hi\n");
+}
+
+#[test]
+fn test_receive() {
+    let (mut bus, _console, sender) = uart_bus();
+    assert_eq!(bus.read_u8(LSR).unwrap() & 0x01, 0);
+
+    sender.send(b'o').unwrap();
+    sender.send(b'k').unwrap();
+    assert_eq!(bus.read_u8(LSR), Ok(0x61));
+    assert_eq!(bus.read_u8(THR), Ok(b'o'));
+    assert_eq!(bus.read_u8(THR), Ok(b'k'));
+    assert_eq!(bus.read_u8(LSR), Ok(0x60));
+}
+
+#[test]
+fn test_divisor_latch_shadows_data_registers() {
+    let (mut bus, console, _sender) = uart_bus();
+    bus.write_u8(LCR, 0x83).unwrap();
+    bus.write_u8(THR, 0x03).unwrap();
+    bus.write_u8(IER, 0x00).unwrap();
+    assert_eq!(bus.read_u8(THR), Ok(0x03));
+    bus.write_u8(LCR, 0x03).unwrap();
+
+    assert!(console.0.borrow().is_empty());
+    assert_eq!(bus.read_u8(IER), Ok(0));
+    assert_eq!(bus.read_u8(LCR), Ok(0x03));
+}
+
+#[test]
+fn test_interrupts() {
+    let (mut bus, _console, sender) = uart_bus();
+    sender.send(b'x').unwrap();
+    // Nothing is signalled until the guest enables it in IER.
+    assert!(!bus.interrupt_pending(UART_BASE));
+    assert_eq!(bus.read_u8(IIR), Ok(0x01));
+
+    bus.write_u8(IER, 0x01).unwrap();
+    assert!(bus.interrupt_pending(UART_BASE));
+    assert_eq!(bus.read_u8(IIR), Ok(0x04));
+    bus.read_u8(THR).unwrap();
+    assert!(!bus.interrupt_pending(UART_BASE));
+
+    bus.write_u8(IER, 0x03).unwrap();
+    assert_eq!(bus.read_u8(IIR), Ok(0x02));
+    assert_eq!(bus.read_u8(IIR), Ok(0x01));
+    bus.write_u8(THR, b'y').unwrap();
+    assert!(bus.interrupt_pending(UART_BASE));
+}
+
+#[test]
+fn test_loopback() {
+    let (mut bus, console, _sender) = uart_bus();
+    bus.write_u8(MCR, 0x10).unwrap();
+    bus.write_u8(THR, b'z').unwrap();
+    assert!(console.0.borrow().is_empty());
+    assert_eq!(bus.read_u8(THR), Ok(b'z'));
+}
+
+#[test]
+fn test_unknown_register_faults() {
+    let (mut bus, _console, _sender) = uart_bus();
+    assert!(bus.read_u8(UART_BASE + 8).is_err());
+}
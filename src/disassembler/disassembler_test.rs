@@ -0,0 +1,51 @@
+use super::*;
+
+#[test]
+fn test_disassemble_test_hex() {
+    let mut imem = Vec::new();
+    load_into_imem("risc-v/assembled/test.hex", &mut imem).unwrap();
+    let lines = disassemble(&imem, 0);
+
+    assert_eq!(lines.len(), 6);
+    assert_eq!(lines[0], "       0:\t00a00293\taddi\tt0,zero,10");
+    assert_eq!(lines[2], "       8:\t02629533\tmulh\ta0,t0,t1");
+    assert_eq!(lines[5], "      14:\t026286b3\tmul\ta3,t0,t1");
+}
+
+#[test]
+fn test_branch_targets_are_resolved() {
+    assert_eq!(disassemble_word(0x100, 0x00535463), "     100:\t00535463\tbge\tt1,t0,0x108");
+    assert_eq!(disassemble_word(0x100, 0xfe000ee3), "     100:\tfe000ee3\tbeq\tzero,zero,0xfc");
+    assert_eq!(disassemble_word(0x100, 0x008000ef), "     100:\t008000ef\tjal\tra,0x108");
+}
+
+#[test]
+fn test_unknown_words_are_emitted_as_data() {
+    assert_eq!(disassemble_word(0, 0xffffffff), "       0:\tffffffff\t.4byte\t0xffffffff");
+    let lines = disassemble(&[0x02, 0x40, 0x13], 0x10);
+    assert_eq!(lines, vec!["      10:\t4002    \t.2byte\t0x4002", "      12:\t13      \t.byte\t0x13"]);
+}
+
+#[test]
+fn test_compressed_instructions_are_expanded() {
+    let lines = disassemble(&[0x15, 0x45, 0x93, 0x05, 0x70, 0x00, 0xf5, 0xbf], 0x100);
+    assert_eq!(lines, vec![
+        "     100:\t4515    \taddi\ta0,zero,5",
+        "     102:\t00700593\taddi\ta1,zero,7",
+        "     106:\tbff5    \tjal\tzero,0x102",
+    ]);
+}
+
+#[test]
+fn test_instruction_display() {
+    assert_eq!(decode(0x00532023).unwrap().to_string(), "sw t0,0(t1)");
+    assert_eq!(decode(0x800002b7).unwrap().to_string(), "lui t0,0x80000");
+    assert_eq!(decode(0x00000163).unwrap().to_string(), "beq zero,zero,.+2");
+    // An RV64 pc above 4 GiB is not cut to 32 bits.
+    let jal = decode(0x008000ef).unwrap();
+    assert_eq!(jal.display_at(0x1_0000_0100), "jal ra,0x100000108");
+    assert_eq!(jal.branch_target(0x1_0000_0100), Some(0x1_0000_0108));
+    assert_eq!(decode(0x00100073).unwrap().to_string(), "ebreak");
+    assert_eq!(decode(0x12000073).unwrap().to_string(), "sfence.vma");
+    assert_eq!(decode(0x12050073).unwrap().to_string(), "sfence.vma a0");
+}
@@ -0,0 +1,82 @@
+use super::decoder::*;
+use super::elf::{ElfFile, SymbolTable, PF_X};
+
+/// One objdump-style line: address, raw encoding, mnemonic and operands.
+pub fn disassemble_word(address: u32, word: u32) -> String {
+    symbolized_word(address, word, &SymbolTable::default())
+}
+
+fn symbolized_word(address: u32, word: u32, symbols: &SymbolTable) -> String {
+    match decode(word) {
+        Ok(inst) => symbolized(address, &format!("{:08x}", word), inst, symbols),
+        Err(_) => format!("{:8x}:\t{:08x}\t.4byte\t0x{:x}", address, word, word),
+    }
+}
+
+/// A compressed instruction is listed as the instruction it expands to.
+fn symbolized_parcel(address: u32, parcel: u16, symbols: &SymbolTable) -> String {
+    match decode_compressed(parcel, 32) {
+        Ok(inst) => symbolized(address, &format!("{:04x}    ", parcel), inst, symbols),
+        Err(_) => format!("{:8x}:\t{:04x}    \t.2byte\t0x{:x}", address, parcel, parcel),
+    }
+}
+
+fn symbolized(address: u32, encoding: &str, inst: Instruction, symbols: &SymbolTable) -> String {
+    let operands = inst.operands(Some(address as u64));
+    let line = format!("{:8x}:\t{}\t{}\t{}", address, encoding, inst.mnemonic(), operands);
+    // Listings are of RV32 code, so targets wrap at 32 bits.
+    match inst.branch_target(address as u64).and_then(|target| symbols.describe(target as u32)) {
+        Some(name) => format!("{} <{}>", line, name),
+        None => line.trim_end().into(),
+    }
+}
+
+/// Disassemble a little-endian code image that starts at `base`.
+pub fn disassemble(bytes: &[u8], base: u32) -> Vec<String> {
+    disassemble_with_symbols(bytes, base, &SymbolTable::default())
+}
+
+/// Disassemble the executable segments of an ELF file, labelling symbols
+/// and the targets of jumps and branches.
+pub fn disassemble_elf(elf: &ElfFile) -> Vec<String> {
+    elf.segments.iter()
+        .filter(|segment| segment.flags & PF_X != 0)
+        .flat_map(|segment| disassemble_with_symbols(&segment.data, segment.address, &elf.symbols))
+        .collect()
+}
+
+/// Like `disassemble`, with a `<name>:` header wherever a symbol starts.
+pub fn disassemble_with_symbols(bytes: &[u8], base: u32, symbols: &SymbolTable) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = base.wrapping_add(offset as u32);
+        let remaining = &bytes[offset..];
+
+        if let Some(symbol) = symbols.at(address) {
+            if !lines.is_empty() { lines.push(String::new()); }
+            lines.push(format!("{:08x} <{}>:", address, symbol.name));
+        }
+
+        if remaining.len() >= 4 && get_bits(remaining[0]) == 32 {
+            let word = u32::from_le_bytes([remaining[0], remaining[1], remaining[2], remaining[3]]);
+            lines.push(symbolized_word(address, word, symbols));
+            offset += 4;
+        }
+        else if remaining.len() >= 2 {
+            let parcel = u16::from_le_bytes([remaining[0], remaining[1]]);
+            lines.push(symbolized_parcel(address, parcel, symbols));
+            offset += 2;
+        }
+        else {
+            lines.push(format!("{:8x}:\t{:02x}      \t.byte\t0x{:x}", address, remaining[0], remaining[0]));
+            offset += 1;
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod disassembler_test;
@@ -0,0 +1,170 @@
+use super::*;
+use machine::{Config, Machine, StopReason};
+use assembler::assemble;
+use disassembler::disassemble_elf;
+use MEM_SIZE;
+
+const TEXT: u32 = 0x1000;
+const DATA: u32 = 0x2000;
+
+fn push_u16(out: &mut Vec<u8>, value: u16) { out.extend_from_slice(&value.to_le_bytes()); }
+fn push_u32(out: &mut Vec<u8>, value: u32) { out.extend_from_slice(&value.to_le_bytes()); }
+
+/// A minimal executable: a text segment, a data segment with 12 bytes of
+/// `.bss` after its 4 file bytes, and a symbol table.
+fn build_elf() -> Vec<u8> {
+    let text = assemble("
+        _start:
+            lui t0, 0x2
+            lw t1, 0(t0)
+            lw t2, 4(t0)
+            jal ra, helper
+            j done
+        helper:
+            addi t1, t1, 1
+            ret
+        done:
+    ", TEXT).unwrap();
+    let data = 0x1234_5678u32.to_le_bytes().to_vec();
+
+    let mut strtab = vec![0];
+    let mut symtab = vec![0; 16];
+    for &(name, value, size, info) in &[("_start", TEXT, 20, STT_FUNC), ("helper", TEXT + 20, 8, STT_FUNC),
+                                         ("done", TEXT + 28, 0, 0), ("counter", DATA, 4, STT_OBJECT)] {
+        push_u32(&mut symtab, strtab.len() as u32);
+        push_u32(&mut symtab, value);
+        push_u32(&mut symtab, size);
+        symtab.push(0x10 | info); // STB_GLOBAL
+        symtab.push(0);
+        push_u16(&mut symtab, 1);
+        strtab.extend_from_slice(name.as_bytes());
+        strtab.push(0);
+    }
+
+    let text_offset = 52 + 2 * 32;
+    let data_offset = text_offset + text.len();
+    let symtab_offset = data_offset + data.len();
+    let strtab_offset = symtab_offset + symtab.len();
+    let shoff = strtab_offset + strtab.len();
+
+    let mut out = vec![0x7F, b'E', b'L', b'F', ELFCLASS32, ELFDATA2LSB, 1];
+    out.resize(16, 0);
+    push_u16(&mut out, ET_EXEC);
+    push_u16(&mut out, EM_RISCV);
+    push_u32(&mut out, 1);
+    push_u32(&mut out, TEXT);
+    push_u32(&mut out, 52);
+    push_u32(&mut out, shoff as u32);
+    push_u32(&mut out, 0);
+    for &value in &[52, 32, 2, 40, 3, 0] { push_u16(&mut out, value); }
+
+    for &(offset, address, file_size, mem_size, flags) in &[
+        (text_offset, TEXT, text.len(), text.len(), PF_X | 0x4),
+        (data_offset, DATA, data.len(), 16, 0x6),
+    ] {
+        for &value in &[PT_LOAD, offset as u32, address, address, file_size as u32, mem_size as u32, flags, 4] {
+            push_u32(&mut out, value);
+        }
+    }
+
+    out.extend_from_slice(&text);
+    out.extend_from_slice(&data);
+    out.extend_from_slice(&symtab);
+    out.extend_from_slice(&strtab);
+
+    out.extend_from_slice(&[0; 40]);
+    for &(kind, offset, size, link, entsize) in &[
+        (SHT_SYMTAB, symtab_offset, symtab.len(), 2, 16),
+        (3, strtab_offset, strtab.len(), 0, 0),
+    ] {
+        for &value in &[0, kind, 0, 0, offset as u32, size as u32, link, 0, 4, entsize] {
+            push_u32(&mut out, value);
+        }
+    }
+    out
+}
+
+#[test]
+fn test_parse_segments_and_entry() {
+    let elf = parse(&build_elf()).unwrap();
+    assert_eq!(elf.entry, TEXT);
+    assert_eq!(elf.segments.len(), 2);
+    assert_eq!(elf.segments[0].address, TEXT);
+    assert_eq!(elf.segments[0].data.len(), 28);
+    assert_eq!(elf.segments[1].data, vec![0x78, 0x56, 0x34, 0x12]);
+    assert_eq!(elf.segments[1].mem_size, 16);
+}
+
+#[test]
+fn test_rejects_foreign_files() {
+    let elf = build_elf();
+
+    assert_eq!(parse(b"#!/bin/sh\n"), Err(ElfError::NotElf));
+
+    let mut wrong_class = elf.clone();
+    wrong_class[EI_CLASS] = 2;
+    assert_eq!(parse(&wrong_class), Err(ElfError::WrongClass(2)));
+
+    let mut big_endian = elf.clone();
+    big_endian[EI_DATA] = 2;
+    assert_eq!(parse(&big_endian), Err(ElfError::WrongEndianness(2)));
+
+    let mut x86 = elf.clone();
+    x86[18] = 3;
+    x86[19] = 0;
+    assert_eq!(parse(&x86), Err(ElfError::WrongMachine(3)));
+
+    let mut relocatable = elf.clone();
+    relocatable[16] = 1;
+    assert_eq!(parse(&relocatable), Err(ElfError::NotExecutable(1)));
+
+    assert_eq!(parse(&elf[..100]), Err(ElfError::Truncated));
+}
+
+#[test]
+fn test_symbol_lookup() {
+    let elf = parse(&build_elf()).unwrap();
+    let symbols = &elf.symbols;
+
+    assert_eq!(symbols.address_of("helper"), Some(TEXT + 20));
+    assert_eq!(symbols.describe(TEXT), Some("_start".into()));
+    assert_eq!(symbols.describe(TEXT + 8), Some("_start+0x8".into()));
+    assert_eq!(symbols.describe(TEXT + 24), Some("helper+0x4".into()));
+    assert_eq!(symbols.describe(DATA + 2), Some("counter+0x2".into()));
+    assert_eq!(symbols.describe(DATA + 8), Some("done+0xfec".into()));
+    assert_eq!(symbols.describe(TEXT - 4), None);
+}
+
+#[test]
+fn test_load_and_run() {
+    let mut machine = Machine::new(Config { bare: true, ..Config::default() });
+    machine.load(DATA as u64, &[0xFF; 16]).unwrap();
+    machine.load_elf(&parse(&build_elf()).unwrap()).unwrap();
+
+    assert_eq!(machine.pc(), TEXT as u64);
+    assert_eq!(machine.bus_mut().read_u32(DATA as u64 + 4), Ok(0));
+    assert_eq!(machine.bus_mut().read_u32(DATA as u64 + 12), Ok(0));
+
+    assert_eq!(machine.run(None), StopReason::EndOfProgram);
+    assert_eq!(machine.reg(6), 0x1234_5679);
+    assert_eq!(machine.reg(7), 0);
+    assert_eq!(machine.symbols().describe(machine.pc() as u32), Some("done".into()));
+}
+
+#[test]
+fn test_segment_outside_memory() {
+    let mut elf = parse(&build_elf()).unwrap();
+    elf.segments[1].address = MEM_SIZE as u32 - 8;
+    let mut machine = Machine::new(Config::default());
+    assert_eq!(machine.load_elf(&elf), Err(ElfError::SegmentOutsideMemory(MEM_SIZE as u32 - 8)));
+}
+
+#[test]
+fn test_disassemble_with_symbols() {
+    let lines = disassemble_elf(&parse(&build_elf()).unwrap());
+    assert_eq!(lines[0], "00001000 <_start>:");
+    assert_eq!(lines[4], "    100c:\t008000ef\tjal\tra,0x1014 <helper>");
+    assert_eq!(lines[5], "    1010:\t00c0006f\tjal\tzero,0x101c <done>");
+    assert_eq!(lines[6], "");
+    assert_eq!(lines[7], "00001014 <helper>:");
+}
@@ -0,0 +1,214 @@
+use std::fmt;
+
+const EI_CLASS: usize = 4;
+const EI_DATA: usize = 5;
+const ELFCLASS32: u8 = 1;
+const ELFDATA2LSB: u8 = 1;
+const ET_EXEC: u16 = 2;
+const EM_RISCV: u16 = 243;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+/// Program header flag marking a segment as executable.
+pub const PF_X: u32 = 0x1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ElfError {
+    NotElf,
+    WrongClass(u8),
+    WrongEndianness(u8),
+    WrongMachine(u16),
+    NotExecutable(u16),
+    Truncated,
+    SegmentOutsideMemory(u32),
+}
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::NotElf => write!(f, "Not an ELF file"),
+            ElfError::WrongClass(class) => write!(f, "Expected a 32-bit ELF, found class {}", class),
+            ElfError::WrongEndianness(data) => write!(f, "Expected a little-endian ELF, found encoding {}", data),
+            ElfError::WrongMachine(machine) => write!(f, "Expected a RISC-V ELF, found machine {}", machine),
+            ElfError::NotExecutable(kind) => write!(f, "Expected an executable ELF, found type {}", kind),
+            ElfError::Truncated => write!(f, "The ELF file is truncated"),
+            ElfError::SegmentOutsideMemory(address) => {
+                write!(f, "The segment at 0x{:08x} does not fit in memory", address)
+            }
+        }
+    }
+}
+
+/// A `PT_LOAD` segment. `data` holds the file contents; the remaining
+/// `mem_size - data.len()` bytes are zero-filled when loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+    pub mem_size: u32,
+    pub flags: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u32,
+    pub size: u32,
+    pub kind: SymbolKind,
+}
+
+/// Named addresses, used to print `<function+offset>` in listings and errors.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolTable {
+    symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn new(mut symbols: Vec<Symbol>) -> SymbolTable {
+        symbols.sort_by_key(|s| s.address);
+        SymbolTable { symbols }
+    }
+
+    pub fn symbols(&self) -> &[Symbol] { &self.symbols }
+
+    pub fn is_empty(&self) -> bool { self.symbols.is_empty() }
+
+    pub fn address_of(&self, name: &str) -> Option<u32> {
+        self.symbols.iter().find(|s| s.name == name).map(|s| s.address)
+    }
+
+    /// The symbol that starts exactly at `address`, preferring functions.
+    pub fn at(&self, address: u32) -> Option<&Symbol> {
+        let mut candidates = self.symbols.iter().filter(|s| s.address == address);
+        let first = candidates.next()?;
+        Some(candidates.find(|s| s.kind == SymbolKind::Function).unwrap_or(first))
+    }
+
+    /// The closest symbol at or below `address` and the offset into it.
+    /// Sized symbols only match addresses inside them.
+    pub fn containing(&self, address: u32) -> Option<(&Symbol, u32)> {
+        self.symbols.iter()
+            .rev()
+            .filter(|s| s.address <= address)
+            .find(|s| s.size == 0 || address - s.address < s.size)
+            .map(|s| (s, address - s.address))
+    }
+
+    /// `name` or `name+0x10` for `address`, if any symbol covers it.
+    pub fn describe(&self, address: u32) -> Option<String> {
+        self.containing(address).map(|(symbol, offset)| {
+            if offset == 0 { symbol.name.clone() } else { format!("{}+0x{:x}", symbol.name, offset) }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElfFile {
+    pub entry: u32,
+    pub flags: u32,
+    pub segments: Vec<Segment>,
+    pub symbols: SymbolTable,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    bytes.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or(ElfError::Truncated)
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    bytes.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or(ElfError::Truncated)
+}
+
+fn slice(bytes: &[u8], offset: u32, size: u32) -> Result<&[u8], ElfError> {
+    let start = offset as usize;
+    let end = start.checked_add(size as usize).ok_or(ElfError::Truncated)?;
+    bytes.get(start..end).ok_or(ElfError::Truncated)
+}
+
+fn read_string(table: &[u8], offset: u32) -> String {
+    let start = (offset as usize).min(table.len());
+    let end = table[start..].iter().position(|&b| b == 0).map_or(table.len(), |p| start + p);
+    String::from_utf8_lossy(&table[start..end]).into_owned()
+}
+
+/// Parse and validate an ELF32 RISC-V executable.
+pub fn parse(bytes: &[u8]) -> Result<ElfFile, ElfError> {
+    if bytes.len() < 52 || bytes[0..4] != [0x7F, b'E', b'L', b'F'] {
+        return Err(ElfError::NotElf);
+    }
+    if bytes[EI_CLASS] != ELFCLASS32 { return Err(ElfError::WrongClass(bytes[EI_CLASS])); }
+    if bytes[EI_DATA] != ELFDATA2LSB { return Err(ElfError::WrongEndianness(bytes[EI_DATA])); }
+
+    let kind = read_u16(bytes, 16)?;
+    let machine = read_u16(bytes, 18)?;
+    if machine != EM_RISCV { return Err(ElfError::WrongMachine(machine)); }
+    if kind != ET_EXEC { return Err(ElfError::NotExecutable(kind)); }
+
+    let entry = read_u32(bytes, 24)?;
+    let phoff = read_u32(bytes, 28)? as usize;
+    let shoff = read_u32(bytes, 32)? as usize;
+    let flags = read_u32(bytes, 36)?;
+    let phentsize = read_u16(bytes, 42)? as usize;
+    let phnum = read_u16(bytes, 44)? as usize;
+    let shentsize = read_u16(bytes, 46)? as usize;
+    let shnum = read_u16(bytes, 48)? as usize;
+
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let header = phoff + i * phentsize;
+        if read_u32(bytes, header)? != PT_LOAD { continue; }
+
+        let offset = read_u32(bytes, header + 4)?;
+        let address = read_u32(bytes, header + 12)?; // p_paddr
+        let file_size = read_u32(bytes, header + 16)?;
+        let mem_size = read_u32(bytes, header + 20)?;
+        let flags = read_u32(bytes, header + 24)?;
+
+        segments.push(Segment {
+            address,
+            data: slice(bytes, offset, file_size)?.to_vec(),
+            mem_size: mem_size.max(file_size),
+            flags,
+        });
+    }
+
+    let mut symbols = Vec::new();
+    for i in 0..shnum {
+        let header = shoff + i * shentsize;
+        if read_u32(bytes, header + 4)? != SHT_SYMTAB { continue; }
+
+        let table = slice(bytes, read_u32(bytes, header + 16)?, read_u32(bytes, header + 20)?)?;
+        let entsize = (read_u32(bytes, header + 36)? as usize).max(16);
+        let strings_header = shoff + read_u32(bytes, header + 24)? as usize * shentsize;
+        let strings = slice(bytes, read_u32(bytes, strings_header + 16)?, read_u32(bytes, strings_header + 20)?)?;
+
+        for entry in table.chunks(entsize).filter(|e| e.len() >= 16) {
+            let name = read_string(strings, read_u32(entry, 0)?);
+            let shndx = read_u16(entry, 14)?;
+            let kind = match entry[12] & 0xF {
+                STT_FUNC => SymbolKind::Function,
+                STT_OBJECT => SymbolKind::Object,
+                0 => SymbolKind::Other,
+                _ => continue, // sections and files
+            };
+            if name.is_empty() || shndx == 0 || name.starts_with("$x") || name.starts_with("$d") { continue; }
+
+            symbols.push(Symbol { name, address: read_u32(entry, 4)?, size: read_u32(entry, 8)?, kind });
+        }
+    }
+
+    Ok(ElfFile { entry, flags, segments, symbols: SymbolTable::new(symbols) })
+}
+
+#[cfg(test)]
+mod elf_test;
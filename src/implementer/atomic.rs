@@ -0,0 +1,61 @@
+use super::*;
+use super::rtype::sext_w;
+
+impl Machine {
+    pub(crate) fn handle_atomic(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        if !self.extensions.a {
+            return Err(ExecutionError::Extension("A".into()));
+        }
+
+        // With a single hart every access is already ordered, so aq and rl
+        // need no further handling.
+        match inst {
+            LrW { rd, rs1, .. } => {
+                let address = self.reg(rs1);
+                if !address.is_multiple_of(4) { return Err(ExecutionError::LoadAddressMisaligned(address)); }
+                let value = self.read_memory(address, 4, Access::Load)?;
+                self.reservation = Some(address);
+                self.set_reg(rd, sext_w(value));
+            }
+            ScW { rd, rs1, rs2, .. } => {
+                let address = self.amo_address(rs1)?;
+                let reserved = self.reservation.take() == Some(address);
+                if reserved {
+                    self.write_memory(address, 4, self.reg(rs2))?;
+                }
+                self.set_reg(rd, !reserved as u64);
+            }
+            AmoswapW { rd, rs1, rs2, .. } | AmoaddW { rd, rs1, rs2, .. } | AmoxorW { rd, rs1, rs2, .. } |
+            AmoandW { rd, rs1, rs2, .. } | AmoorW { rd, rs1, rs2, .. } | AmominW { rd, rs1, rs2, .. } |
+            AmomaxW { rd, rs1, rs2, .. } | AmominuW { rd, rs1, rs2, .. } | AmomaxuW { rd, rs1, rs2, .. } => {
+                let address = self.amo_address(rs1)?;
+                // AMOs report faults on the read half as store faults too.
+                let old = self.read_memory(address, 4, Access::Store)? as u32;
+                let operand = self.reg(rs2) as u32;
+                let new = match inst {
+                    AmoswapW { .. } => operand,
+                    AmoaddW { .. } => old.wrapping_add(operand),
+                    AmoxorW { .. } => old ^ operand,
+                    AmoandW { .. } => old & operand,
+                    AmoorW { .. } => old | operand,
+                    AmominW { .. } => (old as i32).min(operand as i32) as u32,
+                    AmomaxW { .. } => (old as i32).max(operand as i32) as u32,
+                    AmominuW { .. } => old.min(operand),
+                    _ => old.max(operand),
+                };
+                self.write_memory(address, 4, new as u64)?;
+                self.set_reg(rd, sext_w(old as u64));
+            }
+            _ => unreachable!("{:?} is not an A instruction", inst),
+        }
+
+        Ok(())
+    }
+
+    /// The word address in `rs1` for a store-like atomic access.
+    fn amo_address(&self, rs1: usize) -> Result<u64, ExecutionError> {
+        let address = self.reg(rs1);
+        if !address.is_multiple_of(4) { return Err(ExecutionError::StoreAddressMisaligned(address)); }
+        Ok(address)
+    }
+}
@@ -0,0 +1,125 @@
+use super::*;
+use super::rtype::sext_w;
+
+/// Rotate the low `xlen` bits of `value` right by `amount`.
+fn rotate_right(value: u64, amount: u32, xlen: u32) -> u64 {
+    if xlen == 32 { (value as u32).rotate_right(amount) as u64 } else { value.rotate_right(amount) }
+}
+
+/// The full 128-bit carry-less product of two 64-bit operands.
+fn clmul(first: u64, second: u64) -> u128 {
+    (0..64).filter(|i| second >> i & 1 != 0).fold(0, |acc, i| acc ^ (first as u128) << i)
+}
+
+/// Set every byte of `value` that has any bit set to all ones.
+fn orc_b(value: u64) -> u64 {
+    (0..8).filter(|i| value >> (8 * i) & 0xFF != 0).fold(0, |acc, i| acc | 0xFF << (8 * i))
+}
+
+impl Machine {
+    /// Check that the bit-manipulation extension `inst` belongs to is enabled.
+    fn bitmanip_enabled(&self, inst: Instruction) -> Result<(), ExecutionError> {
+        let (enabled, extension) = match inst {
+            Sh1add { .. } | Sh2add { .. } | Sh3add { .. } | AddUw { .. } | Sh1addUw { .. } |
+            Sh2addUw { .. } | Sh3addUw { .. } | SlliUw { .. } => (self.extensions.zba, "Zba"),
+            Andn { .. } | Orn { .. } | Xnor { .. } | Clz { .. } | Ctz { .. } | Cpop { .. } |
+            Clzw { .. } | Ctzw { .. } | Cpopw { .. } | Max { .. } | Maxu { .. } | Min { .. } |
+            Minu { .. } | SextB { .. } | SextH { .. } | ZextH { .. } | Rol { .. } | Ror { .. } |
+            Rori { .. } | Rolw { .. } | Rorw { .. } | Roriw { .. } | OrcB { .. } |
+            Rev8 { .. } => (self.extensions.zbb, "Zbb"),
+            Clmul { .. } | Clmulh { .. } | Clmulr { .. } => (self.extensions.zbc, "Zbc"),
+            Bclr { .. } | Bclri { .. } | Bext { .. } | Bexti { .. } | Binv { .. } | Binvi { .. } |
+            Bset { .. } | Bseti { .. } => (self.extensions.zbs, "Zbs"),
+            _ => unreachable!("{:?} is not a bit-manipulation instruction", inst),
+        };
+        if enabled { Ok(()) } else { Err(ExecutionError::Extension(extension.into())) }
+    }
+
+    pub(crate) fn handle_bitmanip(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        self.bitmanip_enabled(inst)?;
+
+        let illegal = || Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        match inst {
+            AddUw { .. } | Sh1addUw { .. } | Sh2addUw { .. } | Sh3addUw { .. } | SlliUw { .. } |
+            Clzw { .. } | Ctzw { .. } | Cpopw { .. } | Rolw { .. } | Rorw { .. } | Roriw { .. } => {
+                self.require_rv64(inst)?;
+            }
+            // Like the shifts, immediates of XLEN and up are reserved.
+            Rori { shamt, .. } | Bclri { shamt, .. } | Bexti { shamt, .. } | Binvi { shamt, .. } |
+            Bseti { shamt, .. } if shamt >= self.xlen => return illegal(),
+            Rev8 { xlen, .. } | ZextH { xlen, .. } if xlen != self.xlen => return illegal(),
+            _ => {}
+        }
+
+        let xlen = self.xlen;
+        match inst {
+            Sh1add { rd, rs1, rs2 } => self.set_reg(rd, (self.reg(rs1) << 1).wrapping_add(self.reg(rs2))),
+            Sh2add { rd, rs1, rs2 } => self.set_reg(rd, (self.reg(rs1) << 2).wrapping_add(self.reg(rs2))),
+            Sh3add { rd, rs1, rs2 } => self.set_reg(rd, (self.reg(rs1) << 3).wrapping_add(self.reg(rs2))),
+            // The .uw forms zero-extend the low word of rs1 first.
+            AddUw { rd, rs1, rs2 } => self.set_reg(rd, (self.reg(rs1) as u32 as u64).wrapping_add(self.reg(rs2))),
+            Sh1addUw { rd, rs1, rs2 } => {
+                self.set_reg(rd, ((self.reg(rs1) as u32 as u64) << 1).wrapping_add(self.reg(rs2)));
+            }
+            Sh2addUw { rd, rs1, rs2 } => {
+                self.set_reg(rd, ((self.reg(rs1) as u32 as u64) << 2).wrapping_add(self.reg(rs2)));
+            }
+            Sh3addUw { rd, rs1, rs2 } => {
+                self.set_reg(rd, ((self.reg(rs1) as u32 as u64) << 3).wrapping_add(self.reg(rs2)));
+            }
+            SlliUw { rd, rs1, shamt } => self.set_reg(rd, (self.reg(rs1) as u32 as u64) << shamt),
+            Andn { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) & !self.reg(rs2)),
+            Orn { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) | !self.reg(rs2)),
+            Xnor { rd, rs1, rs2 } => self.set_reg(rd, !(self.reg(rs1) ^ self.reg(rs2))),
+            // Registers are zero-extended to 64 bits, which clz must not count.
+            Clz { rd, rs1 } => self.set_reg(rd, (self.reg(rs1).leading_zeros() - (64 - xlen)) as u64),
+            Ctz { rd, rs1 } => self.set_reg(rd, self.reg(rs1).trailing_zeros().min(xlen) as u64),
+            Cpop { rd, rs1 } => self.set_reg(rd, self.reg(rs1).count_ones() as u64),
+            Clzw { rd, rs1 } => self.set_reg(rd, (self.reg(rs1) as u32).leading_zeros() as u64),
+            Ctzw { rd, rs1 } => self.set_reg(rd, (self.reg(rs1) as u32).trailing_zeros() as u64),
+            Cpopw { rd, rs1 } => self.set_reg(rd, (self.reg(rs1) as u32).count_ones() as u64),
+            Max { rd, rs1, rs2 } => self.set_reg(rd, self.sreg(rs1).max(self.sreg(rs2)) as u64),
+            Maxu { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1).max(self.reg(rs2))),
+            Min { rd, rs1, rs2 } => self.set_reg(rd, self.sreg(rs1).min(self.sreg(rs2)) as u64),
+            Minu { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1).min(self.reg(rs2))),
+            SextB { rd, rs1 } => self.set_reg(rd, self.reg(rs1) as i8 as i64 as u64),
+            SextH { rd, rs1 } => self.set_reg(rd, self.reg(rs1) as i16 as i64 as u64),
+            ZextH { rd, rs1, .. } => self.set_reg(rd, self.reg(rs1) as u16 as u64),
+            Rol { rd, rs1, rs2 } => {
+                let amount = self.reg(rs2) as u32 & (xlen - 1);
+                self.set_reg(rd, rotate_right(self.reg(rs1), (xlen - amount) % xlen, xlen));
+            }
+            Ror { rd, rs1, rs2 } => {
+                let amount = self.reg(rs2) as u32 & (xlen - 1);
+                self.set_reg(rd, rotate_right(self.reg(rs1), amount, xlen));
+            }
+            Rori { rd, rs1, shamt } => self.set_reg(rd, rotate_right(self.reg(rs1), shamt, xlen)),
+            Rolw { rd, rs1, rs2 } => {
+                self.set_reg(rd, sext_w((self.reg(rs1) as u32).rotate_left(self.reg(rs2) as u32 & 0x1F) as u64));
+            }
+            Rorw { rd, rs1, rs2 } => {
+                self.set_reg(rd, sext_w((self.reg(rs1) as u32).rotate_right(self.reg(rs2) as u32 & 0x1F) as u64));
+            }
+            Roriw { rd, rs1, shamt } => {
+                self.set_reg(rd, sext_w((self.reg(rs1) as u32).rotate_right(shamt) as u64));
+            }
+            OrcB { rd, rs1 } => self.set_reg(rd, orc_b(self.reg(rs1))),
+            Rev8 { rd, rs1, .. } => self.set_reg(rd, self.reg(rs1).swap_bytes() >> (64 - xlen)),
+            // clmulr returns bits 2*XLEN-2 down to XLEN-1 of the product.
+            Clmul { rd, rs1, rs2 } => self.set_reg(rd, clmul(self.reg(rs1), self.reg(rs2)) as u64),
+            Clmulh { rd, rs1, rs2 } => self.set_reg(rd, (clmul(self.reg(rs1), self.reg(rs2)) >> xlen) as u64),
+            Clmulr { rd, rs1, rs2 } => self.set_reg(rd, (clmul(self.reg(rs1), self.reg(rs2)) >> (xlen - 1)) as u64),
+            Bclr { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) & !(1 << (self.reg(rs2) & (xlen as u64 - 1)))),
+            Bclri { rd, rs1, shamt } => self.set_reg(rd, self.reg(rs1) & !(1 << shamt)),
+            Bext { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) >> (self.reg(rs2) & (xlen as u64 - 1)) & 1),
+            Bexti { rd, rs1, shamt } => self.set_reg(rd, self.reg(rs1) >> shamt & 1),
+            Binv { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) ^ 1 << (self.reg(rs2) & (xlen as u64 - 1))),
+            Binvi { rd, rs1, shamt } => self.set_reg(rd, self.reg(rs1) ^ 1 << shamt),
+            Bset { rd, rs1, rs2 } => self.set_reg(rd, self.reg(rs1) | 1 << (self.reg(rs2) & (xlen as u64 - 1))),
+            Bseti { rd, rs1, shamt } => self.set_reg(rd, self.reg(rs1) | 1 << shamt),
+            _ => unreachable!("{:?} is not a bit-manipulation instruction", inst),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use super::*;
+use super::rtype::sext_w;
+use super::super::softfloat::{self, Format, Rounding};
+
+fn precision_of(inst: Instruction) -> Precision {
+    match inst {
+        Fload { fmt, .. } | Fstore { fmt, .. } | Fmadd { fmt, .. } | Fmsub { fmt, .. } |
+        Fnmsub { fmt, .. } | Fnmadd { fmt, .. } | Fadd { fmt, .. } | Fsub { fmt, .. } |
+        Fmul { fmt, .. } | Fdiv { fmt, .. } | Fsqrt { fmt, .. } | Fsgnj { fmt, .. } |
+        Fsgnjn { fmt, .. } | Fsgnjx { fmt, .. } | Fmin { fmt, .. } | Fmax { fmt, .. } |
+        FcvtToInt { fmt, .. } | FcvtFromInt { fmt, .. } | FmvToInt { fmt, .. } |
+        FmvFromInt { fmt, .. } | Feq { fmt, .. } | Flt { fmt, .. } | Fle { fmt, .. } |
+        Fclass { fmt, .. } | FcvtFloat { fmt, .. } => fmt,
+        _ => unreachable!("{:?} is not a floating-point instruction", inst),
+    }
+}
+
+impl Machine {
+    pub(crate) fn handle_float(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        let fmt = self.float_format(precision_of(inst))?;
+        if !self.csrs.float_enabled() {
+            return Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        }
+
+        let sign = fmt.sign_bit();
+        let mut flags = 0;
+        match inst {
+            Fload { rd, rs1, imm, .. } => {
+                let address = self.effective_address(rs1, imm);
+                let value = match fmt.width() {
+                    32 => self.read_memory(address, 4, Access::Load)? as u128,
+                    64 => self.read_memory(address, 8, Access::Load)? as u128,
+                    _ => {
+                        let low = self.read_memory(address, 8, Access::Load)?;
+                        let high = self.read_memory(address.wrapping_add(8) & self.xlen_mask(), 8, Access::Load)?;
+                        (high as u128) << 64 | low as u128
+                    }
+                };
+                self.set_float(fmt, rd, value);
+            }
+            Fstore { rs1, rs2, imm, .. } => {
+                // Stores move the raw bits, whatever their NaN-boxing.
+                let address = self.effective_address(rs1, imm);
+                let bits = self.fregs[rs2];
+                match fmt.width() {
+                    32 => self.write_memory(address, 4, bits as u64)?,
+                    64 => self.write_memory(address, 8, bits as u64)?,
+                    _ => {
+                        self.write_memory(address, 8, bits as u64)?;
+                        self.write_memory(address.wrapping_add(8) & self.xlen_mask(), 8, (bits >> 64) as u64)?;
+                    }
+                }
+            }
+            Fmadd { rd, rs1, rs2, rs3, rm, .. } | Fmsub { rd, rs1, rs2, rs3, rm, .. } |
+            Fnmsub { rd, rs1, rs2, rs3, rm, .. } | Fnmadd { rd, rs1, rs2, rs3, rm, .. } => {
+                let rm = self.rounding(rm, inst)?;
+                let (a, b, c) = (self.float(fmt, rs1), self.float(fmt, rs2), self.float(fmt, rs3));
+                // The negated forms flip the product, the addend, or both.
+                let (a, c) = match inst {
+                    Fmadd { .. } => (a, c),
+                    Fmsub { .. } => (a, c ^ sign),
+                    Fnmsub { .. } => (a ^ sign, c),
+                    _ => (a ^ sign, c ^ sign),
+                };
+                let result = softfloat::fma(fmt, a, b, c, rm, &mut flags);
+                self.set_float(fmt, rd, result);
+            }
+            Fadd { rd, rs1, rs2, rm, .. } | Fsub { rd, rs1, rs2, rm, .. } |
+            Fmul { rd, rs1, rs2, rm, .. } | Fdiv { rd, rs1, rs2, rm, .. } => {
+                let rm = self.rounding(rm, inst)?;
+                let (a, b) = (self.float(fmt, rs1), self.float(fmt, rs2));
+                let result = match inst {
+                    Fadd { .. } => softfloat::add(fmt, a, b, rm, &mut flags),
+                    Fsub { .. } => softfloat::sub(fmt, a, b, rm, &mut flags),
+                    Fmul { .. } => softfloat::mul(fmt, a, b, rm, &mut flags),
+                    _ => softfloat::div(fmt, a, b, rm, &mut flags),
+                };
+                self.set_float(fmt, rd, result);
+            }
+            Fsqrt { rd, rs1, rm, .. } => {
+                let rm = self.rounding(rm, inst)?;
+                let result = softfloat::sqrt(fmt, self.float(fmt, rs1), rm, &mut flags);
+                self.set_float(fmt, rd, result);
+            }
+            Fsgnj { rd, rs1, rs2, .. } | Fsgnjn { rd, rs1, rs2, .. } | Fsgnjx { rd, rs1, rs2, .. } => {
+                let (a, b) = (self.float(fmt, rs1), self.float(fmt, rs2));
+                let injected = match inst {
+                    Fsgnj { .. } => b,
+                    Fsgnjn { .. } => !b,
+                    _ => a ^ b,
+                };
+                self.set_float(fmt, rd, (a & !sign) | (injected & sign));
+            }
+            Fmin { rd, rs1, rs2, .. } | Fmax { rd, rs1, rs2, .. } => {
+                let (a, b) = (self.float(fmt, rs1), self.float(fmt, rs2));
+                let result = softfloat::min_max(fmt, a, b, matches!(inst, Fmax { .. }), &mut flags);
+                self.set_float(fmt, rd, result);
+            }
+            FcvtToInt { rd, rs1, rm, int, .. } => {
+                let rm = self.rounding(rm, inst)?;
+                if int.width() == 64 { self.require_rv64(inst)?; }
+                let value = softfloat::to_int(fmt, self.float(fmt, rs1), rm, int.is_signed(), int.width(), &mut flags);
+                // 32-bit results are sign-extended to XLEN, even unsigned ones.
+                let value = if int.width() == 32 { sext_w(value as u64) } else { value as u64 };
+                self.set_reg(rd, value);
+            }
+            FcvtFromInt { rd, rs1, rm, int, .. } => {
+                if int.width() == 64 { self.require_rv64(inst)?; }
+                let rm = self.rounding(rm, inst)?;
+                let value = match (int.is_signed(), int.width()) {
+                    (true, 32) => self.reg(rs1) as i32 as i128,
+                    (false, 32) => self.reg(rs1) as u32 as i128,
+                    (true, _) => self.reg(rs1) as i64 as i128,
+                    (false, _) => self.reg(rs1) as i128,
+                };
+                let result = softfloat::from_int(fmt, value, rm, &mut flags);
+                self.set_float(fmt, rd, result);
+            }
+            FmvToInt { rd, rs1, .. } => {
+                if fmt.width() > self.xlen { return Err(ExecutionError::InvalidInstruction(inst.to_string())); }
+                let bits = self.fregs[rs1] as u64 & fmt.mask() as u64;
+                // fmv.x.w sign-extends the single on RV64.
+                let bits = if fmt.width() == 32 { sext_w(bits) } else { bits };
+                self.set_reg(rd, bits);
+            }
+            FmvFromInt { rd, rs1, .. } => {
+                if fmt.width() > self.xlen { return Err(ExecutionError::InvalidInstruction(inst.to_string())); }
+                let bits = self.reg(rs1) as u128 & fmt.mask();
+                self.set_float(fmt, rd, bits);
+            }
+            Feq { rd, rs1, rs2, .. } | Flt { rd, rs1, rs2, .. } | Fle { rd, rs1, rs2, .. } => {
+                let (a, b) = (self.float(fmt, rs1), self.float(fmt, rs2));
+                let result = match inst {
+                    Feq { .. } => softfloat::eq(fmt, a, b, &mut flags),
+                    Flt { .. } => softfloat::lt(fmt, a, b, &mut flags),
+                    _ => softfloat::le(fmt, a, b, &mut flags),
+                };
+                self.set_reg(rd, result as u64);
+            }
+            Fclass { rd, rs1, .. } => {
+                let class = softfloat::classify(fmt, self.float(fmt, rs1));
+                self.set_reg(rd, class as u64);
+            }
+            FcvtFloat { rd, rs1, rm, from, .. } => {
+                let rm = self.rounding(rm, inst)?;
+                let from = self.float_format(from)?;
+                let result = softfloat::convert(from, fmt, self.float(from, rs1), rm, &mut flags);
+                self.set_float(fmt, rd, result);
+            }
+            _ => unreachable!("{:?} is not an F, D or Q instruction", inst),
+        }
+
+        if flags != 0 {
+            self.csrs.fflags |= flags as u32;
+            self.csrs.set_float_dirty();
+        }
+        Ok(())
+    }
+
+    /// The softfloat format for `precision`, if its extension is enabled.
+    fn float_format(&self, precision: Precision) -> Result<Format, ExecutionError> {
+        match precision {
+            Precision::S if self.extensions.f => Ok(softfloat::F32),
+            Precision::S => Err(ExecutionError::Extension("F".into())),
+            Precision::D if self.extensions.d => Ok(softfloat::F64),
+            Precision::D => Err(ExecutionError::Extension("D".into())),
+            Precision::Q if self.extensions.q => Ok(softfloat::F128),
+            Precision::Q => Err(ExecutionError::Extension("Q".into())),
+        }
+    }
+
+    /// The rounding mode an `rm` field selects; 7 defers to `frm`, and
+    /// reserved modes make the instruction illegal.
+    fn rounding(&self, rm: u8, inst: Instruction) -> Result<Rounding, ExecutionError> {
+        let rm = if rm == 7 { self.csrs.frm } else { rm as u32 };
+        Rounding::from_bits(rm).ok_or_else(|| ExecutionError::InvalidInstruction(inst.to_string()))
+    }
+
+    /// Register `index` read as a `fmt` value. A value that is not properly
+    /// NaN-boxed reads as the canonical NaN.
+    fn float(&self, fmt: Format, index: usize) -> u128 {
+        let bits = self.fregs[index];
+        if bits | fmt.mask() != !0 { fmt.canonical_nan() } else { bits & fmt.mask() }
+    }
+
+    /// Write a `fmt` value to register `index`, NaN-boxing it.
+    fn set_float(&mut self, fmt: Format, index: usize, value: u128) {
+        self.fregs[index] = value | !fmt.mask();
+        self.csrs.set_float_dirty();
+    }
+}
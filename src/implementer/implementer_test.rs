@@ -0,0 +1,777 @@
+use super::*;
+
+fn run_program(words: &[u32], m: bool) -> (Machine, StopReason) {
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.m = m;
+    let mut machine = Machine::new(config);
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect();
+    machine.load(RAM_BASE, &bytes).unwrap();
+    let reason = machine.run(Some(100));
+    (machine, reason)
+}
+
+#[test]
+fn test_add_wraps_around() {
+    let (machine, reason) = run_program(&[
+        0xfff00293, // addi x5, x0, -1
+        0x00100313, // addi x6, x0, 1
+        0x006283b3, // add x7, x5, x6
+    ], false);
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(7), 0);
+}
+
+#[test]
+fn test_bge_taken_when_greater() {
+    let (machine, _) = run_program(&[
+        0xfff00293, // addi x5, x0, -1
+        0x00100313, // addi x6, x0, 1
+        0x00535463, // bge x6, x5, 8
+        0x00100413, // addi x8, x0, 1
+        0x00100493, // addi x9, x0, 1
+    ], false);
+    assert_eq!(machine.reg(8), 0);
+    assert_eq!(machine.reg(9), 1);
+}
+
+#[test]
+fn test_mulh_variants() {
+    let (machine, _) = run_program(&[
+        0xfff00293, // addi x5, x0, -1
+        0x02529533, // mulh x10, x5, x5
+        0x0252b5b3, // mulhu x11, x5, x5
+        0x0252a633, // mulhsu x12, x5, x5
+    ], true);
+    assert_eq!(machine.reg(10), 0);
+    assert_eq!(machine.reg(11), 0xFFFF_FFFE);
+    assert_eq!(machine.reg(12), 0xFFFF_FFFF);
+}
+
+#[test]
+fn test_division_edge_cases() {
+    let (machine, _) = run_program(&[
+        0xfff00293, // addi x5, x0, -1
+        0x0202c6b3, // div x13, x5, x0
+        0x0202e733, // rem x14, x5, x0
+        0x800002b7, // lui x5, 0x80000
+        0xfff00313, // addi x6, x0, -1
+        0x0262c3b3, // div x7, x5, x6
+        0x0262e433, // rem x8, x5, x6
+    ], true);
+    assert_eq!(machine.reg(13), 0xFFFF_FFFF);
+    assert_eq!(machine.reg(14), 0xFFFF_FFFF);
+    assert_eq!(machine.reg(7), 0x8000_0000);
+    assert_eq!(machine.reg(8), 0);
+}
+
+#[test]
+fn test_jal_and_jalr_link() {
+    let (machine, reason) = run_program(&[
+        0x008000ef, // jal x1, 8
+        0x00100493, // addi x9, x0, 1
+        0x000080e7, // jalr x1, 0(x1)
+    ], false);
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(9), 1);
+    assert_eq!(machine.reg(1), 12);
+    assert_eq!(machine.pc(), 12);
+}
+
+#[test]
+fn test_srai_shifts_in_sign_bits() {
+    let (machine, _) = run_program(&[
+        0x800002b7, // lui x5, 0x80000
+        0x4042d793, // srai x15, x5, 4
+    ], false);
+    assert_eq!(machine.reg(15), 0xF800_0000);
+}
+
+#[test]
+fn test_misaligned_branch_target() {
+    let (machine, reason) = run_program(&[
+        0x00000163, // beq x0, x0, 2
+    ], false);
+    assert_eq!(reason, StopReason::Error(ExecutionError::InstructionAddressMisaligned(2)));
+    assert_eq!(machine.pc(), 0);
+}
+
+#[test]
+fn test_m_instructions_need_extension() {
+    let (_, reason) = run_program(&[
+        0x0252b5b3, // mulhu x11, x5, x5
+    ], false);
+    assert_eq!(reason, StopReason::Error(ExecutionError::Extension("M".into())));
+}
+
+fn run_config(config: Config, src: &str) -> (Machine, StopReason) {
+    let mut machine = Machine::new(config);
+    machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+    let reason = machine.run(Some(100));
+    (machine, reason)
+}
+
+fn run_source(src: &str) -> (Machine, StopReason) {
+    run_config(Config { bare: true, ..Config::default() }, src)
+}
+
+#[test]
+fn test_csr_read_modify_write() {
+    let (machine, reason) = run_source("
+        li t0, 0x55
+        csrrw zero, mscratch, t0
+        li t1, 0x0f
+        csrrs a0, mscratch, t1
+        csrrc a1, mscratch, t0
+        csrrwi a2, mscratch, 31
+        csrrsi a3, mscratch, 0
+        csrrci a4, mscratch, 1
+        csrr a5, mscratch
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 0x55);
+    assert_eq!(machine.reg(11), 0x5f);
+    assert_eq!(machine.reg(12), 0x0a);
+    assert_eq!(machine.reg(13), 31);
+    assert_eq!(machine.reg(14), 31);
+    assert_eq!(machine.reg(15), 30);
+}
+
+#[test]
+fn test_csr_read_only_rules() {
+    // Reading a read-only CSR is fine as long as nothing is written...
+    let (machine, reason) = run_source("
+        li a1, 7
+        csrrs a0, mhartid, zero
+        csrrci a1, mhartid, 0
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 0);
+    assert_eq!(machine.reg(11), 0);
+
+    // ...but a set with a non-x0 source register writes, even if it is zero.
+    let (_, reason) = run_source("csrrs a0, mhartid, a1");
+    assert_eq!(reason, StopReason::Error(ExecutionError::InvalidInstruction("csrrs a0,mhartid,a1".into())));
+
+    let (_, reason) = run_source("csrw mhartid, zero");
+    assert!(matches!(reason, StopReason::Error(ExecutionError::InvalidInstruction(_))));
+}
+
+#[test]
+fn test_unknown_csr_is_illegal() {
+    let (_, reason) = run_source("csrrw zero, 0x7ff, zero");
+    assert_eq!(reason, StopReason::Error(ExecutionError::InvalidInstruction("csrrw zero,0x7ff,zero".into())));
+}
+
+#[test]
+fn test_fence_i_makes_stored_instructions_visible() {
+    let (machine, reason) = run_source("
+        la t0, patch
+        li t1, 0x02a00513    # addi a0, zero, 42
+        sw t1, 0(t0)
+        fence rw, rw
+        fence.i
+    patch:
+        addi a0, zero, 1
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 42);
+}
+
+fn run_atomic_source(src: &str) -> (Machine, StopReason) {
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.a = true;
+    run_config(config, src)
+}
+
+#[test]
+fn test_lr_sc_reservation() {
+    let (mut machine, reason) = run_atomic_source("
+        la s0, value
+        lr.w a0, (s0)
+        addi a0, a0, 1
+        sc.w a1, a0, (s0)
+        sc.w a2, a0, (s0)
+        la s1, other
+        lr.w t0, (s0)
+        sc.w a3, a0, (s1)
+        li a0, 10
+        ecall
+    .data
+    value: .word 41
+    other: .word 0
+    ");
+    assert_eq!(reason, StopReason::Error(ExecutionError::UserTerminate));
+    assert_eq!(machine.reg(11), 0, "first sc.w succeeds");
+    assert_eq!(machine.reg(12), 1, "the reservation is consumed");
+    assert_eq!(machine.reg(13), 1, "sc.w to a different address fails");
+    let value = machine.reg(8);
+    assert_eq!(machine.bus_mut().read_u32(value), Ok(42));
+    assert_eq!(machine.bus_mut().read_u32(value + 4), Ok(0));
+}
+
+#[test]
+fn test_amo_operations() {
+    let (machine, _) = run_atomic_source("
+        la s0, value
+        li t0, -8
+        amoadd.w a0, t0, (s0)
+        amomin.w a1, t0, (s0)
+        li t1, 3
+        amomaxu.w a2, t1, (s0)
+        amomax.w a3, t1, (s0)
+        amoswap.w.aqrl a4, zero, (s0)
+        li t2, 0xf0
+        amoor.w a5, t2, (s0)
+        amoxor.w a6, t1, (s0)
+        amoand.w a7, t1, (s0)
+        amominu.w s1, t2, (s0)
+        lw s2, 0(s0)
+    end:
+        j end
+    .data
+    value: .word 5
+    ");
+    // Each rd holds the memory word from before its AMO.
+    assert_eq!(machine.reg(10), 5);
+    assert_eq!(machine.reg(11), 0xFFFF_FFFD);
+    assert_eq!(machine.reg(12), 0xFFFF_FFF8);
+    assert_eq!(machine.reg(13), 0xFFFF_FFF8);
+    assert_eq!(machine.reg(14), 3);
+    assert_eq!(machine.reg(15), 0);
+    assert_eq!(machine.reg(16), 0xf0);
+    assert_eq!(machine.reg(17), 0xf3);
+    assert_eq!(machine.reg(9), 3);
+    assert_eq!(machine.reg(18), 3);
+}
+
+#[test]
+fn test_atomics_need_extension_and_alignment() {
+    let (_, reason) = run_source("amoadd.w a0, a1, (a2)");
+    assert_eq!(reason, StopReason::Error(ExecutionError::Extension("A".into())));
+
+    let (_, reason) = run_atomic_source("li a2, 2\namoadd.w a0, a1, (a2)");
+    assert_eq!(reason, StopReason::Error(ExecutionError::StoreAddressMisaligned(2)));
+    let (_, reason) = run_atomic_source("li a2, 2\nlr.w a0, (a2)");
+    assert_eq!(reason, StopReason::Error(ExecutionError::LoadAddressMisaligned(2)));
+}
+
+fn run_float_source(src: &str) -> (Machine, StopReason) {
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.f = true;
+    config.extensions.d = true;
+    run_config(config, src)
+}
+
+#[test]
+fn test_float_arithmetic() {
+    let (machine, reason) = run_float_source("
+        la s0, values
+        flw fa0, 0(s0)
+        flw fa1, 4(s0)
+        fadd.s fa2, fa0, fa1
+        fmul.s fa3, fa0, fa1
+        fsqrt.s fa4, fa3
+        fmadd.s fa5, fa0, fa1, fa2
+        fsw fa5, 8(s0)
+        fcvt.w.s a0, fa2
+        li t0, -7
+        fcvt.s.w fa6, t0
+        fmv.x.w a1, fa6
+        flt.s a2, fa6, fa0
+        fclass.s a3, fa6
+        fsgnjx.s fa7, fa0, fa6
+        fmv.x.w a4, fa7
+        lw a5, 8(s0)
+    .data
+    values: .word 0x40200000, 0x40800000, 0   # 2.5, 4.0
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    let single = |r: usize| f32::from_bits(machine.freg(r) as u32);
+    assert_eq!(single(12), 6.5);
+    assert_eq!(single(13), 10.0);
+    assert_eq!(single(14), 10.0f32.sqrt());
+    assert_eq!(single(15), 16.5);
+    assert_eq!(machine.reg(10), 6);
+    assert_eq!(machine.reg(11), (-7.0f32).to_bits() as u64);
+    assert_eq!(machine.reg(12), 1);
+    assert_eq!(machine.reg(13), 1 << 1);
+    assert_eq!(machine.reg(14), (-2.5f32).to_bits() as u64);
+    assert_eq!(machine.reg(15), 16.5f32.to_bits() as u64);
+    assert_eq!(machine.freg(12) >> 32, !0 >> 32, "single-precision results are NaN-boxed");
+}
+
+#[test]
+fn test_float_rounding_modes_and_flags() {
+    let (machine, reason) = run_float_source("
+        li t0, 0x3fc00000        # 1.5
+        fmv.w.x fa0, t0
+        fcvt.w.s a0, fa0
+        fsrm a1, t0              # rtz, keeping the old mode in a1
+        fsrmi 1
+        fcvt.w.s a2, fa0
+        fcvt.w.s a3, fa0, rup
+        frflags a4
+        fmv.w.x fa1, zero
+        fdiv.s fa2, fa0, fa1
+        fdiv.s fa3, fa1, fa1
+        fmv.x.w a5, fa3
+        frcsr a6
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 2, "ties to even");
+    assert_eq!(machine.reg(11), 0);
+    assert_eq!(machine.reg(12), 1);
+    assert_eq!(machine.reg(13), 2);
+    assert_eq!(machine.reg(14), 0x01, "inexact");
+    assert_eq!(machine.reg(15), 0x7fc00000, "canonical NaN");
+    assert_eq!(machine.reg(16), (1 << 5) | 0x10 | 0x08 | 0x01);
+}
+
+#[test]
+fn test_double_arithmetic_and_conversions() {
+    let (machine, reason) = run_float_source("
+        la s0, values
+        fld fa0, 0(s0)
+        fld fa1, 8(s0)
+        fdiv.d fa2, fa0, fa1
+        fnmadd.d fa3, fa0, fa1, fa0
+        fsd fa3, 16(s0)
+        fcvt.s.d fa4, fa2
+        fcvt.d.s fa5, fa4
+        feq.d a0, fa5, fa2
+        flt.d a1, fa1, fa0
+        fclass.d a2, fa3
+        fcvt.w.d a3, fa3
+        lw a4, 16(s0)
+        lw a5, 20(s0)
+        frflags a6
+    .data
+    values: .word 0, 0x40040000, 0, 0x40080000, 0, 0   # 2.5, 3.0
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    let double = |r: usize| f64::from_bits(machine.freg(r) as u64);
+    assert_eq!(double(12), 2.5 / 3.0);
+    assert_eq!(double(13), -10.0);
+    assert_eq!(machine.freg(14) as u32, (2.5f32 / 3.0).to_bits());
+    assert_eq!(double(15), (2.5f32 / 3.0) as f64);
+    assert_eq!(machine.reg(10), 0);
+    assert_eq!(machine.reg(11), 0);
+    assert_eq!(machine.reg(12), 1 << 1);
+    assert_eq!(machine.reg(13), -10i32 as u32 as u64);
+    assert_eq!(machine.reg(14), 0);
+    assert_eq!(machine.reg(15), (-10.0f64).to_bits() >> 32);
+    assert_eq!(machine.reg(16), 0x01, "inexact");
+}
+
+#[test]
+fn test_nan_boxing() {
+    let (machine, reason) = run_float_source("
+        la s0, values
+        flw fa0, 0(s0)           # a single, boxed as 0xffffffff_3f800000
+        fsgnj.d fa1, fa0, fa0
+        fld fa2, 0(s0)           # a double whose upper half is not all ones
+        fadd.s fa3, fa2, fa2
+        fmv.x.w a0, fa3
+        fsw fa2, 8(s0)
+        lw a1, 8(s0)
+    .data
+    values: .word 0x3f800000, 0x3ff00000, 0
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.freg(11) as u64, 0xffffffff_3f800000);
+    assert_eq!(machine.reg(10), 0x7fc00000, "an unboxed single reads as the canonical NaN");
+    assert_eq!(machine.reg(11), 0x3f800000, "stores move the raw bits");
+}
+
+#[test]
+fn test_float_illegal_cases() {
+    let (_, reason) = run_source("fadd.s fa0, fa1, fa2");
+    assert_eq!(reason, StopReason::Error(ExecutionError::Extension("F".into())));
+
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.f = true;
+    let mut machine = Machine::new(config);
+    machine.load(RAM_BASE, &assembler::assemble("fadd.d fa0, fa1, fa2", RAM_BASE as u32).unwrap()).unwrap();
+    assert_eq!(machine.run(Some(10)), StopReason::Error(ExecutionError::Extension("D".into())));
+
+    let (_, reason) = run_float_source("
+        csrwi frm, 5
+        fadd.s fa0, fa1, fa2
+    ");
+    assert_eq!(reason, StopReason::Error(ExecutionError::InvalidInstruction("fadd.s fa0,fa1,fa2".into())));
+
+    // With mstatus.FS off the unit and its CSRs are unavailable.
+    let (_, reason) = run_float_source("
+        li t0, 0x6000
+        csrc mstatus, t0
+        fmv.w.x fa0, zero
+    ");
+    assert_eq!(reason, StopReason::Error(ExecutionError::InvalidInstruction("fmv.w.x fa0,zero".into())));
+}
+
+#[test]
+fn test_quad_arithmetic_and_conversions() {
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.f = true;
+    config.extensions.d = true;
+    config.extensions.q = true;
+    let mut machine = Machine::new(config);
+    let src = "
+        la s0, values
+        li t0, 1
+        fcvt.q.w fa0, t0
+        li t0, 3
+        fcvt.q.w fa1, t0
+        fdiv.q fa2, fa0, fa1
+        fsq fa2, 16(s0)
+        lw a0, 28(s0)
+        flq fa3, 16(s0)
+        feq.q a1, fa2, fa3
+        fcvt.d.q fa4, fa2
+        fmul.q fa5, fa2, fa1     # rounds back to exactly one
+        fcvt.w.q a2, fa5
+        fld fa6, 0(s0)
+        fcvt.q.d fa7, fa6
+        fclass.q a3, fa7
+    .data
+    values: .word 0, 0xbff00000, 0, 0, 0, 0, 0, 0
+    ";
+    machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+    assert_eq!(machine.run(Some(100)), StopReason::EndOfProgram);
+    assert_eq!(machine.freg(12), 0x3ffd_5555_5555_5555_5555_5555_5555_5555);
+    assert_eq!(machine.reg(10), 0x3ffd_5555);
+    assert_eq!(machine.reg(11), 1);
+    assert_eq!(machine.freg(14), 0xffff_ffff_ffff_ffff_0000_0000_0000_0000 | (1.0f64 / 3.0).to_bits() as u128);
+    assert_eq!(machine.reg(12), 1);
+    assert_eq!(machine.freg(17), 0xbfff_0000_0000_0000_0000_0000_0000_0000);
+    assert_eq!(machine.reg(13), 1 << 1, "negative normal");
+    assert_eq!(machine.csrs().read(csr::FFLAGS), Some(0x01));
+
+    let (_, reason) = run_float_source("fadd.q fa0, fa1, fa2");
+    assert_eq!(reason, StopReason::Error(ExecutionError::Extension("Q".into())));
+}
+
+fn run_rv64_source(src: &str) -> (Machine, StopReason) {
+    let mut config = Config { xlen: 64, bare: true, ..Config::default() };
+    config.extensions.m = true;
+    config.extensions.f = true;
+    config.extensions.d = true;
+    config.extensions.f = true;
+    run_config(config, src)
+}
+
+#[test]
+fn test_rv64_word_instructions_sign_extend() {
+    let (machine, reason) = run_rv64_source("
+        lui t0, 0x80000
+        addiw t0, t0, -1         # 0x7fffffff
+        addiw a0, t0, 1          # wraps to a negative word
+        addi a1, t0, 1           # full width: no wrap
+        slli t1, t0, 33
+        srai a2, t1, 63
+        sraw a3, t1, zero        # only the low word, which is zero
+        srliw a4, a0, 4
+        li t2, -1
+        srli a5, t2, 60
+        sext.w a6, a1
+        subw a7, zero, t0
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 0xffff_ffff_8000_0000);
+    assert_eq!(machine.reg(11), 0x8000_0000);
+    assert_eq!(machine.reg(12), !0);
+    assert_eq!(machine.reg(13), 0);
+    assert_eq!(machine.reg(14), 0x0800_0000);
+    assert_eq!(machine.reg(15), 0xf);
+    assert_eq!(machine.reg(16), 0xffff_ffff_8000_0000);
+    assert_eq!(machine.reg(17), 0xffff_ffff_8000_0001);
+}
+
+#[test]
+fn test_rv64_loads_and_stores() {
+    let (machine, reason) = run_rv64_source("
+        la s0, values
+        ld a0, 0(s0)
+        lw a1, 8(s0)
+        lwu a2, 8(s0)
+        sd a0, 16(s0)
+        lw a3, 20(s0)
+    .data
+            .dword 0                 # ends the program
+    values: .dword 0x0123456789abcdef
+            .word 0xfffffff0, 0
+            .dword 0
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 0x0123_4567_89ab_cdef);
+    assert_eq!(machine.reg(11), 0xffff_ffff_ffff_fff0);
+    assert_eq!(machine.reg(12), 0xffff_fff0);
+    assert_eq!(machine.reg(13), 0x0123_4567);
+}
+
+#[test]
+fn test_rv64_multiply_and_divide() {
+    let (machine, reason) = run_rv64_source("
+        li t0, -1
+        mulhu a0, t0, t0         # (2^64 - 1)^2 >> 64
+        mulh a1, t0, t0
+        li t1, 0x80000000
+        sext.w t1, t1            # the most negative word
+        li t2, -1
+        divw a2, t1, t2          # overflows back to the dividend
+        remw a3, t1, t2
+        divuw a4, t1, zero
+        mulw a5, t1, t1
+        div a6, t1, t2           # does not overflow at 64 bits
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 0xffff_ffff_ffff_fffe);
+    assert_eq!(machine.reg(11), 0);
+    assert_eq!(machine.reg(12), 0xffff_ffff_8000_0000);
+    assert_eq!(machine.reg(13), 0);
+    assert_eq!(machine.reg(14), !0);
+    assert_eq!(machine.reg(15), 0);
+    assert_eq!(machine.reg(16), 0x8000_0000);
+}
+
+#[test]
+fn test_rv64_float_conversions() {
+    let (machine, reason) = run_rv64_source("
+        la s0, values
+        fld fa0, 0(s0)
+        fcvt.l.d a0, fa0, rtz
+        fmv.x.d a1, fa0
+        li t0, -3
+        fcvt.s.l fa1, t0
+        fmv.x.w a2, fa1          # sign-extended from 32 bits
+        fcvt.wu.d a3, fa0, rtz   # saturates to 0 and sign-extends
+    .data
+    values: .dword 0xc202a05f20000000  # -1e10
+    ");
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), -10_000_000_000i64 as u64);
+    assert_eq!(machine.reg(11), 0xc202_a05f_2000_0000);
+    assert_eq!(machine.reg(12), (-3.0f32).to_bits() as i32 as i64 as u64);
+    assert_eq!(machine.reg(13), 0);
+}
+
+#[test]
+fn test_rv64_instructions_are_illegal_on_rv32() {
+    for src in &["addw a0, a1, a2", "ld a0, 0(sp)", "slli a0, a0, 32", "sext.w a0, a0"] {
+        let (_, reason) = run_source(src);
+        match reason {
+            StopReason::Error(ExecutionError::InvalidInstruction(_)) => {}
+            other => panic!("`{}` stopped with {:?}", src, other),
+        }
+    }
+}
+
+fn run_bitmanip_source(src: &str, xlen: u32) -> (Machine, StopReason) {
+    let mut config = Config { xlen, bare: true, ..Config::default() };
+    config.extensions.zba = true;
+    config.extensions.zbb = true;
+    config.extensions.zbc = true;
+    config.extensions.zbs = true;
+    run_config(config, src)
+}
+
+#[test]
+fn test_bitmanip_operations() {
+    let (machine, reason) = run_bitmanip_source("
+        li t0, -5
+        li t1, 3
+        li t2, 0x12345678
+        li t3, 4
+        li t4, 0x00f00000
+        li t5, 0x80
+        clz a0, t4
+        ctz a1, t4
+        cpop a2, t4
+        min a3, t0, t1
+        minu a4, t0, t1
+        maxu a5, t0, t1
+        sh3add a6, t1, t0
+        rev8 a7, t2
+        rori s2, t2, 4
+        rol s3, t2, t3
+        sext.b s4, t5
+        zext.h s5, t0
+        andn s6, t2, t4
+        bseti s7, zero, 31
+        bext s8, t2, t3
+        clmulh s9, s7, s7
+        clmulr s10, s7, s7
+        orc.b s11, t4
+    ", 32);
+    assert_eq!(reason, StopReason::EndOfProgram);
+    let expected = [8, 20, 4, 0xffff_fffb, 3, 0xffff_fffb, 19, 0x7856_3412];
+    assert_eq!(machine.registers()[10..18], expected);
+    let expected = [
+        0x8123_4567, 0x2345_6781, 0xffff_ff80, 0xfffb, 0x1204_5678, 0x8000_0000, 1, 0x4000_0000, 0x8000_0000,
+        0x00ff_0000,
+    ];
+    assert_eq!(machine.registers()[18..28], expected);
+}
+
+#[test]
+fn test_rv64_bitmanip_operations() {
+    let (machine, reason) = run_bitmanip_source("
+        li t0, -1
+        li t1, 1
+        li t2, 0x12345678
+        slli t2, t2, 32
+        add.uw a0, t0, zero
+        slli.uw a1, t0, 4
+        clzw a2, zero
+        clz a3, t1
+        .word 0x6b83d713    # rev8 a4, t2
+        roriw a5, t1, 1
+        cpop a6, t0
+        bseti a7, zero, 63
+    ", 64);
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!(machine.registers()[10..18], [
+        0xffff_ffff, 0xf_ffff_fff0, 32, 63, 0x7856_3412, 0xffff_ffff_8000_0000, 64, 1 << 63,
+    ]);
+
+    // The RV32 encoding of rev8 is a different instruction on RV64.
+    let (_, reason) = run_bitmanip_source("rev8 a0, a1", 64);
+    match reason {
+        StopReason::Error(ExecutionError::InvalidInstruction(_)) => {}
+        other => panic!("rev8 stopped with {:?}", other),
+    }
+}
+
+#[test]
+fn test_bitmanip_instructions_need_their_extension() {
+    for &(src, extension) in &[("sh1add a0, a1, a2", "Zba"), ("cpop a0, a1", "Zbb"),
+                               ("clmul a0, a1, a2", "Zbc"), ("bset a0, a1, a2", "Zbs")] {
+        let (_, reason) = run_source(src);
+        assert_eq!(reason, StopReason::Error(ExecutionError::Extension(extension.into())));
+    }
+}
+
+fn run_vector_source(src: &str, vlen: u32) -> (Machine, StopReason) {
+    let mut config = Config { vlen, bare: true, ..Config::default() };
+    config.extensions.v = true;
+    run_config(config, src)
+}
+
+/// Element `index` of vector register `reg`, `width` bits wide.
+fn velement(machine: &Machine, reg: usize, index: usize, width: usize) -> u64 {
+    let bytes = &machine.vreg(reg)[index * width / 8..(index + 1) * width / 8];
+    bytes.iter().rev().fold(0, |acc, &byte| acc << 8 | byte as u64)
+}
+
+#[test]
+fn test_vsetvl_picks_vl_from_vlen_and_vtype() {
+    let (machine, reason) = run_vector_source("
+        vsetvli a0, zero, e32, m1, ta, ma
+        vsetvli a1, zero, e8, m8, ta, ma
+        li t0, 3
+        vsetvli a2, t0, e16, mf2, tu, mu
+        vsetivli a3, 31, e64, m2, ta, ma
+        csrr a4, vtype
+        csrr a5, vlenb
+        vsetvli a6, zero, e64, mf8, ta, ma
+        csrr a7, vtype
+    ", 256);
+    assert_eq!(reason, StopReason::EndOfProgram);
+    // SEW 64 with LMUL 1/8 leaves no room for an element, so it sets vill.
+    assert_eq!(machine.registers()[10..18], [8, 256, 3, 8, 0xD9, 32, 0, 0x8000_0000]);
+    assert_eq!(machine.csrs().read(csr::VL), Some(0));
+}
+
+#[test]
+fn test_vector_loads_and_stores() {
+    let (machine, reason) = run_vector_source("
+        la a0, source
+        la a1, dest
+        li t0, 8
+        vsetivli zero, 4, e32, m1, ta, ma
+        vle32.v v1, (a0)
+        vadd.vi v1, v1, 1
+        vse32.v v1, (a1)
+        vlse32.v v2, (a0), t0
+        vsetivli zero, 4, e8, m1, ta, ma
+        vle8.v v3, (a1)
+        vsetivli zero, 2, e32, m1, ta, ma
+        vluxei8.v v4, (a0), v3
+        vl1re32.v v5, (a0)
+        .data
+        .word 0     # ends the program
+    source: .word 10, 20, 30, 40, 50, 60
+    dest:   .word 0, 0, 0, 0
+    ", 128);
+    assert_eq!(reason, StopReason::EndOfProgram);
+    assert_eq!((0..4).map(|i| velement(&machine, 1, i, 32)).collect::<Vec<_>>(), [11, 21, 31, 41]);
+    // The last strided element runs past the source into dest.
+    assert_eq!((0..4).map(|i| velement(&machine, 2, i, 32)).collect::<Vec<_>>(), [10, 30, 50, 11]);
+    // Indexed loads use v3's bytes, 11 and 0, as byte offsets.
+    assert_eq!((0..4).map(|i| velement(&machine, 3, i, 8)).collect::<Vec<_>>(), [11, 0, 0, 0]);
+    assert_eq!(velement(&machine, 4, 1, 32), 10);
+    assert_eq!((0..4).map(|i| velement(&machine, 5, i, 32)).collect::<Vec<_>>(), [10, 20, 30, 40]);
+}
+
+#[test]
+fn test_vector_arithmetic_masking_and_reductions() {
+    let (machine, reason) = run_vector_source("
+        vsetivli zero, 8, e16, m1, ta, mu
+        vid.v v1
+        li t0, 4
+        vmsltu.vx v0, v1, t0
+        vmv.v.i v2, -1
+        vadd.vv v2, v1, v1, v0.t
+        vmerge.vim v3, v1, 7, v0
+        li t1, 3
+        vmul.vx v4, v1, t1
+        vmv.s.x v5, zero
+        vredsum.vs v5, v4, v5
+        vmv.x.s a0, v5
+        vcpop.m a1, v0
+        vmnand.mm v6, v0, v0
+        vfirst.m a2, v6
+        vsetivli zero, 4, e16, m1, ta, mu
+        vmv.v.i v7, -8
+        vdiv.vx v7, v7, t1
+        vmv.x.s a3, v7
+    ", 128);
+    assert_eq!(reason, StopReason::EndOfProgram);
+    // Masked-off elements keep their old value.
+    assert_eq!((0..8).map(|i| velement(&machine, 2, i, 16)).collect::<Vec<_>>(),
+               [0, 2, 4, 6, 0xffff, 0xffff, 0xffff, 0xffff]);
+    assert_eq!((0..8).map(|i| velement(&machine, 3, i, 16)).collect::<Vec<_>>(), [7, 7, 7, 7, 4, 5, 6, 7]);
+    assert_eq!(machine.registers()[10..14], [84, 4, 4, 0xffff_fffe]);
+}
+
+#[test]
+fn test_vector_instructions_need_a_valid_configuration() {
+    let mut machine = Machine::new(Config { bare: true, ..Config::default() });
+    machine.load(RAM_BASE, &assembler::assemble("vadd.vv v1, v2, v3", 0).unwrap()).unwrap();
+    assert_eq!(machine.run(Some(1)), StopReason::Error(ExecutionError::Extension("V".into())));
+
+    for src in &[
+        "vadd.vv v1, v2, v3",
+        "vsetvli zero, zero, e32, m2, ta, ma\n vadd.vv v1, v2, v4",
+        "vsetvli zero, zero, e32, m1, ta, ma\n vadd.vv v0, v2, v4, v0.t",
+        "vsetvli zero, zero, e8, m1, ta, ma\n vle64.v v1, (zero)",
+        "vsetvli zero, zero, e8, m1, ta, ma\n vredsum.vs v1, v2, v3\n csrwi vstart, 1\n vredsum.vs v1, v2, v3",
+    ] {
+        let (_, reason) = run_vector_source(src, 128);
+        match reason {
+            StopReason::Error(ExecutionError::InvalidInstruction(_)) => {}
+            other => panic!("`{}` stopped with {:?}", src, other),
+        }
+    }
+}
+
+#[test]
+fn test_vector_load_fault_records_vstart() {
+    let (machine, reason) = run_vector_source("
+        li a0, 0x3ffff8
+        vsetivli zero, 4, e32, m1, ta, ma
+        vle32.v v1, (a0)
+    ", 128);
+    assert_eq!(reason, StopReason::Error(ExecutionError::LoadAccessFault(MEM_SIZE as u64)));
+    assert_eq!(machine.csrs().read(csr::VSTART), Some(2));
+}
@@ -1,6 +1,6 @@
-#![allow(overflowing_literals)]
-
+use super::machine::mmu::Access;
 use super::decoder::*;
+use super::decoder::Instruction::*;
 use super::*;
 
 pub mod rtype;
@@ -9,25 +9,66 @@ pub mod itype;
 pub mod ujtype;
 pub mod utype;
 pub mod sbtype;
+pub mod atomic;
+pub mod float;
+pub mod bitmanip;
+pub mod vector;
 
 #[cfg(test)]
 mod implementer_test;
 
-pub fn handle_fence(_regfile: &mut [u32], bytes: &[u8], _pc: &mut u32) -> Result<(), ExecutionError> {
-    let opcode = get_opcode(bytes);
-    let _rd = get_rd(bytes);
-    let f3 = get_f3(bytes);
-    let _rs1 = get_rs1(bytes);
-
-    //TODO: decode immediate
-
-    if opcode == 0x00FF && f3 == 0x0 {
-        return Err(ExecutionError::Unimplemented("FENCE".into()));
+impl Machine {
+    pub(crate) fn execute(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        match inst {
+            Lui { .. } | Auipc { .. } => self.handle_u_type(inst),
+            Jal { .. } => self.handle_uj_type(inst),
+            Beq { .. } | Bne { .. } | Blt { .. } | Bge { .. } | Bltu { .. } | Bgeu { .. } => {
+                self.handle_sb_type(inst)
+            }
+            Sb { .. } | Sh { .. } | Sw { .. } | Sd { .. } => self.handle_s_type(inst),
+            Add { .. } | Sub { .. } | Sll { .. } | Slt { .. } | Sltu { .. } | Xor { .. } |
+            Srl { .. } | Sra { .. } | Or { .. } | And { .. } |
+            Mul { .. } | Mulh { .. } | Mulhsu { .. } | Mulhu { .. } |
+            Div { .. } | Divu { .. } | Rem { .. } | Remu { .. } |
+            Addw { .. } | Subw { .. } | Sllw { .. } | Srlw { .. } | Sraw { .. } |
+            Mulw { .. } | Divw { .. } | Divuw { .. } | Remw { .. } | Remuw { .. } => self.handle_r_type(inst),
+            LrW { .. } | ScW { .. } | AmoswapW { .. } | AmoaddW { .. } | AmoxorW { .. } |
+            AmoandW { .. } | AmoorW { .. } | AmominW { .. } | AmomaxW { .. } |
+            AmominuW { .. } | AmomaxuW { .. } => self.handle_atomic(inst),
+            Fload { .. } | Fstore { .. } | Fmadd { .. } | Fmsub { .. } | Fnmsub { .. } | Fnmadd { .. } |
+            Fadd { .. } | Fsub { .. } | Fmul { .. } | Fdiv { .. } | Fsqrt { .. } |
+            Fsgnj { .. } | Fsgnjn { .. } | Fsgnjx { .. } | Fmin { .. } | Fmax { .. } |
+            FcvtToInt { .. } | FcvtFromInt { .. } | FmvToInt { .. } | FmvFromInt { .. } |
+            Feq { .. } | Flt { .. } | Fle { .. } | Fclass { .. } | FcvtFloat { .. } => self.handle_float(inst),
+            Sh1add { .. } | Sh2add { .. } | Sh3add { .. } | AddUw { .. } | Sh1addUw { .. } |
+            Sh2addUw { .. } | Sh3addUw { .. } | SlliUw { .. } | Andn { .. } | Orn { .. } | Xnor { .. } |
+            Clz { .. } | Ctz { .. } | Cpop { .. } | Clzw { .. } | Ctzw { .. } | Cpopw { .. } |
+            Max { .. } | Maxu { .. } | Min { .. } | Minu { .. } | SextB { .. } | SextH { .. } |
+            ZextH { .. } | Rol { .. } | Ror { .. } | Rori { .. } | Rolw { .. } | Rorw { .. } |
+            Roriw { .. } | OrcB { .. } | Rev8 { .. } | Clmul { .. } | Clmulh { .. } | Clmulr { .. } |
+            Bclr { .. } | Bclri { .. } | Bext { .. } | Bexti { .. } | Binv { .. } | Binvi { .. } |
+            Bset { .. } | Bseti { .. } => self.handle_bitmanip(inst),
+            Vsetvli { .. } | Vsetivli { .. } | Vsetvl { .. } | Vload { .. } | Vstore { .. } | Varith { .. } |
+            Vmerge { .. } | Vmv { .. } | Vreduce { .. } | Vmask { .. } | VmvXS { .. } | VmvSX { .. } |
+            Vcpop { .. } | Vfirst { .. } | Viota { .. } | Vid { .. } | VmvNr { .. } => self.handle_vector(inst),
+            _ => self.handle_i_type(inst),
+        }
     }
-    else if opcode == 0x00FF && f3 == 0x1 {
-        return Err(ExecutionError::Unimplemented("FENCE.I".into()));
+
+    /// Redirect control flow to `target` once the current instruction retires.
+    /// Targets need only be 2-byte aligned when compressed instructions are on.
+    pub(crate) fn jump(&mut self, target: u64) -> Result<(), ExecutionError> {
+        let target = target & self.xlen_mask();
+        let alignment = if self.extensions.c { 2 } else { INSTRUCTION_ADDRESS_MISALIGNED_THRESHOLD as u64 };
+        if !target.is_multiple_of(alignment) {
+            return Err(ExecutionError::InstructionAddressMisaligned(target));
+        }
+        self.next_pc = target;
+        Ok(())
     }
-    else {
-        return Err(ExecutionError::InvalidInstruction(encode_hex(bytes)));
+
+    /// Instructions that only exist on RV64 are illegal on an RV32 hart.
+    pub(crate) fn require_rv64(&self, inst: Instruction) -> Result<(), ExecutionError> {
+        if self.xlen == 64 { Ok(()) } else { Err(ExecutionError::InvalidInstruction(inst.to_string())) }
     }
 }
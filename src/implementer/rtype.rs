@@ -1,181 +1,97 @@
 use super::*;
 
-fn mulh(first: u32, second: u32, weight: i64) -> u32 {
-    (((first as i64) * (second as i64) * weight) >> 32) as u32
+/// The upper half of the signed 2*XLEN-bit product of two operands that
+/// have already been extended to 128 bits.
+fn mulh(first: i128, second: i128, xlen: u32) -> u64 {
+    ((first * second) >> xlen) as u64
 }
 
-pub fn handle_r_type(regfile: &mut [u32], bytes: &[u8], pc: &mut u32, extensions: &Extensions) -> Result<(), ExecutionError> {
-    
-    let opcode = get_opcode(bytes);
-    let rd     = get_rd(bytes) as usize;
-    let f3     = get_f3(bytes);
-    let rs1    = get_rs1(bytes) as usize;
-    let rs2    = get_rs2(bytes) as usize;
-    let f7     = get_f7(bytes) as u32;
-
-    if opcode == 0x33 && f3 == 0x0 && f7 == 0x0 { // add
-        regfile[rd] = regfile[rs1] + regfile[rs2];
-        *pc += 4;
-    }
-    else if opcode == 0x33 && f3 == 0x0 && f7 == 0x20 { // sub
-        regfile[rd] = regfile[rs1] - regfile[rs2];
-        *pc += 4;
-    }
-    else if opcode == 0x33 && f3 == 0x1 && f7 == 0x00 { // sll
-        regfile[rd] = regfile[rs1] << regfile[rs2];
-        *pc += 4;
-    }
-    else if opcode == 0x33 && f3 == 0x2 && f7 == 0x00 { // slt
-        regfile[rd] = if (regfile[rs1] as i32) < (regfile[rs2] as i32) { 1 } else { 0 }; 
-        *pc += 4;
-    }
-    else if opcode == 0x33 && f3 == 0x3 && f7 == 0x00 { // sltu
-        regfile[rd] = if regfile[rs1] < regfile[rs2] { 1 } else { 0 };
-        *pc += 4;
-    }
-    else if opcode == 0x33 && f3 == 0x4 && f7 == 0x00 { // xor
-        regfile[rd] = regfile[rs1] ^ regfile[rs2];
-        *pc += 4;
-    }
-    else if opcode == 0x33 && f3 == 0x5 && f7 == 0x00 { // srl
-        regfile[rd] = regfile[rs1] >> regfile[rs2];
-        *pc += 4;
+fn div(first: i64, second: i64) -> u64 {
+    if second == 0 {
+        !0
     }
-    else if opcode == 0x33 && f3 == 0x5 && f7 == 0x20 { // sra
-        regfile[rd] = ((regfile[rs1] as i32) >> (regfile[rs2] as i32)) as u32;
-        *pc += 4;
-    }
-    else if opcode == 0x33 && f3 == 0x6 && f7 == 0x00 { // or
-        regfile[rd] = regfile[rs1] | regfile[rs2];
-        *pc += 4;
+    else {
+        first.wrapping_div(second) as u64
     }
-    else if opcode == 0x33 && f3 == 0x7 && f7 == 0x00 { // and
-        regfile[rd] = regfile[rs1] & regfile[rs2];
-        *pc += 4;
+}
+
+fn rem(first: i64, second: i64) -> u64 {
+    if second == 0 {
+        first as u64
     }
-    else if opcode == 0x33 && f3 == 0x0 && f7 == 0x1 { //mul
-        if extensions.m {
-            regfile[rd] = ((regfile[rs1] as u64) * (regfile[rs2] as u64)) as u32;
-            *pc += 4;
-        }
-        else {
-            return Err(ExecutionError::Extension("M".into()));
-        }
+    else {
+        first.wrapping_rem(second) as u64
     }
-    else if opcode == 0x33 && f3 == 0x1 && f7 == 0x1 { //mulh
-        if extensions.m {
-            let mut first = regfile[rs1];
-            let mut second = regfile[rs2];
+}
 
-            let mut weight = 1;
-            if (first as i32) < 0 { 
-                first = ((first as i32) * -1) as u32; 
-                weight *= -1; 
-            }
-            if (second as i32) < 0 { 
-                second = ((second as i32) * -1) as u32; 
-                weight *= -1; 
-            }
+/// Sign-extend the low 32 bits of a W instruction's result.
+pub(crate) fn sext_w(value: u64) -> u64 { value as i32 as i64 as u64 }
 
-            regfile[rd] = mulh(first, second, weight);
-            *pc += 4;
-        }
-        else {
+impl Machine {
+    pub(crate) fn handle_r_type(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        let is_m = matches!(inst,
+            Mul { .. } | Mulh { .. } | Mulhsu { .. } | Mulhu { .. } |
+            Div { .. } | Divu { .. } | Rem { .. } | Remu { .. } |
+            Mulw { .. } | Divw { .. } | Divuw { .. } | Remw { .. } | Remuw { .. });
+        if is_m && !self.extensions.m {
             return Err(ExecutionError::Extension("M".into()));
         }
-    }
-    else if opcode == 0x33 && f3 == 0x2 && f7 == 0x1 { //mulhsu
-        if extensions.m {
-            let mut first = regfile[rs1];
-            let second = regfile[rs2];
 
-            let mut weight = 1;
-            if (first as i32) < 0 { 
-                first = ((first as i32) * -1) as u32; 
-                weight *= -1; 
+        let (rd, rs1, rs2) = match inst {
+            Add { rd, rs1, rs2 } | Sub { rd, rs1, rs2 } | Sll { rd, rs1, rs2 } |
+            Slt { rd, rs1, rs2 } | Sltu { rd, rs1, rs2 } | Xor { rd, rs1, rs2 } |
+            Srl { rd, rs1, rs2 } | Sra { rd, rs1, rs2 } | Or { rd, rs1, rs2 } |
+            And { rd, rs1, rs2 } | Mul { rd, rs1, rs2 } | Mulh { rd, rs1, rs2 } |
+            Mulhsu { rd, rs1, rs2 } | Mulhu { rd, rs1, rs2 } | Div { rd, rs1, rs2 } |
+            Divu { rd, rs1, rs2 } | Rem { rd, rs1, rs2 } | Remu { rd, rs1, rs2 } => (rd, rs1, rs2),
+            Addw { rd, rs1, rs2 } | Subw { rd, rs1, rs2 } | Sllw { rd, rs1, rs2 } |
+            Srlw { rd, rs1, rs2 } | Sraw { rd, rs1, rs2 } | Mulw { rd, rs1, rs2 } |
+            Divw { rd, rs1, rs2 } | Divuw { rd, rs1, rs2 } | Remw { rd, rs1, rs2 } |
+            Remuw { rd, rs1, rs2 } => {
+                self.require_rv64(inst)?;
+                (rd, rs1, rs2)
             }
+            _ => unreachable!("{:?} is not an R-type instruction", inst),
+        };
+        let (first, second) = (self.reg(rs1), self.reg(rs2));
+        let (sfirst, ssecond) = (self.sreg(rs1), self.sreg(rs2));
+        let shamt = second & (self.xlen as u64 - 1);
+        // The W instructions work on the low 32 bits of their operands.
+        let (word1, word2) = (first as u32, second as u32);
 
-            regfile[rd] = mulh(first, second, weight);
-            *pc += 4;
-        }
-        else {
-            return Err(ExecutionError::Extension("M".into()));
-        }
-    }
-    else if opcode == 0x33 && f3 == 0x3 && f7 == 0x1 { //mulhu
-        if extensions.m {
-            regfile[rd] = (((regfile[rs1] as u64) * (regfile[rs2] as u64)) >> 32) as u32;
-            *pc += 4;
-        }
-        else {
-            return Err(ExecutionError::Extension("M".into()));
-        }
-    }
-    else if opcode == 0x33 && f3 == 0x4 && f7 == 0x1 { //div
-        if extensions.m {
-            if regfile[rs2] == 0 {
-                regfile[rd] = 0xFF_FF_FF_FF;
-            }
-            else if (regfile[rs1] as i32) == -0x80000000 && (regfile[rs2] as i32) == -0x1 {
-                regfile[rd] = regfile[rs1];
-            }
-            else {
-                regfile[rd] = ((regfile[rs1] as i32) / (regfile[rs2] as i32)) as u32;
-            }
-            *pc += 4;
-        }
-        else {
-            return Err(ExecutionError::Extension("M".into()));
-        }
-    }
-    else if opcode == 0x33 && f3 == 0x5 && f7 == 0x1 { //divu
-        if extensions.m {
-            if regfile[rs2] == 0 {
-                regfile[rd] = 0xFF_FF_FF_FF;
-            }
-            else {
-                regfile[rd] = regfile[rs1] / regfile[rs2];
-            }
-            *pc += 4;
-        }
-        else {
-            return Err(ExecutionError::Extension("M".into()));;
-        }
-    }
-    else if opcode == 0x33 && f3 == 0x6 && f7 == 0x1 { //rem
-        if extensions.m {
-            if regfile[rs2] == 0 {
-                regfile[rd] = regfile[rs1];
-            }
-            else if (regfile[rs1] as i32) == -0x80000000 && (regfile[rs2] as i32) == -0x1 {
-                regfile[rd] = 0;
-            }
-            else {
-                regfile[rd] = ((regfile[rs1] as i32) % (regfile[rs2] as i32)) as u32;
-            }
-            *pc += 4;
-        }
-        else {
-            return Err(ExecutionError::Extension("M".into()));
-        }
-    }
-    else if opcode == 0x33 && f3 == 0x7 && f7 == 0x1 { //remu
-        if extensions.m {
-            if regfile[rs2] == 0 {
-                regfile[rd] = regfile[rs1];
-            }
-            else {
-                regfile[rd] = regfile[rs1] % regfile[rs2];
-            }
-            *pc += 4;
-        }
-        else {
-            return Err(ExecutionError::Extension("M".into()));
-        }
-    }
-    else {
-        return Err(ExecutionError::InvalidInstruction(encode_hex(bytes)));
+        let value = match inst {
+            Add { .. } => first.wrapping_add(second),
+            Sub { .. } => first.wrapping_sub(second),
+            Sll { .. } => first << shamt,
+            Slt { .. } => (sfirst < ssecond) as u64,
+            Sltu { .. } => (first < second) as u64,
+            Xor { .. } => first ^ second,
+            Srl { .. } => first >> shamt,
+            Sra { .. } => (sfirst >> shamt) as u64,
+            Or { .. } => first | second,
+            And { .. } => first & second,
+            Mul { .. } => first.wrapping_mul(second),
+            Mulh { .. } => mulh(sfirst as i128, ssecond as i128, self.xlen),
+            Mulhsu { .. } => mulh(sfirst as i128, second as i128, self.xlen),
+            Mulhu { .. } => ((first as u128 * second as u128) >> self.xlen) as u64,
+            Div { .. } => div(sfirst, ssecond),
+            Divu { .. } => first.checked_div(second).unwrap_or(!0),
+            Rem { .. } => rem(sfirst, ssecond),
+            Remu { .. } => first.checked_rem(second).unwrap_or(first),
+            Addw { .. } => sext_w(word1.wrapping_add(word2) as u64),
+            Subw { .. } => sext_w(word1.wrapping_sub(word2) as u64),
+            Sllw { .. } => sext_w((word1 << (word2 & 0x1F)) as u64),
+            Srlw { .. } => sext_w((word1 >> (word2 & 0x1F)) as u64),
+            Sraw { .. } => ((word1 as i32) >> (word2 & 0x1F)) as i64 as u64,
+            Mulw { .. } => sext_w(word1.wrapping_mul(word2) as u64),
+            Divw { .. } => sext_w(div(word1 as i32 as i64, word2 as i32 as i64)),
+            Divuw { .. } => sext_w(word1.checked_div(word2).unwrap_or(!0) as u64),
+            Remw { .. } => sext_w(rem(word1 as i32 as i64, word2 as i32 as i64)),
+            Remuw { .. } => sext_w(word1.checked_rem(word2).unwrap_or(word1) as u64),
+            _ => unreachable!(),
+        };
+
+        self.set_reg(rd, value);
+        Ok(())
     }
-    
-    Ok(())
-}
\ No newline at end of file
+}
@@ -1,22 +1,13 @@
 use super::*;
 
-pub fn handle_u_type(regfile: &mut [u32], bytes: &[u8], pc: &mut u32, _extensions: &Extensions) -> Result<(), ExecutionError> {
-    let opcode = get_opcode(bytes);
-    let rd     = get_rd(bytes);
-
-    let immediate = decode_u_type_immediate(bytes);
-
-    if opcode == 0x17 { // auipc
-        regfile[rd as usize] = ((*pc as i32) + immediate) as u32;
-        *pc += 4;
-    }
-    else if opcode == 0x37 { // lui
-        regfile[rd as usize] = immediate as u32;
-        *pc += 4;
+impl Machine {
+    pub(crate) fn handle_u_type(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        match inst {
+            Auipc { rd, imm } => self.set_reg(rd, self.pc.wrapping_add(imm as i64 as u64)),
+            Lui { rd, imm } => self.set_reg(rd, imm as i64 as u64),
+            _ => unreachable!("{:?} is not a U-type instruction", inst),
+        }
+
+        Ok(())
     }
-    else { 
-        return Err(ExecutionError::InvalidInstruction(encode_hex(bytes)));
-    }
-
-    Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,369 @@
+use super::*;
+
+/// The SEW and LMUL (in eighths of a register) that `vtype` selects, or
+/// `None` if it is reserved or beyond what the hart supports, which sets
+/// vill. That includes any `vtype` with vill itself set.
+fn vector_config(vtype: u64, elen: u32) -> Option<(u32, u32)> {
+    let (vlmul, vsew) = (vtype & 0x7, (vtype >> 3) & 0x7);
+    if vtype >> 8 != 0 || vsew > 3 || vlmul == 4 { return None; }
+    let sew = 8 << vsew;
+    let lmul8 = if vlmul < 4 { 8 << vlmul } else { 1 << (vlmul - 5) };
+    // Fractional LMUL must still leave room for one SEW element per ELEN.
+    if sew > elen || sew * 8 > lmul8 * elen { None } else { Some((sew, lmul8)) }
+}
+
+/// A group of LMUL registers has to start at a multiple of LMUL.
+fn aligned(reg: usize, lmul8: u32) -> bool {
+    reg.is_multiple_of((lmul8 as usize / 8).max(1))
+}
+
+fn sew_mask(sew: u32) -> u64 { !0 >> (64 - sew) }
+
+/// The low `sew` bits of `value` as a signed number.
+fn signed(value: u64, sew: u32) -> i64 {
+    ((value << (64 - sew)) as i64) >> (64 - sew)
+}
+
+/// One element of `op` applied to `a` from `vs2`, `b` from the second
+/// source and, for the multiply-adds, `d` from the destination. Mask
+/// results are 0 or 1; division follows the scalar M rules.
+fn compute(op: VectorOp, a: u64, b: u64, d: u64, sew: u32) -> u64 {
+    use self::VectorOp::*;
+    let (sa, sb) = (signed(a, sew), signed(b, sew));
+    let shift = b & (sew as u64 - 1);
+    let result = match op {
+        Add => a.wrapping_add(b),
+        Sub => a.wrapping_sub(b),
+        Rsub => b.wrapping_sub(a),
+        Minu => a.min(b),
+        Min => sa.min(sb) as u64,
+        Maxu => a.max(b),
+        Max => sa.max(sb) as u64,
+        And => a & b,
+        Or => a | b,
+        Xor => a ^ b,
+        Sll => a << shift,
+        Srl => a >> shift,
+        Sra => (sa >> shift) as u64,
+        Mseq => (a == b) as u64,
+        Msne => (a != b) as u64,
+        Msltu => (a < b) as u64,
+        Mslt => (sa < sb) as u64,
+        Msleu => (a <= b) as u64,
+        Msle => (sa <= sb) as u64,
+        Msgtu => (a > b) as u64,
+        Msgt => (sa > sb) as u64,
+        Mul => a.wrapping_mul(b),
+        Mulh => ((sa as i128 * sb as i128) >> sew) as u64,
+        Mulhu => ((a as u128 * b as u128) >> sew) as u64,
+        Mulhsu => ((sa as i128 * b as i128) >> sew) as u64,
+        Divu if b == 0 => !0,
+        Divu => a / b,
+        // Sign-extending to 64 bits first lets MIN / -1 fit, and it then
+        // truncates back to MIN as the overflow rule requires.
+        Div if sb == 0 => !0,
+        Div => sa.wrapping_div(sb) as u64,
+        Remu if b == 0 => a,
+        Remu => a % b,
+        Rem if sb == 0 => a,
+        Rem => sa.wrapping_rem(sb) as u64,
+        Macc => b.wrapping_mul(a).wrapping_add(d),
+        Nmsac => d.wrapping_sub(b.wrapping_mul(a)),
+        Madd => b.wrapping_mul(d).wrapping_add(a),
+        Nmsub => a.wrapping_sub(b.wrapping_mul(d)),
+    };
+    result & sew_mask(sew)
+}
+
+fn reduce(op: VectorReduction, acc: u64, value: u64, sew: u32) -> u64 {
+    use self::VectorReduction::*;
+    let (sa, sv) = (signed(acc, sew), signed(value, sew));
+    let result = match op {
+        Sum => acc.wrapping_add(value),
+        And => acc & value,
+        Or => acc | value,
+        Xor => acc ^ value,
+        Minu => acc.min(value),
+        Min => sa.min(sv) as u64,
+        Maxu => acc.max(value),
+        Max => sa.max(sv) as u64,
+    };
+    result & sew_mask(sew)
+}
+
+fn mask_op(op: MaskOp, a: bool, b: bool) -> bool {
+    match op {
+        MaskOp::Andn => a && !b,
+        MaskOp::And => a && b,
+        MaskOp::Or => a || b,
+        MaskOp::Xor => a != b,
+        MaskOp::Orn => a || !b,
+        MaskOp::Nand => !(a && b),
+        MaskOp::Nor => !(a || b),
+        MaskOp::Xnor => a == b,
+    }
+}
+
+impl Machine {
+    pub(crate) fn handle_vector(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        if !self.extensions.v {
+            return Err(ExecutionError::Extension("V".into()));
+        }
+        if !self.csrs.vector_enabled() {
+            return Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        }
+
+        match inst {
+            Vsetvli { rd, rs1, vtypei } => {
+                let avl = self.avl(rd, rs1);
+                self.set_vtype(rd, avl, vtypei as u64);
+            }
+            Vsetivli { rd, uimm, vtypei } => self.set_vtype(rd, Some(uimm as u64), vtypei as u64),
+            Vsetvl { rd, rs1, rs2 } => {
+                let avl = self.avl(rd, rs1);
+                self.set_vtype(rd, avl, self.reg(rs2));
+            }
+            Vload { .. } | Vstore { .. } => self.vector_memory(inst)?,
+            _ => self.vector_arithmetic(inst)?,
+        }
+
+        // Every instruction that gets this far at least resets vstart.
+        self.csrs.vstart = 0;
+        self.csrs.set_vector_dirty();
+        Ok(())
+    }
+
+    /// The application vector length vsetvli and vsetvl ask for. With
+    /// rs1 = x0 it is VLMAX if rd is not x0, and otherwise the current vl.
+    fn avl(&self, rd: usize, rs1: usize) -> Option<u64> {
+        match (rs1, rd) {
+            (0, 0) => None,
+            (0, _) => Some(!0),
+            _ => Some(self.reg(rs1)),
+        }
+    }
+
+    fn set_vtype(&mut self, rd: usize, avl: Option<u64>, vtype: u64) {
+        match vector_config(vtype, self.elen) {
+            Some((sew, lmul8)) => {
+                let vlmax = (self.vlen * lmul8 / 8 / sew) as u64;
+                self.csrs.vtype = vtype;
+                self.csrs.vl = avl.unwrap_or(self.csrs.vl).min(vlmax);
+            }
+            None => {
+                self.csrs.vtype = 1 << (self.xlen - 1);
+                self.csrs.vl = 0;
+            }
+        }
+        self.set_reg(rd, self.csrs.vl);
+    }
+
+    /// SEW and LMUL in eighths. Instructions that depend on them are
+    /// illegal while vill is set.
+    fn current_vtype(&self, inst: Instruction) -> Result<(u32, u32), ExecutionError> {
+        vector_config(self.csrs.vtype, self.elen).ok_or_else(|| ExecutionError::InvalidInstruction(inst.to_string()))
+    }
+
+    /// Element `index` of the group starting at register `reg`, `width`
+    /// bits wide.
+    fn velement(&self, reg: usize, index: usize, width: u32) -> u64 {
+        let bytes = width as usize / 8;
+        let start = reg * self.vlen as usize / 8 + index * bytes;
+        self.vregs[start..start + bytes].iter().rev().fold(0, |acc, &byte| acc << 8 | byte as u64)
+    }
+
+    fn set_velement(&mut self, reg: usize, index: usize, width: u32, value: u64) {
+        let bytes = width as usize / 8;
+        let start = reg * self.vlen as usize / 8 + index * bytes;
+        for (i, byte) in self.vregs[start..start + bytes].iter_mut().enumerate() {
+            *byte = (value >> (8 * i)) as u8;
+        }
+    }
+
+    fn mask_bit(&self, reg: usize, index: usize) -> bool {
+        self.vregs[reg * self.vlen as usize / 8 + index / 8] >> (index % 8) & 1 != 0
+    }
+
+    fn set_mask_bit(&mut self, reg: usize, index: usize, value: bool) {
+        let byte = &mut self.vregs[reg * self.vlen as usize / 8 + index / 8];
+        *byte = (*byte & !(1 << (index % 8))) | (value as u8) << (index % 8);
+    }
+
+    /// Whether element `index` takes part: always when unmasked, and
+    /// otherwise when its bit in v0 is set.
+    fn active(&self, masked: bool, index: usize) -> bool {
+        !masked || self.mask_bit(0, index)
+    }
+
+    /// The indices from vstart up to `end` that take part. Masked-off and
+    /// tail elements are left undisturbed.
+    fn active_elements(&self, masked: bool, end: usize) -> Vec<usize> {
+        (self.csrs.vstart as usize..end).filter(|&i| self.active(masked, i)).collect()
+    }
+
+    /// Element `index` of a second source: scalars are sign-extended or
+    /// truncated to SEW, and immediates sign-extended.
+    fn operand(&self, src: VectorOperand, index: usize, sew: u32) -> u64 {
+        match src {
+            VectorOperand::Vector(vs1) => self.velement(vs1, index, sew),
+            VectorOperand::Scalar(rs1) => self.sreg(rs1) as u64 & sew_mask(sew),
+            VectorOperand::Immediate(imm) => imm as i64 as u64 & sew_mask(sew),
+        }
+    }
+
+    fn vector_arithmetic(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        let illegal = || Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        // Whole-register moves are the only ones that ignore vtype.
+        if let VmvNr { vd, vs2, nregs } = inst {
+            if !aligned(vd, nregs * 8) || !aligned(vs2, nregs * 8) { return illegal(); }
+            let (vlenb, bytes) = (self.vlen as usize / 8, nregs as usize * self.vlen as usize / 8);
+            self.vregs.copy_within(vs2 * vlenb..vs2 * vlenb + bytes, vd * vlenb);
+            return Ok(());
+        }
+
+        let (sew, lmul8) = self.current_vtype(inst)?;
+        let vl = self.csrs.vl as usize;
+        let vector_aligned = |src: VectorOperand| match src {
+            VectorOperand::Vector(vs1) => aligned(vs1, lmul8),
+            _ => true,
+        };
+        match inst {
+            Varith { op, vd, vs2, src, masked } => {
+                // Only a mask result may overwrite the mask it is computed under.
+                if !aligned(vs2, lmul8) || !vector_aligned(src) ||
+                    (!op.writes_mask() && (!aligned(vd, lmul8) || masked && vd == 0)) {
+                    return illegal();
+                }
+                // Compute every element before writing any, since vd may
+                // overlap a source.
+                let results: Vec<(usize, u64)> = self.active_elements(masked, vl).into_iter().map(|i| {
+                    let old = if op.is_multiply_add() { self.velement(vd, i, sew) } else { 0 };
+                    (i, compute(op, self.velement(vs2, i, sew), self.operand(src, i, sew), old, sew))
+                }).collect();
+                for (i, value) in results {
+                    if op.writes_mask() { self.set_mask_bit(vd, i, value != 0); } else { self.set_velement(vd, i, sew, value); }
+                }
+            }
+            Vmerge { vd, vs2, src } => {
+                if vd == 0 || !aligned(vd, lmul8) || !aligned(vs2, lmul8) || !vector_aligned(src) { return illegal(); }
+                let results: Vec<(usize, u64)> = self.active_elements(false, vl).into_iter().map(|i| {
+                    (i, if self.mask_bit(0, i) { self.operand(src, i, sew) } else { self.velement(vs2, i, sew) })
+                }).collect();
+                for (i, value) in results { self.set_velement(vd, i, sew, value); }
+            }
+            Vmv { vd, src } => {
+                if !aligned(vd, lmul8) || !vector_aligned(src) { return illegal(); }
+                let results: Vec<(usize, u64)> = self.active_elements(false, vl).into_iter()
+                    .map(|i| (i, self.operand(src, i, sew)))
+                    .collect();
+                for (i, value) in results { self.set_velement(vd, i, sew, value); }
+            }
+            // Reductions and the mask-population instructions cannot be
+            // resumed part way, so vstart must be 0.
+            Vreduce { .. } | Vcpop { .. } | Vfirst { .. } | Viota { .. } if self.csrs.vstart != 0 => return illegal(),
+            Vreduce { op, vd, vs2, vs1, masked } => {
+                if !aligned(vs2, lmul8) { return illegal(); }
+                let result = self.active_elements(masked, vl).into_iter()
+                    .fold(self.velement(vs1, 0, sew), |acc, i| reduce(op, acc, self.velement(vs2, i, sew), sew));
+                if vl > 0 { self.set_velement(vd, 0, sew, result); }
+            }
+            Vmask { op, vd, vs2, vs1 } => {
+                let results: Vec<(usize, bool)> = self.active_elements(false, vl).into_iter()
+                    .map(|i| (i, mask_op(op, self.mask_bit(vs2, i), self.mask_bit(vs1, i))))
+                    .collect();
+                for (i, value) in results { self.set_mask_bit(vd, i, value); }
+            }
+            // The scalar moves ignore vl, except that vmv.s.x writes nothing
+            // when vl is 0.
+            VmvXS { rd, vs2 } => self.set_reg(rd, signed(self.velement(vs2, 0, sew), sew) as u64),
+            VmvSX { vd, rs1 } => {
+                if self.csrs.vstart < self.csrs.vl { self.set_velement(vd, 0, sew, self.sreg(rs1) as u64 & sew_mask(sew)); }
+            }
+            Vcpop { rd, vs2, masked } => {
+                let count = self.active_elements(masked, vl).into_iter().filter(|&i| self.mask_bit(vs2, i)).count();
+                self.set_reg(rd, count as u64);
+            }
+            Vfirst { rd, vs2, masked } => {
+                let first = self.active_elements(masked, vl).into_iter().find(|&i| self.mask_bit(vs2, i));
+                self.set_reg(rd, first.map_or(!0, |i| i as u64));
+            }
+            // viota.m writes each element the number of active set bits
+            // before it, so the destination cannot overlap its source.
+            Viota { vd, vs2, masked } => {
+                let groups = (lmul8 as usize / 8).max(1);
+                if !aligned(vd, lmul8) || masked && vd == 0 || (vd..vd + groups).contains(&vs2) { return illegal(); }
+                let mut count = 0;
+                for i in self.active_elements(masked, vl) {
+                    self.set_velement(vd, i, sew, count);
+                    if self.mask_bit(vs2, i) { count += 1; }
+                }
+            }
+            Vid { vd, masked } => {
+                if !aligned(vd, lmul8) || masked && vd == 0 { return illegal(); }
+                for i in self.active_elements(masked, vl) { self.set_velement(vd, i, sew, i as u64); }
+            }
+            _ => unreachable!("{:?} is not a vector instruction", inst),
+        }
+        Ok(())
+    }
+
+    /// Loads and stores walk their elements from vstart. An access fault
+    /// leaves vstart at the faulting element, so the access can resume
+    /// there once the trap handler returns.
+    fn vector_memory(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        let illegal = || Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        let (load, vd, rs1, eew, mode, masked) = match inst {
+            Vload { vd, rs1, eew, mode, masked } => (true, vd, rs1, eew, mode, masked),
+            Vstore { vs3, rs1, eew, mode, masked } => (false, vs3, rs1, eew, mode, masked),
+            _ => unreachable!("{:?} is not a vector load or store", inst),
+        };
+        if eew > self.elen { return illegal(); }
+
+        // Element count, data width and the group alignment of the data.
+        let (count, width, data_lmul8) = match mode {
+            VectorAddressing::WholeRegister(nregs) => {
+                ((nregs * self.vlen / eew) as usize, eew, nregs * 8)
+            }
+            VectorAddressing::Mask => {
+                self.current_vtype(inst)?;
+                (self.csrs.vl.div_ceil(8) as usize, 8, 8)
+            }
+            // Indexed accesses move SEW-wide data at EEW-wide offsets.
+            VectorAddressing::Indexed { vs2, .. } => {
+                let (sew, lmul8) = self.current_vtype(inst)?;
+                let index_lmul8 = eew * lmul8 / sew;
+                if !(1..=64).contains(&index_lmul8) || !aligned(vs2, index_lmul8) { return illegal(); }
+                (self.csrs.vl as usize, sew, lmul8)
+            }
+            // Unit-stride and strided data has EMUL = EEW / SEW * LMUL.
+            _ => {
+                let (sew, lmul8) = self.current_vtype(inst)?;
+                let emul8 = eew * lmul8 / sew;
+                if !(1..=64).contains(&emul8) { return illegal(); }
+                (self.csrs.vl as usize, eew, emul8)
+            }
+        };
+        if !aligned(vd, data_lmul8) || masked && vd == 0 && load { return illegal(); }
+
+        let base = self.reg(rs1);
+        for i in self.active_elements(masked, count) {
+            let offset = match mode {
+                VectorAddressing::Strided(rs2) => (self.sreg(rs2) as u64).wrapping_mul(i as u64),
+                VectorAddressing::Indexed { vs2, .. } => self.velement(vs2, i, eew),
+                _ => (i * width as usize / 8) as u64,
+            };
+            let address = base.wrapping_add(offset) & self.xlen_mask();
+            let bytes = width as usize / 8;
+            let result = if load {
+                self.read_memory(address, bytes, Access::Load).map(|value| self.set_velement(vd, i, width, value))
+            } else {
+                self.write_memory(address, bytes, self.velement(vd, i, width))
+            };
+            if let Err(e) = result {
+                self.csrs.vstart = i as u64;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
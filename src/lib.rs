@@ -0,0 +1,128 @@
+#![allow(overflowing_literals)]
+
+use std::fmt;
+
+pub mod bus;
+pub mod csr;
+pub mod decoder;
+pub mod devices;
+pub mod disassembler;
+pub mod assembler;
+pub mod elf;
+pub mod machine;
+mod implementer;
+mod softfloat;
+
+pub use machine::{Machine, Config, StopReason};
+
+pub const REGFILE_SIZE: usize = 32;
+/// RV32E only has x0-x15.
+pub const E_REGFILE_SIZE: usize = 16;
+pub const MEM_SIZE: usize = 1048576 * 4; // 32 address space in RV32I
+/// The vector register and element widths a machine gets unless configured otherwise.
+pub const DEFAULT_VLEN: u32 = 128;
+pub const DEFAULT_ELEN: u32 = 64;
+pub const DEFAULT_TLB_ENTRIES: usize = 16;
+pub const RAM_BASE: u64 = 0x0;
+pub const TEXT_BASE: u64 = RAM_BASE;
+pub const CLINT_BASE: u64 = 0x0200_0000;
+pub const CLINT_SIZE: u64 = 0x1_0000;
+pub const PLIC_BASE: u64 = 0x0C00_0000;
+pub const PLIC_SIZE: u64 = 0x400_0000;
+pub const UART_BASE: u64 = 0x1000_0000;
+pub const UART_SIZE: u64 = 0x100;
+/// The PLIC source the UART's interrupt line is connected to.
+pub const UART_IRQ: u32 = 10;
+
+pub const INSTRUCTION_ADDRESS_MISALIGNED_THRESHOLD: i32 = 4;
+
+#[derive(Debug, Clone, Default)]
+pub struct Extensions {
+    pub m: bool,
+    pub a: bool,
+    pub f: bool,
+    pub e: bool,
+    pub c: bool,
+    pub d: bool,
+    pub q: bool,
+    pub v: bool,
+    /// Supervisor mode, which needs user mode as well.
+    pub s: bool,
+    /// User mode.
+    pub u: bool,
+    pub zba: bool,
+    pub zbb: bool,
+    pub zbc: bool,
+    pub zbs: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecutionError {
+    Extension(String),
+    InvalidInstruction(String),
+    InstructionAddressMisaligned(u64),
+    InstructionAccessFault(u64),
+    LoadAddressMisaligned(u64),
+    LoadAccessFault(u64),
+    StoreAddressMisaligned(u64),
+    StoreAccessFault(u64),
+    InstructionPageFault(u64),
+    LoadPageFault(u64),
+    StorePageFault(u64),
+    Breakpoint,
+    EnvironmentCall,
+    Unimplemented(String),
+    UserTerminate
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecutionError::Extension(ext) => {
+                write!(f, "The {} extension was not activated", ext)
+            }
+            ExecutionError::InstructionAddressMisaligned(address) => {
+                write!(f, "Instruction address misaligned exception at 0x{:08x}", address)
+            }
+            ExecutionError::InstructionAccessFault(address) => {
+                write!(f, "Instruction access fault at 0x{:08x}", address)
+            }
+            ExecutionError::LoadAddressMisaligned(address) => {
+                write!(f, "Load address misaligned at 0x{:08x}", address)
+            }
+            ExecutionError::StoreAddressMisaligned(address) => {
+                write!(f, "Store address misaligned at 0x{:08x}", address)
+            }
+            ExecutionError::LoadAccessFault(address) => {
+                write!(f, "Load access fault at 0x{:08x}", address)
+            }
+            ExecutionError::StoreAccessFault(address) => {
+                write!(f, "Store access fault at 0x{:08x}", address)
+            }
+            ExecutionError::InstructionPageFault(address) => {
+                write!(f, "Instruction page fault at 0x{:08x}", address)
+            }
+            ExecutionError::LoadPageFault(address) => {
+                write!(f, "Load page fault at 0x{:08x}", address)
+            }
+            ExecutionError::StorePageFault(address) => {
+                write!(f, "Store page fault at 0x{:08x}", address)
+            }
+            ExecutionError::Breakpoint => {
+                write!(f, "Breakpoint")
+            }
+            ExecutionError::EnvironmentCall => {
+                write!(f, "Environment call")
+            }
+            ExecutionError::InvalidInstruction(inst) => {
+                write!(f, "{} is an invalid instruction", inst)
+            }
+            ExecutionError::Unimplemented(inst) => {
+                write!(f, "The {} instruction is not implemented", inst)
+            }
+            ExecutionError::UserTerminate => {
+                write!(f, "The user terminated the program")
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+use super::super::csr::*;
+use super::super::devices::PLIC_SOURCES;
+
+impl Machine {
+    /// Connect the interrupt line of the device mapped at `base` to PLIC
+    /// source `source`.
+    pub fn connect_interrupt(&mut self, source: u32, base: u64) {
+        assert!(self.plic.is_some(), "Interrupt lines need a PLIC");
+        assert!(source > 0 && (source as usize) < PLIC_SOURCES, "No PLIC source {}", source);
+        self.interrupt_lines.push((source, base));
+    }
+
+    /// Copy the interrupt sources' lines into mip, and the CLINT's `mtime`,
+    /// which software may have written, into `time`. Device lines go through
+    /// the PLIC, whose machine context drives MEIP and supervisor context
+    /// SEIP.
+    pub(crate) fn poll_interrupt_sources(&mut self) {
+        if let Some(ref plic) = self.plic {
+            let mut plic = plic.borrow_mut();
+            for &(source, base) in &self.interrupt_lines {
+                plic.set_level(source, self.bus.interrupt_pending(base));
+            }
+            let meip = if plic.interrupt_pending(0) { MIP_MEIP } else { 0 };
+            self.csrs.mip = (self.csrs.mip & !MIP_MEIP) | meip;
+            self.csrs.seip = plic.interrupt_pending(1);
+        }
+        if let Some(ref clint) = self.clint {
+            let clint = clint.borrow();
+            let mut pending = 0;
+            if clint.software_interrupt() { pending |= MIP_MSIP; }
+            if clint.timer_interrupt() { pending |= MIP_MTIP; }
+            self.csrs.mip = (self.csrs.mip & !(MIP_MSIP | MIP_MTIP)) | pending;
+            self.csrs.time = clint.mtime();
+        }
+    }
+
+    /// Let `ticks` ticks of real time pass. With a CLINT, `time` follows its
+    /// `mtime`.
+    pub(crate) fn advance_time(&mut self, ticks: u64) {
+        match self.clint {
+            Some(ref clint) => {
+                clint.borrow_mut().tick(ticks);
+                self.csrs.time = clint.borrow().mtime();
+            }
+            None => self.csrs.time = self.csrs.time.wrapping_add(ticks),
+        }
+    }
+
+    /// Wait for an interrupt. Rather than spin, the hart skips ahead to the
+    /// CLINT's timer when that is the next thing that can wake it; with
+    /// nothing to wait for, including a timer that was never programmed,
+    /// `wfi` does nothing. Illegal in user mode when
+    /// supervisor mode exists, and below machine mode when mstatus.TW is
+    /// set.
+    pub(crate) fn wfi(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        let tw = self.privilege < Privilege::Machine && self.csrs.mstatus & MSTATUS_TW != 0;
+        let user = self.privilege == Privilege::User && self.csrs.privilege_supported(Privilege::Supervisor);
+        if tw || user {
+            return Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        }
+        if self.csrs.pending_interrupts() & self.csrs.mie != 0 || self.csrs.mie & MIP_MTIP == 0 { return Ok(()); }
+        let ticks = match self.clint.as_ref().and_then(|clint| clint.borrow().ticks_until_timer()) {
+            Some(ticks) => ticks,
+            None => return Ok(()),
+        };
+        self.advance_time(ticks);
+        self.tick_cycles = 0;
+        Ok(())
+    }
+}
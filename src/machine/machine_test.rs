@@ -0,0 +1,797 @@
+use super::*;
+
+/// A machine built from `config` with `risc-v/assembled/<hex>` loaded.
+fn test_machine(hex: &str, config: Config) -> Machine {
+    let mut machine = Machine::new(config);
+    let mut imem = Vec::new();
+    load_into_imem(&format!("risc-v/assembled/{}", hex), &mut imem).expect("Could not load the hex file");
+    machine.load(RAM_BASE, &imem).expect("Could not load the hex file into memory");
+    machine
+}
+
+fn bare_config(extensions: Extensions) -> Config {
+    Config { bare: true, extensions, ..Config::default() }
+}
+
+#[test]
+fn test_run_to_end_of_program() {
+    let mut machine = test_machine("test.hex", bare_config(Extensions { m: true, ..Extensions::default() }));
+    assert_eq!(machine.run(None), StopReason::EndOfProgram);
+    assert_eq!(machine.reg(5), 10);
+    assert_eq!(machine.reg(6), 11);
+    assert_eq!(machine.reg(13), 110);
+    assert_eq!(machine.pc(), 24);
+}
+
+#[test]
+fn test_run_instruction_limit() {
+    let mut machine = test_machine("test.hex", bare_config(Extensions { m: true, ..Extensions::default() }));
+    assert_eq!(machine.run(Some(2)), StopReason::InstructionLimit);
+    assert_eq!(machine.pc(), 8);
+    assert_eq!(machine.reg(13), 0);
+}
+
+#[test]
+fn test_step_reports_disabled_extension() {
+    let mut machine = test_machine("test.hex", bare_config(Extensions::default()));
+    assert_eq!(machine.step(), Ok(()));
+    assert_eq!(machine.step(), Ok(()));
+    assert_eq!(machine.step(), Err(StopReason::Error(ExecutionError::Extension("M".into()))));
+    assert_eq!(machine.pc(), 8);
+}
+
+#[test]
+fn test_mixed_length_instructions() {
+    let mut machine = test_machine("compressed.hex", bare_config(Extensions { c: true, ..Extensions::default() }));
+    assert_eq!(machine.step(), Ok(()));
+    assert_eq!(machine.pc(), 2);
+    assert_eq!(machine.step(), Ok(()));
+    assert_eq!(machine.pc(), 6, "a 32-bit instruction may sit on a 2-byte boundary");
+    assert_eq!(machine.run(None), StopReason::EndOfProgram);
+    assert_eq!(machine.reg(10), 12);
+    assert_eq!(machine.reg(12), 10, "c.jal links to the next parcel");
+    assert_eq!(machine.reg(13), 9);
+    assert_eq!(machine.pc(), 18);
+}
+
+#[test]
+fn test_compressed_instructions_need_c() {
+    let mut machine = test_machine("compressed.hex", bare_config(Extensions::default()));
+    assert_eq!(machine.step(), Err(StopReason::Error(ExecutionError::Extension("C".into()))));
+    assert_eq!(machine.pc(), 0);
+}
+
+#[test]
+fn test_jump_alignment_follows_c() {
+    let words = [
+        0x00600293, // addi t0, zero, 6
+        0x00028067, // jalr zero, 0(t0)
+    ];
+    let mut machine = bare_machine();
+    load_words(&mut machine, &words);
+    assert_eq!(machine.run(None), StopReason::Error(ExecutionError::InstructionAddressMisaligned(6)));
+
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.c = true;
+    let mut machine = Machine::new(config);
+    load_words(&mut machine, &words);
+    assert_eq!(machine.run(None), StopReason::EndOfProgram);
+    assert_eq!(machine.pc(), 8);
+}
+
+#[test]
+#[should_panic(expected = "Double precision needs single precision")]
+fn test_double_precision_needs_single_precision() {
+    Machine::new(Config { extensions: Extensions { d: true, ..Extensions::default() }, ..Config::default() });
+}
+
+#[test]
+fn test_x0_is_hardwired() {
+    let mut machine = Machine::new(Config::default());
+    machine.set_reg(0, 5);
+    machine.set_reg(1, 5);
+    assert_eq!(machine.reg(0), 0);
+    assert_eq!(machine.reg(1), 5);
+}
+
+#[test]
+fn test_rv32e_has_sixteen_registers() {
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.e = true;
+    let mut machine = Machine::new(config.clone());
+    machine.load(RAM_BASE, &assembler::assemble("
+        li a5, 1
+        li a0, 10
+        ecall
+    ", RAM_BASE as u32).unwrap()).unwrap();
+    assert_eq!(machine.registers().len(), 16);
+    assert_eq!(machine.run(None), StopReason::Error(ExecutionError::UserTerminate));
+    assert_eq!(machine.reg(15), 1);
+
+    // Naming x16-x31 anywhere in an encoding is illegal, and traps as such.
+    for src in &["li a6, 1", "add a0, a0, s2", "sw t3, 0(sp)", "fcvt.w.s a7, fa0"] {
+        let mut machine = Machine::new(config.clone());
+        machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+        match machine.run(None) {
+            StopReason::Error(ExecutionError::InvalidInstruction(_)) => {}
+            other => panic!("`{}` stopped with {:?}", src, other),
+        }
+    }
+    config.bare = false;
+    let mut machine = Machine::new(config);
+    machine.load(RAM_BASE, &assembler::assemble("li a6, 1", RAM_BASE as u32).unwrap()).unwrap();
+    assert_eq!(machine.step(), Ok(()));
+    assert_eq!(machine.csrs().read(csr::MCAUSE), Some(trap::ILLEGAL_INSTRUCTION));
+    assert_eq!(machine.csrs().read(csr::MTVAL), Some(0x00100813));
+}
+
+fn load_words(machine: &mut Machine, words: &[u32]) {
+    let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes().to_vec()).collect();
+    machine.load(RAM_BASE, &bytes).unwrap();
+}
+
+#[test]
+fn test_program_can_read_its_own_code() {
+    let mut machine = bare_machine();
+    load_words(&mut machine, &[
+        0x00002283, // lw x5, 0(x0)
+    ]);
+    assert_eq!(machine.run(None), StopReason::EndOfProgram);
+    assert_eq!(machine.reg(5), 0x00002283);
+}
+
+fn bare_machine() -> Machine {
+    Machine::new(Config { bare: true, ..Config::default() })
+}
+
+#[test]
+fn test_bad_store_reports_access_fault() {
+    let mut machine = bare_machine();
+    load_words(&mut machine, &[
+        0x80000337, // lui x6, 0x80000
+        0x00532023, // sw x5, 0(x6)
+    ]);
+    assert_eq!(machine.run(None), StopReason::Error(ExecutionError::StoreAccessFault(0x8000_0000)));
+    assert_eq!(machine.pc(), 4);
+}
+
+#[test]
+fn test_fetch_outside_memory_reports_access_fault() {
+    let mut machine = bare_machine();
+    machine.set_pc(MEM_SIZE as u64);
+    assert_eq!(machine.step(), Err(StopReason::Error(ExecutionError::InstructionAccessFault(MEM_SIZE as u64))));
+}
+
+#[test]
+fn test_rv64_accesses_at_the_top_of_the_address_space_fault() {
+    let mut config = Config { xlen: 64, bare: true, ..Config::default() };
+    config.extensions.c = true;
+    let top = |src: &str| {
+        let mut machine = Machine::new(config.clone());
+        machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+        machine.run(Some(10))
+    };
+    let error = |e| StopReason::Error(e);
+    assert_eq!(top("ld a1, -4(zero)"), error(ExecutionError::LoadAccessFault(!0 - 3)));
+    assert_eq!(top("sd a1, -4(zero)"), error(ExecutionError::StoreAccessFault(!0 - 3)));
+    assert_eq!(top("li a0, -2\nlw a1, 0(a0)"), error(ExecutionError::LoadAccessFault(!0 - 1)));
+    assert_eq!(top("li a0, -2\njr a0"), error(ExecutionError::InstructionAccessFault(!0 - 1)));
+}
+
+fn run_source(src: &str) -> (Machine, StopReason) {
+    let mut machine = Machine::new(Config::default());
+    machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+    let reason = machine.run(Some(100));
+    (machine, reason)
+}
+
+// Installs `handler` and enables interrupts so the MIE/MPIE stacking is visible.
+const TRAP_PRELUDE: &str = "
+        la t0, handler
+        csrw mtvec, t0
+        csrsi mstatus, 8
+";
+
+#[test]
+fn test_running_off_the_end_traps_as_an_illegal_instruction() {
+    let (machine, reason) = run_source(&format!("{}
+        li t0, 0x100        # zeroed memory
+        jr t0
+    handler:
+        csrr s0, mcause
+        csrr s1, mtval
+        csrr s2, mepc
+    end:
+        j end
+    ", TRAP_PRELUDE));
+
+    assert_eq!(reason, StopReason::InstructionLimit);
+    assert_eq!(machine.reg(8), trap::ILLEGAL_INSTRUCTION);
+    assert_eq!(machine.reg(9), 0);
+    assert_eq!(machine.reg(18), 0x100);
+}
+
+#[test]
+fn test_illegal_instruction_traps_and_mret_returns() {
+    let (machine, reason) = run_source(&format!("{}
+        li a0, 1
+        .word 0x1234500b
+        li a0, 2
+        j end
+    handler:
+        csrr s0, mepc
+        csrr s1, mcause
+        csrr s2, mtval
+        csrr s3, mstatus
+        addi t0, s0, 4
+        csrw mepc, t0
+        mret
+    end:
+        csrr s4, mstatus
+        j end
+    ", TRAP_PRELUDE));
+
+    assert_eq!(reason, StopReason::InstructionLimit);
+    assert_eq!(machine.reg(10), 2);
+    assert_eq!(machine.reg(8), 0x14); // mepc
+    assert_eq!(machine.reg(9), trap::ILLEGAL_INSTRUCTION);
+    assert_eq!(machine.reg(18), 0x1234500b);
+    // In the handler MIE is clear, MPIE holds the old MIE and MPP is M.
+    assert_eq!(machine.reg(19), csr::MSTATUS_MPIE | csr::MSTATUS_MPP);
+    assert_eq!(machine.reg(20), csr::MSTATUS_MIE | csr::MSTATUS_MPIE | csr::MSTATUS_MPP);
+}
+
+#[test]
+fn test_counters_advance_with_each_step() {
+    let mut machine = Machine::new(Config { cycles_per_tick: 3, ..Config::default() });
+    let src = "
+        la t0, handler
+        csrw mtvec, t0
+        .word 0x1234500b    # traps, so it takes a cycle but does not retire
+    handler:
+        rdcycle a0
+        rdinstret a1
+        rdtime a2
+        csrwi mcountinhibit, 4
+        nop
+        nop
+        rdinstret a3
+        li t1, 100
+        csrw mcycle, t1
+        rdcycle a4
+    end:
+        j end
+    ";
+    machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+    assert_eq!(machine.run(Some(20)), StopReason::InstructionLimit);
+    assert_eq!(machine.registers()[10..15], [4, 4, 2, 6, 100]);
+}
+
+#[test]
+fn test_ecall_and_ebreak_trap() {
+    let (machine, _) = run_source(&format!("{}
+        ecall
+        ebreak
+    end:
+        j end
+    handler:
+        csrr t0, mcause
+        csrr t1, mtval
+        add s0, s0, t0
+        add s1, s1, t1
+        csrr t0, mepc
+        addi t0, t0, 4
+        csrw mepc, t0
+        mret
+    ", TRAP_PRELUDE));
+
+    assert_eq!(machine.reg(8), trap::ECALL_FROM_M + trap::BREAKPOINT);
+    assert_eq!(machine.reg(9), 0x14); // ebreak reports its own address
+}
+
+#[test]
+fn test_access_fault_reports_address() {
+    let (machine, _) = run_source(&format!("{}
+        li t1, 0x80000000
+        lw t2, 4(t1)
+    handler:
+        csrr s0, mcause
+        csrr s1, mtval
+    end:
+        j end
+    ", TRAP_PRELUDE));
+
+    assert_eq!(machine.reg(8), trap::LOAD_ACCESS_FAULT);
+    assert_eq!(machine.reg(9), 0x8000_0004);
+}
+
+#[test]
+fn test_vectored_mtvec_only_offsets_interrupts() {
+    let mut machine = Machine::new(Config::default());
+    machine.csrs.write(csr::MTVEC, 0x101).unwrap();
+
+    machine.enter_trap(trap::ILLEGAL_INSTRUCTION, 0);
+    assert_eq!(machine.pc(), 0x100);
+    machine.enter_trap(trap::INTERRUPT | 7, 0);
+    assert_eq!(machine.pc(), 0x11c);
+    assert_eq!(machine.csrs().read(csr::MCAUSE), Some(0x8000_0007));
+}
+
+fn run_privileged(src: &str) -> Machine {
+    let mut config = Config::default();
+    config.extensions.s = true;
+    config.extensions.u = true;
+    let mut machine = Machine::new(config);
+    machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+    assert_eq!(machine.run(Some(100)), StopReason::InstructionLimit);
+    machine
+}
+
+// Installs both trap handlers and leaves machine mode for `kernel` in
+// supervisor mode.
+const ENTER_SUPERVISOR: &str = "
+        la t0, mhandler
+        csrw mtvec, t0
+        la t0, shandler
+        csrw stvec, t0
+        li t0, 0x1800
+        csrc mstatus, t0
+        li t0, 0x800
+        csrs mstatus, t0
+        la t0, kernel
+        csrw mepc, t0
+        mret
+";
+
+#[test]
+fn test_traps_from_user_mode_follow_medeleg() {
+    let mut machine = run_privileged(&format!("
+        li t0, 0x100    # ecall from U
+        csrw medeleg, t0
+        {}
+    kernel:
+        la t0, user
+        csrw sepc, t0
+        sret            # SPP is U out of reset
+    user:
+        li a0, 7
+        csrr s1, mstatus
+        ecall
+    shandler:
+        csrr s2, scause
+        csrr s3, sepc
+        csrr s4, sstatus
+    end:
+        j end
+    mhandler:
+        csrr s5, mcause
+        csrr s6, mstatus
+        csrr t0, mepc
+        addi t0, t0, 4
+        csrw mepc, t0
+        mret
+    ", ENTER_SUPERVISOR));
+
+    assert_eq!(machine.privilege(), csr::Privilege::Supervisor);
+    assert_eq!(machine.reg(10), 7);
+    assert_eq!(machine.reg(9), 0, "user mode cannot read mstatus");
+    assert_eq!(machine.reg(21), trap::ILLEGAL_INSTRUCTION);
+    assert_eq!(machine.reg(22) & csr::MSTATUS_MPP, 0, "the illegal csrr came from U");
+    assert_eq!(machine.reg(18), trap::ECALL_FROM_U);
+    let sepc = machine.reg(19);
+    assert_eq!(machine.bus_mut().read_u32(sepc), Ok(0x00000073), "sepc holds the ecall");
+    assert_eq!(machine.reg(20) & csr::MSTATUS_SPP, 0);
+}
+
+#[test]
+fn test_delegated_interrupts_wait_for_supervisor_mode() {
+    let mut machine = run_privileged(&format!("
+        li t0, 2        # supervisor software interrupt
+        csrw mideleg, t0
+        csrs mie, t0
+        csrsi sstatus, 2
+        csrsi mstatus, 8
+        csrs mip, t0
+        li a0, 1
+        {}
+    kernel:
+        li a1, 1
+    shandler:
+        csrr s0, scause
+        csrr s1, sepc
+        csrci sip, 2
+    end:
+        j end
+    mhandler:
+        li a2, 1
+        j end
+    ", ENTER_SUPERVISOR));
+
+    assert_eq!(machine.registers()[10..13], [1, 0, 0]);
+    assert_eq!(machine.reg(8), 0x8000_0000 | trap::SUPERVISOR_SOFTWARE_INTERRUPT);
+    let sepc = machine.reg(9);
+    assert_eq!(machine.bus_mut().read_u32(sepc), Ok(0x00100593), "sepc holds li a1, 1");
+    assert_eq!(machine.csrs().read(csr::SIP), Some(0));
+}
+
+#[test]
+fn test_return_instructions_check_the_privilege_mode() {
+    let mut machine = Machine::new(Config { extensions: Extensions { s: true, u: true, ..Extensions::default() },
+                                            ..Config::default() });
+    machine.privilege = csr::Privilege::Supervisor;
+    assert_eq!(machine.mret(Instruction::Mret), Err(ExecutionError::InvalidInstruction("mret".into())));
+    machine.csrs.write(csr::MSTATUS, csr::MSTATUS_TSR).unwrap();
+    assert_eq!(machine.sret(Instruction::Sret), Err(ExecutionError::InvalidInstruction("sret".into())));
+    machine.privilege = csr::Privilege::Machine;
+    assert_eq!(machine.sret(Instruction::Sret), Ok(()));
+    assert_eq!(machine.privilege(), csr::Privilege::User);
+
+    let mut machine_only = Machine::new(Config::default());
+    assert!(machine_only.sret(Instruction::Sret).is_err());
+}
+
+/// A bare RV32 hart in supervisor mode with Sv32 on. The root page table is
+/// at 0x10000, and its entry for the megapage at 0x4000_0000 points to a
+/// second-level table at 0x11000 that `map` fills in.
+fn sv32_machine() -> Machine {
+    let mut config = Config { bare: true, ..Config::default() };
+    config.extensions.s = true;
+    config.extensions.u = true;
+    let mut machine = Machine::new(config);
+    machine.bus_mut().write_u32(0x10000 + 0x100 * 4, 0x11 << 10 | mmu::PTE_V as u32).unwrap();
+    machine.csrs.satp = mmu::SATP_SV32 | 0x10;
+    machine.privilege = csr::Privilege::Supervisor;
+    machine
+}
+
+/// Map page `index` of the megapage at 0x4000_0000 to physical page `ppn`.
+fn map(machine: &mut Machine, index: u64, ppn: u64, flags: u64) {
+    machine.bus_mut().write_u32(0x11000 + index * 4, (ppn << 10 | flags) as u32).unwrap();
+}
+
+fn pte(machine: &mut Machine, index: u64) -> u64 {
+    machine.bus_mut().read_u32(0x11000 + index * 4).unwrap() as u64
+}
+
+#[test]
+fn test_sv32_translates_and_sets_accessed_and_dirty() {
+    use self::mmu::*;
+    let mut machine = sv32_machine();
+    map(&mut machine, 0, 0x2, PTE_V | PTE_R | PTE_X);
+    map(&mut machine, 1, 0x3, PTE_V | PTE_R | PTE_W);
+    let program = assembler::assemble("
+        lui t0, 0x40001
+        li t1, 42
+        sw t1, 4(t0)
+        lw t2, 4(t0)
+    end:
+        j end
+    ", 0x4000_0000).unwrap();
+    machine.load(0x2000, &program).unwrap();
+    machine.set_pc(0x4000_0000);
+
+    assert_eq!(machine.run(Some(5)), StopReason::InstructionLimit);
+    assert_eq!(machine.reg(7), 42);
+    assert_eq!(machine.bus_mut().read_u32(0x3004), Ok(42));
+    assert_eq!(pte(&mut machine, 0) & (PTE_A | PTE_D), PTE_A);
+    assert_eq!(pte(&mut machine, 1) & (PTE_A | PTE_D), PTE_A | PTE_D);
+    // One walk for each page; every other fetch, and the load, hit.
+    assert_eq!(machine.tlb().stats(), TlbStats { hits: 10, misses: 2 });
+}
+
+#[test]
+fn test_sv32_permission_checks() {
+    use self::mmu::*;
+    use self::csr::Privilege::*;
+    let mut machine = sv32_machine();
+    map(&mut machine, 0, 0x20, PTE_V | PTE_R | PTE_W | PTE_U);
+    map(&mut machine, 1, 0x21, PTE_V | PTE_X);
+    map(&mut machine, 2, 0x22, PTE_V | PTE_R);
+    let user_page = 0x4000_0010;
+    let exec_only = 0x4000_1000;
+    let read_only = 0x4000_2000;
+
+    assert_eq!(machine.translate(user_page, Access::Load), Err(ExecutionError::LoadPageFault(user_page)));
+    machine.csrs.mstatus |= csr::MSTATUS_SUM;
+    assert_eq!(machine.translate(user_page, Access::Load), Ok(0x20010));
+    assert_eq!(machine.translate(user_page, Access::Fetch), Err(ExecutionError::InstructionPageFault(user_page)));
+
+    assert_eq!(machine.translate(exec_only, Access::Load), Err(ExecutionError::LoadPageFault(exec_only)));
+    machine.csrs.mstatus |= csr::MSTATUS_MXR;
+    assert_eq!(machine.translate(exec_only, Access::Load), Ok(0x21000));
+    assert_eq!(machine.translate(read_only, Access::Store), Err(ExecutionError::StorePageFault(read_only)));
+
+    machine.privilege = User;
+    assert_eq!(machine.translate(exec_only, Access::Fetch), Err(ExecutionError::InstructionPageFault(exec_only)));
+    assert_eq!(machine.translate(user_page, Access::Store), Ok(0x20010));
+    assert_eq!(machine.translate(0x4000_3000, Access::Load), Err(ExecutionError::LoadPageFault(0x4000_3000)));
+
+    // Machine mode uses physical addresses unless MPRV points loads and
+    // stores at a lower mode.
+    machine.privilege = Machine;
+    assert_eq!(machine.translate(exec_only, Access::Load), Ok(exec_only));
+    machine.csrs.mstatus |= csr::MSTATUS_MPRV;
+    machine.csrs.mstatus &= !csr::MSTATUS_MPP;
+    assert_eq!(machine.translate(user_page, Access::Load), Ok(0x20010));
+    assert_eq!(machine.translate(exec_only, Access::Fetch), Ok(exec_only));
+
+    // A megapage must be 4 MiB aligned.
+    machine.privilege = Supervisor;
+    machine.bus_mut().write_u32(0x10000, 0x401 << 10 | (PTE_V | PTE_R) as u32).unwrap();
+    assert_eq!(machine.translate(0x1000, Access::Load), Err(ExecutionError::LoadPageFault(0x1000)));
+    machine.bus_mut().write_u32(0x10000, 0x400 << 10 | (PTE_V | PTE_R) as u32).unwrap();
+    assert_eq!(machine.translate(0x1234, Access::Load), Ok(0x40_1234));
+}
+
+#[test]
+fn test_sfence_vma_drops_stale_translations() {
+    use self::mmu::*;
+    let mut machine = sv32_machine();
+    map(&mut machine, 0, 0x20, PTE_V | PTE_R | PTE_G);
+    map(&mut machine, 1, 0x21, PTE_V | PTE_R);
+    assert_eq!(machine.translate(0x4000_0000, Access::Load), Ok(0x20000));
+    assert_eq!(machine.translate(0x4000_1000, Access::Load), Ok(0x21000));
+    map(&mut machine, 0, 0x30, PTE_V | PTE_R);
+    map(&mut machine, 1, 0x31, PTE_V | PTE_R);
+    assert_eq!(machine.translate(0x4000_0000, Access::Load), Ok(0x20000), "the TLB still holds the old mapping");
+
+    // Flushing ASID 0 spares the global page.
+    machine.regfile[11] = 0;
+    assert_eq!(machine.sfence_vma(Instruction::SfenceVma { rs1: 0, rs2: 11 }, 0, 11), Ok(()));
+    assert_eq!(machine.tlb().len(), 1);
+    assert_eq!(machine.translate(0x4000_1000, Access::Load), Ok(0x31000));
+    machine.regfile[10] = 0x4000_0000;
+    machine.sfence_vma(Instruction::SfenceVma { rs1: 10, rs2: 0 }, 10, 0).unwrap();
+    assert_eq!(machine.translate(0x4000_0000, Access::Load), Ok(0x30000));
+    assert_eq!(machine.tlb().stats(), TlbStats { hits: 1, misses: 4 });
+
+    machine.privilege = csr::Privilege::User;
+    assert!(machine.sfence_vma(Instruction::SfenceVma { rs1: 0, rs2: 0 }, 0, 0).is_err());
+}
+
+#[test]
+fn test_pmp_confines_user_mode() {
+    let mut config = Config { pmp: true, ..Config::default() };
+    config.extensions.u = true;
+    let mut machine = Machine::new(config);
+    let src = "
+        la t0, handler
+        csrw mtvec, t0
+        li t0, 0x1ff        # NAPOT: the 4 KiB at 0
+        csrw pmpaddr0, t0
+        li t0, 0x800        # TOR: from there up to 0x2000
+        csrw pmpaddr1, t0
+        li t0, 0x0b1d       # entry 0 R and X, entry 1 R and W
+        csrw pmpcfg0, t0
+        li t0, 0x1800
+        csrc mstatus, t0
+        la t0, user
+        csrw mepc, t0
+        mret
+    user:
+        li t1, 0x1000
+        li t2, 9
+        sw t2, 0(t1)
+        lw a0, 0(t1)
+        li t1, 0x2000
+        lw a1, 0(t1)
+    handler:
+        csrr s0, mcause
+        csrr s1, mtval
+    end:
+        j end
+    ";
+    machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+    machine.run(Some(100));
+    assert_eq!(machine.reg(10), 9);
+    assert_eq!(machine.reg(8), trap::LOAD_ACCESS_FAULT);
+    assert_eq!(machine.reg(9), 0x2000);
+}
+
+#[test]
+fn test_locked_pmp_entries_bind_machine_mode() {
+    use self::mmu::Access;
+    let mut machine = Machine::new(Config { bare: true, pmp: true, ..Config::default() });
+    machine.csrs.write(csr::PMPADDR0, 0x100 >> 2).unwrap();
+    machine.csrs.write(csr::PMPCFG0, (csr::PMP_L | csr::PMP_NA4 | csr::PMP_R) as u64).unwrap();
+
+    assert_eq!(machine.read_memory(0x100, 4, Access::Load), Ok(0));
+    assert_eq!(machine.write_memory(0x100, 4, 1), Err(ExecutionError::StoreAccessFault(0x100)));
+    assert_eq!(machine.read_memory(0x100, 4, Access::Fetch), Err(ExecutionError::InstructionAccessFault(0x100)));
+    // An access that only partly falls in the entry fails.
+    assert_eq!(machine.read_memory(0xFE, 4, Access::Load), Err(ExecutionError::LoadAccessFault(0xFE)));
+    assert_eq!(machine.write_memory(0x104, 4, 1), Ok(()), "no entry covers 0x104");
+
+    machine.csrs.write(csr::PMPCFG0, 0).unwrap();
+    assert_eq!(machine.csrs.read(csr::PMPCFG0), Some(0x91), "locked until reset");
+}
+
+#[test]
+fn test_pmp_faults_on_accesses_past_the_end_of_the_address_space() {
+    use self::mmu::Access;
+    let mut machine = Machine::new(Config { xlen: 64, bare: true, pmp: true, ..Config::default() });
+    assert_eq!(machine.read_memory(!0 - 7, 8, Access::Load), Err(ExecutionError::LoadAccessFault(!0 - 7)));
+    assert_eq!(machine.read_memory(!0 - 3, 8, Access::Load), Err(ExecutionError::LoadAccessFault(!0 - 3)));
+    assert_eq!(machine.write_memory(!0 - 3, 8, 1), Err(ExecutionError::StoreAccessFault(!0 - 3)));
+}
+
+#[test]
+fn test_bare_machine_stops_on_ecall_terminate() {
+    let mut machine = bare_machine();
+    load_words(&mut machine, &[
+        0x00a00513, // addi a0, zero, 10
+        0x00000073, // ecall
+    ]);
+    assert_eq!(machine.run(None), StopReason::Error(ExecutionError::UserTerminate));
+}
+
+#[test]
+fn test_wfi_skips_ahead_to_the_timer_interrupt() {
+    // Without the skip, reaching mtimecmp would take a thousand steps.
+    let (machine, _) = run_source(&format!("{}
+        li t0, 0x2004000    # mtimecmp = 1000
+        li t1, 1000
+        sw t1, 0(t0)
+        sw zero, 4(t0)
+        li t0, 0x80
+        csrw mie, t0
+        wfi
+        li a0, 1
+    end:
+        j end
+    handler:
+        rdtime s0
+        csrr s1, mcause
+        li t0, 0x2004004    # push mtimecmp out of reach to clear MTIP
+        li t1, -1
+        sw t1, 0(t0)
+        mret
+    ", TRAP_PRELUDE));
+
+    assert_eq!(machine.reg(10), 1);
+    // wfi wakes at 1000 and takes a tick of its own.
+    assert_eq!(machine.reg(8), 1001);
+    assert_eq!(machine.reg(9), 1 << 31 | trap::MACHINE_TIMER_INTERRUPT);
+    assert_eq!(machine.csrs().read(csr::MIP), Some(0));
+}
+
+#[test]
+fn test_wfi_does_not_skip_to_an_unprogrammed_timer() {
+    let (machine, reason) = run_source("
+        li t0, 0x80
+        csrw mie, t0
+        wfi
+        rdtime a0
+    end:
+        j end
+    ");
+
+    assert_eq!(reason, StopReason::InstructionLimit);
+    assert!(machine.reg(10) < 100, "time jumped to {:#x}", machine.reg(10));
+}
+
+#[test]
+fn test_clint_software_interrupt_and_mtime() {
+    let (machine, _) = run_source(&format!("{}
+        li t0, 0x200bff8    # mtime = 5000, which time follows
+        li t1, 5000
+        sw t1, 0(t0)
+        rdtime a0
+        li t0, 8
+        csrw mie, t0
+        li t0, 0x2000000
+        li t1, 1
+        sw t1, 0(t0)        # msip
+        li a1, 1
+    end:
+        j end
+    handler:
+        csrr s0, mcause
+        sw zero, 0(t0)
+        mret
+    ", TRAP_PRELUDE));
+
+    // The store to mtime takes a tick of its own.
+    assert_eq!(machine.reg(10), 5001);
+    assert_eq!(machine.reg(8), 1 << 31 | trap::MACHINE_SOFTWARE_INTERRUPT);
+    assert_eq!(machine.reg(11), 1);
+}
+
+#[test]
+fn test_wfi_checks_the_privilege_mode() {
+    let mut config = Config::default();
+    config.extensions.s = true;
+    config.extensions.u = true;
+    let mut machine = Machine::new(config);
+    assert_eq!(machine.wfi(Instruction::Wfi), Ok(()), "nothing to wait for");
+    machine.privilege = Privilege::Supervisor;
+    assert_eq!(machine.wfi(Instruction::Wfi), Ok(()));
+    machine.csrs.mstatus |= csr::MSTATUS_TW;
+    assert_eq!(machine.wfi(Instruction::Wfi), Err(ExecutionError::InvalidInstruction("wfi".into())));
+    machine.csrs.mstatus &= !csr::MSTATUS_TW;
+    machine.privilege = Privilege::User;
+    assert!(machine.wfi(Instruction::Wfi).is_err());
+}
+
+/// A device that holds its interrupt line high until the guest writes to it.
+struct Doorbell(bool);
+
+impl bus::Device for Doorbell {
+    fn read(&mut self, _offset: u64, _size: usize) -> Option<u64> { None }
+
+    fn write(&mut self, _offset: u64, _size: usize, _value: u64) -> Option<()> {
+        self.0 = false;
+        Some(())
+    }
+
+    fn interrupt_pending(&mut self) -> bool { self.0 }
+}
+
+const DOORBELL_BASE: u64 = 0x2000_0000;
+
+fn doorbell_machine(config: Config, src: &str) -> Machine {
+    let mut machine = Machine::new(config);
+    machine.bus_mut().map_device(DOORBELL_BASE, 4, Box::new(Doorbell(true)));
+    machine.connect_interrupt(1, DOORBELL_BASE);
+    machine.load(RAM_BASE, &assembler::assemble(src, RAM_BASE as u32).unwrap()).unwrap();
+    assert_eq!(machine.run(Some(100)), StopReason::InstructionLimit);
+    machine
+}
+
+#[test]
+fn test_plic_interrupts_machine_mode() {
+    let machine = doorbell_machine(Config::default(), &format!("{}
+        li t0, 0xc000004    # source 1 priority
+        li t1, 1
+        sw t1, 0(t0)
+        li t0, 0xc002000    # enable source 1 for machine mode
+        li t1, 2
+        sw t1, 0(t0)
+        li t0, 0x800
+        csrw mie, t0
+        li a0, 1
+    end:
+        j end
+    handler:
+        li t0, 0xc200004
+        lw s0, 0(t0)        # claim
+        li t1, 0x20000000
+        sw zero, 0(t1)
+        sw s0, 0(t0)        # complete
+        csrr s1, mcause
+        addi s2, s2, 1
+        mret
+    ", TRAP_PRELUDE));
+
+    assert_eq!(machine.reg(10), 1);
+    assert_eq!(machine.reg(8), 1);
+    assert_eq!(machine.reg(9), 1 << 31 | trap::MACHINE_EXTERNAL_INTERRUPT);
+    assert_eq!(machine.reg(18), 1, "completed once the line was low");
+}
+
+#[test]
+fn test_plic_line_shows_in_seip() {
+    let mut config = Config::default();
+    config.extensions.s = true;
+    config.extensions.u = true;
+    let machine = doorbell_machine(config, "
+        li t0, 0xc000004
+        li t1, 1
+        sw t1, 0(t0)
+        li t0, 0xc002080    # enable source 1 for supervisor mode
+        li t1, 2
+        sw t1, 0(t0)
+        nop
+        csrr a0, mip
+        csrsi mip, 2        # must not latch SEIP into its writable bit
+        li t0, 0x20000000
+        sw zero, 0(t0)
+        nop
+        csrr a1, mip
+    end:
+        j end
+    ");
+
+    assert_eq!(machine.reg(10), csr::MIP_SEIP);
+    assert_eq!(machine.reg(11), csr::MIP_SSIP);
+}
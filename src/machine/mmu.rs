@@ -0,0 +1,281 @@
+use super::*;
+use super::super::csr::*;
+
+// Sv32 page table entry bits. The physical page number sits above them,
+// from bit 10 up.
+pub const PTE_V: u64 = 1 << 0;
+pub const PTE_R: u64 = 1 << 1;
+pub const PTE_W: u64 = 1 << 2;
+pub const PTE_X: u64 = 1 << 3;
+pub const PTE_U: u64 = 1 << 4;
+pub const PTE_G: u64 = 1 << 5;
+pub const PTE_A: u64 = 1 << 6;
+pub const PTE_D: u64 = 1 << 7;
+
+/// satp.MODE on RV32: Sv32 when set, Bare when clear. The ASID is in bits
+/// 30:22 and the page number of the root page table in bits 21:0.
+pub const SATP_SV32: u64 = 1 << 31;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// What a memory access is for, which decides the permission a page needs
+/// and the exception a failed access raises.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Fetch,
+    Load,
+    Store,
+}
+
+impl Access {
+    fn access_fault(self, address: u64) -> ExecutionError {
+        match self {
+            Access::Fetch => ExecutionError::InstructionAccessFault(address),
+            Access::Load => ExecutionError::LoadAccessFault(address),
+            Access::Store => ExecutionError::StoreAccessFault(address),
+        }
+    }
+
+    fn page_fault(self, address: u64) -> ExecutionError {
+        match self {
+            Access::Fetch => ExecutionError::InstructionPageFault(address),
+            Access::Load => ExecutionError::LoadPageFault(address),
+            Access::Store => ExecutionError::StorePageFault(address),
+        }
+    }
+}
+
+/// How often translations were found in the TLB, and how often they needed
+/// a page table walk.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TlbStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TlbEntry {
+    /// The virtual page number, with the low 10 bits clear for a megapage.
+    vpn: u64,
+    asid: u64,
+    /// The leaf PTE, whose permissions are checked again on every hit.
+    pte: u64,
+    megapage: bool,
+}
+
+impl TlbEntry {
+    fn covers(&self, vpn: u64) -> bool {
+        self.vpn == if self.megapage { vpn & !0x3FF } else { vpn }
+    }
+}
+
+/// A fully associative TLB that replaces its entries in FIFO order. With no
+/// entries at all, every translation walks the page table.
+#[derive(Debug, Clone)]
+pub struct Tlb {
+    entries: Vec<TlbEntry>,
+    capacity: usize,
+    next: usize,
+    stats: TlbStats,
+}
+
+impl Tlb {
+    pub fn new(capacity: usize) -> Tlb {
+        Tlb { entries: Vec::with_capacity(capacity), capacity, next: 0, stats: TlbStats::default() }
+    }
+
+    pub fn stats(&self) -> TlbStats { self.stats }
+
+    /// The number of translations the TLB holds.
+    pub fn len(&self) -> usize { self.entries.len() }
+
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    fn lookup(&self, vpn: u64, asid: u64) -> Option<TlbEntry> {
+        self.entries.iter().cloned().find(|e| e.covers(vpn) && (e.pte & PTE_G != 0 || e.asid == asid))
+    }
+
+    fn insert(&mut self, entry: TlbEntry) {
+        if self.capacity == 0 { return; }
+        self.entries.retain(|e| !(e.covers(entry.vpn) && e.asid == entry.asid));
+        if self.entries.len() < self.capacity {
+            self.entries.push(entry);
+        }
+        else {
+            self.entries[self.next] = entry;
+            self.next = (self.next + 1) % self.capacity;
+        }
+    }
+
+    /// Drop the translations `sfence.vma` selects: those for the page
+    /// `vpn`, or every page if `None`, and those for `asid`, or every
+    /// address space if `None`. Global mappings survive a flush by ASID.
+    fn flush(&mut self, vpn: Option<u64>, asid: Option<u64>) {
+        self.entries.retain(|e| {
+            let page = vpn.is_none_or(|vpn| e.covers(vpn));
+            let space = asid.is_none_or(|asid| e.pte & PTE_G == 0 && e.asid == asid);
+            !(page && space)
+        });
+        self.next = 0;
+    }
+}
+
+/// Whether the leaf `pte` allows `access` from `privilege`. mstatus.SUM lets
+/// supervisor mode load and store, but not execute, on user pages, and
+/// mstatus.MXR makes executable pages readable.
+fn permitted(pte: u64, access: Access, privilege: Privilege, mstatus: u64) -> bool {
+    let user_page = pte & PTE_U != 0;
+    let mode = match privilege {
+        Privilege::User => user_page,
+        _ => !user_page || access != Access::Fetch && mstatus & MSTATUS_SUM != 0,
+    };
+    mode && match access {
+        Access::Fetch => pte & PTE_X != 0,
+        Access::Load => pte & PTE_R != 0 || mstatus & MSTATUS_MXR != 0 && pte & PTE_X != 0,
+        Access::Store => pte & PTE_W != 0,
+    }
+}
+
+impl Machine {
+    /// The mode an access is made in. Loads and stores from machine mode
+    /// use the mode in MPP when mstatus.MPRV is set.
+    fn effective_privilege(&self, access: Access) -> Privilege {
+        let mstatus = self.csrs.mstatus;
+        match access {
+            Access::Load | Access::Store if self.privilege == Privilege::Machine && mstatus & MSTATUS_MPRV != 0 => {
+                Privilege::from_bits(mstatus >> 11)
+            }
+            _ => self.privilege,
+        }
+    }
+
+    /// The mode an access is translated for, or `None` if it uses physical
+    /// addresses: always in machine mode and in Bare mode.
+    fn translation_privilege(&self, access: Access) -> Option<Privilege> {
+        let privilege = self.effective_privilege(access);
+        let sv32 = self.xlen == 32 && self.csrs.satp & SATP_SV32 != 0;
+        if sv32 && privilege != Privilege::Machine { Some(privilege) } else { None }
+    }
+
+    /// The physical address `access` to the virtual `address` goes to.
+    pub(crate) fn translate(&mut self, address: u64, access: Access) -> Result<u64, ExecutionError> {
+        let privilege = match self.translation_privilege(access) {
+            Some(privilege) => privilege,
+            None => return Ok(address),
+        };
+        let vpn = address >> 12;
+        let asid = self.csrs.satp >> 22 & 0x1FF;
+        // A store to a page that is not yet dirty walks again to set D.
+        let hit = self.tlb.lookup(vpn, asid).filter(|e| access != Access::Store || e.pte & PTE_D != 0);
+        let entry = match hit {
+            Some(entry) => {
+                self.tlb.stats.hits += 1;
+                if !permitted(entry.pte, access, privilege, self.csrs.mstatus) {
+                    return Err(access.page_fault(address));
+                }
+                entry
+            }
+            None => {
+                self.tlb.stats.misses += 1;
+                self.walk(address, asid, access, privilege)?
+            }
+        };
+        let offset_mask = if entry.megapage { (1 << 22) - 1 } else { PAGE_SIZE - 1 };
+        Ok(((entry.pte >> 10) << 12 & !offset_mask) | (address & offset_mask))
+    }
+
+    /// Walk the two-level Sv32 page table for `address`, check the leaf's
+    /// permissions, set its A bit (and D for a store) and cache it. The
+    /// walk's own accesses are checked by PMP as supervisor accesses.
+    fn walk(&mut self, address: u64, asid: u64, access: Access, privilege: Privilege)
+            -> Result<TlbEntry, ExecutionError> {
+        let fault = access.page_fault(address);
+        let vpn = address >> 12;
+        let mut table = (self.csrs.satp & 0x3F_FFFF) * PAGE_SIZE;
+        let mut level = 1;
+        let (pte, pte_address) = loop {
+            let pte_address = table + (vpn >> (10 * level) & 0x3FF) * 4;
+            if !self.pmp_allows(pte_address, 4, Access::Load, Privilege::Supervisor) {
+                return Err(access.access_fault(address));
+            }
+            let pte = self.bus.read_u32(pte_address).map_err(|_| access.access_fault(address))? as u64;
+            if pte & PTE_V == 0 || pte & PTE_R == 0 && pte & PTE_W != 0 { return Err(fault); }
+            if pte & (PTE_R | PTE_X) != 0 { break (pte, pte_address); }
+            if level == 0 { return Err(fault); }
+            level -= 1;
+            table = (pte >> 10) * PAGE_SIZE;
+        };
+
+        // A megapage must be aligned to its 4 MiB size.
+        let megapage = level == 1;
+        if megapage && pte >> 10 & 0x3FF != 0 { return Err(fault); }
+        if !permitted(pte, access, privilege, self.csrs.mstatus) { return Err(fault); }
+
+        let updated = pte | PTE_A | if access == Access::Store { PTE_D } else { 0 };
+        if updated != pte {
+            if !self.pmp_allows(pte_address, 4, Access::Store, Privilege::Supervisor) {
+                return Err(access.access_fault(address));
+            }
+            self.bus.write_u32(pte_address, updated as u32).map_err(|_| access.access_fault(address))?;
+        }
+        let entry = TlbEntry { vpn: if megapage { vpn & !0x3FF } else { vpn }, asid, pte: updated, megapage };
+        self.tlb.insert(entry);
+        Ok(entry)
+    }
+
+    /// Whether an access of `size` bytes at `address` spans two pages that
+    /// are translated separately.
+    fn crosses_page(&self, address: u64, size: usize, access: Access) -> bool {
+        address % PAGE_SIZE + size as u64 > PAGE_SIZE && self.translation_privilege(access).is_some()
+    }
+
+    /// Read `size` bytes, little-endian, from the virtual `address`, after
+    /// translation and the PMP check. The read half of an AMO passes
+    /// `Access::Store`, since it reports its faults as a store.
+    pub(crate) fn read_memory(&mut self, address: u64, size: usize, access: Access) -> Result<u64, ExecutionError> {
+        if self.crosses_page(address, size, access) {
+            let mut value = 0;
+            for i in 0..size {
+                let byte_address = address.wrapping_add(i as u64) & self.xlen_mask();
+                value |= self.read_memory(byte_address, 1, access)? << (8 * i);
+            }
+            return Ok(value);
+        }
+        let physical = self.translate(address, access)?;
+        if !self.pmp_allows(physical, size, access, self.effective_privilege(access)) {
+            return Err(access.access_fault(address));
+        }
+        self.bus.read(physical, size).map_err(|_| access.access_fault(address))
+    }
+
+    /// Write the low `size` bytes of `value`, little-endian, to the virtual
+    /// `address`.
+    pub(crate) fn write_memory(&mut self, address: u64, size: usize, value: u64) -> Result<(), ExecutionError> {
+        if self.crosses_page(address, size, Access::Store) {
+            for i in 0..size {
+                let byte_address = address.wrapping_add(i as u64) & self.xlen_mask();
+                self.write_memory(byte_address, 1, value >> (8 * i))?;
+            }
+            return Ok(());
+        }
+        let physical = self.translate(address, Access::Store)?;
+        if !self.pmp_allows(physical, size, Access::Store, self.effective_privilege(Access::Store)) {
+            return Err(Access::Store.access_fault(address));
+        }
+        self.bus.write(physical, size, value).map_err(|_| Access::Store.access_fault(address))
+    }
+
+    /// Drop the cached translations `sfence.vma` selects. rs1 names the
+    /// virtual address and rs2 the ASID; x0 selects all of them. Illegal in
+    /// user mode, and in supervisor mode when mstatus.TVM is set.
+    pub(crate) fn sfence_vma(&mut self, inst: Instruction, rs1: usize, rs2: usize) -> Result<(), ExecutionError> {
+        let tvm = self.privilege == Privilege::Supervisor && self.csrs.mstatus & MSTATUS_TVM != 0;
+        if !self.csrs.privilege_supported(Privilege::Supervisor) || self.privilege < Privilege::Supervisor || tvm {
+            return Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        }
+        let vpn = if rs1 == 0 { None } else { Some(self.reg(rs1) >> 12) };
+        let asid = if rs2 == 0 { None } else { Some(self.reg(rs2) & 0x1FF) };
+        self.tlb.flush(vpn, asid);
+        Ok(())
+    }
+}
@@ -0,0 +1,345 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::bus::{AccessFault, Bus};
+use super::csr::{CsrFile, Privilege};
+use super::decoder::*;
+use super::devices::{Clint, Plic};
+use super::elf::{ElfError, ElfFile, SymbolTable};
+use super::*;
+
+pub mod trap;
+pub mod mmu;
+pub mod pmp;
+pub mod interrupts;
+
+use self::mmu::{Access, Tlb};
+
+/// Everything needed to build a `Machine`.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub extensions: Extensions,
+    /// The width of the integer registers: 32 or 64.
+    pub xlen: u32,
+    pub ram_base: u64,
+    pub mem_size: usize,
+    /// Stop on the first exception instead of trapping to `mtvec`, and
+    /// service `ecall` in the emulator. For programs without a trap handler.
+    pub bare: bool,
+    /// The width of a vector register in bits, a power of two no smaller
+    /// than `elen`.
+    pub vlen: u32,
+    /// The widest vector element supported: 32 or 64.
+    pub elen: u32,
+    /// Cycles, which are steps, per tick of the `time` counter.
+    pub cycles_per_tick: u64,
+    /// Translations the TLB holds. With none, every access walks the page
+    /// table.
+    pub tlb_entries: usize,
+    /// Implement the 16 PMP entries. Without them, supervisor and user mode
+    /// may access all of memory.
+    pub pmp: bool,
+    /// Map a CLINT at `CLINT_BASE` for the timer and software interrupts.
+    /// Without one, `time` simply counts ticks.
+    pub clint: bool,
+    /// Map a PLIC at `PLIC_BASE` for device interrupts.
+    pub plic: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            extensions: Extensions::default(),
+            xlen: 32,
+            ram_base: RAM_BASE,
+            mem_size: MEM_SIZE,
+            bare: false,
+            vlen: DEFAULT_VLEN,
+            elen: DEFAULT_ELEN,
+            cycles_per_tick: 1,
+            tlb_entries: DEFAULT_TLB_ENTRIES,
+            pmp: false,
+            clint: true,
+            plic: true,
+        }
+    }
+}
+
+/// Why `step` or `run` handed control back to the caller.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopReason {
+    /// `run` executed the requested number of instructions.
+    InstructionLimit,
+    /// The pc of a bare machine ran off the end of the program into zeroed
+    /// memory.
+    EndOfProgram,
+    /// An instruction could not be fetched or executed, and the machine is
+    /// bare or the error is not one the guest can handle.
+    Error(ExecutionError),
+}
+
+/// A single RV32 or RV64 hart together with the bus it fetches, loads and
+/// stores through.
+pub struct Machine {
+    /// Integer registers, holding XLEN-bit values zero-extended to 64 bits.
+    pub(crate) regfile: Vec<u64>,
+    /// Floating-point registers, with narrower values NaN-boxed to 128 bits.
+    pub(crate) fregs: Vec<u128>,
+    /// The 32 vector registers, VLEN/8 bytes each, stored back to back so
+    /// that a register group is a contiguous slice.
+    pub(crate) vregs: Vec<u8>,
+    pub(crate) vlen: u32,
+    pub(crate) elen: u32,
+    pub(crate) bus: Bus,
+    pub(crate) pc: u64,
+    pub(crate) next_pc: u64,
+    pub(crate) xlen: u32,
+    pub(crate) csrs: CsrFile,
+    /// The mode the hart is running in. Harts start in machine mode.
+    pub(crate) privilege: Privilege,
+    pub(crate) tlb: Tlb,
+    /// The CLINT, which the bus also holds so that software can reach it.
+    pub(crate) clint: Option<Rc<RefCell<Clint>>>,
+    pub(crate) plic: Option<Rc<RefCell<Plic>>>,
+    /// PLIC sources and the base of the device whose line drives each.
+    interrupt_lines: Vec<(u32, u64)>,
+    /// The word reserved by the last `lr.w`, if no `sc.w` has consumed it.
+    pub(crate) reservation: Option<u64>,
+    pub(crate) extensions: Extensions,
+    pub(crate) bare: bool,
+    cycles_per_tick: u64,
+    /// Cycles since `time` last ticked.
+    tick_cycles: u64,
+    current_word: u32,
+    symbols: SymbolTable,
+    last_instruction: Option<Instruction>,
+}
+
+impl Machine {
+    pub fn new(config: Config) -> Machine {
+        assert!(config.xlen == 32 || config.xlen == 64, "XLEN must be 32 or 64, not {}", config.xlen);
+        assert!(config.elen == 32 || config.elen == 64, "ELEN must be 32 or 64, not {}", config.elen);
+        assert!(config.vlen.is_power_of_two() && config.vlen >= config.elen && config.vlen <= 65536,
+                "VLEN must be a power of two from ELEN to 65536, not {}", config.vlen);
+        assert!(config.cycles_per_tick > 0, "The time counter needs at least one cycle per tick");
+        assert!(config.extensions.u || !config.extensions.s, "Supervisor mode needs user mode");
+        assert!(config.extensions.f || !config.extensions.d, "Double precision needs single precision");
+        assert!(config.extensions.d || !config.extensions.q, "Quad precision needs double precision");
+        let mut bus = Bus::new();
+        bus.map_ram(config.ram_base, config.mem_size);
+        let clint = if config.clint {
+            let clint = Rc::new(RefCell::new(Clint::new()));
+            bus.map_device(CLINT_BASE, CLINT_SIZE, Box::new(clint.clone()));
+            Some(clint)
+        }
+        else { None };
+        let plic = if config.plic {
+            let plic = Rc::new(RefCell::new(Plic::new()));
+            bus.map_device(PLIC_BASE, PLIC_SIZE, Box::new(plic.clone()));
+            Some(plic)
+        }
+        else { None };
+
+        let mut csrs = CsrFile::new(&config.extensions, config.xlen);
+        csrs.vlenb = config.vlen as u64 / 8;
+        csrs.pmp = config.pmp;
+
+        Machine {
+            regfile: vec![0; if config.extensions.e { E_REGFILE_SIZE } else { REGFILE_SIZE }],
+            fregs: vec![0; REGFILE_SIZE],
+            vregs: vec![0; REGFILE_SIZE * config.vlen as usize / 8],
+            vlen: config.vlen,
+            elen: config.elen,
+            bus,
+            pc: config.ram_base,
+            next_pc: config.ram_base,
+            xlen: config.xlen,
+            csrs,
+            privilege: Privilege::Machine,
+            tlb: Tlb::new(config.tlb_entries),
+            clint,
+            plic,
+            interrupt_lines: Vec::new(),
+            reservation: None,
+            extensions: config.extensions,
+            bare: config.bare,
+            cycles_per_tick: config.cycles_per_tick,
+            tick_cycles: 0,
+            current_word: 0,
+            symbols: SymbolTable::default(),
+            last_instruction: None,
+        }
+    }
+
+    pub fn reg(&self, index: usize) -> u64 { self.regfile[index] }
+
+    /// Write `value`, truncated to XLEN bits, to register `index`.
+    pub fn set_reg(&mut self, index: usize, value: u64) {
+        if index != 0 { self.regfile[index] = value & self.xlen_mask(); }
+    }
+
+    /// Register `index` as a signed XLEN-bit value.
+    pub(crate) fn sreg(&self, index: usize) -> i64 {
+        let shift = 64 - self.xlen;
+        ((self.regfile[index] << shift) as i64) >> shift
+    }
+
+    pub fn registers(&self) -> &[u64] { &self.regfile }
+
+    pub fn xlen(&self) -> u32 { self.xlen }
+
+    pub(crate) fn xlen_mask(&self) -> u64 { !0 >> (64 - self.xlen) }
+
+    /// The raw contents of floating-point register `index`.
+    pub fn freg(&self, index: usize) -> u128 { self.fregs[index] }
+
+    pub fn float_registers(&self) -> &[u128] { &self.fregs }
+
+    /// The bytes of vector register `index`, element 0 first.
+    pub fn vreg(&self, index: usize) -> &[u8] {
+        let vlenb = self.vlen as usize / 8;
+        &self.vregs[index * vlenb..(index + 1) * vlenb]
+    }
+
+    pub fn vlen(&self) -> u32 { self.vlen }
+
+    pub fn pc(&self) -> u64 { self.pc }
+
+    pub fn set_pc(&mut self, pc: u64) { self.pc = pc; }
+
+    pub fn csrs(&self) -> &CsrFile { &self.csrs }
+
+    pub fn privilege(&self) -> Privilege { self.privilege }
+
+    pub fn tlb(&self) -> &Tlb { &self.tlb }
+
+    pub fn bus(&self) -> &Bus { &self.bus }
+
+    pub fn bus_mut(&mut self) -> &mut Bus { &mut self.bus }
+
+    /// Copy a program or data image into memory at `address`.
+    pub fn load(&mut self, address: u64, bytes: &[u8]) -> Result<(), AccessFault> {
+        self.bus.load(address, bytes)
+    }
+
+    /// Copy every `PT_LOAD` segment of `elf` into memory, zero-filling the
+    /// part of each segment that is not backed by the file, and start at
+    /// the entry point. The symbol table is kept for `symbols()`.
+    pub fn load_elf(&mut self, elf: &ElfFile) -> Result<(), ElfError> {
+        for segment in &elf.segments {
+            let mut image = segment.data.clone();
+            image.resize(segment.mem_size as usize, 0);
+            self.bus.load(segment.address as u64, &image)
+                .map_err(|_| ElfError::SegmentOutsideMemory(segment.address))?;
+        }
+        self.pc = elf.entry as u64;
+        self.next_pc = elf.entry as u64;
+        self.symbols = elf.symbols.clone();
+        Ok(())
+    }
+
+    /// Symbols of the loaded ELF file; empty for other kinds of images.
+    pub fn symbols(&self) -> &SymbolTable { &self.symbols }
+
+    pub fn extensions(&self) -> &Extensions { &self.extensions }
+
+    /// The instruction the last `step` decoded, whether or not it completed.
+    pub fn last_instruction(&self) -> Option<Instruction> { self.last_instruction }
+
+    /// Fetch, decode and execute a single instruction. An exception moves
+    /// the pc to the trap handler and counts as a step, but not as a
+    /// retired instruction. A pending interrupt is taken first, and the
+    /// step then runs the first instruction of its handler.
+    pub fn step(&mut self) -> Result<(), StopReason> {
+        self.last_instruction = None;
+        self.current_word = 0;
+        self.poll_interrupt_sources();
+        if !self.bare {
+            if let Some(code) = self.pending_interrupt() { self.enter_trap(trap::INTERRUPT | code, 0); }
+        }
+        let retired = match self.fetch_and_execute() {
+            Err(StopReason::Error(error)) => self.raise(error).map(|_| false),
+            other => other.map(|_| true),
+        }?;
+
+        self.csrs.advance_counters(retired);
+        self.tick_cycles += 1;
+        if self.tick_cycles == self.cycles_per_tick {
+            self.tick_cycles = 0;
+            self.advance_time(1);
+        }
+        Ok(())
+    }
+
+    fn fetch_and_execute(&mut self) -> Result<(), StopReason> {
+        let word = self.fetch_inst()?;
+        let compressed = get_bits(word as u8) == 16;
+        let inst = if compressed { decode_compressed(word as u16, self.xlen) } else { decode(word) }
+            .map_err(|e| StopReason::Error(ExecutionError::InvalidInstruction(e.to_string())))?;
+        self.last_instruction = Some(inst);
+        if inst.integer_registers().iter().any(|&r| r >= self.regfile.len()) {
+            return Err(StopReason::Error(ExecutionError::InvalidInstruction(inst.to_string())));
+        }
+
+        self.next_pc = self.pc.wrapping_add(if compressed { 2 } else { 4 }) & self.xlen_mask();
+        self.execute(inst).map_err(StopReason::Error)?;
+        self.pc = self.next_pc;
+        Ok(())
+    }
+
+    /// Step until something stops the machine or `max_insns` instructions
+    /// have been executed.
+    pub fn run(&mut self, max_insns: Option<u64>) -> StopReason {
+        let mut executed: u64 = 0;
+        loop {
+            if let Some(max) = max_insns {
+                if executed >= max { return StopReason::InstructionLimit; }
+            }
+            if let Err(reason) = self.step() { return reason; }
+            executed += 1;
+        }
+    }
+
+    fn fetch_inst(&mut self) -> Result<u32, StopReason> {
+        let pc = self.pc;
+
+        // An all-zero parcel is illegal in every encoding; in practice it
+        // means the pc walked past the program into untouched memory. A bare
+        // machine stops there, and otherwise it traps like any other
+        // illegal instruction.
+        let parcel = self.read_memory(pc, 2, Access::Fetch).map_err(StopReason::Error)? as u16;
+        if parcel == 0 {
+            if self.bare { return Err(StopReason::EndOfProgram); }
+            return Err(StopReason::Error(ExecutionError::InvalidInstruction("0x0000".into())));
+        }
+        self.current_word = parcel as u32;
+
+        match get_bits(parcel as u8) {
+            32 => {
+                // The two halves are fetched separately, as they may lie
+                // on different pages.
+                let high = self.read_memory(pc.wrapping_add(2) & self.xlen_mask(), 2, Access::Fetch)
+                    .map_err(StopReason::Error)?;
+                let word = parcel as u32 | (high as u32) << 16;
+                self.current_word = word;
+                if word == 0xFFFF_FFFF {
+                    Err(StopReason::Error(ExecutionError::InvalidInstruction(format!("0x{:08x}", word))))
+                }
+                else { Ok(word) }
+            }
+            16 if self.extensions.c => Ok(parcel as u32),
+            16 => Err(StopReason::Error(ExecutionError::Extension("C".into()))),
+            _ => {
+                Err(StopReason::Error(ExecutionError::Unimplemented("instructions longer than 32 bits".into())))
+            }
+        }
+    }
+
+    /// Effective address of a load or store: a register plus a signed offset.
+    pub(crate) fn effective_address(&self, rs1: usize, offset: i32) -> u64 {
+        self.regfile[rs1].wrapping_add(offset as i64 as u64) & self.xlen_mask()
+    }
+}
+
+#[cfg(test)]
+mod machine_test;
@@ -0,0 +1,58 @@
+use super::*;
+use super::super::csr::*;
+use super::mmu::Access;
+
+impl Machine {
+    /// The bytes `start..end` that PMP entry `index` covers, or `None` if
+    /// the entry is off. A TOR entry whose base is not below its top
+    /// covers nothing.
+    fn pmp_region(&self, index: usize) -> Option<(u64, u64)> {
+        let address = self.csrs.pmpaddr[index];
+        match self.csrs.pmpcfg[index] & PMP_A {
+            PMP_TOR => {
+                let base = if index == 0 { 0 } else { self.csrs.pmpaddr[index - 1] << 2 };
+                Some((base, address << 2))
+            }
+            PMP_NA4 => Some((address << 2, (address << 2) + 4)),
+            PMP_NAPOT => {
+                // n trailing ones select a region of 2^(n+3) bytes.
+                let ones = address.trailing_ones();
+                let base = (address & !((1 << ones) - 1)) << 2;
+                Some((base, base + (1 << (ones + 3))))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether PMP lets `privilege` make `access` to the `size` bytes at
+    /// the physical `address`. The lowest-numbered entry that covers any of
+    /// them decides: it must cover all of them, and grant the access to S
+    /// and U mode, or to M mode when it is locked. Machine mode may access
+    /// what no entry covers; the other modes may not. An access that runs
+    /// past the end of the address space is never allowed.
+    pub(crate) fn pmp_allows(&self, address: u64, size: usize, access: Access, privilege: Privilege) -> bool {
+        if !self.csrs.pmp { return true; }
+        let end = match address.checked_add(size as u64) {
+            Some(end) => end,
+            None => return false,
+        };
+        for index in 0..PMP_ENTRIES {
+            let (start, stop) = match self.pmp_region(index) {
+                Some(region) => region,
+                None => continue,
+            };
+            if address >= stop || end <= start { continue; }
+            if address < start || end > stop { return false; }
+
+            let cfg = self.csrs.pmpcfg[index];
+            if privilege == Privilege::Machine && cfg & PMP_L == 0 { return true; }
+            let needed = match access {
+                Access::Fetch => PMP_X,
+                Access::Load => PMP_R,
+                Access::Store => PMP_W,
+            };
+            return cfg & needed != 0;
+        }
+        privilege == Privilege::Machine
+    }
+}
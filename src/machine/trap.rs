@@ -0,0 +1,191 @@
+use super::*;
+use super::super::csr::*;
+
+// Exception codes written to mcause for synchronous traps.
+pub const INSTRUCTION_ADDRESS_MISALIGNED: u64 = 0;
+pub const INSTRUCTION_ACCESS_FAULT: u64 = 1;
+pub const ILLEGAL_INSTRUCTION: u64 = 2;
+pub const BREAKPOINT: u64 = 3;
+pub const LOAD_ADDRESS_MISALIGNED: u64 = 4;
+pub const LOAD_ACCESS_FAULT: u64 = 5;
+pub const STORE_ADDRESS_MISALIGNED: u64 = 6;
+pub const STORE_ACCESS_FAULT: u64 = 7;
+pub const ECALL_FROM_U: u64 = 8;
+pub const ECALL_FROM_S: u64 = 9;
+pub const ECALL_FROM_M: u64 = 11;
+pub const INSTRUCTION_PAGE_FAULT: u64 = 12;
+pub const LOAD_PAGE_FAULT: u64 = 13;
+pub const STORE_PAGE_FAULT: u64 = 15;
+
+// Interrupt codes, which are also the bit positions in mip and mie.
+pub const SUPERVISOR_SOFTWARE_INTERRUPT: u64 = 1;
+pub const MACHINE_SOFTWARE_INTERRUPT: u64 = 3;
+pub const SUPERVISOR_TIMER_INTERRUPT: u64 = 5;
+pub const MACHINE_TIMER_INTERRUPT: u64 = 7;
+pub const SUPERVISOR_EXTERNAL_INTERRUPT: u64 = 9;
+pub const MACHINE_EXTERNAL_INTERRUPT: u64 = 11;
+
+/// Interrupts in the order they are taken when several are pending.
+const INTERRUPT_PRIORITY: [u64; 6] = [
+    MACHINE_EXTERNAL_INTERRUPT, MACHINE_SOFTWARE_INTERRUPT, MACHINE_TIMER_INTERRUPT,
+    SUPERVISOR_EXTERNAL_INTERRUPT, SUPERVISOR_SOFTWARE_INTERRUPT, SUPERVISOR_TIMER_INTERRUPT,
+];
+
+/// Set in a trap cause when the trap was caused by an interrupt. mcause
+/// holds it in its top bit, which is bit 31 on RV32.
+pub const INTERRUPT: u64 = 1 << 63;
+
+impl ExecutionError {
+    /// The exception code this error is reported to the guest with, or
+    /// `None` for conditions that always stop the emulator. An ecall is
+    /// reported as coming from machine mode; `raise` corrects the code for
+    /// calls from the other modes.
+    pub fn exception_code(&self) -> Option<u64> {
+        match self {
+            ExecutionError::InstructionAddressMisaligned(_) => Some(INSTRUCTION_ADDRESS_MISALIGNED),
+            ExecutionError::InstructionAccessFault(_) => Some(INSTRUCTION_ACCESS_FAULT),
+            ExecutionError::Extension(_) | ExecutionError::InvalidInstruction(_) |
+            ExecutionError::Unimplemented(_) => Some(ILLEGAL_INSTRUCTION),
+            ExecutionError::Breakpoint => Some(BREAKPOINT),
+            ExecutionError::LoadAddressMisaligned(_) => Some(LOAD_ADDRESS_MISALIGNED),
+            ExecutionError::LoadAccessFault(_) => Some(LOAD_ACCESS_FAULT),
+            ExecutionError::StoreAddressMisaligned(_) => Some(STORE_ADDRESS_MISALIGNED),
+            ExecutionError::StoreAccessFault(_) => Some(STORE_ACCESS_FAULT),
+            ExecutionError::InstructionPageFault(_) => Some(INSTRUCTION_PAGE_FAULT),
+            ExecutionError::LoadPageFault(_) => Some(LOAD_PAGE_FAULT),
+            ExecutionError::StorePageFault(_) => Some(STORE_PAGE_FAULT),
+            ExecutionError::EnvironmentCall => Some(ECALL_FROM_M),
+            ExecutionError::UserTerminate => None,
+        }
+    }
+}
+
+impl Machine {
+    /// Hand `error` to the guest's trap handler, or stop if the machine is
+    /// bare or the error is not an architectural exception.
+    pub(crate) fn raise(&mut self, error: ExecutionError) -> Result<(), StopReason> {
+        let code = match error.exception_code() {
+            Some(ECALL_FROM_M) if !self.bare => ECALL_FROM_U + self.privilege as u64,
+            Some(code) if !self.bare => code,
+            _ => return Err(StopReason::Error(error)),
+        };
+        let tval = match error {
+            ExecutionError::InstructionAddressMisaligned(address) | ExecutionError::InstructionAccessFault(address) |
+            ExecutionError::LoadAddressMisaligned(address) | ExecutionError::LoadAccessFault(address) |
+            ExecutionError::StoreAddressMisaligned(address) | ExecutionError::StoreAccessFault(address) |
+            ExecutionError::InstructionPageFault(address) | ExecutionError::LoadPageFault(address) |
+            ExecutionError::StorePageFault(address) => address,
+            ExecutionError::Extension(_) | ExecutionError::InvalidInstruction(_) |
+            ExecutionError::Unimplemented(_) => self.current_word as u64,
+            ExecutionError::Breakpoint => self.pc,
+            _ => 0,
+        };
+        self.enter_trap(code, tval);
+        Ok(())
+    }
+
+    /// The highest priority interrupt that is pending, enabled and allowed
+    /// to preempt the current mode. Interrupts for a more privileged mode
+    /// than the current one are always allowed; those for the current mode
+    /// need its global enable bit, and those for a less privileged mode wait.
+    pub(crate) fn pending_interrupt(&self) -> Option<u64> {
+        let csrs = &self.csrs;
+        let pending = csrs.pending_interrupts() & csrs.mie;
+        let mut allowed = 0;
+        if self.privilege < Privilege::Machine || csrs.mstatus & MSTATUS_MIE != 0 {
+            allowed |= !csrs.mideleg;
+        }
+        if self.privilege < Privilege::Supervisor ||
+           self.privilege == Privilege::Supervisor && csrs.mstatus & MSTATUS_SIE != 0 {
+            allowed |= csrs.mideleg;
+        }
+        INTERRUPT_PRIORITY.iter().cloned().find(|&code| (pending & allowed) >> code & 1 != 0)
+    }
+
+    /// Take a trap with the given cause. Traps from below machine mode
+    /// that medeleg or mideleg delegate go to supervisor mode; the rest go
+    /// to machine mode. Either way the pc, the interrupt enable and the
+    /// previous mode are saved, and execution continues at the handler the
+    /// mode's trap vector selects. In vectored mode interrupts jump to
+    /// `base + 4 * code`; exceptions always use `base`.
+    pub(crate) fn enter_trap(&mut self, cause: u64, tval: u64) {
+        let code = cause & !INTERRUPT;
+        let interrupt = if cause & INTERRUPT != 0 { 1 << (self.xlen - 1) } else { 0 };
+        let mask = self.xlen_mask();
+        let previous = self.privilege as u64;
+        let csrs = &mut self.csrs;
+        let delegation = if interrupt != 0 { csrs.mideleg } else { csrs.medeleg };
+
+        let tvec = if self.privilege <= Privilege::Supervisor && delegation >> code & 1 != 0 {
+            csrs.sepc = self.pc;
+            csrs.scause = interrupt | code;
+            csrs.stval = tval & mask;
+
+            let sie = csrs.mstatus & MSTATUS_SIE != 0;
+            csrs.mstatus &= !(MSTATUS_SIE | MSTATUS_SPIE | MSTATUS_SPP);
+            if sie { csrs.mstatus |= MSTATUS_SPIE; }
+            csrs.mstatus |= previous << 8;
+            self.privilege = Privilege::Supervisor;
+            csrs.stvec
+        }
+        else {
+            csrs.mepc = self.pc;
+            csrs.mcause = interrupt | code;
+            csrs.mtval = tval & mask;
+
+            let mie = csrs.mstatus & MSTATUS_MIE != 0;
+            csrs.mstatus &= !(MSTATUS_MIE | MSTATUS_MPIE | MSTATUS_MPP);
+            if mie { csrs.mstatus |= MSTATUS_MPIE; }
+            csrs.mstatus |= previous << 11;
+            self.privilege = Privilege::Machine;
+            csrs.mtvec
+        };
+
+        let base = tvec & !0b11;
+        let vectored = tvec & 0b11 == 1 && interrupt != 0;
+        self.pc = if vectored { base.wrapping_add(4 * code) & mask } else { base };
+        self.next_pc = self.pc;
+    }
+
+    /// Return from a machine-mode trap handler to the mode in mstatus.MPP,
+    /// which is reset to the least privileged mode. Only machine mode may.
+    pub(crate) fn mret(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        if self.privilege != Privilege::Machine {
+            return Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        }
+        let mepc = self.csrs.read(MEPC).unwrap_or(0);
+        self.jump(mepc)?;
+
+        let lowest = self.csrs.lowest_privilege() as u64;
+        let csrs = &mut self.csrs;
+        let mpie = csrs.mstatus & MSTATUS_MPIE != 0;
+        let previous = Privilege::from_bits(csrs.mstatus >> 11);
+        csrs.mstatus &= !(MSTATUS_MIE | MSTATUS_MPP);
+        if mpie { csrs.mstatus |= MSTATUS_MIE; }
+        csrs.mstatus |= MSTATUS_MPIE | lowest << 11;
+        if previous != Privilege::Machine { csrs.mstatus &= !MSTATUS_MPRV; }
+        self.privilege = previous;
+        Ok(())
+    }
+
+    /// Return from a supervisor-mode trap handler to the mode in
+    /// mstatus.SPP. Illegal in user mode, and in supervisor mode when
+    /// mstatus.TSR is set.
+    pub(crate) fn sret(&mut self, inst: Instruction) -> Result<(), ExecutionError> {
+        let tsr = self.privilege == Privilege::Supervisor && self.csrs.mstatus & MSTATUS_TSR != 0;
+        if !self.csrs.privilege_supported(Privilege::Supervisor) || self.privilege < Privilege::Supervisor || tsr {
+            return Err(ExecutionError::InvalidInstruction(inst.to_string()));
+        }
+        let sepc = self.csrs.read(SEPC).unwrap_or(0);
+        self.jump(sepc)?;
+
+        let csrs = &mut self.csrs;
+        let spie = csrs.mstatus & MSTATUS_SPIE != 0;
+        let previous = Privilege::from_bits(csrs.mstatus >> 8 & 1);
+        csrs.mstatus &= !(MSTATUS_SIE | MSTATUS_SPP | MSTATUS_MPRV);
+        if spie { csrs.mstatus |= MSTATUS_SIE; }
+        csrs.mstatus |= MSTATUS_SPIE;
+        self.privilege = previous;
+        Ok(())
+    }
+}
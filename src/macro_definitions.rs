@@ -1,29 +1,3 @@
-macro_rules! process {
-    ($f:expr) => {
-        if let Err(e) = $f {
-            print!("Terminated: ");
-            match e {
-                ExecutionError::Extension(ext) => {
-                    println!("The {} extension was not activated", ext)
-                }
-                ExecutionError::InstructionAddressMisaligned =>{
-                    println!("Instruction address misaligned exception")
-                }
-                ExecutionError::InvalidInstruction(inst) => {
-                    println!("{} is an invalid instruction", inst)
-                }
-                ExecutionError::Unimplemented(inst) => {
-                    println!("The {} instruction is not implemented", inst)
-                }
-                ExecutionError::UserTerminate => {
-                    println!("The user terminated the program")
-                }
-            }
-            break;
-        }
-    }
-}
-
 macro_rules! parse_extensions {
     ([$ap:ident; $ext:ident] $($t:ident),*) => {
         $(
@@ -33,4 +7,4 @@ macro_rules! parse_extensions {
         )*
 
     }
-}
\ No newline at end of file
+}
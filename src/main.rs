@@ -1,159 +1,208 @@
 extern crate argparse;
 use argparse::{ArgumentParser, StoreTrue, Store};
 
-mod decoder;
-use decoder::*;
-
-mod implementer;
-use implementer::{*, rtype::*, itype::*, utype::*, stype::*, ujtype::*, sbtype::*};
-
-mod assembler;
-use assembler::*;
+extern crate riscv_emulator;
+use riscv_emulator::{Machine, Config, StopReason, TEXT_BASE, UART_BASE, UART_IRQ, UART_SIZE};
+use riscv_emulator::devices::Uart;
+use riscv_emulator::decoder::load_into_imem;
+use riscv_emulator::assembler::assemble;
+use riscv_emulator::disassembler::{disassemble, disassemble_elf};
+use riscv_emulator::elf;
 
 #[macro_use]
 mod macro_definitions;
 
-
-const REGFILE_SIZE: usize = 32;
-const MEM_SIZE: usize = 1048576 * 4; // 32 address space in RV32I 
-
-const INSTRUCTION_ADDRESS_MISALIGNED_THRESHOLD: i32 = 4;
-
-#[allow(dead_code)]
-pub struct Extensions {
-    m: bool,
-    a: bool,
-    f: bool,
-    e: bool,
-    c: bool,
-    d: bool,
-    q: bool,
-}
-
-pub enum ExecutionError {
-    Extension(String),
-    InvalidInstruction(String),
-    InstructionAddressMisaligned,
-    Unimplemented(String),  
-    UserTerminate
-}
-
 fn main() {
-    
-    let mut imem: Vec<u8> = Vec::new();
-    let mut regfile: Vec<u32> = vec![0; REGFILE_SIZE];
-    let mut mem: Vec<u8> = vec![0; MEM_SIZE];
 
+    let mut config = Config::default();
     let mut src_filepath: String = "./risc-v/sources/test.S".into();
-    let mut extensions = Extensions{ a: false, m: false, e: false, f: false, d: false, q: false, c: false };
     let mut use_hex = false;
+    let mut use_bin = false;
+    let mut use_elf = false;
+    let mut disasm = false;
+    let mut trace = false;
 
     {
         let mut ap = ArgumentParser::new();
-
-        parse_extensions!([ap;extensions] a, m, e, f, d, q, c);
-
+        let extensions = &mut config.extensions;
+
+        parse_extensions!([ap;extensions] a, m, e, f, d, q, c, v, s, u);
+        ap.refer(&mut extensions.zba).add_option(&["--zba"], StoreTrue, "Enable Zba extension");
+        ap.refer(&mut extensions.zbb).add_option(&["--zbb"], StoreTrue, "Enable Zbb extension");
+        ap.refer(&mut extensions.zbc).add_option(&["--zbc"], StoreTrue, "Enable Zbc extension");
+        ap.refer(&mut extensions.zbs).add_option(&["--zbs"], StoreTrue, "Enable Zbs extension");
+
+        ap.refer(&mut config.xlen)
+            .add_option(&["--xlen"], Store, "Width of the integer registers, 32 or 64");
+        ap.refer(&mut config.vlen)
+            .add_option(&["--vlen"], Store, "Width of the vector registers in bits, a power of two");
+        ap.refer(&mut config.elen)
+            .add_option(&["--elen"], Store, "Widest vector element in bits, 32 or 64");
+        ap.refer(&mut config.cycles_per_tick)
+            .add_option(&["--cycles-per-tick"], Store, "Instructions executed per tick of the time counter");
+        ap.refer(&mut config.pmp)
+            .add_option(&["--pmp"], StoreTrue, "Implement the 16 physical memory protection entries");
+        ap.refer(&mut config.tlb_entries)
+            .add_option(&["--tlb-entries"], Store, "Translations the TLB holds, or 0 to walk the page table every time");
         ap.refer(&mut src_filepath)
             .add_option(&["--file"], Store, "File to emulate");
         ap.refer(&mut use_hex)
             .add_option(&["--hex", "-h"], StoreTrue, "Set if the source file is assembled hex");
+        ap.refer(&mut use_bin)
+            .add_option(&["--bin"], StoreTrue, "Set if the source file is a raw binary image");
+        ap.refer(&mut use_elf)
+            .add_option(&["--elf"], StoreTrue, "Set if the source file is an ELF32 executable");
+        ap.refer(&mut disasm)
+            .add_option(&["--disasm"], StoreTrue, "Print the disassembled program instead of running it");
+        ap.refer(&mut config.bare)
+            .add_option(&["--bare"], StoreTrue, "Stop on the first exception instead of trapping, and handle ecall in the emulator");
+        ap.refer(&mut trace)
+            .add_option(&["--trace"], StoreTrue, "Print each instruction and the registers after it runs");
         ap.parse_args_or_exit();
     }
- 
-    if use_hex {
-        if let Err(e) = load_into_imem(&src_filepath, &mut imem) { 
-            println!("Error loading into IMEM: {}", e); 
-        }
-    } else {
-        assemble_and_load(&src_filepath, &mut mem, &mut imem);
+
+    if config.xlen != 32 && config.xlen != 64 {
+        println!("XLEN must be 32 or 64, not {}", config.xlen);
+        return;
+    }
+    if config.elen != 32 && config.elen != 64 {
+        println!("ELEN must be 32 or 64, not {}", config.elen);
+        return;
+    }
+    if !config.vlen.is_power_of_two() || config.vlen < config.elen || config.vlen > 65536 {
+        println!("VLEN must be a power of two from ELEN to 65536, not {}", config.vlen);
+        return;
+    }
+    if config.cycles_per_tick == 0 {
+        println!("The time counter needs at least one cycle per tick");
+        return;
+    }
+    if config.extensions.s && !config.extensions.u {
+        println!("Supervisor mode needs user mode (-u)");
+        return;
+    }
+    if config.extensions.d && !config.extensions.f {
+        println!("Double precision needs single precision (-f)");
+        return;
     }
-    
-    let mut pc: u32 = 0;
-    loop {
-        
-        let bytes = match fetch_inst(pc, &imem) { 
-            Ok(b) => b, 
+    if config.extensions.q && !config.extensions.d {
+        println!("Quad precision needs double precision (-d)");
+        return;
+    }
+    let digits = config.xlen as usize / 4;
+    let mut machine = Machine::new(config);
+    machine.bus_mut().map_device(UART_BASE, UART_SIZE, Box::new(Uart::stdio()));
+    machine.connect_interrupt(UART_IRQ, UART_BASE);
+
+    if use_elf {
+        let elf = std::fs::read(&src_filepath)
+            .map_err(|e| format!("Could not open {}: {}", src_filepath, e))
+            .and_then(|bytes| elf::parse(&bytes).map_err(|e| format!("Error loading {}: {}", src_filepath, e)));
+        let elf = match elf {
+            Ok(elf) => elf,
             Err(e) => {
                 println!("{}", e);
-                break;
-            } 
+                return;
+            }
         };
 
-        match get_opcode(bytes) {
-            0x3 | 0x13 | 0x1B | 0x67 | 0x73 => { 
-                process! {
-                    handle_i_type(&mut regfile, &mut mem, bytes, &mut pc, &extensions)
-                }
-            }
-            0x17 | 0x37 => { 
-                process! {
-                    handle_u_type(&mut regfile, bytes, &mut pc, &extensions)
-                }
-            }
-            0x23 => { 
-                process! {
-                    handle_s_type(&mut regfile, &mut mem, bytes, &mut pc, &extensions)
-                }
-            }
-            0x33 | 0x3B => { 
-                process! {
-                    handle_r_type(&mut regfile, bytes, &mut pc, &extensions)
-                }
-            }
-            0x63 => { 
-                process! {
-                    handle_sb_type(&mut regfile, bytes, &mut pc, &extensions)
-                }
+        if disasm {
+            for line in disassemble_elf(&elf) {
+                println!("{}", line);
             }
-            0x6F => { 
-                process! {
-                    handle_uj_type(&mut regfile, bytes, &mut pc, &extensions)
-                }
-            }
-            0xFF => {
-                process! {
-                    handle_fence(&mut regfile, bytes, &mut pc)
-                }
+            return;
+        }
+
+        if let Err(e) = machine.load_elf(&elf) {
+            println!("Error loading into memory: {}", e);
+            return;
+        }
+    }
+    else {
+        let image = if use_hex {
+            let mut imem: Vec<u8> = Vec::new();
+            load_into_imem(&src_filepath, &mut imem).map(|_| imem).map_err(|e| format!("Error loading into IMEM: {}", e))
+        } else if use_bin {
+            std::fs::read(&src_filepath).map_err(|e| format!("Error reading binary: {}", e))
+        } else {
+            std::fs::read_to_string(&src_filepath)
+                .map_err(|e| format!("Could not open {}: {}", src_filepath, e))
+                .and_then(|src| assemble(&src, TEXT_BASE as u32).map_err(|e| format!("Error assembling {}: {}", src_filepath, e)))
+        };
+
+        let image = match image {
+            Ok(image) => image,
+            Err(e) => {
+                println!("{}", e);
+                return;
             }
-            _ => {
-                println!("UNRECOGNIZED OPCODE: {}", get_opcode(bytes));
-                println!("Instruction was: {:?}", bytes);
-                break;
+        };
+
+        if disasm {
+            for line in disassemble(&image, TEXT_BASE as u32) {
+                println!("{}", line);
             }
+            return;
         }
-        
-        regfile[0] = 0;
-        print_registers(&mut regfile);
 
-        std::thread::sleep(std::time::Duration::from_millis(1000));
+        if let Err(e) = machine.load(TEXT_BASE, &image) {
+            println!("Error loading into memory: {}", e);
+            return;
+        }
+    }
+
+    let reason = loop {
+        if let Err(reason) = machine.step() { break reason; }
+        if trace {
+            if let Some(inst) = machine.last_instruction() { println!("{}", inst); }
+            print_registers(machine.registers(), digits);
+        }
+    };
+
+    match reason {
+        StopReason::Error(e) => {
+            println!("Terminated: {}", e);
+            let pc = machine.pc();
+            // Symbols come from a 32-bit ELF file, so a wider pc has none.
+            let symbol = if pc >> 32 == 0 { machine.symbols().describe(pc as u32) } else { None };
+            let location = match symbol {
+                Some(name) => format!("0x{:08x} <{}>", machine.pc(), name),
+                None => format!("0x{:08x}", machine.pc()),
+            };
+            match machine.last_instruction() {
+                Some(inst) => println!("  at {}: {}", location, inst.display_at(pc)),
+                None => println!("  at {}", location),
+            }
+        }
+        StopReason::EndOfProgram => println!("End of program"),
+        StopReason::InstructionLimit => {}
+    }
+    print_registers(machine.registers(), digits);
+    let tlb = machine.tlb().stats();
+    if tlb.hits + tlb.misses > 0 {
+        println!("TLB: {} hits, {} misses", tlb.hits, tlb.misses);
+    }
+    let extensions = machine.extensions();
+    if extensions.f || extensions.d || extensions.q {
+        let digits = if extensions.q { 32 } else if extensions.d { 16 } else { 8 };
+        print_float_registers(machine.float_registers(), digits);
     }
 }
 
 // Helper functions
 
-fn print_registers(regfile: &mut [u32]) {
-    for (i, r) in regfile.into_iter().enumerate() {
+fn print_registers(regfile: &[u64], digits: usize) {
+    for (i, r) in regfile.iter().enumerate() {
         if *r != 0 {
-            println!("x{}: 0x{:08x}", i, *r as i32);
+            println!("x{}: 0x{:0width$x}", i, *r, width = digits);
         }
     }
 }
 
-fn fetch_inst(pc: u32, imem: &[u8]) -> Result<&[u8], String> {
-    
-    if (pc as usize + 4) > imem.len() { return Err("End of imem".into()); }
-
-    match get_bits(imem[pc as usize]) {
-        32 => {
-            let bytes = &imem[(pc as usize)..(pc as usize) + 4];
-            if encode_hex(bytes) == "00000000".to_string() || encode_hex(bytes) == "11111111".to_string() {
-                Err(format!("The instruction 0x{} is illegal", encode_hex(bytes)))
-            }
-            else { Ok(bytes) }
-        }
-        _ => {
-            Err("Only 32 bit instructions are supported".into())
+fn print_float_registers(fregs: &[u128], digits: usize) {
+    for (i, r) in fregs.iter().enumerate() {
+        if *r != 0 {
+            println!("f{}: 0x{:0width$x}", i, *r & (!0 >> (128 - 4 * digits)), width = digits);
         }
     }
-    
 }
@@ -0,0 +1,570 @@
+//! IEEE 754 binary floating point in software, with every rounding mode and
+//! exception flag the RISC-V F, D and Q extensions need. Values are passed as
+//! raw bit patterns in the low bits of a `u128`; every NaN result is the
+//! canonical NaN, as RISC-V requires.
+//!
+//! Internally a finite value is a sign, an integer significand and the
+//! exponent of that significand's least significant bit.
+
+/// The shape of a binary interchange format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    exp_bits: u32,
+    frac_bits: u32,
+}
+
+pub const F32: Format = Format { exp_bits: 8, frac_bits: 23 };
+pub const F64: Format = Format { exp_bits: 11, frac_bits: 52 };
+pub const F128: Format = Format { exp_bits: 15, frac_bits: 112 };
+
+// Exception flags, in their fflags bit positions.
+pub const INEXACT: u8 = 0x01;
+pub const UNDERFLOW: u8 = 0x02;
+pub const OVERFLOW: u8 = 0x04;
+pub const DIVIDE_BY_ZERO: u8 = 0x08;
+pub const INVALID: u8 = 0x10;
+
+/// Rounding-mode encodings of the `rm` field and `frm`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    NearestEven,
+    TowardZero,
+    Down,
+    Up,
+    NearestMaxMagnitude,
+}
+
+impl Rounding {
+    pub fn from_bits(bits: u32) -> Option<Rounding> {
+        match bits {
+            0 => Some(Rounding::NearestEven),
+            1 => Some(Rounding::TowardZero),
+            2 => Some(Rounding::Down),
+            3 => Some(Rounding::Up),
+            4 => Some(Rounding::NearestMaxMagnitude),
+            _ => None,
+        }
+    }
+}
+
+// The bit a significand's leading one sits at before rounding. The bits
+// below a format's last significand bit act as guard and sticky bits.
+const LEAD: u32 = 126;
+
+impl Format {
+    pub fn width(self) -> u32 { 1 + self.exp_bits + self.frac_bits }
+
+    /// All bits of the format set.
+    pub fn mask(self) -> u128 {
+        if self.width() == 128 { !0 } else { (1 << self.width()) - 1 }
+    }
+
+    fn bias(self) -> i32 { (1 << (self.exp_bits - 1)) - 1 }
+
+    fn max_exp(self) -> i32 { (1 << self.exp_bits) - 1 }
+
+    fn frac_mask(self) -> u128 { (1 << self.frac_bits) - 1 }
+
+    pub fn sign_bit(self) -> u128 { 1 << (self.exp_bits + self.frac_bits) }
+
+    pub fn canonical_nan(self) -> u128 {
+        ((self.max_exp() as u128) << self.frac_bits) | (1 << (self.frac_bits - 1))
+    }
+
+    fn zero(self, sign: bool) -> u128 { if sign { self.sign_bit() } else { 0 } }
+
+    fn infinity(self, sign: bool) -> u128 { self.zero(sign) | (self.max_exp() as u128) << self.frac_bits }
+
+    fn max_finite(self, sign: bool) -> u128 { self.infinity(sign) - 1 }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value {
+    Nan { signaling: bool },
+    Infinity(bool),
+    Zero(bool),
+    Finite { sign: bool, exp: i32, sig: u128 },
+}
+
+use self::Value::*;
+
+fn unpack(fmt: Format, bits: u128) -> Value {
+    let bits = bits & fmt.mask();
+    let sign = bits & fmt.sign_bit() != 0;
+    let exp = ((bits >> fmt.frac_bits) as i32) & fmt.max_exp();
+    let frac = bits & fmt.frac_mask();
+    let min_exp = 1 - fmt.bias() - fmt.frac_bits as i32;
+
+    if exp == fmt.max_exp() {
+        if frac == 0 { Infinity(sign) } else { Nan { signaling: frac >> (fmt.frac_bits - 1) == 0 } }
+    }
+    else if exp == 0 {
+        if frac == 0 { Zero(sign) } else { Finite { sign, exp: min_exp, sig: frac } }
+    }
+    else {
+        Finite { sign, exp: min_exp + exp - 1, sig: frac | (1 << fmt.frac_bits) }
+    }
+}
+
+pub fn is_nan(fmt: Format, bits: u128) -> bool {
+    matches!(unpack(fmt, bits), Nan { .. })
+}
+
+fn is_signaling(value: Value) -> bool {
+    value == Nan { signaling: true }
+}
+
+/// The canonical NaN, raising invalid if any operand was a signaling NaN.
+fn propagate_nan(fmt: Format, operands: &[Value], flags: &mut u8) -> u128 {
+    if operands.iter().any(|&v| is_signaling(v)) { *flags |= INVALID; }
+    fmt.canonical_nan()
+}
+
+fn invalid(fmt: Format, flags: &mut u8) -> u128 {
+    *flags |= INVALID;
+    fmt.canonical_nan()
+}
+
+/// Shift right, ORing everything shifted out into the lowest bit.
+fn shift_right_sticky(x: u128, n: u32) -> u128 {
+    if n == 0 { x }
+    else if n >= 128 { (x != 0) as u128 }
+    else { (x >> n) | ((x & ((1 << n) - 1) != 0) as u128) }
+}
+
+/// Place the leading one of `sig` at bit `lead`, adjusting `exp` to match.
+fn normalize(exp: i32, sig: u128, lead: u32) -> (i32, u128) {
+    let msb = 127 - sig.leading_zeros();
+    if msb > lead {
+        (exp + (msb - lead) as i32, shift_right_sticky(sig, msb - lead))
+    }
+    else {
+        (exp - (lead - msb) as i32, sig << (lead - msb))
+    }
+}
+
+/// Drop the low `shift` bits of `sig`, returning the kept bits, whether
+/// `rm` rounds them up, and whether anything nonzero was dropped. `sig`
+/// must have its leading one at or below `LEAD`.
+fn round_bits(sig: u128, shift: u32, rm: Rounding, sign: bool) -> (u128, bool, bool) {
+    if shift == 0 { return (sig, false, false); }
+    let (kept, rest, half) = if shift >= 128 {
+        (0, sig, None)
+    } else {
+        (sig >> shift, sig & ((1 << shift) - 1), Some(1u128 << (shift - 1)))
+    };
+    let inexact = rest != 0;
+    let up = match rm {
+        Rounding::NearestEven => half.is_some_and(|h| rest > h || (rest == h && kept & 1 == 1)),
+        Rounding::NearestMaxMagnitude => half.is_some_and(|h| rest >= h),
+        Rounding::TowardZero => false,
+        Rounding::Down => sign && inexact,
+        Rounding::Up => !sign && inexact,
+    };
+    (kept, up, inexact)
+}
+
+/// Round `sig * 2^exp` to `fmt` and pack it. `sig` must be nonzero.
+fn round_pack(fmt: Format, sign: bool, exp: i32, sig: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    let (exp, sig) = normalize(exp, sig, LEAD);
+    let extra = LEAD - fmt.frac_bits;
+    let biased = exp + LEAD as i32 + fmt.bias();
+
+    if biased >= 1 {
+        let (kept, up, inexact) = round_bits(sig, extra, rm, sign);
+        let mut mant = kept + up as u128;
+        let mut biased = biased;
+        if mant >> (fmt.frac_bits + 1) != 0 {
+            mant >>= 1;
+            biased += 1;
+        }
+        if biased >= fmt.max_exp() {
+            *flags |= OVERFLOW | INEXACT;
+            let to_infinity = match rm {
+                Rounding::NearestEven | Rounding::NearestMaxMagnitude => true,
+                Rounding::TowardZero => false,
+                Rounding::Down => sign,
+                Rounding::Up => !sign,
+            };
+            return if to_infinity { fmt.infinity(sign) } else { fmt.max_finite(sign) };
+        }
+        if inexact { *flags |= INEXACT; }
+        return fmt.zero(sign) | (biased as u128) << fmt.frac_bits | (mant & fmt.frac_mask());
+    }
+
+    // Subnormal range. Tininess is detected after rounding: a value that
+    // rounds up to the smallest normal with an unbounded exponent is not tiny.
+    let (kept, up, _) = round_bits(sig, extra, rm, sign);
+    let tiny = biased < 0 || (kept + up as u128) >> (fmt.frac_bits + 1) == 0;
+
+    let shift = extra.saturating_add((1 - biased) as u32);
+    let (kept, up, inexact) = round_bits(sig, shift, rm, sign);
+    if inexact {
+        *flags |= INEXACT;
+        if tiny { *flags |= UNDERFLOW; }
+    }
+    // A carry out of the fraction lands in the exponent field, producing
+    // the smallest normal number.
+    fmt.zero(sign) | (kept + up as u128)
+}
+
+pub fn add(fmt: Format, a: u128, b: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    match (va, vb) {
+        (Nan { .. }, _) | (_, Nan { .. }) => propagate_nan(fmt, &[va, vb], flags),
+        (Infinity(sa), Infinity(sb)) if sa != sb => invalid(fmt, flags),
+        (Infinity(s), _) | (_, Infinity(s)) => fmt.infinity(s),
+        (Zero(sa), Zero(sb)) => fmt.zero(if sa == sb { sa } else { rm == Rounding::Down }),
+        (Zero(_), _) => b & fmt.mask(),
+        (_, Zero(_)) => a & fmt.mask(),
+        (Finite { sign: sa, exp: ea, sig: ma }, Finite { sign: sb, exp: eb, sig: mb }) => {
+            // Leave a bit of headroom for the carry out of the addition.
+            let (ea, ma) = normalize(ea, ma, LEAD - 1);
+            let (eb, mb) = normalize(eb, mb, LEAD - 1);
+            let ((sa, ea, ma), (sb, eb, mb)) = if ea >= eb { ((sa, ea, ma), (sb, eb, mb)) } else { ((sb, eb, mb), (sa, ea, ma)) };
+            let mb = shift_right_sticky(mb, (ea - eb) as u32);
+
+            if sa == sb {
+                return round_pack(fmt, sa, ea, ma + mb, rm, flags);
+            }
+            let (sign, diff) = if ma >= mb { (sa, ma - mb) } else { (sb, mb - ma) };
+            if diff == 0 { return fmt.zero(rm == Rounding::Down); }
+            round_pack(fmt, sign, ea, diff, rm, flags)
+        }
+    }
+}
+
+pub fn sub(fmt: Format, a: u128, b: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    add(fmt, a, b ^ fmt.sign_bit(), rm, flags)
+}
+
+/// The full 256-bit product of two `u128`s as `(high, low)` halves.
+fn wide_mul(a: u128, b: u128) -> (u128, u128) {
+    let (a1, a0) = (a >> 64, a & 0xFFFF_FFFF_FFFF_FFFF);
+    let (b1, b0) = (b >> 64, b & 0xFFFF_FFFF_FFFF_FFFF);
+    let (p00, p01, p10, p11) = (a0 * b0, a0 * b1, a1 * b0, a1 * b1);
+    let mid = (p00 >> 64) + (p01 & 0xFFFF_FFFF_FFFF_FFFF) + (p10 & 0xFFFF_FFFF_FFFF_FFFF);
+    let low = (p00 & 0xFFFF_FFFF_FFFF_FFFF) | (mid << 64);
+    let high = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (high, low)
+}
+
+/// An unsigned 256-bit integer, just wide enough for an exact fused
+/// multiply-add of two quad-precision significands.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+struct U256 {
+    high: u128,
+    low: u128,
+}
+
+impl U256 {
+    fn leading_zeros(self) -> u32 {
+        if self.high != 0 { self.high.leading_zeros() } else { 128 + self.low.leading_zeros() }
+    }
+
+    fn shl(self, n: u32) -> U256 {
+        match n {
+            0 => self,
+            1..=127 => U256 { high: (self.high << n) | (self.low >> (128 - n)), low: self.low << n },
+            128..=255 => U256 { high: self.low << (n - 128), low: 0 },
+            _ => U256 { high: 0, low: 0 },
+        }
+    }
+
+    fn shr_sticky(self, n: u32) -> U256 {
+        let shifted = match n {
+            0 => return self,
+            1..=127 => U256 { high: self.high >> n, low: (self.low >> n) | (self.high << (128 - n)) },
+            128..=255 => U256 { high: 0, low: self.high >> (n - 128) },
+            _ => U256 { high: 0, low: 0 },
+        };
+        let lost = shifted.shl(n) != self;
+        U256 { high: shifted.high, low: shifted.low | lost as u128 }
+    }
+
+    fn add(self, other: U256) -> U256 {
+        let (low, carry) = self.low.overflowing_add(other.low);
+        U256 { high: self.high + other.high + carry as u128, low }
+    }
+
+    fn sub(self, other: U256) -> U256 {
+        let (low, borrow) = self.low.overflowing_sub(other.low);
+        U256 { high: self.high - other.high - borrow as u128, low }
+    }
+
+    /// Narrow to a `u128` with its leading one at or below `LEAD`, keeping
+    /// the exponent of the least significant bit in step.
+    fn narrow(self, exp: i32) -> (i32, u128) {
+        let msb = 255 - self.leading_zeros();
+        if msb <= LEAD { return (exp, self.low); }
+        let shift = msb - LEAD;
+        (exp + shift as i32, self.shr_sticky(shift).low)
+    }
+}
+
+pub fn mul(fmt: Format, a: u128, b: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    match (va, vb) {
+        (Nan { .. }, _) | (_, Nan { .. }) => propagate_nan(fmt, &[va, vb], flags),
+        (Infinity(_), Zero(_)) | (Zero(_), Infinity(_)) => invalid(fmt, flags),
+        (Infinity(sa), Infinity(sb)) | (Infinity(sa), Finite { sign: sb, .. }) |
+        (Finite { sign: sa, .. }, Infinity(sb)) => fmt.infinity(sa != sb),
+        (Zero(sa), Zero(sb)) | (Zero(sa), Finite { sign: sb, .. }) |
+        (Finite { sign: sa, .. }, Zero(sb)) => fmt.zero(sa != sb),
+        (Finite { sign: sa, exp: ea, sig: ma }, Finite { sign: sb, exp: eb, sig: mb }) => {
+            let (high, low) = wide_mul(ma, mb);
+            let (exp, sig) = U256 { high, low }.narrow(ea + eb);
+            round_pack(fmt, sa != sb, exp, sig, rm, flags)
+        }
+    }
+}
+
+pub fn div(fmt: Format, a: u128, b: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    match (va, vb) {
+        (Nan { .. }, _) | (_, Nan { .. }) => propagate_nan(fmt, &[va, vb], flags),
+        (Infinity(_), Infinity(_)) | (Zero(_), Zero(_)) => invalid(fmt, flags),
+        (Infinity(sa), Zero(sb)) | (Infinity(sa), Finite { sign: sb, .. }) => fmt.infinity(sa != sb),
+        (Zero(sa), Infinity(sb)) | (Finite { sign: sa, .. }, Infinity(sb)) |
+        (Zero(sa), Finite { sign: sb, .. }) => fmt.zero(sa != sb),
+        (Finite { sign: sa, .. }, Zero(sb)) => {
+            *flags |= DIVIDE_BY_ZERO;
+            fmt.infinity(sa != sb)
+        }
+        (Finite { sign: sa, exp: ea, sig: ma }, Finite { sign: sb, exp: eb, sig: mb }) => {
+            let (ea, ma) = normalize(ea, ma, LEAD);
+            let (eb, mb) = normalize(eb, mb, LEAD);
+            // Long division: one quotient bit per step, 2^LEAD * ma / mb.
+            let (mut quotient, mut rem) = (0u128, ma);
+            for _ in 0..=LEAD {
+                quotient <<= 1;
+                if rem >= mb {
+                    rem -= mb;
+                    quotient |= 1;
+                }
+                rem <<= 1;
+            }
+            round_pack(fmt, sa != sb, ea - eb - LEAD as i32, quotient | (rem != 0) as u128, rm, flags)
+        }
+    }
+}
+
+pub fn sqrt(fmt: Format, a: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    let va = unpack(fmt, a);
+    match va {
+        Nan { .. } => propagate_nan(fmt, &[va], flags),
+        Zero(sign) => fmt.zero(sign),
+        Infinity(true) | Finite { sign: true, .. } => invalid(fmt, flags),
+        Infinity(false) => fmt.infinity(false),
+        Finite { exp, sig, .. } => {
+            // Widen the radicand to 248 or 249 bits, keeping its exponent
+            // even, so the root has enough bits to round and its partial
+            // remainders still fit in a u128.
+            let (exp, sig) = normalize(exp, sig, LEAD);
+            let widen: i32 = if (exp - 122) % 2 == 0 { 122 } else { 123 };
+            let radicand = U256 { high: 0, low: sig }.shl(widen as u32);
+
+            let (mut root, mut rem) = (0u128, 0u128);
+            for i in (0..128).rev() {
+                let pair = if i >= 64 { radicand.high >> (2 * i - 128) } else { radicand.low >> (2 * i) } & 0b11;
+                rem = (rem << 2) | pair;
+                let trial = (root << 2) | 1;
+                root <<= 1;
+                if rem >= trial {
+                    rem -= trial;
+                    root |= 1;
+                }
+            }
+            round_pack(fmt, false, (exp - widen) / 2, root | (rem != 0) as u128, rm, flags)
+        }
+    }
+}
+
+/// `a * b + c` with a single rounding.
+pub fn fma(fmt: Format, a: u128, b: u128, c: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    let (va, vb, vc) = (unpack(fmt, a), unpack(fmt, b), unpack(fmt, c));
+    let infinite_times_zero = matches!((va, vb), (Infinity(_), Zero(_)) | (Zero(_), Infinity(_)));
+    if infinite_times_zero {
+        // Invalid even when the addend is a quiet NaN.
+        return invalid(fmt, flags);
+    }
+    if let (Nan { .. }, _, _) | (_, Nan { .. }, _) | (_, _, Nan { .. }) = (va, vb, vc) {
+        return propagate_nan(fmt, &[va, vb, vc], flags);
+    }
+
+    let sign_of = |v: Value| match v {
+        Infinity(s) | Zero(s) | Finite { sign: s, .. } => s,
+        Nan { .. } => false,
+    };
+    let product_sign = sign_of(va) != sign_of(vb);
+
+    match (va, vb, vc) {
+        (Infinity(_), _, _) | (_, Infinity(_), _) => {
+            if vc == Infinity(!product_sign) { invalid(fmt, flags) } else { fmt.infinity(product_sign) }
+        }
+        (_, _, Infinity(sc)) => fmt.infinity(sc),
+        (Zero(_), _, Zero(sc)) | (_, Zero(_), Zero(sc)) => {
+            fmt.zero(if product_sign == sc { sc } else { rm == Rounding::Down })
+        }
+        (Zero(_), _, _) | (_, Zero(_), _) => c & fmt.mask(),
+        (Finite { exp: ea, sig: ma, .. }, Finite { exp: eb, sig: mb, .. }, Zero(_)) => {
+            let (high, low) = wide_mul(ma, mb);
+            let (exp, sig) = U256 { high, low }.narrow(ea + eb);
+            round_pack(fmt, product_sign, exp, sig, rm, flags)
+        }
+        (Finite { exp: ea, sig: ma, .. }, Finite { exp: eb, sig: mb, .. }, Finite { sign: sc, exp: ec, sig: mc }) => {
+            // Line both terms up with their leading one at bit 253, then
+            // align the smaller one to the larger exponent.
+            let (high, low) = wide_mul(ma, mb);
+            let product = U256 { high, low };
+            let product_shift = product.leading_zeros() - 2;
+            let (ep, product) = (ea + eb - product_shift as i32, product.shl(product_shift));
+            let addend = U256 { high: 0, low: mc };
+            let addend_shift = addend.leading_zeros() - 2;
+            let (ec, addend) = (ec - addend_shift as i32, addend.shl(addend_shift));
+
+            let ((sx, ex, x), (sy, ey, y)) = if ep >= ec {
+                ((product_sign, ep, product), (sc, ec, addend))
+            } else {
+                ((sc, ec, addend), (product_sign, ep, product))
+            };
+            let y = y.shr_sticky((ex - ey).min(256) as u32);
+
+            let (sign, sum) = if sx == sy {
+                (sx, x.add(y))
+            } else if x >= y {
+                (sx, x.sub(y))
+            } else {
+                (sy, y.sub(x))
+            };
+            if sum == (U256 { high: 0, low: 0 }) { return fmt.zero(rm == Rounding::Down); }
+            let (exp, sig) = sum.narrow(ex);
+            round_pack(fmt, sign, exp, sig, rm, flags)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// A key that orders non-NaN values numerically, with -0 equal to +0.
+fn order_key(fmt: Format, bits: u128) -> i128 {
+    let magnitude = (bits & fmt.mask() & !fmt.sign_bit()) as i128;
+    if bits & fmt.sign_bit() != 0 { -magnitude } else { magnitude }
+}
+
+/// `feq`: quiet, so only signaling NaNs raise invalid.
+pub fn eq(fmt: Format, a: u128, b: u128, flags: &mut u8) -> bool {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if let (Nan { .. }, _) | (_, Nan { .. }) = (va, vb) {
+        propagate_nan(fmt, &[va, vb], flags);
+        return false;
+    }
+    order_key(fmt, a) == order_key(fmt, b)
+}
+
+/// `flt`: signaling, so any NaN raises invalid.
+pub fn lt(fmt: Format, a: u128, b: u128, flags: &mut u8) -> bool {
+    if is_nan(fmt, a) || is_nan(fmt, b) {
+        *flags |= INVALID;
+        return false;
+    }
+    order_key(fmt, a) < order_key(fmt, b)
+}
+
+/// `fle`: signaling, so any NaN raises invalid.
+pub fn le(fmt: Format, a: u128, b: u128, flags: &mut u8) -> bool {
+    if is_nan(fmt, a) || is_nan(fmt, b) {
+        *flags |= INVALID;
+        return false;
+    }
+    order_key(fmt, a) <= order_key(fmt, b)
+}
+
+/// `fmin`/`fmax`: a NaN operand yields the other operand, and -0 < +0.
+pub fn min_max(fmt: Format, a: u128, b: u128, max: bool, flags: &mut u8) -> u128 {
+    let (va, vb) = (unpack(fmt, a), unpack(fmt, b));
+    if is_signaling(va) || is_signaling(vb) { *flags |= INVALID; }
+    match (is_nan(fmt, a), is_nan(fmt, b)) {
+        (true, true) => return fmt.canonical_nan(),
+        (true, false) => return b & fmt.mask(),
+        (false, true) => return a & fmt.mask(),
+        (false, false) => {}
+    }
+    let (ka, kb) = (order_key(fmt, a), order_key(fmt, b));
+    let a_first = ka < kb || (ka == kb && a & fmt.sign_bit() != 0);
+    let a_wins = if max { !a_first } else { a_first };
+    if a_wins { a & fmt.mask() } else { b & fmt.mask() }
+}
+
+/// The `fclass` mask: exactly one of ten bits, from negative infinity (0) to
+/// quiet NaN (9).
+pub fn classify(fmt: Format, a: u128) -> u32 {
+    let subnormal = |bits: u128| bits & fmt.infinity(false) == 0;
+    let bit = match unpack(fmt, a) {
+        Infinity(true) => 0,
+        Finite { sign: true, .. } if subnormal(a) => 2,
+        Finite { sign: true, .. } => 1,
+        Zero(true) => 3,
+        Zero(false) => 4,
+        Finite { sign: false, .. } if subnormal(a) => 5,
+        Finite { sign: false, .. } => 6,
+        Infinity(false) => 7,
+        Nan { signaling: true } => 8,
+        Nan { signaling: false } => 9,
+    };
+    1 << bit
+}
+
+/// Round to an integer of `width` bits. Out-of-range values and NaNs raise
+/// invalid and saturate; NaN saturates to the largest value.
+pub fn to_int(fmt: Format, a: u128, rm: Rounding, signed: bool, width: u32, flags: &mut u8) -> i128 {
+    let (min, max): (i128, i128) = if signed {
+        (-(1 << (width - 1)), (1 << (width - 1)) - 1)
+    } else {
+        (0, (1 << width) - 1)
+    };
+    let (sign, exp, sig) = match unpack(fmt, a) {
+        Nan { .. } => { *flags |= INVALID; return max; }
+        Infinity(sign) => { *flags |= INVALID; return if sign { min } else { max }; }
+        Zero(_) => return 0,
+        Finite { sign, exp, sig } => (sign, exp, sig),
+    };
+
+    let (magnitude, inexact) = if exp >= 0 {
+        // Anything of 2^65 or more is out of range for every width we support.
+        if exp as u32 + (128 - sig.leading_zeros()) > 65 { (None, false) } else { (Some(sig << exp), false) }
+    } else {
+        let (kept, up, inexact) = round_bits(sig, (-exp) as u32, rm, sign);
+        (Some(kept + up as u128), inexact)
+    };
+    let value = magnitude.map(|m| if sign { -(m as i128) } else { m as i128 });
+    match value {
+        Some(value) if value >= min && value <= max => {
+            if inexact { *flags |= INEXACT; }
+            value
+        }
+        _ => {
+            *flags |= INVALID;
+            if sign { min } else { max }
+        }
+    }
+}
+
+/// Convert an integer, rounding it if `fmt` cannot represent it exactly.
+pub fn from_int(fmt: Format, value: i128, rm: Rounding, flags: &mut u8) -> u128 {
+    if value == 0 { return 0; }
+    round_pack(fmt, value < 0, 0, value.unsigned_abs(), rm, flags)
+}
+
+/// Convert between formats, rounding when narrowing.
+pub fn convert(from: Format, to: Format, a: u128, rm: Rounding, flags: &mut u8) -> u128 {
+    let va = unpack(from, a);
+    match va {
+        Nan { .. } => propagate_nan(to, &[va], flags),
+        Infinity(sign) => to.infinity(sign),
+        Zero(sign) => to.zero(sign),
+        Finite { sign, exp, sig } => round_pack(to, sign, exp, sig, rm, flags),
+    }
+}
+
+#[cfg(test)]
+mod softfloat_test;
@@ -0,0 +1,292 @@
+use super::*;
+
+const RNE: Rounding = Rounding::NearestEven;
+const NAN: u128 = 0x7fc0_0000;
+const SNAN: u128 = 0x7f80_0001;
+
+fn f(value: f32) -> u128 { value.to_bits() as u128 }
+
+fn d(value: f64) -> u128 { value.to_bits() as u128 }
+
+fn xorshift() -> impl FnMut() -> u64 {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    }
+}
+
+/// A deterministic stream of f32 bit patterns, biased towards the edges of
+/// the exponent range where rounding is most interesting.
+fn samples(count: usize) -> Vec<u32> {
+    let mut next = xorshift();
+    (0..count).map(|_| {
+        let bits = next() as u32;
+        match next() % 4 {
+            0 => bits & 0x807f_ffff,                 // zero or subnormal
+            1 => bits | 0x7f00_0000,                 // huge
+            2 => (bits & 0x83ff_ffff) | 0x3c00_0000, // near one
+            _ => bits,
+        }
+    }).collect()
+}
+
+/// The same for f64.
+fn samples64(count: usize) -> Vec<u64> {
+    let mut next = xorshift();
+    (0..count).map(|_| {
+        let bits = next();
+        match next() % 4 {
+            0 => bits & 0x800f_ffff_ffff_ffff,
+            1 => bits | 0x7fe0_0000_0000_0000,
+            2 => (bits & 0x807f_ffff_ffff_ffff) | 0x3f80_0000_0000_0000,
+            _ => bits,
+        }
+    }).collect()
+}
+
+/// Our result against the host's, where every host NaN must be ours canonical.
+fn check(ours: u128, host: f32, what: &str) {
+    if host.is_nan() {
+        assert_eq!(ours, NAN, "{} should be the canonical NaN", what);
+    } else {
+        assert_eq!(ours, f(host), "{}: got 0x{:08x}, host 0x{:08x}", what, ours, host.to_bits());
+    }
+}
+
+#[test]
+fn test_matches_host_arithmetic_when_rounding_to_nearest() {
+    let values = samples(600);
+    for pair in values.chunks(3) {
+        let (a, b, c) = (f32::from_bits(pair[0]), f32::from_bits(pair[1]), f32::from_bits(pair[2]));
+        let mut flags = 0;
+        check(add(F32, f(a), f(b), RNE, &mut flags), a + b, &format!("{:e} + {:e}", a, b));
+        check(sub(F32, f(a), f(b), RNE, &mut flags), a - b, &format!("{:e} - {:e}", a, b));
+        check(mul(F32, f(a), f(b), RNE, &mut flags), a * b, &format!("{:e} * {:e}", a, b));
+        check(div(F32, f(a), f(b), RNE, &mut flags), a / b, &format!("{:e} / {:e}", a, b));
+        check(sqrt(F32, f(a), RNE, &mut flags), a.sqrt(), &format!("sqrt {:e}", a));
+        check(fma(F32, f(a), f(b), f(c), RNE, &mut flags), a.mul_add(b, c),
+            &format!("{:e} * {:e} + {:e}", a, b, c));
+    }
+}
+
+#[test]
+fn test_matches_host_double_arithmetic() {
+    let host = |ours: u128, host: f64, what: String| {
+        if host.is_nan() { assert_eq!(ours, F64.canonical_nan(), "{}", what); }
+        else { assert_eq!(ours, d(host), "{}", what); }
+    };
+    let values = samples64(600);
+    for pair in values.chunks(3) {
+        let (a, b, c) = (f64::from_bits(pair[0]), f64::from_bits(pair[1]), f64::from_bits(pair[2]));
+        host(add(F64, d(a), d(b), RNE, &mut 0), a + b, format!("{:e} + {:e}", a, b));
+        host(mul(F64, d(a), d(b), RNE, &mut 0), a * b, format!("{:e} * {:e}", a, b));
+        host(div(F64, d(a), d(b), RNE, &mut 0), a / b, format!("{:e} / {:e}", a, b));
+        host(sqrt(F64, d(a), RNE, &mut 0), a.sqrt(), format!("sqrt {:e}", a));
+        host(fma(F64, d(a), d(b), d(c), RNE, &mut 0), a.mul_add(b, c), format!("{:e} * {:e} + {:e}", a, b, c));
+    }
+}
+
+#[test]
+fn test_quad_results_round_to_the_host_double_results() {
+    // Quad has more than twice the precision of double plus two bits, so
+    // rounding a quad result again to double cannot differ from rounding
+    // the exact result once.
+    let widen = |x: f64| convert(F64, F128, d(x), RNE, &mut 0);
+    let host = |ours: u128, host: f64, what: String| {
+        let ours = convert(F128, F64, ours, RNE, &mut 0);
+        if host.is_nan() { assert_eq!(ours, F64.canonical_nan(), "{}", what); }
+        else { assert_eq!(ours, d(host), "{}", what); }
+    };
+    let values = samples64(600);
+    for pair in values.chunks(2) {
+        let (a, b) = (f64::from_bits(pair[0]), f64::from_bits(pair[1]));
+        host(add(F128, widen(a), widen(b), RNE, &mut 0), a + b, format!("{:e} + {:e}", a, b));
+        host(sub(F128, widen(a), widen(b), RNE, &mut 0), a - b, format!("{:e} - {:e}", a, b));
+        host(mul(F128, widen(a), widen(b), RNE, &mut 0), a * b, format!("{:e} * {:e}", a, b));
+        host(div(F128, widen(a), widen(b), RNE, &mut 0), a / b, format!("{:e} / {:e}", a, b));
+        host(sqrt(F128, widen(a), RNE, &mut 0), a.sqrt(), format!("sqrt {:e}", a));
+    }
+}
+
+#[test]
+fn test_quad_precision() {
+    let one = from_int(F128, 1, RNE, &mut 0);
+    assert_eq!(one, 0x3fff_0000_0000_0000_0000_0000_0000_0000);
+
+    let mut flags = 0;
+    let third = div(F128, one, from_int(F128, 3, RNE, &mut 0), RNE, &mut flags);
+    assert_eq!(third, 0x3ffd_5555_5555_5555_5555_5555_5555_5555);
+    assert_eq!(flags, INEXACT);
+    let tenth = div(F128, one, from_int(F128, 10, RNE, &mut 0), RNE, &mut 0);
+    assert_eq!(tenth, 0x3ffb_9999_9999_9999_9999_9999_9999_999a);
+    let root2 = sqrt(F128, from_int(F128, 2, RNE, &mut 0), RNE, &mut 0);
+    assert_eq!(root2, 0x3fff_6a09_e667_f3bc_c908_b2fb_1366_ea95);
+
+    // Every 64-bit integer is exact, and so are doubles.
+    let mut flags = 0;
+    let big = from_int(F128, i64::MIN as i128, RNE, &mut flags);
+    assert_eq!(to_int(F128, big, RNE, true, 64, &mut flags), i64::MIN as i128);
+    assert_eq!(convert(F64, F128, d(0.1), RNE, &mut flags) >> 64, 0x3ffb_9999_9999_9999);
+    assert_eq!(flags, 0);
+
+    let huge = from_int(F128, 1 << 100, RNE, &mut 0);
+    let mut flags = 0;
+    assert_eq!(to_int(F128, huge, RNE, false, 64, &mut flags), u64::MAX as i128);
+    assert_eq!(flags, INVALID);
+    assert_eq!(classify(F128, F128.canonical_nan()), 1 << 9);
+}
+
+#[test]
+fn test_conversions_between_formats() {
+    for &bits in &samples64(300) {
+        let value = f64::from_bits(bits);
+        let narrowed = convert(F64, F32, d(value), RNE, &mut 0);
+        check(narrowed, value as f32, &format!("{:e} as f32", value));
+        let widened = convert(F32, F64, narrowed, RNE, &mut 0);
+        if !value.is_nan() { assert_eq!(widened, d(value as f32 as f64)); }
+    }
+
+    let mut flags = 0;
+    assert_eq!(convert(F32, F64, f(1.5), RNE, &mut flags), d(1.5));
+    assert_eq!(flags, 0, "widening is exact");
+    assert_eq!(convert(F32, F64, SNAN, RNE, &mut flags), F64.canonical_nan());
+    assert_eq!(flags, INVALID);
+
+    let mut flags = 0;
+    assert_eq!(convert(F64, F32, d(1e300), Rounding::TowardZero, &mut flags), f(f32::MAX));
+    assert_eq!(flags, OVERFLOW | INEXACT);
+}
+
+#[test]
+fn test_directed_rounding_brackets_the_exact_product() {
+    let values = samples(400);
+    for pair in values.chunks(2) {
+        let (a, b) = (f32::from_bits(pair[0]), f32::from_bits(pair[1]));
+        let exact = a as f64 * b as f64;
+        if !exact.is_finite() || exact.abs() > f32::MAX as f64 || exact.abs() < f32::MIN_POSITIVE as f64 {
+            continue;
+        }
+        let result = |rm| f32::from_bits(mul(F32, f(a), f(b), rm, &mut 0) as u32) as f64;
+        let (down, up, toward_zero) = (result(Rounding::Down), result(Rounding::Up), result(Rounding::TowardZero));
+        assert!(down <= exact && exact <= up, "{:e} * {:e}", a, b);
+        assert_eq!(down == up, exact as f32 as f64 == exact);
+        assert_eq!(toward_zero, if exact < 0.0 { up } else { down });
+    }
+}
+
+#[test]
+fn test_exception_flags() {
+    let flags_of = |op: &dyn Fn(&mut u8) -> u128| { let mut flags = 0; op(&mut flags); flags };
+
+    assert_eq!(flags_of(&|fl| add(F32, f(1.0), f(2.0), RNE, fl)), 0);
+    assert_eq!(flags_of(&|fl| div(F32, f(1.0), f(3.0), RNE, fl)), INEXACT);
+    assert_eq!(flags_of(&|fl| div(F32, f(1.0), f(0.0), RNE, fl)), DIVIDE_BY_ZERO);
+    assert_eq!(flags_of(&|fl| div(F32, f(0.0), f(0.0), RNE, fl)), INVALID);
+    assert_eq!(flags_of(&|fl| sub(F32, f(f32::INFINITY), f(f32::INFINITY), RNE, fl)), INVALID);
+    assert_eq!(flags_of(&|fl| sqrt(F32, f(-1.0), RNE, fl)), INVALID);
+    assert_eq!(flags_of(&|fl| mul(F32, f(f32::MAX), f(2.0), RNE, fl)), OVERFLOW | INEXACT);
+    assert_eq!(flags_of(&|fl| mul(F32, f(1e-30), f(1e-10), RNE, fl)), UNDERFLOW | INEXACT);
+    assert_eq!(flags_of(&|fl| add(F32, NAN, f(1.0), RNE, fl)), 0);
+    assert_eq!(flags_of(&|fl| add(F32, SNAN, f(1.0), RNE, fl)), INVALID);
+    assert_eq!(flags_of(&|fl| fma(F32, f(f32::INFINITY), f(0.0), NAN, RNE, fl)), INVALID);
+}
+
+#[test]
+fn test_overflow_respects_rounding_direction() {
+    let max = f(f32::MAX);
+    assert_eq!(mul(F32, max, f(2.0), Rounding::TowardZero, &mut 0), max);
+    assert_eq!(mul(F32, max, f(-2.0), Rounding::Up, &mut 0), f(-f32::MAX));
+    assert_eq!(mul(F32, max, f(-2.0), Rounding::Down, &mut 0), f(f32::NEG_INFINITY));
+    assert_eq!(mul(F32, max, f(2.0), Rounding::NearestMaxMagnitude, &mut 0), f(f32::INFINITY));
+}
+
+#[test]
+fn test_tininess_is_detected_after_rounding() {
+    // 2^-126 - 2^-151 rounds to the smallest normal number when rounding to
+    // nearest, so it is not tiny; truncated it stays subnormal.
+    let mut flags = 0;
+    assert_eq!(round_pack(F32, false, -151, (1 << 25) - 1, RNE, &mut flags), 0x0080_0000);
+    assert_eq!(flags, INEXACT);
+
+    let mut flags = 0;
+    assert_eq!(round_pack(F32, false, -151, (1 << 25) - 1, Rounding::TowardZero, &mut flags), 0x007f_ffff);
+    assert_eq!(flags, UNDERFLOW | INEXACT);
+}
+
+#[test]
+fn test_zero_signs() {
+    assert_eq!(add(F32, f(1.0), f(-1.0), RNE, &mut 0), f(0.0));
+    assert_eq!(add(F32, f(1.0), f(-1.0), Rounding::Down, &mut 0), f(-0.0));
+    assert_eq!(add(F32, f(-0.0), f(-0.0), RNE, &mut 0), f(-0.0));
+    assert_eq!(sqrt(F32, f(-0.0), RNE, &mut 0), f(-0.0));
+    assert_eq!(fma(F32, f(-1.0), f(0.0), f(0.0), RNE, &mut 0), f(0.0));
+    assert_eq!(fma(F32, f(-1.0), f(0.0), f(-0.0), RNE, &mut 0), f(-0.0));
+}
+
+#[test]
+fn test_conversions_to_integers_saturate() {
+    let to_i32 = |x: u128, rm, flags: &mut u8| to_int(F32, x, rm, true, 32, flags);
+    let to_u32 = |x: u128, rm, flags: &mut u8| to_int(F32, x, rm, false, 32, flags);
+
+    let mut flags = 0;
+    assert_eq!(to_i32(f(2.5), RNE, &mut flags), 2);
+    assert_eq!(to_i32(f(2.5), Rounding::NearestMaxMagnitude, &mut flags), 3);
+    assert_eq!(to_i32(f(-2.5), Rounding::Down, &mut flags), -3);
+    assert_eq!(to_i32(f(-2.5), Rounding::TowardZero, &mut flags), -2);
+    assert_eq!(flags, INEXACT);
+
+    let mut flags = 0;
+    assert_eq!(to_i32(f(-2147483648.0), RNE, &mut flags), -2147483648);
+    assert_eq!(to_u32(f(-0.25), RNE, &mut flags), 0);
+    assert_eq!(flags, INEXACT);
+
+    let mut flags = 0;
+    assert_eq!(to_i32(f(2147483648.0), RNE, &mut flags), i32::MAX as i128);
+    assert_eq!(flags, INVALID);
+    assert_eq!(to_i32(NAN, RNE, &mut 0), i32::MAX as i128);
+    assert_eq!(to_i32(f(f32::NEG_INFINITY), RNE, &mut 0), i32::MIN as i128);
+    assert_eq!(to_u32(f(-1.0), RNE, &mut 0), 0);
+    assert_eq!(to_u32(f(1e20), RNE, &mut 0), u32::MAX as i128);
+}
+
+#[test]
+fn test_conversions_from_integers_round() {
+    let mut flags = 0;
+    assert_eq!(from_int(F32, 16777217, RNE, &mut flags), f(16777216.0));
+    assert_eq!(from_int(F32, 16777217, Rounding::Up, &mut flags), f(16777218.0));
+    assert_eq!(flags, INEXACT);
+    assert_eq!(from_int(F32, -7, RNE, &mut 0), f(-7.0));
+    assert_eq!(from_int(F32, u32::MAX as i128, RNE, &mut 0), f(4294967296.0));
+}
+
+#[test]
+fn test_comparisons_and_min_max() {
+    let mut flags = 0;
+    assert!(eq(F32, f(0.0), f(-0.0), &mut flags));
+    assert!(!eq(F32, NAN, NAN, &mut flags));
+    assert_eq!(flags, 0, "feq is quiet");
+    assert!(!lt(F32, NAN, f(1.0), &mut flags));
+    assert_eq!(flags, INVALID, "flt signals on quiet NaNs");
+    assert!(le(F32, f(-1.0), f(-0.0), &mut 0));
+    assert!(lt(F32, f(f32::NEG_INFINITY), f(-f32::MAX), &mut 0));
+
+    let mut flags = 0;
+    assert_eq!(min_max(F32, f(-0.0), f(0.0), false, &mut flags), f(-0.0));
+    assert_eq!(min_max(F32, f(0.0), f(-0.0), true, &mut flags), f(0.0));
+    assert_eq!(min_max(F32, NAN, f(3.0), false, &mut flags), f(3.0));
+    assert_eq!(flags, 0);
+    assert_eq!(min_max(F32, SNAN, f(3.0), true, &mut flags), f(3.0));
+    assert_eq!(flags, INVALID);
+    assert_eq!(min_max(F32, SNAN, NAN, true, &mut 0), NAN);
+}
+
+#[test]
+fn test_classify() {
+    let classes: Vec<u32> = [f(f32::NEG_INFINITY), f(-1.0), 0x8000_0001, f(-0.0), f(0.0), 0x0000_0001,
+                             f(1.0), f(f32::INFINITY), SNAN, NAN]
+        .iter().map(|&x| classify(F32, x)).collect();
+    assert_eq!(classes, (0..10).map(|bit| 1 << bit).collect::<Vec<u32>>());
+}